@@ -0,0 +1,53 @@
+#!/usr/bin/env bash
+#
+# Compares the run time of the scripts in benches/compare with a release
+# build of a baseline commit. Fails if a script runs slower than the
+# baseline by more than the tolerated factor.
+#
+# Usage: benches/compare.sh [baseline-ref] [max-slowdown]
+#
+# The scripts only use functions the baseline knows as well.
+
+set -euo pipefail
+
+BASELINE=${1:-${BOLI_BASELINE:-d3340a1}}
+MAX_SLOWDOWN=${2:-${BOLI_MAX_SLOWDOWN:-1.2}}
+
+CRATE_DIR=$(cd "$(dirname "$0")/.." && pwd)
+WORK_DIR=$(mktemp -d)
+trap 'git -C "$CRATE_DIR" worktree remove --force "$WORK_DIR/baseline" >/dev/null 2>&1 || true; rm -rf "$WORK_DIR"' EXIT
+
+echo "Building baseline $BASELINE ..."
+git -C "$CRATE_DIR" worktree add --detach "$WORK_DIR/baseline" "$BASELINE" >/dev/null
+CRATE_PATH=${CRATE_DIR#"$(git -C "$CRATE_DIR" rev-parse --show-toplevel)"}
+(cd "$WORK_DIR/baseline$CRATE_PATH" && CARGO_TARGET_DIR="$WORK_DIR/target-baseline" cargo build --release --quiet)
+BASELINE_BIN="$WORK_DIR/target-baseline/release/boli"
+
+echo "Building current tree ..."
+(cd "$CRATE_DIR" && cargo build --release --quiet)
+CURRENT_BIN="$CRATE_DIR/target/release/boli"
+
+run_time() {
+    local bin=$1 script=$2
+    local start end
+    start=$(date +%s%N)
+    "$bin" "$script" >/dev/null
+    end=$(date +%s%N)
+    echo $(((end - start) / 1000000))
+}
+
+status=0
+printf "%-20s %12s %12s %8s\n" "script" "baseline ms" "current ms" "ratio"
+for script in "$CRATE_DIR"/benches/compare/*.boli; do
+    name=$(basename "$script" .boli)
+    baseline_ms=$(run_time "$BASELINE_BIN" "$script")
+    current_ms=$(run_time "$CURRENT_BIN" "$script")
+    ratio=$(awk -v c="$current_ms" -v b="$baseline_ms" 'BEGIN { printf "%.2f", (c + 1) / (b + 1) }')
+    printf "%-20s %12d %12d %8s\n" "$name" "$baseline_ms" "$current_ms" "$ratio"
+    if awk -v r="$ratio" -v m="$MAX_SLOWDOWN" 'BEGIN { exit !(r > m) }'; then
+        echo "  $name is slower than $BASELINE by more than a factor of $MAX_SLOWDOWN"
+        status=1
+    fi
+done
+
+exit $status
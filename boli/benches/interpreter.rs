@@ -0,0 +1,23 @@
+use boli::bench::{Workload, HASHES, RECURSION, STRINGS, VECTORS};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn bench_workloads(c: &mut Criterion, group_name: &str, workloads: &[Workload]) {
+    let mut group = c.benchmark_group(group_name);
+    for workload in workloads {
+        let mut interpreter = workload.prepare().unwrap();
+        group.bench_function(workload.name, |b| {
+            b.iter(|| black_box(workload.run(&mut interpreter).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+fn interpreter_benchmarks(c: &mut Criterion) {
+    bench_workloads(c, "recursion", RECURSION);
+    bench_workloads(c, "vectors", VECTORS);
+    bench_workloads(c, "strings", STRINGS);
+    bench_workloads(c, "hashes", HASHES);
+}
+
+criterion_group!(benches, interpreter_benchmarks);
+criterion_main!(benches);
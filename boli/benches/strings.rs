@@ -0,0 +1,59 @@
+use std::rc::Rc;
+
+use boli::interpreter::values::SharedStr;
+use boli::interpreter::Interpreter;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn eval(interpreter: &mut Interpreter, code: &str) {
+    black_box(interpreter.eval(code).unwrap());
+}
+
+fn string_benchmarks(c: &mut Criterion) {
+    let mut interpreter = Interpreter::with_prelude();
+    interpreter
+        .eval(
+            r#"
+            (def text (string-concat "Lorem ipsum dolor sit amet, consectetur adipiscing elit, "
+                                     "sed do eiusmod tempor incididunt ut labore et dolore magna aliqua."))
+            (def (double s n)
+                (if (= n 0) s (double (string-concat s s) (- n 1))))
+            (def long-text (double text 10))
+            (def (concat-loop s n)
+                (if (= n 0) s (concat-loop (string-concat s "") (- n 1))))
+            (def (sub-loop s n)
+                (if (= n 0) s (sub-loop (string-sub s 1) (- n 1))))
+            "#,
+        )
+        .unwrap();
+
+    c.bench_function("string-concat", |b| {
+        b.iter(|| eval(&mut interpreter, "(string-concat text text text)"))
+    });
+    c.bench_function("string-sub", |b| {
+        b.iter(|| eval(&mut interpreter, "(string-sub text 6 30)"))
+    });
+    c.bench_function("string-concat shared", |b| {
+        b.iter(|| eval(&mut interpreter, "(concat-loop long-text 100)"))
+    });
+    c.bench_function("string-sub shared", |b| {
+        b.iter(|| eval(&mut interpreter, "(sub-loop long-text 100)"))
+    });
+}
+
+// Compares copying a substring (as string values did before they shared
+// their text) with slicing the shared text
+fn substring_benchmarks(c: &mut Criterion) {
+    let text: String = "Lorem ipsum dolor sit amet. ".repeat(4096);
+    let shared = SharedStr::from(text.as_str());
+    let end = text.len() - 1;
+
+    let mut group = c.benchmark_group("substring");
+    group.bench_function("copied", |b| {
+        b.iter(|| black_box(Rc::<str>::from(&text[1..end])))
+    });
+    group.bench_function("shared", |b| b.iter(|| black_box(shared.slice(1..end))));
+    group.finish();
+}
+
+criterion_group!(benches, string_benchmarks, substring_benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+// Built with `make playground`, which puts the bindings into ./pkg
+import init, { evalHtml, reset } from "./pkg/boli.js";
+
+const code = document.getElementById("code");
+const highlight = document.getElementById("highlight");
+const results = document.getElementById("results");
+
+function escapeHtml(text) {
+  return text.replace(/&/g, "&amp;").replace(/</g, "&lt;").replace(/>/g, "&gt;");
+}
+
+// Marks the span of a parse error, lines and columns start at 1
+function showErrorSpan(error) {
+  const lines = code.value.split("\n");
+  if (!error || !error.dataset.line) {
+    highlight.innerHTML = escapeHtml(code.value);
+    return;
+  }
+  const line = Number(error.dataset.line);
+  const column = Number(error.dataset.column);
+  const length = Number(error.dataset.length);
+  let start = lines.slice(0, line - 1).reduce((sum, text) => sum + text.length + 1, 0);
+  start += column - 1;
+  const text = code.value;
+  highlight.innerHTML =
+    escapeHtml(text.slice(0, start)) +
+    "<mark>" + escapeHtml(text.slice(start, start + length)) + "</mark>" +
+    escapeHtml(text.slice(start + length));
+}
+
+function run() {
+  const result = document.createElement("div");
+  result.className = "result";
+  result.innerHTML = evalHtml(code.value);
+  results.prepend(result);
+  showErrorSpan(result.querySelector(".boli-error"));
+}
+
+await init();
+
+document.getElementById("run").addEventListener("click", run);
+document.getElementById("reset").addEventListener("click", () => {
+  reset();
+  results.innerHTML = "";
+  showErrorSpan(null);
+});
+code.addEventListener("keydown", (event) => {
+  if (event.ctrlKey && event.key === "Enter") {
+    event.preventDefault();
+    run();
+  }
+});
+code.addEventListener("input", () => showErrorSpan(null));
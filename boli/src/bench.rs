@@ -0,0 +1,145 @@
+use crate::interpreter::{
+    values::{EvalResult, InterpreterError},
+    Interpreter,
+};
+
+// Programs measured by the criterion benchmarks in benches/interpreter.rs.
+// They are kept in the library so that the tests make sure each of them
+// still evaluates to the expected value instead of timing an error.
+// Criterion only compares with its own previous runs; benches/compare.sh
+// (make bench-compare) times the scripts in benches/compare against a
+// release build of a baseline commit.
+pub struct Workload {
+    pub name: &'static str,
+    pub setup: &'static str,
+    pub code: &'static str,
+    pub expected: &'static str,
+}
+
+impl Workload {
+    pub fn prepare(&self) -> Result<Interpreter, InterpreterError> {
+        let mut interpreter = Interpreter::with_prelude();
+        interpreter.eval(self.setup)?;
+        Ok(interpreter)
+    }
+
+    pub fn run(&self, interpreter: &mut Interpreter) -> EvalResult {
+        interpreter.eval(self.code)
+    }
+}
+
+const RECURSION_SETUP: &str = r#"
+    (def (fib n)
+        (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+    (def (factorial n)
+        (if (= n 0) 1 (* n (factorial (- n 1)))))
+"#;
+
+const VECTOR_SETUP: &str = r#"
+    (def (numbers n acc)
+        (if (= n 0) acc (numbers (- n 1) (vector-cons (- n 1) acc))))
+    (def data (numbers 1000 (vector)))
+    (def (square n) (* n n))
+    (def (even? n) (= (% n 2) 0))
+"#;
+
+const STRING_SETUP: &str = r#"
+    (def (numbers n acc)
+        (if (= n 0) acc (numbers (- n 1) (vector-cons (- n 1) acc))))
+    (def data (numbers 1000 (vector)))
+    (def (join-loop n acc)
+        (if (= n 0) acc (join-loop (- n 1) (string-concat acc (number->string n) ","))))
+"#;
+
+const HASH_SETUP: &str = r#"
+    (def (fill! table n)
+        (if (= n 0)
+            table
+            (block
+                (hash-set! table n (* n n))
+                (fill! table (- n 1)))))
+    (def (lookup table n acc)
+        (if (= n 0) acc (lookup table (- n 1) (+ acc (hash-get table n)))))
+    (def table (fill! (create-hash-table) 500))
+"#;
+
+pub const RECURSION: &[Workload] = &[
+    Workload {
+        name: "fib 15",
+        setup: RECURSION_SETUP,
+        code: "(fib 15)",
+        expected: "610",
+    },
+    Workload {
+        name: "factorial 20",
+        setup: RECURSION_SETUP,
+        code: "(factorial 20)",
+        expected: "2432902008176640000",
+    },
+];
+
+pub const VECTORS: &[Workload] = &[
+    Workload {
+        name: "map",
+        setup: VECTOR_SETUP,
+        code: "(count (map square data))",
+        expected: "1000",
+    },
+    Workload {
+        name: "map filter",
+        setup: VECTOR_SETUP,
+        code: "(count (filter even? (map square data)))",
+        expected: "500",
+    },
+];
+
+pub const STRINGS: &[Workload] = &[
+    Workload {
+        name: "concat loop",
+        setup: STRING_SETUP,
+        code: "(string-count (join-loop 200 \"\"))",
+        expected: "692",
+    },
+    Workload {
+        name: "join",
+        setup: STRING_SETUP,
+        code: "(string-count (string-join (map number->string data) \",\"))",
+        expected: "3889",
+    },
+];
+
+pub const HASHES: &[Workload] = &[
+    Workload {
+        name: "insert",
+        setup: HASH_SETUP,
+        code: "(hash-length (fill! (create-hash-table) 500))",
+        expected: "500",
+    },
+    Workload {
+        name: "lookup",
+        setup: HASH_SETUP,
+        code: "(lookup table 500 0)",
+        expected: "41791750",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workloads() {
+        for workloads in [RECURSION, VECTORS, STRINGS, HASHES] {
+            for workload in workloads {
+                let mut interpreter = workload.prepare().unwrap();
+                let result = workload.run(&mut interpreter).unwrap();
+                assert_eq!(
+                    result.borrow().to_string(),
+                    workload.expected,
+                    "{}",
+                    workload.name
+                );
+            }
+        }
+    }
+}
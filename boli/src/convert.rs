@@ -0,0 +1,411 @@
+// Conversions between Rust values and BOLI values for embedding the
+// interpreter. ValueRef is an Rc of a trait object, so the orphan rules do
+// not allow From/TryFrom impls for it; IntoValue and FromValue take their
+// place.
+
+use std::{collections::HashMap, marker::PhantomData, rc::Rc};
+
+use crate::interpreter::{
+    environment::EnvironmentRef,
+    struct_functions::get_key,
+    values::{
+        borrow_value, downcast_value, new_valueref, BoolValue, Callable, EvalResult, IntValue,
+        InterpreterError, NilValue, RationalValue, RealValue, StrValue, StructEntry, StructValue,
+        ValueRef, ValueType, VectorValue,
+    },
+};
+
+pub trait IntoValue {
+    fn into_value(self) -> ValueRef;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError>;
+}
+
+pub fn to_value<T: IntoValue>(value: T) -> ValueRef {
+    value.into_value()
+}
+
+pub fn from_value<T: FromValue>(value: &ValueRef) -> Result<T, InterpreterError> {
+    T::from_value(value)
+}
+
+fn conversion_error<T>(expected: &str, value: &ValueRef) -> Result<T, InterpreterError> {
+    Err(InterpreterError::new(&format!(
+        "expected {} but got {}",
+        expected,
+        borrow_value(value)
+    )))
+}
+
+impl IntoValue for ValueRef {
+    fn into_value(self) -> ValueRef {
+        self
+    }
+}
+
+impl FromValue for ValueRef {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> ValueRef {
+        new_valueref(NilValue {})
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> ValueRef {
+        new_valueref(BoolValue { value: self })
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        match downcast_value::<BoolValue>(&borrow_value(value)) {
+            Some(bool) => Ok(bool.value),
+            None => conversion_error("a boolean", value),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> ValueRef {
+        new_valueref(IntValue { value: self })
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        match downcast_value::<IntValue>(&borrow_value(value)) {
+            Some(int) => Ok(int.value),
+            None => conversion_error("an integer", value),
+        }
+    }
+}
+
+impl IntoValue for usize {
+    fn into_value(self) -> ValueRef {
+        (self as i64).into_value()
+    }
+}
+
+impl FromValue for usize {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        match usize::try_from(i64::from_value(value)?) {
+            Ok(value) => Ok(value),
+            Err(_) => conversion_error("a non-negative integer", value),
+        }
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> ValueRef {
+        new_valueref(RealValue { value: self })
+    }
+}
+
+// Integers and rationals are converted to reals as well
+impl FromValue for f64 {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        let number = borrow_value(value);
+        if let Some(real) = downcast_value::<RealValue>(&number) {
+            return Ok(real.value);
+        }
+        if let Some(int) = downcast_value::<IntValue>(&number) {
+            return Ok(int.value as f64);
+        }
+        if let Some(rational) = downcast_value::<RationalValue>(&number) {
+            return Ok(rational.numerator as f64 / rational.denominator as f64);
+        }
+        conversion_error("a number", value)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> ValueRef {
+        new_valueref(StrValue { value: self.into() })
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> ValueRef {
+        self.as_str().into_value()
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        match downcast_value::<StrValue>(&borrow_value(value)) {
+            Some(str) => Ok(str.value.to_string()),
+            None => conversion_error("a string", value),
+        }
+    }
+}
+
+// None is represented by nil
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> ValueRef {
+        match self {
+            Some(value) => value.into_value(),
+            None => new_valueref(NilValue {}),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        if value.borrow().get_type() == ValueType::Nil {
+            Ok(None)
+        } else {
+            T::from_value(value).map(Some)
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> ValueRef {
+        new_valueref(VectorValue {
+            elements: self.into_iter().map(IntoValue::into_value).collect(),
+        })
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        match downcast_value::<VectorValue>(&borrow_value(value)) {
+            Some(vector) => vector.elements.iter().map(T::from_value).collect(),
+            None => conversion_error("a list", value),
+        }
+    }
+}
+
+// Hash maps are converted to hash tables with string keys
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> ValueRef {
+        let mut hash_table = StructValue::new_hash_table();
+        for (key, value) in self {
+            let key = key.into_value();
+            hash_table.values.insert(
+                get_key(&key),
+                StructEntry {
+                    key,
+                    value: value.into_value(),
+                },
+            );
+        }
+        new_valueref(hash_table)
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: &ValueRef) -> Result<Self, InterpreterError> {
+        match downcast_value::<StructValue>(&borrow_value(value)) {
+            Some(hash_table) if hash_table.struct_type.is_none() && !hash_table.is_set() => {
+                hash_table
+                    .values
+                    .values()
+                    .map(|entry| {
+                        Ok((
+                            String::from_value(&entry.key)?,
+                            T::from_value(&entry.value)?,
+                        ))
+                    })
+                    .collect()
+            }
+            _ => conversion_error("a hash table", value),
+        }
+    }
+}
+
+// Return values of Rust functions that are registered as built-ins
+pub trait IntoEvalResult {
+    fn into_eval_result(self) -> EvalResult;
+}
+
+impl<T: IntoValue> IntoEvalResult for T {
+    fn into_eval_result(self) -> EvalResult {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> IntoEvalResult for Result<T, InterpreterError> {
+    fn into_eval_result(self) -> EvalResult {
+        self.map(IntoValue::into_value)
+    }
+}
+
+// Implemented for closures whose arguments implement FromValue, the type
+// parameter is the tuple of the argument types. Functions with any number
+// of arguments use the marker type Variadic.
+pub trait IntoBuiltin<Args> {
+    fn arity(&self) -> Option<usize>;
+    fn call_with(&self, args: &[ValueRef]) -> EvalResult;
+}
+
+pub struct Variadic;
+
+impl<F> IntoBuiltin<Variadic> for F
+where
+    F: Fn(&[ValueRef]) -> EvalResult,
+{
+    fn arity(&self) -> Option<usize> {
+        None
+    }
+
+    fn call_with(&self, args: &[ValueRef]) -> EvalResult {
+        self(args)
+    }
+}
+
+macro_rules! impl_into_builtin {
+    ($arity:expr $(, $arg:ident)*) => {
+        impl<F, R, $($arg),*> IntoBuiltin<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoEvalResult,
+            $($arg: FromValue),*
+        {
+            fn arity(&self) -> Option<usize> {
+                Some($arity)
+            }
+
+            #[allow(unused_variables, unused_mut, non_snake_case)]
+            fn call_with(&self, args: &[ValueRef]) -> EvalResult {
+                let mut args = args.iter();
+                $(let $arg = $arg::from_value(args.next().unwrap())?;)*
+                self($($arg),*).into_eval_result()
+            }
+        }
+    };
+}
+
+impl_into_builtin!(0);
+impl_into_builtin!(1, A);
+impl_into_builtin!(2, A, B);
+impl_into_builtin!(3, A, B, C);
+impl_into_builtin!(4, A, B, C, D);
+
+pub struct RustFunction<Args, F> {
+    name: String,
+    function: F,
+    args: PhantomData<fn() -> Args>,
+}
+
+impl<Args, F: IntoBuiltin<Args>> RustFunction<Args, F> {
+    pub fn new(name: &str, function: F) -> Self {
+        Self {
+            name: name.to_string(),
+            function,
+            args: PhantomData,
+        }
+    }
+}
+
+impl<Args, F: IntoBuiltin<Args>> Callable for RustFunction<Args, F> {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        match self.function.arity() {
+            Some(arity) if arity != args.len() => Err(InterpreterError::new(&format!(
+                "{} expects {} argument{}",
+                self.name,
+                arity,
+                if arity == 1 { "" } else { "s" }
+            ))),
+            _ => self.function.call_with(args),
+        }
+    }
+}
+
+// Makes a Rust closure available as built-in function, e.g.
+//
+//   register_function(&env, "add", |a: i64, b: i64| a + b);
+pub fn register_function<Args: 'static, F: IntoBuiltin<Args> + 'static>(
+    env: &EnvironmentRef,
+    name: &str,
+    function: F,
+) {
+    env.borrow_mut()
+        .set_builtin(name, &Rc::new(RustFunction::new(name, function)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    fn round_trip<T: IntoValue + FromValue>(value: T) -> T {
+        from_value(&to_value(value)).unwrap()
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(round_trip(42i64), 42);
+        assert_eq!(round_trip(2.5), 2.5);
+        assert!(round_trip(true));
+        assert_eq!(round_trip("boli".to_string()), "boli");
+        assert_eq!(round_trip(Some(7i64)), Some(7));
+        assert_eq!(round_trip::<Option<i64>>(None), None);
+        assert_eq!(
+            round_trip(vec![vec![1i64], vec![2, 3]]),
+            vec![vec![1], vec![2, 3]]
+        );
+
+        let map = HashMap::from([("a".to_string(), 1i64), ("b".to_string(), 2)]);
+        assert_eq!(round_trip(map.clone()), map);
+
+        assert_eq!(from_value::<f64>(&to_value(3i64)).unwrap(), 3.0);
+        let err = from_value::<i64>(&to_value("x")).unwrap_err();
+        assert_eq!(err.message, "expected an integer but got \"x\"");
+        assert!(from_value::<usize>(&to_value(-1i64)).is_err());
+    }
+
+    #[test]
+    fn test_register_function() {
+        let mut interpreter = Interpreter::new();
+        let env = interpreter.env.clone();
+        register_function(&env, "add", |a: i64, b: i64| a + b);
+        register_function(&env, "greet", |name: Option<String>| {
+            format!("Hello, {}!", name.unwrap_or("World".to_string()))
+        });
+        register_function(&env, "checked-div", |a: i64, b: i64| {
+            if b == 0 {
+                Err(InterpreterError::new("division by zero"))
+            } else {
+                Ok(a / b)
+            }
+        });
+        register_function(&env, "count-args", |args: &[ValueRef]| {
+            Ok(to_value(args.len()))
+        });
+
+        let eval = |interpreter: &mut Interpreter, code: &str| {
+            interpreter
+                .eval(code)
+                .map(|value| borrow_value(&value).to_string())
+        };
+
+        assert_eq!(eval(&mut interpreter, "(add 1 2)").unwrap(), "3");
+        assert_eq!(
+            eval(&mut interpreter, "(greet nil)").unwrap(),
+            "\"Hello, World!\""
+        );
+        assert_eq!(eval(&mut interpreter, "(count-args 1 2 3)").unwrap(), "3");
+        assert_eq!(
+            eval(&mut interpreter, "(checked-div 1 0)")
+                .unwrap_err()
+                .message,
+            "division by zero"
+        );
+        assert_eq!(
+            eval(&mut interpreter, "(add 1)").unwrap_err().message,
+            "add expects 2 arguments"
+        );
+        assert_eq!(
+            eval(&mut interpreter, "(add 1 \"2\")").unwrap_err().message,
+            "expected an integer but got \"2\""
+        );
+    }
+}
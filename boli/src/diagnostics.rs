@@ -0,0 +1,146 @@
+// Renders errors for the terminal with the offending source line and a
+// caret under the token that caused the error, e.g.
+//
+//   error: Unexpected token
+//    --> line 1, column 8
+//     |
+//   1 | (+ 1 2))
+//     |        ^
+
+use std::io::IsTerminal;
+
+use crate::{
+    frontend::parser::{ParseError, Span},
+    interpreter::values::InterpreterError,
+};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+pub struct Diagnostics {
+    color: bool,
+}
+
+impl Diagnostics {
+    pub fn new(color: bool) -> Self {
+        Self { color }
+    }
+
+    // Colors are only used if the output goes to a terminal and neither
+    // --no-color nor the NO_COLOR environment variable are set
+    pub fn for_stdout(no_color: bool) -> Self {
+        Self::new(Self::color_allowed(no_color) && std::io::stdout().is_terminal())
+    }
+
+    pub fn for_stderr(no_color: bool) -> Self {
+        Self::new(Self::color_allowed(no_color) && std::io::stderr().is_terminal())
+    }
+
+    fn color_allowed(no_color: bool) -> bool {
+        !no_color && std::env::var_os("NO_COLOR").is_none()
+    }
+
+    pub fn render_parse_error(&self, source: &str, err: &ParseError) -> String {
+        self.render(source, &err.message, err.span().as_ref())
+    }
+
+    pub fn render_error(&self, source: &str, err: &InterpreterError) -> String {
+        self.render(source, &err.headline(), err.span.as_ref())
+    }
+
+    pub fn render(&self, source: &str, message: &str, span: Option<&Span>) -> String {
+        let mut text = format!(
+            "{}: {}\n",
+            self.paint(RED, "error"),
+            self.paint(BOLD, message)
+        );
+
+        let span = match span {
+            Some(span) => span,
+            None => return text,
+        };
+        let line = match source.lines().nth(span.line.saturating_sub(1)) {
+            Some(line) => line,
+            None => return text,
+        };
+
+        let line_number = span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        // Keep tabs so that the caret lines up with the token
+        let indent: String = line
+            .chars()
+            .take(span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(span.length.max(1));
+
+        text.push_str(&format!(
+            "{}{} line {}, column {}\n",
+            gutter,
+            self.paint(BLUE, "-->"),
+            span.line,
+            span.column
+        ));
+        text.push_str(&format!("{} {}\n", gutter, self.paint(BLUE, "|")));
+        text.push_str(&format!(
+            "{} {} {}\n",
+            self.paint(BLUE, &line_number),
+            self.paint(BLUE, "|"),
+            line
+        ));
+        text.push_str(&format!(
+            "{} {} {}{}\n",
+            gutter,
+            self.paint(BLUE, "|"),
+            indent,
+            self.paint(RED, &carets)
+        ));
+        text
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frontend::parser::Parser, interpreter::Interpreter};
+
+    #[test]
+    fn test_render_parse_error() {
+        let code = "(def a 1)\n\t(+ a 2))";
+        let err = Parser::new().parse(code).err().unwrap();
+
+        let text = Diagnostics::new(false).render_parse_error(code, &err);
+        assert_eq!(
+            text,
+            "error: Unexpected token\n \
+             --> line 2, column 9\n  \
+             |\n\
+             2 | \t(+ a 2))\n  \
+             | \t       ^\n"
+        );
+
+        let text = Diagnostics::new(true).render_parse_error(code, &err);
+        assert!(text.starts_with("\x1b[1;31merror\x1b[0m: "));
+        assert!(text.ends_with("\x1b[1;31m^\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_render_error() {
+        let mut interpreter = Interpreter::new();
+        let code = "(+ 1 x)";
+        let err = interpreter.eval(code).unwrap_err();
+
+        let text = Diagnostics::new(false).render_error(code, &err);
+        assert_eq!(text, "error: NameError: Undefined identifier: x\n");
+    }
+}
@@ -0,0 +1,538 @@
+use std::collections::HashSet;
+
+use super::parser::{ast::*, resolver::ScopeScanner, Span};
+
+// Semantic analysis of a program: which scopes exist, which names are
+// defined in them and which definition every name refers to. Tools like the
+// linter derive their findings from the report instead of tracking scopes
+// themselves.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScopeKind {
+    Program,
+    Block,
+    Function,
+    Loop,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScopeInfo {
+    pub kind: ScopeKind,
+    pub parent: Option<usize>,
+    // Indices of the definitions of the scope
+    pub definitions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DefinitionKind {
+    Definition,
+    Constant,
+    Parameter,
+    LoopVariable,
+}
+
+#[derive(Debug, Clone)]
+pub struct DefinitionInfo {
+    pub name: String,
+    pub kind: DefinitionKind,
+    pub scope: usize,
+    // Index (starting at 1) of the top-level form the definition is part of
+    pub form: usize,
+    // Location of the name, if the definition has one in the source code
+    pub span: Option<Span>,
+    // Position in evaluation order, comparable with the positions of
+    // references
+    pub position: usize,
+    // Number of parameters and whether the function is variadic, if the
+    // name is known to be bound to a function
+    pub arity: Option<(usize, bool)>,
+    // Definition of an enclosing scope with the same name
+    pub shadows: Option<usize>,
+    // Indices of the references to the definition
+    pub references: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferenceKind {
+    Read,
+    Call { arguments: usize, spread: bool },
+    Assign,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub kind: ReferenceKind,
+    pub scope: usize,
+    pub form: usize,
+    pub span: Option<Span>,
+    pub position: usize,
+    // None if the name is not defined by the program itself
+    pub definition: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalysisReport {
+    // The first scope is the one of the program
+    pub scopes: Vec<ScopeInfo>,
+    pub definitions: Vec<DefinitionInfo>,
+    pub references: Vec<Reference>,
+    // Names can also be defined at runtime (e.g. by eval)
+    pub dynamic: bool,
+}
+
+impl AnalysisReport {
+    pub fn is_used(&self, definition: usize) -> bool {
+        self.definitions[definition]
+            .references
+            .iter()
+            .any(|reference| self.references[*reference].kind != ReferenceKind::Assign)
+    }
+
+    // Local definitions that are never read or called. Top-level
+    // definitions and parameters are not reported, neither are names that
+    // start with an underscore.
+    pub fn unused_definitions(&self) -> Vec<&DefinitionInfo> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .filter(|(i, definition)| {
+                matches!(
+                    definition.kind,
+                    DefinitionKind::Definition | DefinitionKind::Constant
+                ) && self.scopes[definition.scope].kind == ScopeKind::Block
+                    && !definition.name.starts_with('_')
+                    && !self.is_used(*i)
+            })
+            .map(|(_, definition)| definition)
+            .collect()
+    }
+
+    // Pairs of a definition and the definition it shadows
+    pub fn shadowed_definitions(&self) -> Vec<(&DefinitionInfo, &DefinitionInfo)> {
+        self.definitions
+            .iter()
+            .filter_map(|definition| {
+                definition
+                    .shadows
+                    .map(|shadowed| (definition, &self.definitions[shadowed]))
+            })
+            .collect()
+    }
+
+    // References that are evaluated before the definition they refer to,
+    // like (block (def a b) (def b 1)). They fail at runtime or see a
+    // binding of an enclosing scope. References inside a function body are
+    // evaluated when the function is called and are not reported.
+    pub fn uses_before_definition(&self) -> Vec<(&Reference, &DefinitionInfo)> {
+        self.references
+            .iter()
+            .filter_map(|reference| {
+                let definition = &self.definitions[reference.definition?];
+                (reference.position < definition.position
+                    && !self.is_deferred(reference.scope, definition.scope))
+                .then_some((reference, definition))
+            })
+            .collect()
+    }
+
+    // Whether a function scope lies between scope and the enclosing scope
+    // outer
+    fn is_deferred(&self, scope: usize, outer: usize) -> bool {
+        let mut current = Some(scope);
+        while let Some(scope) = current {
+            if scope == outer {
+                return false;
+            }
+            if self.scopes[scope].kind == ScopeKind::Function {
+                return true;
+            }
+            current = self.scopes[scope].parent;
+        }
+        true
+    }
+
+    pub fn unresolved_references(&self) -> Vec<&Reference> {
+        self.references
+            .iter()
+            .filter(|reference| reference.definition.is_none())
+            .collect()
+    }
+}
+
+pub fn analyze(program: &Program) -> AnalysisReport {
+    let scanner = ScopeScanner::scan(&program.children);
+    let mut analyzer = Analyzer {
+        assigned: scanner.assigned,
+        report: AnalysisReport {
+            scopes: Vec::new(),
+            definitions: Vec::new(),
+            references: Vec::new(),
+            dynamic: scanner.dynamic,
+        },
+        current: Vec::new(),
+        form: 0,
+        position: 0,
+        pending: HashSet::new(),
+    };
+    program.accept(&mut analyzer);
+    analyzer.report
+}
+
+struct Analyzer {
+    assigned: HashSet<String>,
+    report: AnalysisReport,
+    // Stack of the indices of the scopes that are currently open
+    current: Vec<usize>,
+    form: usize,
+    position: usize,
+    // Definitions that are declared in advance but not evaluated yet
+    pending: HashSet<usize>,
+}
+
+impl Analyzer {
+    fn enter_scope(&mut self, kind: ScopeKind) {
+        self.report.scopes.push(ScopeInfo {
+            kind,
+            parent: self.current.last().copied(),
+            definitions: Vec::new(),
+        });
+        self.current.push(self.report.scopes.len() - 1);
+    }
+
+    fn leave_scope(&mut self) {
+        self.current.pop();
+    }
+
+    fn next_position(&mut self) -> usize {
+        self.position += 1;
+        self.position
+    }
+
+    // Moves a definition declared in advance to the position its def form
+    // is evaluated at
+    fn place(&mut self, name: &str) {
+        let scope = *self.current.last().unwrap();
+        if let Some(definition) = self.find_in_scope(scope, name) {
+            if self.pending.remove(&definition) {
+                self.report.definitions[definition].position = self.next_position();
+            }
+        }
+    }
+
+    fn find_in_scope(&self, scope: usize, name: &str) -> Option<usize> {
+        self.report.scopes[scope]
+            .definitions
+            .iter()
+            .copied()
+            .find(|definition| self.report.definitions[*definition].name == name)
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.current
+            .iter()
+            .rev()
+            .find_map(|scope| self.find_in_scope(*scope, name))
+    }
+
+    fn is_defined_locally(&self, name: &str) -> bool {
+        self.find_in_scope(*self.current.last().unwrap(), name)
+            .is_some()
+    }
+
+    fn define(
+        &mut self,
+        name: &str,
+        kind: DefinitionKind,
+        arity: Option<(usize, bool)>,
+        span: Option<Span>,
+    ) -> Option<usize> {
+        let scope = *self.current.last().unwrap();
+
+        // The arity of names that are assigned or defined more than once
+        // is not statically known
+        if let Some(existing) = self.find_in_scope(scope, name) {
+            self.report.definitions[existing].arity = None;
+            return None;
+        }
+        let arity = arity.filter(|_| !self.assigned.contains(name));
+
+        // Definitions of the program scope do not shadow anything
+        let enclosing = &self.current[..self.current.len() - 1];
+        let shadows = enclosing
+            .iter()
+            .rev()
+            .find_map(|scope| self.find_in_scope(*scope, name));
+        let position = self.next_position();
+
+        self.report.definitions.push(DefinitionInfo {
+            name: name.to_string(),
+            kind,
+            scope,
+            form: self.form,
+            span,
+            position,
+            arity,
+            shadows,
+            references: Vec::new(),
+        });
+        let definition = self.report.definitions.len() - 1;
+        self.report.scopes[scope].definitions.push(definition);
+        Some(definition)
+    }
+
+    fn refer(&mut self, name: &str, kind: ReferenceKind, span: Option<Span>) {
+        let definition = self.lookup(name);
+        let position = self.next_position();
+        self.report.references.push(Reference {
+            name: name.to_string(),
+            kind,
+            scope: *self.current.last().unwrap(),
+            form: self.form,
+            span,
+            position,
+            definition,
+        });
+        if let Some(definition) = definition {
+            let reference = self.report.references.len() - 1;
+            self.report.definitions[definition]
+                .references
+                .push(reference);
+        }
+    }
+
+    fn declare_definitions(&mut self, children: &[AstRef]) {
+        for child in children {
+            let child = &borrow_ast(child);
+            if let Some(def) = downcast_ast::<Definition>(child) {
+                let arity = Self::arity(&def.value);
+                let definition =
+                    self.define(&def.name, Self::definition_kind(def), arity, def.span);
+                self.pending.extend(definition);
+            } else if let Some(def) = downcast_ast::<DestructuringDefinition>(child) {
+                for name in def.pattern.names() {
+                    let definition = self.define(&name, DefinitionKind::Definition, None, None);
+                    self.pending.extend(definition);
+                }
+            }
+        }
+    }
+
+    fn definition_kind(def: &Definition) -> DefinitionKind {
+        if def.constant {
+            DefinitionKind::Constant
+        } else {
+            DefinitionKind::Definition
+        }
+    }
+
+    fn arity(value: &AstRef) -> Option<(usize, bool)> {
+        let value = &borrow_ast(value);
+        downcast_ast::<Lambda>(value)
+            .map(|lambda| (lambda.parameters.len(), lambda.variadic.is_some()))
+    }
+}
+
+impl AstVisitor for Analyzer {
+    fn visit_program(&mut self, program: &Program) {
+        self.enter_scope(ScopeKind::Program);
+        self.declare_definitions(&program.children);
+        for (i, child) in program.children.iter().enumerate() {
+            self.form = i + 1;
+            child.borrow().accept(self);
+        }
+        self.leave_scope();
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.enter_scope(ScopeKind::Block);
+        self.declare_definitions(&block.children);
+        block.children.iter().for_each(|child| {
+            child.borrow().accept(self);
+        });
+        self.leave_scope();
+    }
+
+    fn visit_integer(&mut self, _integer: &Integer) {}
+
+    fn visit_rational(&mut self, _rational: &Rational) {}
+
+    fn visit_real(&mut self, _real: &Real) {}
+
+    fn visit_bool(&mut self, _bool: &Bool) {}
+
+    fn visit_str(&mut self, _str: &Str) {}
+
+    fn visit_nil(&mut self) {}
+
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        self.refer(&identifier.value, ReferenceKind::Read, identifier.span);
+    }
+
+    fn visit_absolute_name(&mut self, _absolute_name: &AbsoluteName) {}
+
+    fn visit_symbol(&mut self, _symbol: &Symbol) {}
+
+    fn visit_quote(&mut self, _quote: &Quote) {}
+
+    fn visit_operator(&mut self, _operator: &Operator) {}
+
+    fn visit_logical_operator(&mut self, _operator: &LogicalOperator) {}
+
+    fn visit_pair(&mut self, pair: &Pair) {
+        pair.left.borrow().accept(self);
+        pair.right.borrow().accept(self);
+    }
+
+    fn visit_list(&mut self, list: &List) {
+        list.elements.iter().for_each(|element| {
+            element.borrow().accept(self);
+        });
+    }
+
+    fn visit_def(&mut self, def: &Definition) {
+        if !self.is_defined_locally(&def.name) {
+            let arity = Self::arity(&def.value);
+            let definition = self.define(&def.name, Self::definition_kind(def), arity, def.span);
+            self.pending.extend(definition);
+        }
+        def.value.borrow().accept(self);
+        self.place(&def.name);
+    }
+
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) {
+        for name in def.pattern.names() {
+            if !self.is_defined_locally(&name) {
+                let definition = self.define(&name, DefinitionKind::Definition, None, None);
+                self.pending.extend(definition);
+            }
+        }
+        def.value.borrow().accept(self);
+        for name in def.pattern.names() {
+            self.place(&name);
+        }
+    }
+
+    fn visit_struct_def(&mut self, _struct_def: &StructDefinition) {}
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) {
+        method_def.value.borrow().accept(self);
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) {
+        self.refer(&set_bang.name, ReferenceKind::Assign, set_bang.span);
+        set_bang.value.borrow().accept(self);
+    }
+
+    fn visit_if(&mut self, if_expr: &IfExpression) {
+        if_expr.condition.borrow().accept(self);
+        if_expr.consequent.borrow().accept(self);
+        if_expr.alternate.borrow().accept(self);
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) {
+        case_expr.key.borrow().accept(self);
+        for (_, consequent) in &case_expr.clauses {
+            consequent.borrow().accept(self);
+        }
+        case_expr.alternate.borrow().accept(self);
+    }
+
+    fn visit_while(&mut self, while_loop: &WhileLoop) {
+        while_loop.condition.borrow().accept(self);
+        while_loop.body.borrow().accept(self);
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) {
+        for_loop.start.borrow().accept(self);
+        for_loop.end.borrow().accept(self);
+        for_loop.step.borrow().accept(self);
+        self.enter_scope(ScopeKind::Loop);
+        self.define(&for_loop.variable, DefinitionKind::LoopVariable, None, None);
+        for_loop.body.borrow().accept(self);
+        self.leave_scope();
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        self.enter_scope(ScopeKind::Function);
+        for parameter in lambda.parameters.iter().chain(lambda.variadic.iter()) {
+            self.define(parameter, DefinitionKind::Parameter, None, None);
+        }
+        lambda.body.borrow().accept(self);
+        self.leave_scope();
+    }
+
+    fn visit_call(&mut self, call: &Call) {
+        let callee = &borrow_ast(&call.callee);
+        match downcast_ast::<Identifier>(callee) {
+            Some(identifier) => {
+                let spread = call
+                    .arguments
+                    .iter()
+                    .any(|arg| downcast_ast::<SpreadExpr>(&borrow_ast(arg)).is_some());
+                let kind = ReferenceKind::Call {
+                    arguments: call.arguments.len(),
+                    spread,
+                };
+                self.refer(&identifier.value, kind, identifier.span);
+            }
+            None => callee.accept(self),
+        }
+        call.arguments.iter().for_each(|arg| {
+            arg.borrow().accept(self);
+        });
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+        spread_expr.expr.borrow().accept(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::parser::Parser;
+
+    #[test]
+    fn test_analyze() {
+        let code = r#"
+        (def (f x)
+            (def y 1)
+            (let [(x 2)] (+ x y)))
+        (f 1 2)
+        (set! z 3)
+        "#;
+        let program = Parser::new().parse(code).unwrap();
+        let report = analyze(&program);
+
+        assert_eq!(report.scopes[0].kind, ScopeKind::Program);
+        let f = &report.definitions[report.scopes[0].definitions[0]];
+        assert_eq!(f.name, "f");
+        assert_eq!(f.arity, Some((1, false)));
+        assert_eq!(f.references.len(), 1);
+        let call = &report.references[f.references[0]];
+        assert_eq!(
+            call.kind,
+            ReferenceKind::Call {
+                arguments: 2,
+                spread: false
+            }
+        );
+        assert_eq!(call.form, 2);
+
+        let shadowed: Vec<(&str, DefinitionKind)> = report
+            .shadowed_definitions()
+            .iter()
+            .map(|(_, shadowed)| (shadowed.name.as_str(), shadowed.kind))
+            .collect();
+        assert_eq!(shadowed, vec![("x", DefinitionKind::Parameter)]);
+
+        assert!(report.unused_definitions().is_empty());
+        let unresolved: Vec<&str> = report
+            .unresolved_references()
+            .iter()
+            .map(|reference| reference.name.as_str())
+            .collect();
+        assert_eq!(unresolved, vec!["z"]);
+    }
+}
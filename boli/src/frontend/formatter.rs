@@ -0,0 +1,474 @@
+use super::lexer::{
+    number_format::number_format,
+    stream::Stream,
+    tokens::{Token, TokenType},
+    Lexer,
+};
+use super::parser::{ParseError, Parser};
+
+pub const DEFAULT_WIDTH: usize = 80;
+const INDENT: usize = 2;
+
+// Forms that keep their first argument (name, parameters, condition...)
+// on the line of the keyword when they have to be broken up
+const HEADER_FORMS: [&str; 17] = [
+    "def",
+    "def-const",
+    "def-struct",
+    "def-method",
+    "def-test",
+    "set!",
+    "if",
+    "case",
+    "when",
+    "unless",
+    "while",
+    "for",
+    "dotimes",
+    "λ",
+    "let",
+    "parameterize",
+    "stream-cons",
+];
+
+struct Node {
+    kind: NodeKind,
+    blank_before: bool,
+}
+
+enum NodeKind {
+    Atom(String),
+    Comment {
+        text: String,
+        trailing: bool,
+    },
+    List {
+        open: String,
+        close: &'static str,
+        children: Vec<Node>,
+        multiline: bool,
+    },
+}
+
+// Formats BOLI source code in its canonical layout. Comments are kept and
+// multiple blank lines are collapsed into one.
+pub fn format_code(code: &str, width: usize) -> Result<String, ParseError> {
+    Parser::new().parse(code)?;
+
+    let mut lexer = Lexer::with_comments(code);
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.next() {
+        tokens.push(token);
+    }
+    let nodes = build_tree(tokens)?;
+
+    let mut formatter = Formatter::new(width);
+    formatter.write_top_level(&nodes);
+
+    Ok(formatter.out)
+}
+
+fn build_tree(tokens: Vec<Token>) -> Result<Vec<Node>, ParseError> {
+    let mut top_level = Vec::new();
+    let mut open_lists: Vec<(Node, usize)> = Vec::new();
+    let mut prev_end_line: Option<usize> = None;
+    let mut prev_token: Option<Token> = None;
+
+    for token in tokens {
+        let adjacent = prev_token.replace(token.clone()).is_some_and(|prev| {
+            let length = match prev.token_type {
+                TokenType::Dot3 => 3,
+                TokenType::Identifier => {
+                    prev.get_string_value().unwrap_or_default().chars().count()
+                }
+                _ => 0,
+            };
+            length > 0 && prev.line == token.line && prev.column + length == token.column
+        });
+
+        let blank_before = prev_end_line.is_some_and(|line| token.line > line + 1);
+        let trailing = prev_end_line == Some(token.line);
+        let end_line = token.line
+            + token
+                .lexeme
+                .as_ref()
+                .map_or(0, |lexeme| lexeme.matches('\n').count());
+        prev_end_line = Some(end_line);
+
+        let kind = match token.token_type {
+            TokenType::LeftParen
+            | TokenType::LeftBrace
+            | TokenType::LeftBracket
+            | TokenType::QuoteParen
+            | TokenType::QuoteBrace
+            | TokenType::QuoteBracket => {
+                let close = match token.token_type {
+                    TokenType::LeftParen | TokenType::QuoteParen => ")",
+                    TokenType::LeftBrace | TokenType::QuoteBrace => "}",
+                    _ => "]",
+                };
+                let node = Node {
+                    kind: NodeKind::List {
+                        open: token.get_display_str().unwrap_or_default(),
+                        close,
+                        children: Vec::new(),
+                        multiline: false,
+                    },
+                    blank_before,
+                };
+                open_lists.push((node, token.line));
+                continue;
+            }
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                let (mut node, open_line) = match open_lists.pop() {
+                    Some(open_list) => open_list,
+                    None => return Err(ParseError::with_token("Unexpected token", token)),
+                };
+                match &mut node.kind {
+                    NodeKind::List {
+                        close, multiline, ..
+                    } if Some(close.to_string()) == token.get_display_str() => {
+                        *multiline = token.line > open_line;
+                    }
+                    _ => return Err(ParseError::with_token("Unexpected token", token)),
+                }
+                add_node(&mut open_lists, &mut top_level, node);
+                continue;
+            }
+            TokenType::Comment => NodeKind::Comment {
+                text: token.get_string_value().unwrap_or_default(),
+                trailing,
+            },
+            TokenType::Error => return Err(ParseError::with_token("Invalid token", token)),
+            _ => {
+                // Keep variadic parameters (args...) and spread arguments
+                // (...args) glued together. Literals are written as they
+                // are spelled in the source code.
+                let text = token.lexeme.clone().unwrap_or_else(|| atom_text(&token));
+                if adjacent {
+                    if let Some(NodeKind::Atom(prev_text)) =
+                        last_node_kind(&mut open_lists, &mut top_level)
+                    {
+                        prev_text.push_str(&text);
+                        continue;
+                    }
+                }
+                NodeKind::Atom(text)
+            }
+        };
+
+        add_node(&mut open_lists, &mut top_level, Node { kind, blank_before });
+    }
+
+    if !open_lists.is_empty() {
+        return Err(ParseError::new("Unexpected end of input"));
+    }
+
+    Ok(top_level)
+}
+
+fn add_node(open_lists: &mut [(Node, usize)], top_level: &mut Vec<Node>, node: Node) {
+    match open_lists.last_mut() {
+        Some((
+            Node {
+                kind: NodeKind::List { children, .. },
+                ..
+            },
+            _,
+        )) => children.push(node),
+        _ => top_level.push(node),
+    }
+}
+
+fn last_node_kind<'a>(
+    open_lists: &'a mut [(Node, usize)],
+    top_level: &'a mut [Node],
+) -> Option<&'a mut NodeKind> {
+    let siblings = match open_lists.last_mut() {
+        Some((
+            Node {
+                kind: NodeKind::List { children, .. },
+                ..
+            },
+            _,
+        )) => children,
+        _ => top_level,
+    };
+    siblings.last_mut().map(|node| &mut node.kind)
+}
+
+pub(crate) fn atom_text(token: &Token) -> String {
+    match token.token_type {
+        TokenType::Str => {
+            let value = token.get_string_value().unwrap_or_default();
+            let mut text = String::from("\"");
+            for c in value.chars() {
+                match c {
+                    '"' => text.push_str("\\\""),
+                    '\\' => text.push_str("\\\\"),
+                    '\n' => text.push_str("\\n"),
+                    '\t' => text.push_str("\\t"),
+                    _ => text.push(c),
+                }
+            }
+            text.push('"');
+            text
+        }
+        TokenType::Real => number_format().format_real(token.get_real_value().unwrap_or_default()),
+        TokenType::Bool => match token.get_bool_value() {
+            Some(true) => "#true".to_string(),
+            _ => "#false".to_string(),
+        },
+        _ => token.get_display_str().unwrap_or_default(),
+    }
+}
+
+fn flat_text(node: &Node) -> Option<String> {
+    match &node.kind {
+        NodeKind::Atom(text) => Some(text.clone()),
+        NodeKind::Comment { .. } => None,
+        NodeKind::List {
+            multiline: true, ..
+        } => None,
+        NodeKind::List {
+            open,
+            close,
+            children,
+            ..
+        } => {
+            let mut elements = Vec::new();
+            for (i, child) in children.iter().enumerate() {
+                if i > 0 && child.blank_before {
+                    return None;
+                }
+                elements.push(flat_text(child)?);
+            }
+            Some(format!("{}{}{}", open, elements.join(" "), close))
+        }
+    }
+}
+
+struct Formatter {
+    width: usize,
+    out: String,
+    column: usize,
+}
+
+impl Formatter {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            out: String::new(),
+            column: 0,
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+        match text.rfind('\n') {
+            Some(pos) => self.column = text[pos + 1..].chars().count(),
+            None => self.column += text.chars().count(),
+        }
+    }
+
+    fn newline(&mut self, indent: usize, blank_line: bool) {
+        while self.out.ends_with(' ') {
+            self.out.pop();
+        }
+        self.out.push('\n');
+        if blank_line {
+            self.out.push('\n');
+        }
+        self.out.push_str(&" ".repeat(indent));
+        self.column = indent;
+    }
+
+    fn write_top_level(&mut self, nodes: &[Node]) {
+        for (i, node) in nodes.iter().enumerate() {
+            match &node.kind {
+                NodeKind::Comment {
+                    text,
+                    trailing: true,
+                } if i > 0 => {
+                    self.write(" ");
+                    self.write(text);
+                }
+                _ => {
+                    if i > 0 {
+                        self.newline(0, node.blank_before);
+                    }
+                    self.write_node(node);
+                }
+            }
+        }
+        if !nodes.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        match &node.kind {
+            NodeKind::Atom(text) => self.write(text),
+            NodeKind::Comment { text, .. } => self.write(text),
+            NodeKind::List {
+                open,
+                close,
+                children,
+                ..
+            } => {
+                if let Some(flat) = flat_text(node) {
+                    if self.column + flat.chars().count() <= self.width {
+                        self.write(&flat);
+                        return;
+                    }
+                }
+                self.write_broken_list(open, close, children);
+            }
+        }
+    }
+
+    fn write_broken_list(&mut self, open: &str, close: &str, children: &[Node]) {
+        let start_column = self.column;
+        let head = children.iter().find_map(|child| match &child.kind {
+            NodeKind::Atom(text) => Some(text.as_str()),
+            NodeKind::Comment { .. } => None,
+            NodeKind::List { .. } => Some(""),
+        });
+
+        // Calls are indented by a fixed amount, data elements are aligned
+        // with the first element
+        let (inline_count, indent) = match head {
+            Some(head) if open == "(" && !head.is_empty() => {
+                let inline_count = if HEADER_FORMS.contains(&head) { 2 } else { 1 };
+                (inline_count, start_column + INDENT)
+            }
+            _ => (1, start_column + open.chars().count()),
+        };
+
+        self.write(open);
+        let mut placed = 0;
+        let mut needs_newline = false;
+
+        for child in children {
+            match &child.kind {
+                NodeKind::Comment { text, trailing } => {
+                    if *trailing {
+                        self.write(" ");
+                    } else {
+                        self.newline(indent, child.blank_before);
+                    }
+                    self.write(text);
+                    needs_newline = true;
+                }
+                _ => {
+                    if needs_newline || placed >= inline_count {
+                        self.newline(indent, child.blank_before);
+                    } else if placed > 0 {
+                        self.write(" ");
+                    }
+                    self.write_node(child);
+                    placed += 1;
+                    needs_newline = false;
+                }
+            }
+        }
+
+        if needs_newline {
+            self.newline(indent, false);
+        }
+        self.write(close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(code: &str, width: usize) -> String {
+        format_code(code, width).unwrap()
+    }
+
+    #[test]
+    fn test_format_flat_forms() {
+        let code = "(def   x   42)\n\n\n\n(displayln   [1 2   3] \"a \\\"b\\\"\")";
+        let expected = "(def x 42)\n\n(displayln [1 2 3] \"a \\\"b\\\"\")\n";
+        assert_eq!(format(code, DEFAULT_WIDTH), expected);
+    }
+
+    #[test]
+    fn test_format_breaks_long_forms() {
+        let code = "(def (add a b) (+ a b))";
+        let expected = "(def (add a b)\n  (+ a b))\n";
+        assert_eq!(format(code, 16), expected);
+
+        let code = "(def numbers '(1 2 3 4 5))";
+        let expected = "(def numbers\n  '(1\n    2\n    3\n    4\n    5))\n";
+        assert_eq!(format(code, 10), expected);
+    }
+
+    #[test]
+    fn test_format_keeps_line_breaks() {
+        let code = "(def (run)\n(def lst '(1 2 3))\n    (displayln (reverse lst)))";
+        let expected = "(def (run)\n  (def lst '(1 2 3))\n  (displayln (reverse lst)))\n";
+        assert_eq!(format(code, DEFAULT_WIDTH), expected);
+    }
+
+    #[test]
+    fn test_format_keeps_variadic_names() {
+        let code = "(def (my-add numbers...) (+ ...numbers))\n(list ... x)";
+        let expected = "(def (my-add numbers...) (+ ...numbers))\n(list ... x)\n";
+        assert_eq!(format(code, DEFAULT_WIDTH), expected);
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let code = "
+        ;; Adds two numbers
+        (def (add a b) ; signature
+        #| block |# (+ a b))
+        (add 1 2) ; trailing";
+        let expected = ";; Adds two numbers
+(def (add a b) ; signature
+  #| block |#
+  (+ a b))
+(add 1 2) ; trailing
+";
+        assert_eq!(format(code, DEFAULT_WIDTH), expected);
+    }
+
+    #[test]
+    fn test_format_literals() {
+        let code = "(list 1.000 3,50 1/2 #t #false 'sym nil (lambda (x) x) (λ () 0))";
+        let expected = "(list 1.000 3,50 1/2 #t #false 'sym nil (lambda (x) x) (λ () 0))\n";
+        assert_eq!(format(code, DEFAULT_WIDTH), expected);
+    }
+
+    #[test]
+    fn test_format_round_trips_literals() {
+        for code in [
+            "(list 0xFF 0b101 -0o17 +42 1/2 2/4)\n",
+            "(list 1.000,5 -0,50 3,14159)\n",
+            "(list #t #f #true #false)\n",
+            "(list \"a\\tb\\\\c\\\"\" \"\\x41\")\n",
+            "(list 'sym 'Sym? nil)\n",
+        ] {
+            assert_eq!(format(code, DEFAULT_WIDTH), code);
+        }
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        let code = "
+        (def (fact n)
+        (cond [(= n 0) 1]
+        [else (* n (fact (- n 1)))]))
+        ";
+        let formatted = format(code, 30);
+        assert_eq!(format(&formatted, 30), formatted);
+    }
+
+    #[test]
+    fn test_format_rejects_invalid_code() {
+        assert!(format_code("(def x", DEFAULT_WIDTH).is_err());
+    }
+}
@@ -1,6 +1,8 @@
+pub mod number_format;
 pub mod stream;
 pub mod tokens;
 
+use number_format::{number_format, NumberFormat};
 use std::collections::HashSet;
 use stream::{BufferedStream, CharsStream, Stream};
 use tokens::{
@@ -14,6 +16,10 @@ pub struct Lexer {
     stream: BufferedStream<char>,
     line: usize,
     column: usize,
+    emit_comments: bool,
+    number_format: NumberFormat,
+    // Characters of the token that is being scanned
+    lexeme: String,
 }
 
 impl Lexer {
@@ -22,11 +28,24 @@ impl Lexer {
             stream: BufferedStream::new(Box::new(CharsStream::new(code))),
             line: 1,
             column: 0,
+            emit_comments: false,
+            number_format: number_format(),
+            lexeme: String::new(),
+        }
+    }
+
+    pub fn with_comments(code: &str) -> Self {
+        Self {
+            emit_comments: true,
+            ..Self::new(code)
         }
     }
 
     fn next_char(&mut self) -> Option<char> {
         let c = self.stream.next_item();
+        if let Some(c) = c {
+            self.lexeme.push(c);
+        }
         if c == Some('\n') {
             self.line += 1;
             self.column = 0;
@@ -46,15 +65,19 @@ impl Lexer {
         }
     }
 
-    fn skip_line_comment(&mut self) {
+    fn skip_line_comment(&mut self) -> String {
+        let mut comment = String::new();
         while let Some(c) = self.next_char() {
             if c == '\n' {
                 break;
             }
+            comment.push(c);
         }
+        comment
     }
 
-    fn skip_block_comment(&mut self) {
+    fn skip_block_comment(&mut self) -> String {
+        let mut comment = String::new();
         loop {
             let c = self.next_char();
             if c == Some('|') {
@@ -65,7 +88,9 @@ impl Lexer {
             } else if c.is_none() {
                 break;
             }
+            comment.extend(c);
         }
+        comment
     }
 
     fn scan_logical_operator(
@@ -93,6 +118,24 @@ impl Lexer {
     }
 
     fn scan_number(&mut self, first_char: char, line: usize, column: usize) -> Option<Token> {
+        let radix_char = match first_char {
+            '0' => self.stream.peek(),
+            '+' | '-' => match self.stream.peek_many(2)[..] {
+                ['0', c] => Some(c),
+                _ => None,
+            },
+            _ => None,
+        };
+        let radix = match radix_char {
+            Some('x') => Some(16),
+            Some('o') => Some(8),
+            Some('b') => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            return self.scan_radix_number(first_char, radix, line, column);
+        }
+
         let mut number = String::new();
         number.push(first_char);
 
@@ -103,7 +146,7 @@ impl Lexer {
         let next_char = self.stream.peek();
 
         match next_char {
-            Some(',') => {
+            Some(c) if c == self.number_format.decimal_separator() => {
                 number.push('.');
                 self.next_char();
                 let digits = self.scan_digits()?;
@@ -125,15 +168,45 @@ impl Lexer {
         }
     }
 
+    // Integers like 0xff, 0o777 or 0b1010
+    fn scan_radix_number(
+        &mut self,
+        first_char: char,
+        radix: u32,
+        line: usize,
+        column: usize,
+    ) -> Option<Token> {
+        let mut text = String::from(first_char);
+        if first_char != '0' {
+            text.push(self.next_char()?);
+        }
+        text.push(self.next_char()?);
+
+        let mut digits = String::new();
+        while let Some(c) = self.stream.peek() {
+            if !c.is_ascii_alphanumeric() {
+                break;
+            }
+            digits.push(self.next_char()?);
+        }
+        text.push_str(&digits);
+
+        let sign = if first_char == '-' { "-" } else { "" };
+        match i64::from_str_radix(&format!("{}{}", sign, digits), radix) {
+            Ok(value) => Some(Token::new_int(value, line, column)),
+            Err(_) => Some(Token::new_error(text, line, column)),
+        }
+    }
+
     fn scan_digits(&mut self) -> Option<String> {
         let mut ret = String::new();
 
         while let Some(c) = self.stream.peek() {
             if c.is_digit(10) {
                 ret.push(self.next_char()?);
-            } else if c == '.' {
+            } else if c == self.number_format.grouping_separator() {
                 self.next_char();
-                continue; // . can be used for grouping digits
+                continue;
             } else {
                 break;
             }
@@ -189,7 +262,8 @@ impl Lexer {
         }
     }
 
-    fn scan_identifier(&mut self, first_char: char, line: usize, column: usize) -> Option<Token> {
+    // Scans the characters of a name, tells whether it is an absolute name
+    fn scan_name(&mut self, first_char: char) -> Option<(String, bool)> {
         let invalid_start = HashSet::<char>::from_iter("!?.,*:".chars());
 
         if invalid_start.contains(&first_char) {
@@ -229,9 +303,17 @@ impl Lexer {
             }
         }
 
+        Some((identifier, is_absolute_name))
+    }
+
+    fn scan_identifier(&mut self, first_char: char, line: usize, column: usize) -> Option<Token> {
+        let (identifier, is_absolute_name) = self.scan_name(first_char)?;
+
         let token = match identifier.as_str() {
             "def" => Token::new(Def, line, column),
             "def-struct" => Token::new(DefStruct, line, column),
+            "def-const" => Token::new(DefConst, line, column),
+            "def-method" => Token::new(DefMethod, line, column),
             "set!" => Token::new(SetBang, line, column),
             "if" => Token::new(If, line, column),
             "and" => Token::new(Conjunction, line, column),
@@ -240,7 +322,19 @@ impl Lexer {
             "nil" => Token::new(Nil, line, column),
             "block" => Token::new(Block, line, column),
             "cond" => Token::new(Cond, line, column),
+            "case" => Token::new(Case, line, column),
+            "when" => Token::new(When, line, column),
+            "unless" => Token::new(Unless, line, column),
+            "while" => Token::new(While, line, column),
+            "for" => Token::new(For, line, column),
+            "dotimes" => Token::new(Dotimes, line, column),
+            "parameterize" => Token::new(Parameterize, line, column),
+            "unwind-protect" => Token::new(UnwindProtect, line, column),
             "let" => Token::new(Let, line, column),
+            "stream-cons" => Token::new(StreamCons, line, column),
+            "generator" => Token::new(Generator, line, column),
+            "def-test" => Token::new(DefTest, line, column),
+            "export" => Token::new(Export, line, column),
             "#f" | "#false" => Token::new_bool(false, line, column),
             "#t" | "#true" => Token::new_bool(true, line, column),
             _ => {
@@ -266,8 +360,8 @@ impl Lexer {
             '{' => Some(Token::new(QuoteBrace, line, column)),
             '[' => Some(Token::new(QuoteBracket, line, column)),
             _ => {
-                if let Some(ident) = self.scan_identifier(next_char, line, column) {
-                    let symbol = format!("{}{}", ch, ident.get_string_value().unwrap());
+                if let Some((name, _)) = self.scan_name(next_char) {
+                    let symbol = format!("{}{}", ch, name);
                     Some(Token::new_symbol(symbol, line, column))
                 } else {
                     Some(Token::new_error(ch.to_string(), line, column))
@@ -288,19 +382,21 @@ impl Lexer {
             None
         }
     }
-}
 
-impl Stream<Token> for Lexer {
-    fn next(&mut self) -> Option<Token> {
+    fn scan_token(&mut self) -> Option<Token> {
         loop {
             self.skip_whitespace();
+            self.lexeme.clear();
             let ch = self.next_char()?;
 
             let line = self.line;
             let column = self.column;
 
             if ch == ';' {
-                self.skip_line_comment();
+                let comment = self.skip_line_comment();
+                if self.emit_comments {
+                    return Some(Token::new_comment(format!(";{}", comment), line, column));
+                }
                 continue;
             }
 
@@ -308,12 +404,26 @@ impl Stream<Token> for Lexer {
                 match self.stream.peek() {
                     Some('!') => {
                         self.next_char();
-                        self.skip_line_comment();
+                        let comment = self.skip_line_comment();
+                        if self.emit_comments {
+                            return Some(Token::new_comment(
+                                format!("#!{}", comment),
+                                line,
+                                column,
+                            ));
+                        }
                         continue; // skip shebang
                     }
                     Some('|') => {
                         self.next_char();
-                        self.skip_block_comment();
+                        let comment = self.skip_block_comment();
+                        if self.emit_comments {
+                            return Some(Token::new_comment(
+                                format!("#|{}|#", comment),
+                                line,
+                                column,
+                            ));
+                        }
                         continue; // skip block comment
                     }
                     _ => {}
@@ -359,6 +469,11 @@ impl Stream<Token> for Lexer {
                 }
             }
 
+            // A single colon separates a name from its type annotation
+            if ch == ':' && self.stream.peek().is_some_and(|next| next.is_whitespace()) {
+                return Some(Token::new(Colon, line, column));
+            }
+
             if let Some(token) = self.scan_identifier(ch, line, column) {
                 return Some(token);
             }
@@ -368,6 +483,16 @@ impl Stream<Token> for Lexer {
     }
 }
 
+impl Stream<Token> for Lexer {
+    // Tokens keep their text in the source code, so that tools like the
+    // formatter can reproduce the spelling of literals
+    fn next(&mut self) -> Option<Token> {
+        let mut token = self.scan_token()?;
+        token.lexeme = Some(self.lexeme.trim_end().to_string());
+        Some(token)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -433,6 +558,38 @@ mod tests {
         assert!(lexer.next().is_none());
     }
 
+    #[test]
+    fn test_ignores_shebang() {
+        let code = "#!/usr/bin/env boli\n(main)";
+        let mut lexer = Lexer::new(code);
+        let token = lexer.next().unwrap();
+        assert_eq!(token.token_type, LeftParen);
+        assert_eq!((token.line, token.column), (2, 1));
+        assert_eq!(lexer.next().unwrap().token_type, Identifier);
+        assert_eq!(lexer.next().unwrap().token_type, RightParen);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_emits_comments_on_request() {
+        let code = "; line\n(+ #| block |# 1) ; trailing";
+        let mut lexer = Lexer::with_comments(code);
+
+        let comment = lexer.next().unwrap();
+        assert_eq!(comment.token_type, Comment);
+        assert_eq!(comment.get_string_value(), Some("; line".to_string()));
+        assert_eq!(lexer.next().unwrap().token_type, LeftParen);
+        assert_eq!(lexer.next().unwrap().token_type, Operator(Op::Plus));
+        let comment = lexer.next().unwrap();
+        assert_eq!(comment.get_string_value(), Some("#| block |#".to_string()));
+        assert_eq!(lexer.next().unwrap().token_type, Integer);
+        assert_eq!(lexer.next().unwrap().token_type, RightParen);
+        let comment = lexer.next().unwrap();
+        assert_eq!(comment.get_string_value(), Some("; trailing".to_string()));
+        assert_eq!(comment.line, 2);
+        assert!(lexer.next().is_none());
+    }
+
     #[test]
     fn test_scan_numbers() {
         let code = "(+ 41 1,0 1.000.000 1/2)";
@@ -460,6 +617,42 @@ mod tests {
         assert!(lexer.next().is_none());
     }
 
+    #[test]
+    fn test_tokens_keep_lexemes() {
+        let code = "(if #t 0xFF 1.000,50) ; done";
+        let mut lexer = Lexer::with_comments(code);
+        let mut lexemes = vec![];
+        while let Some(token) = lexer.next() {
+            lexemes.push(token.lexeme.unwrap());
+        }
+        assert_eq!(
+            lexemes,
+            vec!["(", "if", "#t", "0xFF", "1.000,50", ")", "; done"]
+        );
+    }
+
+    #[test]
+    fn test_scan_radix_numbers() {
+        let code = "0xFF 0o777 0b1010 -0x10 0 0x 0b102";
+        let mut lexer = Lexer::new(code);
+        let mut values = vec![];
+        while let Some(token) = lexer.next() {
+            values.push(token.token_value);
+        }
+        assert_eq!(
+            values,
+            vec![
+                Some(TokenValue::Integer(255)),
+                Some(TokenValue::Integer(511)),
+                Some(TokenValue::Integer(10)),
+                Some(TokenValue::Integer(-16)),
+                Some(TokenValue::Integer(0)),
+                Some(TokenValue::Error("0x".to_string())),
+                Some(TokenValue::Error("0b102".to_string())),
+            ]
+        );
+    }
+
     #[test]
     fn test_scan_string() {
         let code = r#"(+ "hello, world" "hello \"world")"#;
@@ -488,7 +681,7 @@ mod tests {
     #[test]
     fn test_scan_identifiers() {
         let code = r#"an-identifier defined? :legal 
-            def def-struct if and or lambda λ 
+            def def-struct def-method if and or lambda λ 
             nil block cond let let-alone #f #false #t #true"#;
         let mut lexer = Lexer::new(code);
         assert_eq!(
@@ -509,6 +702,7 @@ mod tests {
         );
         assert_eq!(lexer.next().unwrap().token_type, Def);
         assert_eq!(lexer.next().unwrap().token_type, DefStruct);
+        assert_eq!(lexer.next().unwrap().token_type, DefMethod);
         assert_eq!(lexer.next().unwrap().token_type, If);
         assert_eq!(lexer.next().unwrap().token_type, Conjunction);
         assert_eq!(lexer.next().unwrap().token_type, Disjunction);
@@ -568,6 +762,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_quoted_keyword() {
+        let mut lexer = Lexer::new("'if ''a");
+
+        let symbol_token = lexer.next().unwrap();
+        assert_eq!(
+            symbol_token.token_value,
+            Some(TokenValue::Symbol("'if".to_string()))
+        );
+
+        let symbol_token = lexer.next().unwrap();
+        assert_eq!(
+            symbol_token.token_value,
+            Some(TokenValue::Symbol("''a".to_string()))
+        );
+    }
+
     #[test]
     fn test_quotation() {
         let code = r#"'a '{1 2 3}"#;
@@ -0,0 +1,79 @@
+use std::{cell::Cell, str::FromStr};
+
+// By default BOLI writes numbers the German way: "," separates the decimals
+// and "." groups digits (1.000.000,5). The English format swaps both.
+// The format applies to the lexer as well as to the conversion of real
+// numbers from and to strings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumberFormat {
+    #[default]
+    De,
+    En,
+}
+
+impl NumberFormat {
+    pub fn decimal_separator(&self) -> char {
+        match self {
+            NumberFormat::De => ',',
+            NumberFormat::En => '.',
+        }
+    }
+
+    pub fn grouping_separator(&self) -> char {
+        match self {
+            NumberFormat::De => '.',
+            NumberFormat::En => ',',
+        }
+    }
+
+    pub fn format_real(&self, value: f64) -> String {
+        format!("{:?}", value).replace('.', &self.decimal_separator().to_string())
+    }
+
+    pub fn parse_real(&self, text: &str) -> Option<f64> {
+        text.replace(self.grouping_separator(), "")
+            .replace(self.decimal_separator(), ".")
+            .parse::<f64>()
+            .ok()
+    }
+}
+
+impl FromStr for NumberFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "de" => Ok(NumberFormat::De),
+            "en" => Ok(NumberFormat::En),
+            _ => Err(format!("unknown number format: {} (expected en or de)", s)),
+        }
+    }
+}
+
+thread_local! {
+    static NUMBER_FORMAT: Cell<NumberFormat> = const { Cell::new(NumberFormat::De) };
+}
+
+pub fn number_format() -> NumberFormat {
+    NUMBER_FORMAT.with(|format| format.get())
+}
+
+pub fn set_number_format(format: NumberFormat) {
+    NUMBER_FORMAT.with(|current| current.set(format));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_format() {
+        assert_eq!(NumberFormat::De.format_real(3.5), "3,5");
+        assert_eq!(NumberFormat::En.format_real(3.5), "3.5");
+        assert_eq!(NumberFormat::De.parse_real("1.000,25"), Some(1000.25));
+        assert_eq!(NumberFormat::En.parse_real("1,000.25"), Some(1000.25));
+        assert_eq!(NumberFormat::En.parse_real("abc"), None);
+        assert_eq!("en".parse::<NumberFormat>(), Ok(NumberFormat::En));
+        assert!("fr".parse::<NumberFormat>().is_err());
+    }
+}
@@ -30,6 +30,24 @@ impl Stream<char> for CharsStream {
     }
 }
 
+pub struct VecStream<T> {
+    items: VecDeque<T>,
+}
+
+impl<T> VecStream<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        Self {
+            items: items.into(),
+        }
+    }
+}
+
+impl<T: Clone> Stream<T> for VecStream<T> {
+    fn next(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+}
+
 pub struct BufferedStream<T> {
     buffer: VecDeque<T>,
     stream: Box<dyn Stream<T>>,
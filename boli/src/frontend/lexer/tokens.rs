@@ -40,6 +40,7 @@ pub enum TokenType {
     Str,
     Def,
     DefStruct,
+    DefMethod,
     SetBang,
     If,
     Conjunction,
@@ -52,8 +53,23 @@ pub enum TokenType {
     Dot3,
     Block,
     Cond,
+    Case,
+    When,
+    Unless,
+    While,
+    For,
+    Dotimes,
+    Colon,
+    DefConst,
+    Parameterize,
+    UnwindProtect,
     Let,
+    StreamCons,
+    Generator,
+    DefTest,
+    Export,
     Error,
+    Comment,
 }
 
 impl TokenType {
@@ -86,6 +102,7 @@ pub enum TokenValue {
     Identifier(String),
     AbsoluteName(String),
     Error(String),
+    Comment(String),
 }
 
 #[derive(Debug, Clone)]
@@ -94,6 +111,8 @@ pub struct Token {
     pub token_value: Option<TokenValue>,
     pub line: usize,
     pub column: usize,
+    // Text of the token in the source code, if it was scanned by the lexer
+    pub lexeme: Option<String>,
 }
 
 impl Token {
@@ -103,6 +122,7 @@ impl Token {
             token_value: None,
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -112,6 +132,7 @@ impl Token {
             token_value: Some(TokenValue::Integer(value)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -121,6 +142,7 @@ impl Token {
             token_value: Some(TokenValue::Rational(numerator, denominator)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -130,6 +152,7 @@ impl Token {
             token_value: Some(TokenValue::Real(value)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -139,6 +162,7 @@ impl Token {
             token_value: Some(TokenValue::Bool(value)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -148,6 +172,7 @@ impl Token {
             token_value: Some(TokenValue::Str(value)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -157,6 +182,7 @@ impl Token {
             token_value: Some(TokenValue::Symbol(value)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -166,6 +192,7 @@ impl Token {
             token_value: Some(TokenValue::Identifier(value)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -175,6 +202,7 @@ impl Token {
             token_value: Some(TokenValue::AbsoluteName(value)),
             line,
             column,
+            lexeme: None,
         }
     }
 
@@ -184,6 +212,17 @@ impl Token {
             token_value: Some(TokenValue::Error(value)),
             line,
             column,
+            lexeme: None,
+        }
+    }
+
+    pub fn new_comment(value: String, line: usize, column: usize) -> Self {
+        Self {
+            token_type: TokenType::Comment,
+            token_value: Some(TokenValue::Comment(value)),
+            line,
+            column,
+            lexeme: None,
         }
     }
 
@@ -222,6 +261,7 @@ impl Token {
             Some(TokenValue::AbsoluteName(ref value)) => Some(value.to_string()),
             Some(TokenValue::Symbol(ref value)) => Some(value.to_string()),
             Some(TokenValue::Error(ref value)) => Some(value.to_string()),
+            Some(TokenValue::Comment(ref value)) => Some(value.to_string()),
             _ => None,
         }
     }
@@ -239,6 +279,7 @@ impl Token {
             TokenType::AbsoluteName => self.get_string_value(),
             TokenType::Symbol => self.get_string_value(),
             TokenType::Error => self.get_string_value(),
+            TokenType::Comment => self.get_string_value(),
             TokenType::LeftParen => Some("(".to_string()),
             TokenType::RightParen => Some(")".to_string()),
             TokenType::LeftBrace => Some("{".to_string()),
@@ -250,6 +291,7 @@ impl Token {
             TokenType::QuoteBracket => Some("'[".to_string()),
             TokenType::Def => Some("def".to_string()),
             TokenType::DefStruct => Some("def-struct".to_string()),
+            TokenType::DefMethod => Some("def-method".to_string()),
             TokenType::SetBang => Some("set!".to_string()),
             TokenType::If => Some("if".to_string()),
             TokenType::Conjunction => Some("and".to_string()),
@@ -275,7 +317,21 @@ impl Token {
             TokenType::Dot3 => Some("...".to_string()),
             TokenType::Block => Some("block".to_string()),
             TokenType::Cond => Some("cond".to_string()),
+            TokenType::Case => Some("case".to_string()),
+            TokenType::When => Some("when".to_string()),
+            TokenType::Unless => Some("unless".to_string()),
+            TokenType::While => Some("while".to_string()),
+            TokenType::For => Some("for".to_string()),
+            TokenType::Dotimes => Some("dotimes".to_string()),
+            TokenType::Colon => Some(":".to_string()),
+            TokenType::DefConst => Some("def-const".to_string()),
+            TokenType::Parameterize => Some("parameterize".to_string()),
+            TokenType::UnwindProtect => Some("unwind-protect".to_string()),
             TokenType::Let => Some("let".to_string()),
+            TokenType::StreamCons => Some("stream-cons".to_string()),
+            TokenType::Generator => Some("generator".to_string()),
+            TokenType::DefTest => Some("def-test".to_string()),
+            TokenType::Export => Some("export".to_string()),
         }
     }
 }
@@ -304,6 +360,9 @@ impl Display for Token {
                 write!(f, "Token: {:?}({})", self.token_type, value)
             }
             Some(TokenValue::Error(value)) => write!(f, "Token, {:?}({})", self.token_type, value),
+            Some(TokenValue::Comment(value)) => {
+                write!(f, "Token: {:?}({})", self.token_type, value)
+            }
             None => write!(f, "Token: {:?}", self.token_type),
         }
     }
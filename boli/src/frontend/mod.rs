@@ -1,2 +1,5 @@
+pub mod analysis;
+pub mod formatter;
 pub mod lexer;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod typecheck;
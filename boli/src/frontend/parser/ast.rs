@@ -1,5 +1,7 @@
+use super::Span;
 use crate::frontend::lexer::tokens::{self, Token};
 use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
 
 pub trait Ast {
@@ -22,6 +24,7 @@ pub fn downcast_ast<'a, T: 'static>(ast: &'a Ref<dyn Ast>) -> Option<&'a T> {
     ast.as_any().downcast_ref::<T>()
 }
 
+// Visitors that compute a result per node should implement AstFolder instead
 pub trait AstVisitor {
     fn visit_program(&mut self, program: &Program);
     fn visit_block(&mut self, block: &Block);
@@ -40,9 +43,14 @@ pub trait AstVisitor {
     fn visit_pair(&mut self, pair: &Pair);
     fn visit_list(&mut self, list: &List);
     fn visit_def(&mut self, def: &Definition);
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition);
     fn visit_struct_def(&mut self, struct_def: &StructDefinition);
+    fn visit_method_def(&mut self, method_def: &MethodDefinition);
     fn visit_set_bang(&mut self, set_bang: &SetBang);
     fn visit_if(&mut self, if_expr: &IfExpression);
+    fn visit_case(&mut self, case_expr: &CaseExpression);
+    fn visit_while(&mut self, while_loop: &WhileLoop);
+    fn visit_for(&mut self, for_loop: &ForLoop);
     fn visit_lambda(&mut self, lambda: &Lambda);
     fn visit_call(&mut self, call: &Call);
     fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr);
@@ -66,16 +74,190 @@ pub trait AstMutVisitor {
     fn visit_pair(&mut self, pair: &mut Pair);
     fn visit_list(&mut self, list: &mut List);
     fn visit_def(&mut self, def: &mut Definition);
+    fn visit_destructuring_def(&mut self, def: &mut DestructuringDefinition);
     fn visit_struct_def(&mut self, struct_def: &mut StructDefinition);
+    fn visit_method_def(&mut self, method_def: &mut MethodDefinition);
     fn visit_set_bang(&mut self, set_bang: &mut SetBang);
     fn visit_if(&mut self, if_expr: &mut IfExpression);
+    fn visit_case(&mut self, case_expr: &mut CaseExpression);
+    fn visit_while(&mut self, while_loop: &mut WhileLoop);
+    fn visit_for(&mut self, for_loop: &mut ForLoop);
     fn visit_lambda(&mut self, lambda: &mut Lambda);
     fn visit_call(&mut self, call: &mut Call);
     fn visit_spread_expr(&mut self, spread_expr: &mut SpreadExpr);
 }
 
+pub trait AstFolder<T> {
+    fn visit_program(&mut self, program: &Program) -> T;
+    fn visit_block(&mut self, block: &Block) -> T;
+    fn visit_integer(&mut self, integer: &Integer) -> T;
+    fn visit_rational(&mut self, rational: &Rational) -> T;
+    fn visit_real(&mut self, real: &Real) -> T;
+    fn visit_bool(&mut self, bool: &Bool) -> T;
+    fn visit_str(&mut self, str: &Str) -> T;
+    fn visit_nil(&mut self) -> T;
+    fn visit_identifier(&mut self, identifier: &Identifier) -> T;
+    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) -> T;
+    fn visit_symbol(&mut self, symbol: &Symbol) -> T;
+    fn visit_quote(&mut self, quote: &Quote) -> T;
+    fn visit_operator(&mut self, operator: &Operator) -> T;
+    fn visit_logical_operator(&mut self, operator: &LogicalOperator) -> T;
+    fn visit_pair(&mut self, pair: &Pair) -> T;
+    fn visit_list(&mut self, list: &List) -> T;
+    fn visit_def(&mut self, def: &Definition) -> T;
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) -> T;
+    fn visit_struct_def(&mut self, struct_def: &StructDefinition) -> T;
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) -> T;
+    fn visit_set_bang(&mut self, set_bang: &SetBang) -> T;
+    fn visit_if(&mut self, if_expr: &IfExpression) -> T;
+    fn visit_case(&mut self, case_expr: &CaseExpression) -> T;
+    fn visit_while(&mut self, while_loop: &WhileLoop) -> T;
+    fn visit_for(&mut self, for_loop: &ForLoop) -> T;
+    fn visit_lambda(&mut self, lambda: &Lambda) -> T;
+    fn visit_call(&mut self, call: &Call) -> T;
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) -> T;
+
+    fn fold(&mut self, ast: &AstRef) -> T
+    where
+        Self: Sized,
+    {
+        fold_ast(self, &*ast.borrow())
+    }
+}
+
+pub fn fold_ast<T>(folder: &mut dyn AstFolder<T>, ast: &dyn Ast) -> T {
+    let mut adapter = FoldAdapter {
+        folder,
+        result: None,
+    };
+    ast.accept(&mut adapter);
+    adapter.result.unwrap()
+}
+
+struct FoldAdapter<'a, T> {
+    folder: &'a mut dyn AstFolder<T>,
+    result: Option<T>,
+}
+
+impl<T> AstVisitor for FoldAdapter<'_, T> {
+    fn visit_program(&mut self, program: &Program) {
+        self.result = Some(self.folder.visit_program(program));
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.result = Some(self.folder.visit_block(block));
+    }
+
+    fn visit_integer(&mut self, integer: &Integer) {
+        self.result = Some(self.folder.visit_integer(integer));
+    }
+
+    fn visit_rational(&mut self, rational: &Rational) {
+        self.result = Some(self.folder.visit_rational(rational));
+    }
+
+    fn visit_real(&mut self, real: &Real) {
+        self.result = Some(self.folder.visit_real(real));
+    }
+
+    fn visit_bool(&mut self, bool: &Bool) {
+        self.result = Some(self.folder.visit_bool(bool));
+    }
+
+    fn visit_str(&mut self, str: &Str) {
+        self.result = Some(self.folder.visit_str(str));
+    }
+
+    fn visit_nil(&mut self) {
+        self.result = Some(self.folder.visit_nil());
+    }
+
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        self.result = Some(self.folder.visit_identifier(identifier));
+    }
+
+    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) {
+        self.result = Some(self.folder.visit_absolute_name(absolute_name));
+    }
+
+    fn visit_symbol(&mut self, symbol: &Symbol) {
+        self.result = Some(self.folder.visit_symbol(symbol));
+    }
+
+    fn visit_quote(&mut self, quote: &Quote) {
+        self.result = Some(self.folder.visit_quote(quote));
+    }
+
+    fn visit_operator(&mut self, operator: &Operator) {
+        self.result = Some(self.folder.visit_operator(operator));
+    }
+
+    fn visit_logical_operator(&mut self, operator: &LogicalOperator) {
+        self.result = Some(self.folder.visit_logical_operator(operator));
+    }
+
+    fn visit_pair(&mut self, pair: &Pair) {
+        self.result = Some(self.folder.visit_pair(pair));
+    }
+
+    fn visit_list(&mut self, list: &List) {
+        self.result = Some(self.folder.visit_list(list));
+    }
+
+    fn visit_def(&mut self, def: &Definition) {
+        self.result = Some(self.folder.visit_def(def));
+    }
+
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) {
+        self.result = Some(self.folder.visit_destructuring_def(def));
+    }
+
+    fn visit_struct_def(&mut self, struct_def: &StructDefinition) {
+        self.result = Some(self.folder.visit_struct_def(struct_def));
+    }
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) {
+        self.result = Some(self.folder.visit_method_def(method_def));
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) {
+        self.result = Some(self.folder.visit_set_bang(set_bang));
+    }
+
+    fn visit_if(&mut self, if_expr: &IfExpression) {
+        self.result = Some(self.folder.visit_if(if_expr));
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) {
+        self.result = Some(self.folder.visit_case(case_expr));
+    }
+
+    fn visit_while(&mut self, while_loop: &WhileLoop) {
+        self.result = Some(self.folder.visit_while(while_loop));
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) {
+        self.result = Some(self.folder.visit_for(for_loop));
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        self.result = Some(self.folder.visit_lambda(lambda));
+    }
+
+    fn visit_call(&mut self, call: &Call) {
+        self.result = Some(self.folder.visit_call(call));
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+        self.result = Some(self.folder.visit_spread_expr(spread_expr));
+    }
+}
+
 pub struct Program {
     pub children: Vec<AstRef>,
+    // Start of each top-level form in the source code, empty if the program
+    // was not parsed from source code
+    pub spans: Vec<Span>,
 }
 
 impl Ast for Program {
@@ -216,8 +398,16 @@ impl Ast for Nil {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexicalAddress {
+    pub depth: usize,
+    pub slot: usize,
+}
+
 pub struct Identifier {
     pub value: String,
+    pub address: Option<LexicalAddress>,
+    pub span: Option<Span>,
 }
 
 impl Ast for Identifier {
@@ -364,6 +554,10 @@ impl Ast for List {
 pub struct Definition {
     pub name: String,
     pub value: AstRef,
+    // Constants (def-const) cannot be changed by set!
+    pub constant: bool,
+    // Location of the name
+    pub span: Option<Span>,
 }
 
 impl Ast for Definition {
@@ -380,8 +574,96 @@ impl Ast for Definition {
     }
 }
 
+// Patterns of destructuring definitions:
+//   (a b . rest) or (a b rest...) matches a list (a pair is a list with a tail)
+//   [x y rest...] matches a vector
+//   {x y} binds the fields x and y of a struct
+// The name _ matches anything without binding it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Name(String),
+    List(Vec<Pattern>, Option<Box<Pattern>>),
+    Vector(Vec<Pattern>, Option<String>),
+    Struct(Vec<String>),
+}
+
+impl Pattern {
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_names(&mut names);
+        names
+    }
+
+    fn collect_names(&self, names: &mut Vec<String>) {
+        match self {
+            Pattern::Name(name) if name == "_" => {}
+            Pattern::Name(name) => names.push(name.clone()),
+            Pattern::List(elements, tail) => {
+                elements
+                    .iter()
+                    .for_each(|element| element.collect_names(names));
+                if let Some(tail) = tail {
+                    tail.collect_names(names);
+                }
+            }
+            Pattern::Vector(elements, rest) => {
+                elements
+                    .iter()
+                    .for_each(|element| element.collect_names(names));
+                names.extend(rest.iter().filter(|rest| *rest != "_").cloned());
+            }
+            Pattern::Struct(fields) => names.extend(fields.iter().cloned()),
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |elements: &[Pattern]| {
+            elements
+                .iter()
+                .map(|element| element.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self {
+            Pattern::Name(name) => write!(f, "{}", name),
+            Pattern::List(elements, None) => write!(f, "({})", join(elements)),
+            Pattern::List(elements, Some(tail)) => write!(f, "({} . {})", join(elements), tail),
+            Pattern::Vector(elements, None) => write!(f, "[{}]", join(elements)),
+            Pattern::Vector(elements, Some(rest)) if elements.is_empty() => {
+                write!(f, "[{}...]", rest)
+            }
+            Pattern::Vector(elements, Some(rest)) => {
+                write!(f, "[{} {}...]", join(elements), rest)
+            }
+            Pattern::Struct(fields) => write!(f, "{{{}}}", fields.join(" ")),
+        }
+    }
+}
+
+pub struct DestructuringDefinition {
+    pub pattern: Pattern,
+    pub value: AstRef,
+}
+
+impl Ast for DestructuringDefinition {
+    fn accept(&self, visitor: &mut dyn AstVisitor) {
+        visitor.visit_destructuring_def(self);
+    }
+
+    fn accept_mut(&mut self, visitor: &mut dyn AstMutVisitor) {
+        visitor.visit_destructuring_def(self);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
 pub struct StructDefinition {
     pub name: String,
+    pub parent: Option<String>,
     pub fields: Vec<String>,
 }
 
@@ -399,9 +681,31 @@ impl Ast for StructDefinition {
     }
 }
 
+pub struct MethodDefinition {
+    pub name: String,
+    pub struct_type: String,
+    pub value: AstRef,
+}
+
+impl Ast for MethodDefinition {
+    fn accept(&self, visitor: &mut dyn AstVisitor) {
+        visitor.visit_method_def(self);
+    }
+
+    fn accept_mut(&mut self, visitor: &mut dyn AstMutVisitor) {
+        visitor.visit_method_def(self);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
 pub struct SetBang {
     pub name: String,
     pub value: AstRef,
+    // Location of the name
+    pub span: Option<Span>,
 }
 
 impl Ast for SetBang {
@@ -438,11 +742,172 @@ impl Ast for IfExpression {
     }
 }
 
+// Literal that a case clause is selected by. Real numbers are compared by
+// their bits, rational numbers are kept in lowest terms.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CaseKey {
+    Int(i64),
+    Rational(i64, i64),
+    Real(u64),
+    Bool(bool),
+    Str(String),
+    Symbol(String),
+    Nil,
+}
+
+impl CaseKey {
+    pub fn rational(numerator: i64, denominator: i64) -> Self {
+        let (mut a, mut b) = (numerator.abs(), denominator.abs());
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        let divisor = if denominator < 0 { -a } else { a };
+        if divisor == 0 {
+            return CaseKey::Rational(numerator, denominator);
+        }
+        match (numerator / divisor, denominator / divisor) {
+            (numerator, 1) => CaseKey::Int(numerator),
+            (numerator, denominator) => CaseKey::Rational(numerator, denominator),
+        }
+    }
+
+    pub fn from_ast(datum: &AstRef) -> Option<Self> {
+        let datum = borrow_ast(datum);
+        if let Some(integer) = downcast_ast::<Integer>(&datum) {
+            Some(CaseKey::Int(integer.value))
+        } else if let Some(rational) = downcast_ast::<Rational>(&datum) {
+            Some(CaseKey::rational(rational.numerator, rational.denominator))
+        } else if let Some(real) = downcast_ast::<Real>(&datum) {
+            Some(CaseKey::Real(real.value.to_bits()))
+        } else if let Some(bool) = downcast_ast::<Bool>(&datum) {
+            Some(CaseKey::Bool(bool.value))
+        } else if let Some(str) = downcast_ast::<Str>(&datum) {
+            Some(CaseKey::Str(str.value.clone()))
+        } else if let Some(symbol) = downcast_ast::<Symbol>(&datum) {
+            Some(CaseKey::Symbol(symbol.value[1..].to_string()))
+        } else if downcast_ast::<Nil>(&datum).is_some() {
+            Some(CaseKey::Nil)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct CaseExpression {
+    pub key: AstRef,
+    // Literal data and the expression of each clause
+    pub clauses: Vec<(Vec<AstRef>, AstRef)>,
+    pub alternate: AstRef,
+    // Clause index per datum, so that the matching clause is found without
+    // comparing the key with every datum
+    pub dispatch: HashMap<CaseKey, usize>,
+}
+
+impl CaseExpression {
+    pub fn new(key: AstRef, clauses: Vec<(Vec<AstRef>, AstRef)>, alternate: AstRef) -> Self {
+        let mut dispatch = HashMap::new();
+        for (index, (data, _)) in clauses.iter().enumerate() {
+            for datum in data {
+                if let Some(case_key) = CaseKey::from_ast(datum) {
+                    dispatch.entry(case_key).or_insert(index);
+                }
+            }
+        }
+        Self {
+            key,
+            clauses,
+            alternate,
+            dispatch,
+        }
+    }
+}
+
+impl Ast for CaseExpression {
+    fn accept(&self, visitor: &mut dyn AstVisitor) {
+        visitor.visit_case(self);
+    }
+
+    fn accept_mut(&mut self, visitor: &mut dyn AstMutVisitor) {
+        visitor.visit_case(self);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+pub struct WhileLoop {
+    pub condition: AstRef,
+    pub body: AstRef,
+}
+
+impl Ast for WhileLoop {
+    fn accept(&self, visitor: &mut dyn AstVisitor) {
+        visitor.visit_while(self);
+    }
+
+    fn accept_mut(&mut self, visitor: &mut dyn AstMutVisitor) {
+        visitor.visit_while(self);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+// Counts variable from start (inclusive) to end (exclusive) by step
+pub struct ForLoop {
+    pub variable: String,
+    pub start: AstRef,
+    pub end: AstRef,
+    pub step: AstRef,
+    pub body: AstRef,
+    // Set by the resolver if the variable is only accessed through its
+    // frame slot and needs no binding by name
+    pub slot_only: bool,
+}
+
+impl Ast for ForLoop {
+    fn accept(&self, visitor: &mut dyn AstVisitor) {
+        visitor.visit_for(self);
+    }
+
+    fn accept_mut(&mut self, visitor: &mut dyn AstMutVisitor) {
+        visitor.visit_for(self);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+// Type annotations of a function like (def (add [x : int] [y : int]) : int ...)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Signature {
+    pub parameter_types: Vec<Option<String>>,
+    // Type of each element of the variadic parameter
+    pub variadic_type: Option<String>,
+    pub return_type: Option<String>,
+}
+
+impl Signature {
+    pub fn is_empty(&self) -> bool {
+        self.parameter_types.iter().all(|t| t.is_none())
+            && self.variadic_type.is_none()
+            && self.return_type.is_none()
+    }
+}
+
 pub struct Lambda {
     pub name: Option<String>,
     pub parameters: Vec<String>,
     pub variadic: Option<String>,
     pub body: AstRef,
+    pub signature: Option<Signature>,
+    // Parameters (followed by the variadic one) that are only accessed
+    // through their frame slots and need no binding by name, empty if the
+    // lambda has not been resolved
+    pub slot_only: Vec<bool>,
 }
 
 impl Ast for Lambda {
@@ -0,0 +1,824 @@
+use super::super::lexer::tokens::{LogicalOp, Op, Token, TokenType, TokenValue};
+use super::{ast::*, ParseError};
+
+pub const COMPILED_EXTENSION: &str = "bolc";
+
+const MAGIC: &[u8] = b"BOLC";
+const VERSION: u8 = 3;
+
+const PROGRAM: u8 = 0;
+const BLOCK: u8 = 1;
+const INTEGER: u8 = 2;
+const RATIONAL: u8 = 3;
+const REAL: u8 = 4;
+const BOOL: u8 = 5;
+const STR: u8 = 6;
+const NIL: u8 = 7;
+const IDENTIFIER: u8 = 8;
+const ABSOLUTE_NAME: u8 = 9;
+const SYMBOL: u8 = 10;
+const QUOTE: u8 = 11;
+const OPERATOR: u8 = 12;
+const LOGICAL_OPERATOR: u8 = 13;
+const PAIR: u8 = 14;
+const LIST: u8 = 15;
+const DEFINITION: u8 = 16;
+const STRUCT_DEFINITION: u8 = 17;
+const METHOD_DEFINITION: u8 = 18;
+const SET_BANG: u8 = 19;
+const IF_EXPRESSION: u8 = 20;
+const LAMBDA: u8 = 21;
+const CALL: u8 = 22;
+const SPREAD_EXPR: u8 = 23;
+const CASE_EXPRESSION: u8 = 24;
+const WHILE_LOOP: u8 = 25;
+const FOR_LOOP: u8 = 26;
+const DESTRUCTURING_DEFINITION: u8 = 27;
+const CONSTANT_DEFINITION: u8 = 28;
+
+// The position in this table is the code of a token type
+const TOKEN_TYPES: [TokenType; 58] = [
+    TokenType::LeftParen,
+    TokenType::RightParen,
+    TokenType::LeftBrace,
+    TokenType::RightBrace,
+    TokenType::LeftBracket,
+    TokenType::RightBracket,
+    TokenType::Identifier,
+    TokenType::AbsoluteName,
+    TokenType::Symbol,
+    TokenType::QuoteParen,
+    TokenType::QuoteBrace,
+    TokenType::QuoteBracket,
+    TokenType::Integer,
+    TokenType::Rational,
+    TokenType::Real,
+    TokenType::Bool,
+    TokenType::Str,
+    TokenType::Def,
+    TokenType::DefStruct,
+    TokenType::DefMethod,
+    TokenType::SetBang,
+    TokenType::If,
+    TokenType::Conjunction,
+    TokenType::Disjunction,
+    TokenType::Lambda,
+    TokenType::Operator(Op::Plus),
+    TokenType::Operator(Op::Minus),
+    TokenType::Operator(Op::Asterisk),
+    TokenType::Operator(Op::Slash),
+    TokenType::Operator(Op::Caret),
+    TokenType::Operator(Op::Percent),
+    TokenType::LogicalOperator(LogicalOp::Eq),
+    TokenType::LogicalOperator(LogicalOp::Gt),
+    TokenType::LogicalOperator(LogicalOp::Ge),
+    TokenType::LogicalOperator(LogicalOp::Lt),
+    TokenType::LogicalOperator(LogicalOp::Le),
+    TokenType::Nil,
+    TokenType::Dot,
+    TokenType::Dot3,
+    TokenType::Block,
+    TokenType::Cond,
+    TokenType::Let,
+    TokenType::StreamCons,
+    TokenType::Generator,
+    TokenType::DefTest,
+    TokenType::Error,
+    TokenType::Comment,
+    TokenType::Export,
+    TokenType::Case,
+    TokenType::When,
+    TokenType::Unless,
+    TokenType::While,
+    TokenType::For,
+    TokenType::Dotimes,
+    TokenType::Colon,
+    TokenType::DefConst,
+    TokenType::Parameterize,
+    TokenType::UnwindProtect,
+];
+
+// Serializes a program including the results of the resolver and the tail
+// call analysis, so a deserialized program can be evaluated right away
+pub fn serialize_program(program: &Program) -> Vec<u8> {
+    let mut writer = BinaryWriter {
+        bytes: MAGIC.to_vec(),
+    };
+    writer.bytes.push(VERSION);
+    program.accept(&mut writer);
+    writer.bytes
+}
+
+pub fn deserialize_program(bytes: &[u8]) -> Result<Program, ParseError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(ParseError::new("Not a compiled BOLI program"));
+    }
+    let mut reader = BinaryReader {
+        bytes,
+        pos: MAGIC.len(),
+    };
+    if reader.byte()? != VERSION {
+        return Err(ParseError::new("Unsupported version of compiled program"));
+    }
+    if reader.byte()? != PROGRAM {
+        return Err(ParseError::new("Compiled program expected"));
+    }
+    let children = reader.asts()?;
+    if reader.pos != bytes.len() {
+        return Err(ParseError::new("Unexpected data after compiled program"));
+    }
+
+    Ok(Program {
+        children,
+        spans: vec![],
+    })
+}
+
+struct BinaryWriter {
+    bytes: Vec<u8>,
+}
+
+impl BinaryWriter {
+    fn write_usize(&mut self, value: usize) {
+        let mut value = value as u64;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                break;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn write_i64(&mut self, value: i64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write_usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn write_strings(&mut self, values: &[String]) {
+        self.write_usize(values.len());
+        for value in values {
+            self.write_str(value);
+        }
+    }
+
+    fn write_flags(&mut self, flags: &[bool]) {
+        self.write_usize(flags.len());
+        for flag in flags {
+            self.bytes.push(*flag as u8);
+        }
+    }
+
+    fn write_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Name(name) => {
+                self.bytes.push(0);
+                self.write_str(name);
+            }
+            Pattern::List(elements, tail) => {
+                self.bytes.push(1);
+                self.write_patterns(elements);
+                match tail {
+                    Some(tail) => {
+                        self.bytes.push(1);
+                        self.write_pattern(tail);
+                    }
+                    None => self.bytes.push(0),
+                }
+            }
+            Pattern::Vector(elements, rest) => {
+                self.bytes.push(2);
+                self.write_patterns(elements);
+                self.write_optional_str(rest);
+            }
+            Pattern::Struct(fields) => {
+                self.bytes.push(3);
+                self.write_strings(fields);
+            }
+        }
+    }
+
+    fn write_patterns(&mut self, patterns: &[Pattern]) {
+        self.write_usize(patterns.len());
+        for pattern in patterns {
+            self.write_pattern(pattern);
+        }
+    }
+
+    fn write_optional_str(&mut self, value: &Option<String>) {
+        match value {
+            Some(value) => {
+                self.bytes.push(1);
+                self.write_str(value);
+            }
+            None => self.bytes.push(0),
+        }
+    }
+
+    fn write_ast(&mut self, ast: &AstRef) {
+        ast.borrow().accept(self);
+    }
+
+    fn write_asts(&mut self, asts: &[AstRef]) {
+        self.write_usize(asts.len());
+        for ast in asts {
+            self.write_ast(ast);
+        }
+    }
+
+    fn write_token(&mut self, token: &Token) {
+        let code = TOKEN_TYPES
+            .iter()
+            .position(|token_type| *token_type == token.token_type)
+            .unwrap();
+        self.bytes.push(code as u8);
+        self.write_usize(token.line);
+        self.write_usize(token.column);
+
+        match &token.token_value {
+            None => self.bytes.push(0),
+            Some(TokenValue::Integer(value)) => {
+                self.bytes.push(1);
+                self.write_i64(*value);
+            }
+            Some(TokenValue::Rational(numerator, denominator)) => {
+                self.bytes.push(2);
+                self.write_i64(*numerator);
+                self.write_i64(*denominator);
+            }
+            Some(TokenValue::Real(value)) => {
+                self.bytes.push(3);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Some(TokenValue::Bool(value)) => {
+                self.bytes.push(4);
+                self.bytes.push(*value as u8);
+            }
+            Some(TokenValue::Str(value)) => {
+                self.bytes.push(5);
+                self.write_str(value);
+            }
+            Some(TokenValue::Symbol(value)) => {
+                self.bytes.push(6);
+                self.write_str(value);
+            }
+            Some(TokenValue::Identifier(value)) => {
+                self.bytes.push(7);
+                self.write_str(value);
+            }
+            Some(TokenValue::AbsoluteName(value)) => {
+                self.bytes.push(8);
+                self.write_str(value);
+            }
+            Some(TokenValue::Error(value)) => {
+                self.bytes.push(9);
+                self.write_str(value);
+            }
+            Some(TokenValue::Comment(value)) => {
+                self.bytes.push(10);
+                self.write_str(value);
+            }
+        }
+    }
+}
+
+impl AstVisitor for BinaryWriter {
+    fn visit_program(&mut self, program: &Program) {
+        self.bytes.push(PROGRAM);
+        self.write_asts(&program.children);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.bytes.push(BLOCK);
+        self.write_asts(&block.children);
+    }
+
+    fn visit_integer(&mut self, integer: &Integer) {
+        self.bytes.push(INTEGER);
+        self.write_i64(integer.value);
+    }
+
+    fn visit_rational(&mut self, rational: &Rational) {
+        self.bytes.push(RATIONAL);
+        self.write_i64(rational.numerator);
+        self.write_i64(rational.denominator);
+    }
+
+    fn visit_real(&mut self, real: &Real) {
+        self.bytes.push(REAL);
+        self.bytes.extend_from_slice(&real.value.to_le_bytes());
+    }
+
+    fn visit_bool(&mut self, bool: &Bool) {
+        self.bytes.push(BOOL);
+        self.bytes.push(bool.value as u8);
+    }
+
+    fn visit_str(&mut self, str: &Str) {
+        self.bytes.push(STR);
+        self.write_str(&str.value);
+    }
+
+    fn visit_nil(&mut self) {
+        self.bytes.push(NIL);
+    }
+
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        self.bytes.push(IDENTIFIER);
+        self.write_str(&identifier.value);
+        match &identifier.address {
+            Some(address) => {
+                self.bytes.push(1);
+                self.write_usize(address.depth);
+                self.write_usize(address.slot);
+            }
+            None => self.bytes.push(0),
+        }
+    }
+
+    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) {
+        self.bytes.push(ABSOLUTE_NAME);
+        self.write_strings(&absolute_name.segments);
+    }
+
+    fn visit_symbol(&mut self, symbol: &Symbol) {
+        self.bytes.push(SYMBOL);
+        self.write_str(&symbol.value);
+    }
+
+    fn visit_quote(&mut self, quote: &Quote) {
+        self.bytes.push(QUOTE);
+        self.write_token(&quote.value);
+    }
+
+    fn visit_operator(&mut self, operator: &Operator) {
+        self.bytes.push(OPERATOR);
+        self.write_token(&Token::new(
+            TokenType::Operator(operator.value.clone()),
+            0,
+            0,
+        ));
+    }
+
+    fn visit_logical_operator(&mut self, operator: &LogicalOperator) {
+        self.bytes.push(LOGICAL_OPERATOR);
+        self.write_token(&Token::new(
+            TokenType::LogicalOperator(operator.value.clone()),
+            0,
+            0,
+        ));
+    }
+
+    fn visit_pair(&mut self, pair: &Pair) {
+        self.bytes.push(PAIR);
+        self.write_ast(&pair.left);
+        self.write_ast(&pair.right);
+    }
+
+    fn visit_list(&mut self, list: &List) {
+        self.bytes.push(LIST);
+        self.write_asts(&list.elements);
+    }
+
+    fn visit_def(&mut self, def: &Definition) {
+        self.bytes.push(if def.constant {
+            CONSTANT_DEFINITION
+        } else {
+            DEFINITION
+        });
+        self.write_str(&def.name);
+        self.write_ast(&def.value);
+    }
+
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) {
+        self.bytes.push(DESTRUCTURING_DEFINITION);
+        self.write_pattern(&def.pattern);
+        self.write_ast(&def.value);
+    }
+
+    fn visit_struct_def(&mut self, struct_def: &StructDefinition) {
+        self.bytes.push(STRUCT_DEFINITION);
+        self.write_str(&struct_def.name);
+        self.write_optional_str(&struct_def.parent);
+        self.write_strings(&struct_def.fields);
+    }
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) {
+        self.bytes.push(METHOD_DEFINITION);
+        self.write_str(&method_def.name);
+        self.write_str(&method_def.struct_type);
+        self.write_ast(&method_def.value);
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) {
+        self.bytes.push(SET_BANG);
+        self.write_str(&set_bang.name);
+        self.write_ast(&set_bang.value);
+    }
+
+    fn visit_if(&mut self, if_expr: &IfExpression) {
+        self.bytes.push(IF_EXPRESSION);
+        self.write_ast(&if_expr.condition);
+        self.write_ast(&if_expr.consequent);
+        self.write_ast(&if_expr.alternate);
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) {
+        self.bytes.push(CASE_EXPRESSION);
+        self.write_ast(&case_expr.key);
+        self.write_usize(case_expr.clauses.len());
+        for (data, consequent) in &case_expr.clauses {
+            self.write_asts(data);
+            self.write_ast(consequent);
+        }
+        self.write_ast(&case_expr.alternate);
+    }
+
+    fn visit_while(&mut self, while_loop: &WhileLoop) {
+        self.bytes.push(WHILE_LOOP);
+        self.write_ast(&while_loop.condition);
+        self.write_ast(&while_loop.body);
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) {
+        self.bytes.push(FOR_LOOP);
+        self.write_str(&for_loop.variable);
+        self.write_ast(&for_loop.start);
+        self.write_ast(&for_loop.end);
+        self.write_ast(&for_loop.step);
+        self.write_ast(&for_loop.body);
+        self.bytes.push(for_loop.slot_only as u8);
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        self.bytes.push(LAMBDA);
+        self.write_optional_str(&lambda.name);
+        self.write_strings(&lambda.parameters);
+        self.write_optional_str(&lambda.variadic);
+        self.write_ast(&lambda.body);
+        match &lambda.signature {
+            Some(signature) => {
+                self.bytes.push(1);
+                self.write_usize(signature.parameter_types.len());
+                for parameter_type in &signature.parameter_types {
+                    self.write_optional_str(parameter_type);
+                }
+                self.write_optional_str(&signature.variadic_type);
+                self.write_optional_str(&signature.return_type);
+            }
+            None => self.bytes.push(0),
+        }
+        self.write_flags(&lambda.slot_only);
+    }
+
+    fn visit_call(&mut self, call: &Call) {
+        self.bytes.push(CALL);
+        self.write_ast(&call.callee);
+        self.write_asts(&call.arguments);
+        self.bytes.push(call.is_tail_call as u8);
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+        self.bytes.push(SPREAD_EXPR);
+        self.write_ast(&spread_expr.expr);
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl BinaryReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], ParseError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(ParseError::new("Unexpected end of compiled program"));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, ParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn flag(&mut self) -> Result<bool, ParseError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            flag => Err(ParseError::new(&format!("Invalid flag {}", flag))),
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, ParseError> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift > 63 {
+                return Err(ParseError::new("Invalid length in compiled program"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value as usize);
+            }
+            shift += 7;
+        }
+    }
+
+    fn i64(&mut self) -> Result<i64, ParseError> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64, ParseError> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| ParseError::new("Invalid string in compiled program"))
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, ParseError> {
+        let len = self.usize()?;
+        (0..len).map(|_| self.string()).collect()
+    }
+
+    fn flags(&mut self) -> Result<Vec<bool>, ParseError> {
+        let len = self.usize()?;
+        (0..len).map(|_| self.flag()).collect()
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, ParseError> {
+        if self.flag()? {
+            Ok(Some(self.string()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        match self.byte()? {
+            0 => Ok(Pattern::Name(self.string()?)),
+            1 => {
+                let elements = self.patterns()?;
+                let tail = if self.flag()? {
+                    Some(Box::new(self.pattern()?))
+                } else {
+                    None
+                };
+                Ok(Pattern::List(elements, tail))
+            }
+            2 => Ok(Pattern::Vector(self.patterns()?, self.optional_string()?)),
+            3 => Ok(Pattern::Struct(self.strings()?)),
+            tag => Err(ParseError::new(&format!("Invalid pattern {}", tag))),
+        }
+    }
+
+    fn patterns(&mut self) -> Result<Vec<Pattern>, ParseError> {
+        let len = self.usize()?;
+        (0..len).map(|_| self.pattern()).collect()
+    }
+
+    fn signature(&mut self) -> Result<Option<Signature>, ParseError> {
+        if !self.flag()? {
+            return Ok(None);
+        }
+        let len = self.usize()?;
+        let parameter_types = (0..len)
+            .map(|_| self.optional_string())
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Signature {
+            parameter_types,
+            variadic_type: self.optional_string()?,
+            return_type: self.optional_string()?,
+        }))
+    }
+
+    fn asts(&mut self) -> Result<Vec<AstRef>, ParseError> {
+        let len = self.usize()?;
+        (0..len).map(|_| self.ast()).collect()
+    }
+
+    fn token(&mut self) -> Result<Token, ParseError> {
+        let code = self.byte()? as usize;
+        let token_type = match TOKEN_TYPES.get(code) {
+            Some(token_type) => token_type.clone(),
+            None => return Err(ParseError::new(&format!("Invalid token type {}", code))),
+        };
+        let line = self.usize()?;
+        let column = self.usize()?;
+        let token_value = match self.byte()? {
+            0 => None,
+            1 => Some(TokenValue::Integer(self.i64()?)),
+            2 => Some(TokenValue::Rational(self.i64()?, self.i64()?)),
+            3 => Some(TokenValue::Real(self.f64()?)),
+            4 => Some(TokenValue::Bool(self.flag()?)),
+            5 => Some(TokenValue::Str(self.string()?)),
+            6 => Some(TokenValue::Symbol(self.string()?)),
+            7 => Some(TokenValue::Identifier(self.string()?)),
+            8 => Some(TokenValue::AbsoluteName(self.string()?)),
+            9 => Some(TokenValue::Error(self.string()?)),
+            10 => Some(TokenValue::Comment(self.string()?)),
+            tag => return Err(ParseError::new(&format!("Invalid token value {}", tag))),
+        };
+
+        Ok(Token {
+            token_type,
+            token_value,
+            line,
+            column,
+            lexeme: None,
+        })
+    }
+
+    fn ast(&mut self) -> Result<AstRef, ParseError> {
+        let tag = self.byte()?;
+        let ast = match tag {
+            BLOCK => new_astref(Block {
+                children: self.asts()?,
+            }),
+            INTEGER => new_astref(Integer { value: self.i64()? }),
+            RATIONAL => new_astref(Rational {
+                numerator: self.i64()?,
+                denominator: self.i64()?,
+            }),
+            REAL => new_astref(Real { value: self.f64()? }),
+            BOOL => new_astref(Bool {
+                value: self.flag()?,
+            }),
+            STR => new_astref(Str {
+                value: self.string()?,
+            }),
+            NIL => new_astref(Nil {}),
+            IDENTIFIER => {
+                let value = self.string()?;
+                let address = if self.flag()? {
+                    Some(LexicalAddress {
+                        depth: self.usize()?,
+                        slot: self.usize()?,
+                    })
+                } else {
+                    None
+                };
+                new_astref(Identifier {
+                    value,
+                    address,
+                    span: None,
+                })
+            }
+            ABSOLUTE_NAME => new_astref(AbsoluteName {
+                segments: self.strings()?,
+            }),
+            SYMBOL => new_astref(Symbol {
+                value: self.string()?,
+            }),
+            QUOTE => new_astref(Quote {
+                value: self.token()?,
+            }),
+            OPERATOR => match self.token()?.token_type {
+                TokenType::Operator(value) => new_astref(Operator { value }),
+                _ => return Err(ParseError::new("Operator expected")),
+            },
+            LOGICAL_OPERATOR => match self.token()?.token_type {
+                TokenType::LogicalOperator(value) => new_astref(LogicalOperator { value }),
+                _ => return Err(ParseError::new("Logical operator expected")),
+            },
+            PAIR => new_astref(Pair {
+                left: self.ast()?,
+                right: self.ast()?,
+            }),
+            LIST => new_astref(List {
+                elements: self.asts()?,
+            }),
+            DEFINITION | CONSTANT_DEFINITION => new_astref(Definition {
+                name: self.string()?,
+                value: self.ast()?,
+                constant: tag == CONSTANT_DEFINITION,
+                span: None,
+            }),
+            DESTRUCTURING_DEFINITION => new_astref(DestructuringDefinition {
+                pattern: self.pattern()?,
+                value: self.ast()?,
+            }),
+            STRUCT_DEFINITION => new_astref(StructDefinition {
+                name: self.string()?,
+                parent: self.optional_string()?,
+                fields: self.strings()?,
+            }),
+            METHOD_DEFINITION => new_astref(MethodDefinition {
+                name: self.string()?,
+                struct_type: self.string()?,
+                value: self.ast()?,
+            }),
+            SET_BANG => new_astref(SetBang {
+                name: self.string()?,
+                value: self.ast()?,
+                span: None,
+            }),
+            IF_EXPRESSION => new_astref(IfExpression {
+                condition: self.ast()?,
+                consequent: self.ast()?,
+                alternate: self.ast()?,
+            }),
+            CASE_EXPRESSION => {
+                let key = self.ast()?;
+                let num_clauses = self.usize()?;
+                let mut clauses = Vec::with_capacity(num_clauses);
+                for _ in 0..num_clauses {
+                    clauses.push((self.asts()?, self.ast()?));
+                }
+                new_astref(CaseExpression::new(key, clauses, self.ast()?))
+            }
+            WHILE_LOOP => new_astref(WhileLoop {
+                condition: self.ast()?,
+                body: self.ast()?,
+            }),
+            FOR_LOOP => new_astref(ForLoop {
+                variable: self.string()?,
+                start: self.ast()?,
+                end: self.ast()?,
+                step: self.ast()?,
+                body: self.ast()?,
+                slot_only: self.flag()?,
+            }),
+            LAMBDA => new_astref(Lambda {
+                name: self.optional_string()?,
+                parameters: self.strings()?,
+                variadic: self.optional_string()?,
+                body: self.ast()?,
+                signature: self.signature()?,
+                slot_only: self.flags()?,
+            }),
+            CALL => new_astref(Call {
+                callee: self.ast()?,
+                arguments: self.asts()?,
+                is_tail_call: self.flag()?,
+            }),
+            SPREAD_EXPR => new_astref(SpreadExpr { expr: self.ast()? }),
+            tag => return Err(ParseError::new(&format!("Invalid node type {}", tag))),
+        };
+
+        Ok(ast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{json_visitor::JsonData, Parser};
+    use super::*;
+
+    const CODE: &str = r#"
+    (def-struct point (x y))
+    (def-struct point3d extends point (z))
+    (def-method (describe point p) (displayln "point" p))
+    (def-const limit 10)
+    (def (sum first rest...) (+ first ...rest))
+    (def (add [x : int] y [zs : real]...) : number (+ x y ...zs))
+    (def (count-down n) (if (> n 0) (count-down (- n 1)) 'done))
+    (for (i 0 10 2) (while (> i 0) (set! i (- i 1))))
+    (def ([a b] . {x y}) (cons (vector 1 2) (create-point 3 4)))
+    (let (((c _ rest...) '(1 2 3))) rest)
+    (def (size n) (case n ((1 2) "small") ((3) (size 2)) (else 'large)))
+    (let ([x 1/2] [y 2,5]) (set! x (* x y)) (core::list::reverse '(a "b" #t (1 . 2) '(c))))
+    "#;
+
+    #[test]
+    fn test_round_trip() {
+        let program = Parser::new().parse(CODE).unwrap();
+        let bytes = serialize_program(&program);
+        assert!(bytes.starts_with(b"BOLC"));
+
+        let restored = deserialize_program(&bytes).unwrap();
+        assert_eq!(JsonData::from(restored), JsonData::from(program));
+    }
+
+    #[test]
+    fn test_keeps_analysis_results() {
+        let program = Parser::new()
+            .parse("(def (f n) (λ (m) (f (+ n m))))")
+            .unwrap();
+        let restored = deserialize_program(&serialize_program(&program)).unwrap();
+        assert_eq!(serialize_program(&restored), serialize_program(&program));
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let bytes = serialize_program(&Parser::new().parse(CODE).unwrap());
+
+        assert!(deserialize_program(b"(def x 1)").is_err());
+        assert!(deserialize_program(&bytes[..bytes.len() - 3]).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[MAGIC.len()] = VERSION + 1;
+        assert!(deserialize_program(&wrong_version).is_err());
+    }
+}
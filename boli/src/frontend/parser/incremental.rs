@@ -0,0 +1,274 @@
+use std::ops::Range;
+
+use super::super::lexer::{stream::BufferedStream, Lexer};
+use super::{ast::Program, ParseError, Parser, Span};
+
+// Replaces the text between the byte offsets start and end of the
+// previously parsed source by new_text
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub new_text: String,
+}
+
+impl TextEdit {
+    pub fn new(start: usize, end: usize, new_text: &str) -> Self {
+        Self {
+            start,
+            end,
+            new_text: new_text.to_string(),
+        }
+    }
+
+    fn apply(&self, source: &str) -> String {
+        let mut result = String::with_capacity(source.len() + self.new_text.len());
+        result.push_str(&source[..self.start]);
+        result.push_str(&self.new_text);
+        result.push_str(&source[self.end..]);
+        result
+    }
+
+    fn delta(&self) -> isize {
+        self.new_text.len() as isize - (self.end - self.start) as isize
+    }
+}
+
+// A parsed program together with its source and the byte ranges of its
+// top-level forms. A span reaches up to the start of the next form. The
+// spans within the forms of an incrementally parsed program refer to the
+// source the forms were parsed from, only the spans of the program itself
+// are updated.
+pub struct ParsedSource {
+    pub program: Program,
+    pub source: String,
+    pub spans: Vec<Range<usize>>,
+}
+
+impl Parser {
+    pub fn parse_source(&self, code: &str) -> Result<ParsedSource, ParseError> {
+        let mut stream = BufferedStream::new(Box::new(Lexer::new(code)));
+        let (program, positions) = self.program_with_positions(&mut stream)?;
+
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(code.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let starts: Vec<usize> = positions
+            .iter()
+            .map(|(line, column)| {
+                let line_start = line_starts[line - 1];
+                code[line_start..]
+                    .char_indices()
+                    .nth(column - 1)
+                    .map_or(code.len(), |(i, _)| line_start + i)
+            })
+            .collect();
+
+        Ok(ParsedSource {
+            program,
+            source: code.to_string(),
+            spans: spans_from_starts(&starts, code.len()),
+        })
+    }
+
+    // Parses the source after the edit has been applied. Top-level forms the
+    // edit does not touch are taken over from the old program, only the
+    // forms in between are parsed again.
+    pub fn parse_incremental(
+        &self,
+        old: &ParsedSource,
+        edit: &TextEdit,
+    ) -> Result<ParsedSource, ParseError> {
+        if edit.start > edit.end || edit.end > old.source.len() {
+            return Err(ParseError::new("Edit is out of the source range"));
+        }
+        if !old.source.is_char_boundary(edit.start) || !old.source.is_char_boundary(edit.end) {
+            return Err(ParseError::new("Edit does not start or end at a character"));
+        }
+        let source = edit.apply(&old.source);
+
+        let touched = |span: &Range<usize>| edit.start <= span.end && edit.end >= span.start;
+        let first = old.spans.iter().position(touched);
+        let last = old.spans.iter().rposition(touched);
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) => (first, last + 1),
+            _ => {
+                let index = old
+                    .spans
+                    .iter()
+                    .position(|span| span.start > edit.end)
+                    .unwrap_or(old.spans.len());
+                (index, index)
+            }
+        };
+
+        let region_start = match old.spans.get(first) {
+            Some(span) if first < last => span.start.min(edit.start),
+            _ => edit.start,
+        };
+        let region_end = match old.spans.get(last.wrapping_sub(1)) {
+            Some(span) if first < last => span.end.max(edit.end),
+            _ => edit.end,
+        };
+        let region_end = (region_end as isize + edit.delta()) as usize;
+        let region = &source[region_start..region_end];
+
+        // Unterminated block comments silently swallow the rest of the input,
+        // so the following forms cannot be reused
+        if region.contains("#|") {
+            return self.parse_source(&source);
+        }
+        let parsed_region = match self.parse_source(region) {
+            Ok(parsed_region) => parsed_region,
+            Err(_) => return self.parse_source(&source),
+        };
+
+        let mut children = old.program.children[..first].to_vec();
+        let mut starts: Vec<usize> = old.spans[..first].iter().map(|span| span.start).collect();
+
+        children.extend(parsed_region.program.children);
+        starts.extend(
+            parsed_region
+                .spans
+                .iter()
+                .map(|span| span.start + region_start),
+        );
+
+        children.extend(old.program.children[last..].iter().cloned());
+        starts.extend(
+            old.spans[last..]
+                .iter()
+                .map(|span| (span.start as isize + edit.delta()) as usize),
+        );
+
+        let program = Program {
+            children,
+            spans: starts
+                .iter()
+                .map(|start| form_span(&source, *start))
+                .collect(),
+        };
+
+        Ok(ParsedSource {
+            program,
+            spans: spans_from_starts(&starts, source.len()),
+            source,
+        })
+    }
+}
+
+fn form_span(source: &str, start: usize) -> Span {
+    let before = &source[..start];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Span {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+        length: 1,
+    }
+}
+
+fn spans_from_starts(starts: &[usize], len: usize) -> Vec<Range<usize>> {
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, start)| *start..starts.get(i + 1).copied().unwrap_or(len))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::frontend::parser::json_visitor::JsonData;
+
+    fn reparse(code: &str, edit: TextEdit) -> (ParsedSource, ParsedSource) {
+        let parser = Parser::new();
+        let old = parser.parse_source(code).unwrap();
+        let new = parser.parse_incremental(&old, &edit).unwrap();
+
+        let expected = parser.parse(&new.source).unwrap();
+        let position = |span: &Span| (span.line, span.column);
+        assert_eq!(
+            new.program.spans.iter().map(position).collect::<Vec<_>>(),
+            expected.spans.iter().map(position).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            JsonData::from(Program {
+                children: new.program.children.clone(),
+                spans: vec![],
+            }),
+            JsonData::from(expected)
+        );
+        let expected_spans = parser.parse_source(&new.source).unwrap().spans;
+        assert_eq!(new.spans, expected_spans);
+
+        (old, new)
+    }
+
+    fn reused(old: &ParsedSource, new: &ParsedSource, old_index: usize, new_index: usize) -> bool {
+        Rc::ptr_eq(
+            &old.program.children[old_index],
+            &new.program.children[new_index],
+        )
+    }
+
+    #[test]
+    fn test_parse_source_spans() {
+        let code = "(def x 1)\n  (def ä \"ö\")\n(displayln x)";
+        let parsed = Parser::new().parse_source(code).unwrap();
+        assert_eq!(parsed.spans, vec![0..12, 12..26, 26..39]);
+        assert_eq!(&code[parsed.spans[1].clone()], "(def ä \"ö\")\n");
+    }
+
+    #[test]
+    fn test_reuses_unchanged_forms() {
+        let code = "(def x 1)\n(def y 2)\n(def z 3)\n";
+        let start = code.find('2').unwrap();
+        let (old, new) = reparse(code, TextEdit::new(start, start + 1, "42"));
+
+        assert_eq!(new.source, "(def x 1)\n(def y 42)\n(def z 3)\n");
+        assert!(reused(&old, &new, 0, 0));
+        assert!(!reused(&old, &new, 1, 1));
+        assert!(reused(&old, &new, 2, 2));
+    }
+
+    #[test]
+    fn test_inserts_and_removes_forms() {
+        let code = "(def x 1)\n\n(def z 3)\n";
+        let (old, new) = reparse(code, TextEdit::new(10, 10, "(def y 2)\n"));
+        assert_eq!(new.program.children.len(), 3);
+        assert!(reused(&old, &new, 1, 2));
+
+        let code = "(def x 1)\n(def y 2)\n(def z 3)\n(def w 4)\n";
+        let (old, new) = reparse(code, TextEdit::new(10, 20, ""));
+        assert_eq!(new.program.children.len(), 3);
+        assert!(reused(&old, &new, 3, 2));
+    }
+
+    #[test]
+    fn test_falls_back_to_full_parse() {
+        let code = "(def x 1)\n(def y 2)\n(def z 3)\n";
+        let (_, new) = reparse(code, TextEdit::new(10, 10, "#| "));
+        assert_eq!(new.program.children.len(), 1);
+
+        let parser = Parser::new();
+        let old = parser.parse_source(code).unwrap();
+        let result = parser.parse_incremental(&old, &TextEdit::new(10, 10, "("));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_rejects_edits_within_characters() {
+        let parser = Parser::new();
+        let old = parser.parse_source("(def s \"äöü\")\n").unwrap();
+        for edit in [TextEdit::new(9, 9, "x"), TextEdit::new(8, 11, "")] {
+            let result = parser.parse_incremental(&old, &edit);
+            assert!(result.is_err());
+        }
+        let new = parser
+            .parse_incremental(&old, &TextEdit::new(8, 10, "o"))
+            .unwrap();
+        assert_eq!(new.source, "(def s \"oöü\")\n");
+    }
+}
@@ -21,12 +21,28 @@ impl JsonData {
         result
     }
 
+    fn push_string(result: &mut String, value: &str) {
+        result.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => result.push_str("\\\""),
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push_str("\\t"),
+                c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+                c => result.push(c),
+            }
+        }
+        result.push('"');
+    }
+
     fn pretty_print_internal(&self, result: &mut String, indent: usize) {
         match self {
             JsonData::Null => result.push_str("null"),
             JsonData::Bool(value) => result.push_str(&format!("{}", value)),
             JsonData::Number(value) => result.push_str(&format!("{}", value)),
-            JsonData::String(value) => result.push_str(&format!("\"{}\"", value)),
+            JsonData::String(value) => Self::push_string(result, value),
             JsonData::Array(elements) => {
                 result.push(char::from('['));
                 if !elements.is_empty() {
@@ -81,18 +97,15 @@ impl From<Program> for JsonData {
     }
 }
 
-pub struct AstToJsonVisitor {
-    stack: Vec<JsonData>,
-}
+pub struct AstToJsonVisitor {}
 
 impl AstToJsonVisitor {
     pub fn new() -> Self {
-        Self { stack: Vec::new() }
+        Self {}
     }
 
     pub fn to_json(&mut self, ast: &dyn Ast) -> JsonData {
-        ast.accept(self);
-        self.stack.pop().unwrap()
+        fold_ast(self, ast)
     }
 
     fn new_object_content() -> (HashMap<String, JsonData>, Vec<String>) {
@@ -110,8 +123,8 @@ impl AstToJsonVisitor {
     }
 }
 
-impl AstVisitor for AstToJsonVisitor {
-    fn visit_program(&mut self, program: &Program) {
+impl AstFolder<JsonData> for AstToJsonVisitor {
+    fn visit_program(&mut self, program: &Program) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         let mut children: Vec<JsonData> = Vec::new();
 
@@ -123,8 +136,7 @@ impl AstVisitor for AstToJsonVisitor {
         );
 
         for child in &program.children {
-            child.borrow().accept(self);
-            children.push(self.stack.pop().unwrap());
+            children.push(self.fold(child));
         }
 
         Self::add_field(
@@ -134,10 +146,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_block(&mut self, block: &Block) {
+    fn visit_block(&mut self, block: &Block) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         let mut children: Vec<JsonData> = Vec::new();
 
@@ -149,8 +161,7 @@ impl AstVisitor for AstToJsonVisitor {
         );
 
         for child in &block.children {
-            child.borrow().accept(self);
-            children.push(self.stack.pop().unwrap());
+            children.push(self.fold(child));
         }
 
         Self::add_field(
@@ -160,10 +171,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_integer(&mut self, integer: &Integer) {
+    fn visit_integer(&mut self, integer: &Integer) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -177,10 +188,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_rational(&mut self, rational: &Rational) {
+    fn visit_rational(&mut self, rational: &Rational) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -200,10 +211,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_real(&mut self, real: &Real) {
+    fn visit_real(&mut self, real: &Real) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -217,10 +228,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_bool(&mut self, bool: &Bool) {
+    fn visit_bool(&mut self, bool: &Bool) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -229,10 +240,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
         Self::add_field("value", JsonData::Bool(bool.value), &mut data, &mut fields);
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_str(&mut self, str: &Str) {
+    fn visit_str(&mut self, str: &Str) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -246,10 +257,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_nil(&mut self) {
+    fn visit_nil(&mut self) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -257,10 +268,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_identifier(&mut self, identifier: &Identifier) {
+    fn visit_identifier(&mut self, identifier: &Identifier) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -274,10 +285,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) {
+    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -297,10 +308,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_symbol(&mut self, symbol: &Symbol) {
+    fn visit_symbol(&mut self, symbol: &Symbol) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -314,10 +325,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_quote(&mut self, quote: &Quote) {
+    fn visit_quote(&mut self, quote: &Quote) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -332,10 +343,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_operator(&mut self, operator: &Operator) {
+    fn visit_operator(&mut self, operator: &Operator) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -350,10 +361,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_logical_operator(&mut self, operator: &LogicalOperator) {
+    fn visit_logical_operator(&mut self, operator: &LogicalOperator) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -368,10 +379,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_pair(&mut self, pair: &Pair) {
+    fn visit_pair(&mut self, pair: &Pair) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -380,16 +391,14 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        pair.left.borrow().accept(self);
-        Self::add_field("left", self.stack.pop().unwrap(), &mut data, &mut fields);
+        Self::add_field("left", self.fold(&pair.left), &mut data, &mut fields);
 
-        pair.right.borrow().accept(self);
-        Self::add_field("right", self.stack.pop().unwrap(), &mut data, &mut fields);
+        Self::add_field("right", self.fold(&pair.right), &mut data, &mut fields);
 
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_list(&mut self, list: &List) {
+    fn visit_list(&mut self, list: &List) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -400,8 +409,7 @@ impl AstVisitor for AstToJsonVisitor {
 
         let mut elements: Vec<JsonData> = Vec::new();
         for element in &list.elements {
-            element.borrow().accept(self);
-            elements.push(self.stack.pop().unwrap());
+            elements.push(self.fold(element));
         }
 
         Self::add_field(
@@ -410,10 +418,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_def(&mut self, def: &Definition) {
+    fn visit_def(&mut self, def: &Definition) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -427,13 +435,34 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
+        if def.constant {
+            Self::add_field("constant", JsonData::Bool(true), &mut data, &mut fields);
+        }
 
-        def.value.borrow().accept(self);
-        Self::add_field("value", self.stack.pop().unwrap(), &mut data, &mut fields);
-        self.stack.push(JsonData::Object(data, fields));
+        Self::add_field("value", self.fold(&def.value), &mut data, &mut fields);
+        JsonData::Object(data, fields)
     }
 
-    fn visit_struct_def(&mut self, struct_def: &StructDefinition) {
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) -> JsonData {
+        let (mut data, mut fields) = Self::new_object_content();
+        Self::add_field(
+            "type",
+            JsonData::String("DestructuringDefinition".to_string()),
+            &mut data,
+            &mut fields,
+        );
+        Self::add_field(
+            "pattern",
+            JsonData::String(def.pattern.to_string()),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field("value", self.fold(&def.value), &mut data, &mut fields);
+        JsonData::Object(data, fields)
+    }
+
+    fn visit_struct_def(&mut self, struct_def: &StructDefinition) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -447,6 +476,15 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
+        Self::add_field(
+            "parent",
+            match &struct_def.parent {
+                Some(parent) => JsonData::String(parent.clone()),
+                None => JsonData::Null,
+            },
+            &mut data,
+            &mut fields,
+        );
 
         let mut struct_fields: Vec<JsonData> = Vec::new();
         for field in &struct_def.fields {
@@ -459,10 +497,40 @@ impl AstVisitor for AstToJsonVisitor {
             &mut data,
             &mut fields,
         );
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_set_bang(&mut self, set_bang: &SetBang) {
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) -> JsonData {
+        let (mut data, mut fields) = Self::new_object_content();
+        Self::add_field(
+            "type",
+            JsonData::String("MethodDefinition".to_string()),
+            &mut data,
+            &mut fields,
+        );
+        Self::add_field(
+            "name",
+            JsonData::String(method_def.name.clone()),
+            &mut data,
+            &mut fields,
+        );
+        Self::add_field(
+            "structType",
+            JsonData::String(method_def.struct_type.clone()),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field(
+            "value",
+            self.fold(&method_def.value),
+            &mut data,
+            &mut fields,
+        );
+        JsonData::Object(data, fields)
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -477,12 +545,11 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        set_bang.value.borrow().accept(self);
-        Self::add_field("value", self.stack.pop().unwrap(), &mut data, &mut fields);
-        self.stack.push(JsonData::Object(data, fields));
+        Self::add_field("value", self.fold(&set_bang.value), &mut data, &mut fields);
+        JsonData::Object(data, fields)
     }
 
-    fn visit_if(&mut self, if_expr: &IfExpression) {
+    fn visit_if(&mut self, if_expr: &IfExpression) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -491,34 +558,110 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        if_expr.condition.borrow().accept(self);
         Self::add_field(
             "condition",
-            self.stack.pop().unwrap(),
+            self.fold(&if_expr.condition),
             &mut data,
             &mut fields,
         );
 
-        if_expr.consequent.borrow().accept(self);
         Self::add_field(
             "consequent",
-            self.stack.pop().unwrap(),
+            self.fold(&if_expr.consequent),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field(
+            "alternate",
+            self.fold(&if_expr.alternate),
             &mut data,
             &mut fields,
         );
 
-        if_expr.alternate.borrow().accept(self);
+        JsonData::Object(data, fields)
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) -> JsonData {
+        let (mut data, mut fields) = Self::new_object_content();
+        Self::add_field(
+            "type",
+            JsonData::String("CaseExpression".to_string()),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field("key", self.fold(&case_expr.key), &mut data, &mut fields);
+
+        let clauses = case_expr
+            .clauses
+            .iter()
+            .map(|(clause_data, consequent)| {
+                let (mut data, mut fields) = Self::new_object_content();
+                let clause_data = clause_data.iter().map(|datum| self.fold(datum)).collect();
+                Self::add_field("data", JsonData::Array(clause_data), &mut data, &mut fields);
+                Self::add_field("consequent", self.fold(consequent), &mut data, &mut fields);
+                JsonData::Object(data, fields)
+            })
+            .collect();
+        Self::add_field("clauses", JsonData::Array(clauses), &mut data, &mut fields);
+
         Self::add_field(
             "alternate",
-            self.stack.pop().unwrap(),
+            self.fold(&case_expr.alternate),
             &mut data,
             &mut fields,
         );
 
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_lambda(&mut self, lambda: &Lambda) {
+    fn visit_while(&mut self, while_loop: &WhileLoop) -> JsonData {
+        let (mut data, mut fields) = Self::new_object_content();
+        Self::add_field(
+            "type",
+            JsonData::String("WhileLoop".to_string()),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field(
+            "condition",
+            self.fold(&while_loop.condition),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field("body", self.fold(&while_loop.body), &mut data, &mut fields);
+
+        JsonData::Object(data, fields)
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) -> JsonData {
+        let (mut data, mut fields) = Self::new_object_content();
+        Self::add_field(
+            "type",
+            JsonData::String("ForLoop".to_string()),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field(
+            "variable",
+            JsonData::String(for_loop.variable.clone()),
+            &mut data,
+            &mut fields,
+        );
+
+        Self::add_field("start", self.fold(&for_loop.start), &mut data, &mut fields);
+        Self::add_field("end", self.fold(&for_loop.end), &mut data, &mut fields);
+        Self::add_field("step", self.fold(&for_loop.step), &mut data, &mut fields);
+        Self::add_field("body", self.fold(&for_loop.body), &mut data, &mut fields);
+
+        JsonData::Object(data, fields)
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -559,14 +702,50 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        lambda.body.borrow().accept(self);
+        if let Some(signature) = &lambda.signature {
+            let optional_type = |type_name: &Option<String>| match type_name {
+                Some(type_name) => JsonData::String(type_name.clone()),
+                None => JsonData::Null,
+            };
+            let (mut signature_data, mut signature_fields) = Self::new_object_content();
+            Self::add_field(
+                "parameterTypes",
+                JsonData::Array(
+                    signature
+                        .parameter_types
+                        .iter()
+                        .map(optional_type)
+                        .collect(),
+                ),
+                &mut signature_data,
+                &mut signature_fields,
+            );
+            Self::add_field(
+                "variadicType",
+                optional_type(&signature.variadic_type),
+                &mut signature_data,
+                &mut signature_fields,
+            );
+            Self::add_field(
+                "returnType",
+                optional_type(&signature.return_type),
+                &mut signature_data,
+                &mut signature_fields,
+            );
+            Self::add_field(
+                "signature",
+                JsonData::Object(signature_data, signature_fields),
+                &mut data,
+                &mut fields,
+            );
+        }
 
-        Self::add_field("body", self.stack.pop().unwrap(), &mut data, &mut fields);
+        Self::add_field("body", self.fold(&lambda.body), &mut data, &mut fields);
 
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_call(&mut self, call: &Call) {
+    fn visit_call(&mut self, call: &Call) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -582,13 +761,11 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        call.callee.borrow().accept(self);
-        Self::add_field("callee", self.stack.pop().unwrap(), &mut data, &mut fields);
+        Self::add_field("callee", self.fold(&call.callee), &mut data, &mut fields);
 
         let mut arguments: Vec<JsonData> = Vec::new();
         for argument in &call.arguments {
-            argument.borrow().accept(self);
-            arguments.push(self.stack.pop().unwrap());
+            arguments.push(self.fold(argument));
         }
 
         Self::add_field(
@@ -598,10 +775,10 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 
-    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) -> JsonData {
         let (mut data, mut fields) = Self::new_object_content();
         Self::add_field(
             "type",
@@ -610,10 +787,9 @@ impl AstVisitor for AstToJsonVisitor {
             &mut fields,
         );
 
-        spread_expr.expr.borrow().accept(self);
-        Self::add_field("expr", self.stack.pop().unwrap(), &mut data, &mut fields);
+        Self::add_field("expr", self.fold(&spread_expr.expr), &mut data, &mut fields);
 
-        self.stack.push(JsonData::Object(data, fields));
+        JsonData::Object(data, fields)
     }
 }
 
@@ -648,6 +824,7 @@ mod tests {
         let integer = Integer { value: 42 };
         let program = Program {
             children: vec![new_astref(integer)],
+            spans: vec![],
         };
         let mut visitor = AstToJsonVisitor::new();
         let json = visitor.to_json(&program);
@@ -1,25 +1,60 @@
 use core::str;
+use std::cell::Cell;
 use std::error::Error;
 use std::vec;
 
-use super::lexer::stream::BufferedStream;
+use super::formatter::atom_text;
+use super::lexer::stream::{BufferedStream, Stream, VecStream};
 use super::lexer::tokens::{Token, TokenType, TokenType::*};
 use super::lexer::Lexer;
 
 pub mod ast;
+pub mod binary;
+pub mod incremental;
 pub mod json_visitor;
+pub mod resolver;
+pub mod sexpr_visitor;
 pub mod tail_call;
 
 use ast::{new_astref, AstRef};
 
-pub struct Parser {}
+pub struct Parser {
+    // Counter for the names of hidden temporary bindings
+    temporaries: Cell<usize>,
+}
+
+#[derive(Default)]
+struct ParameterList {
+    names: Vec<String>,
+    types: Vec<Option<String>>,
+    variadic: Option<String>,
+    variadic_type: Option<String>,
+}
+
+impl ParameterList {
+    fn signature(&self, return_type: Option<String>) -> Option<ast::Signature> {
+        let signature = ast::Signature {
+            parameter_types: self.types.clone(),
+            variadic_type: self.variadic_type.clone(),
+            return_type,
+        };
+        if signature.is_empty() {
+            None
+        } else {
+            Some(signature)
+        }
+    }
+}
 
 impl Parser {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            temporaries: Cell::new(0),
+        }
     }
 
     pub fn parse(&self, code: &str) -> Result<ast::Program, ParseError> {
+        self.temporaries.set(0);
         let mut stream = BufferedStream::new(Box::new(Lexer::new(code)));
         let mut result = self.program(&mut stream);
 
@@ -30,6 +65,86 @@ impl Parser {
         result
     }
 
+    // Unlike parse, this does not stop at the first error. After an error
+    // parsing continues with the next top-level form, so that all errors
+    // are reported in one pass. The program contains the valid forms.
+    pub fn parse_with_recovery(&self, code: &str) -> (ast::Program, Vec<ParseError>) {
+        self.temporaries.set(0);
+        let mut lexer = Lexer::new(code);
+        let mut children = Vec::new();
+        let mut spans = Vec::new();
+        let mut errors = Vec::new();
+
+        while let Some(form_tokens) = Self::next_top_level_form(&mut lexer) {
+            let first_token = form_tokens[0].clone();
+            let mut stream = BufferedStream::new(Box::new(VecStream::new(form_tokens)));
+            let result =
+                self.expression(&mut stream, true).and_then(|expr| {
+                    match Self::peek_token(&mut stream, &vec![]) {
+                        Some(token) => Err(ParseError::with_token("Unexpected token", token)),
+                        None => Ok(expr),
+                    }
+                });
+            match result {
+                Ok(expr) => {
+                    children.push(expr);
+                    spans.push(Span::of(&first_token));
+                }
+                Err(mut err) => {
+                    // Point to the unclosed form if the input ended too early
+                    if err.token.is_none() {
+                        err.token = Some(first_token);
+                    }
+                    errors.push(err);
+                }
+            }
+        }
+
+        let mut program = ast::Program { children, spans };
+        tail_call::TailCallFinder::new().mark_tail_calls(&mut program);
+        resolver::Resolver::new().resolve(&mut program);
+
+        (program, errors)
+    }
+
+    // Collects the tokens up to the closing token of the next top-level form
+    fn next_top_level_form(lexer: &mut Lexer) -> Option<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        while let Some(token) = lexer.next() {
+            match token.token_type {
+                LeftParen | LeftBrace | LeftBracket | QuoteParen | QuoteBrace | QuoteBracket => {
+                    depth += 1
+                }
+                RightParen | RightBrace | RightBracket if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+            if depth == 0 {
+                break;
+            }
+        }
+        if tokens.is_empty() {
+            None
+        } else {
+            Some(tokens)
+        }
+    }
+
+    pub fn parse_tokens(&self, tokens: Vec<Token>) -> Result<ast::Program, ParseError> {
+        let mut stream = BufferedStream::new(Box::new(VecStream::new(tokens)));
+        self.program(&mut stream)
+    }
+
+    pub fn parse_data(&self, code: &str) -> Result<Vec<AstRef>, ParseError> {
+        let mut stream = BufferedStream::new(Box::new(Lexer::new(code)));
+        let mut elements = Vec::new();
+        while Self::peek_token(&mut stream, &vec![]).is_some() {
+            elements.push(self.get_quoted_element(&mut stream)?);
+        }
+        Ok(elements)
+    }
+
     fn next_token(
         stream: &mut BufferedStream<Token>,
         expected_types: &Vec<&TokenType>,
@@ -65,15 +180,29 @@ impl Parser {
     }
 
     fn program(&self, stream: &mut BufferedStream<Token>) -> Result<ast::Program, ParseError> {
+        self.program_with_positions(stream)
+            .map(|(program, _)| program)
+    }
+
+    // Also returns the line and column where each top-level form starts
+    fn program_with_positions(
+        &self,
+        stream: &mut BufferedStream<Token>,
+    ) -> Result<(ast::Program, Vec<(usize, usize)>), ParseError> {
         let mut children = Vec::new();
-        while let Some(_) = Self::peek_token(stream, &vec![]) {
+        let mut spans = Vec::new();
+        let mut positions = Vec::new();
+        while let Some(token) = Self::peek_token(stream, &vec![]) {
+            positions.push((token.line, token.column));
+            spans.push(Span::of(&token));
             children.push(self.expression(stream, true)?);
         }
 
-        let mut program = ast::Program { children };
+        let mut program = ast::Program { children, spans };
         tail_call::TailCallFinder::new().mark_tail_calls(&mut program);
+        resolver::Resolver::new().resolve(&mut program);
 
-        Ok(program)
+        Ok((program, positions))
     }
 
     fn expression(
@@ -106,11 +235,20 @@ impl Parser {
             Nil => Ok(new_astref(ast::Nil {})),
             Identifier => Ok(new_astref(ast::Identifier {
                 value: token.get_string_value().unwrap(),
+                address: None,
+                span: Some(Span::of(&token)),
             })),
             AbsoluteName => self.absolute_name(&token),
-            Symbol => Ok(new_astref(ast::Symbol {
-                value: token.get_string_value().unwrap(),
-            })),
+            Symbol => {
+                let value = token.get_string_value().unwrap();
+                if value.starts_with("''") {
+                    // ''a is the same as '(quote a)
+                    let inner = Token::new_symbol(value[1..].to_string(), token.line, token.column);
+                    Ok(Self::quoted_symbol(&inner))
+                } else {
+                    Ok(new_astref(ast::Symbol { value }))
+                }
+            }
             Dot3 => Ok(new_astref(ast::SpreadExpr {
                 expr: self.expression(stream, false)?,
             })),
@@ -186,14 +324,19 @@ impl Parser {
             Str => Ok(new_astref(ast::Str {
                 value: token.get_string_value().unwrap(),
             })),
-            Symbol => Ok(new_astref(ast::Symbol {
-                value: token.get_string_value().unwrap(),
-            })),
+            Symbol => Ok(Self::quoted_symbol(&token)),
             LeftParen | LeftBrace | LeftBracket => {
                 self.quoted_expression(stream, Self::closing_token_type(&token.token_type))
             }
             QuoteParen | QuoteBrace | QuoteBracket => {
-                Err(ParseError::new("Quotation nesting not allowed"))
+                let quote = new_astref(ast::Quote {
+                    value: Token::new_identifier("quote".to_string(), token.line, token.column),
+                });
+                let quoted =
+                    self.quoted_expression(stream, Self::closing_token_type(&token.token_type))?;
+                Ok(new_astref(ast::List {
+                    elements: vec![quote, quoted],
+                }))
             }
             _ => Ok(new_astref(ast::Quote {
                 value: token.clone(),
@@ -201,6 +344,31 @@ impl Parser {
         }
     }
 
+    // Within quoted data 'a stands for (quote a), so that nested quotes of
+    // names and of lists are represented the same way
+    fn quoted_symbol(token: &Token) -> AstRef {
+        let value = token.get_string_value().unwrap();
+        let name = &value[1..];
+        let quote = new_astref(ast::Quote {
+            value: Token::new_identifier("quote".to_string(), token.line, token.column),
+        });
+        let quoted = if name.starts_with('\'') {
+            Self::quoted_symbol(&Token::new_symbol(
+                name.to_string(),
+                token.line,
+                token.column,
+            ))
+        } else {
+            new_astref(ast::Quote {
+                value: Token::new_identifier(name.to_string(), token.line, token.column),
+            })
+        };
+
+        new_astref(ast::List {
+            elements: vec![quote, quoted],
+        })
+    }
+
     fn symbolic_expression(
         &self,
         stream: &mut BufferedStream<Token>,
@@ -217,6 +385,13 @@ impl Parser {
                     Err(ParseError::new("Definition not allowed here"))
                 }
             }
+            DefConst => {
+                if define_allowed {
+                    self.constant_definition(stream, &end_token_type)
+                } else {
+                    Err(ParseError::new("Definition not allowed here"))
+                }
+            }
             DefStruct => {
                 if define_allowed {
                     self.struct_definition(stream, end_token_type)
@@ -224,14 +399,33 @@ impl Parser {
                     Err(ParseError::new("Definition not allowed here"))
                 }
             }
+            DefMethod => {
+                if define_allowed {
+                    self.method_definition(stream, end_token_type)
+                } else {
+                    Err(ParseError::new("Definition not allowed here"))
+                }
+            }
             SetBang => self.set_bang(stream, end_token_type),
             If => self.if_expression(stream, end_token_type),
             Cond => self.cond_expression(stream, end_token_type),
+            Case => self.case_expression(stream, end_token_type),
+            When => self.when_expression(stream, end_token_type, true),
+            Unless => self.when_expression(stream, end_token_type, false),
+            While => self.while_loop(stream, end_token_type),
+            For => self.for_loop(stream, end_token_type),
+            Dotimes => self.dotimes(stream, end_token_type),
             Conjunction => self.conjunction(stream, end_token_type),
             Disjunction => self.disjunction(stream, end_token_type),
             Lambda => self.lambda(stream, end_token_type),
             Block => self.block(stream, &end_token_type),
             Let => self.let_expression(stream, end_token_type),
+            Parameterize => self.parameterize(stream, end_token_type),
+            UnwindProtect => self.unwind_protect(stream, end_token_type),
+            StreamCons => self.stream_cons(stream, end_token_type),
+            Generator => self.generator(stream, end_token_type),
+            DefTest => self.test_definition(stream, end_token_type),
+            Export => self.export(stream, end_token_type),
             _ => {
                 stream.push_back(token);
                 self.call(stream, end_token_type)
@@ -278,10 +472,154 @@ impl Parser {
         Ok(new_astref(ast::Block { children }))
     }
 
+    // (parameterize ((p v) ...) body) evaluates body with the parameter
+    // objects p bound to the values v
+    fn parameterize(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let opening = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
+        let closing_type = Self::closing_token_type(&opening.token_type);
+
+        let mut bindings = Vec::new();
+        while Self::peek_token(stream, &vec![&closing_type]).is_none() {
+            let opening = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
+            bindings.push(self.expression(stream, false)?);
+            bindings.push(self.expression(stream, false)?);
+            Self::next_token(
+                stream,
+                &vec![&Self::closing_token_type(&opening.token_type)],
+            )?;
+        }
+
+        Self::next_token(stream, &vec![&closing_type])?; // consume closing token
+
+        let body = self.block(stream, &end_token_type)?;
+        let mut arguments = vec![Self::thunk(body)];
+        arguments.extend(bindings);
+
+        Ok(Self::builtin_call("call-with-parameters", arguments))
+    }
+
+    // (unwind-protect body cleanup ...) is a shorthand for
+    // (dynamic-wind (λ () nil) (λ () body) (λ () cleanup ...))
+    fn unwind_protect(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let body = self.expression(stream, false)?;
+        let cleanup = self.block(stream, &end_token_type)?;
+
+        Ok(Self::builtin_call(
+            "dynamic-wind",
+            vec![
+                Self::thunk(new_astref(ast::Nil {})),
+                Self::thunk(body),
+                Self::thunk(cleanup),
+            ],
+        ))
+    }
+
+    fn stream_cons(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let head = self.expression(stream, false)?;
+        let tail = self.expression(stream, false)?;
+
+        Self::next_token(stream, &vec![&end_token_type])?; // consume closing token
+
+        let delayed_tail = Self::thunk(new_astref(ast::Block {
+            children: vec![tail],
+        }));
+
+        Ok(Self::builtin_call(
+            "make-stream-cons",
+            vec![head, delayed_tail],
+        ))
+    }
+
+    fn generator(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let body = self.block(stream, &end_token_type)?;
+
+        Ok(Self::builtin_call(
+            "make-generator",
+            vec![Self::thunk(body)],
+        ))
+    }
+
+    fn test_definition(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let name = self.expression(stream, false)?;
+        let body = self.block(stream, &end_token_type)?;
+
+        Ok(Self::builtin_call(
+            "register-test",
+            vec![name, Self::thunk(body)],
+        ))
+    }
+
+    // (export name ...) is a shorthand for (provide '(name ...))
+    fn export(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let mut names = Vec::new();
+        while Self::peek_token(stream, &vec![&end_token_type]).is_none() {
+            let name = Self::next_token(stream, &vec![&Identifier])?;
+            names.push(new_astref(ast::Quote { value: name }));
+        }
+
+        Self::next_token(stream, &vec![&end_token_type])?; // consume closing token
+
+        Ok(Self::builtin_call(
+            "provide",
+            vec![new_astref(ast::List { elements: names })],
+        ))
+    }
+
+    fn thunk(body: AstRef) -> AstRef {
+        new_astref(ast::Lambda {
+            name: None,
+            parameters: vec![],
+            variadic: None,
+            body,
+            signature: None,
+            slot_only: vec![],
+        })
+    }
+
+    fn builtin_call(name: &str, arguments: Vec<AstRef>) -> AstRef {
+        new_astref(ast::Call {
+            callee: new_astref(ast::Identifier {
+                value: name.to_string(),
+                address: None,
+                span: None,
+            }),
+            arguments,
+            is_tail_call: false,
+        })
+    }
+
     fn let_definition(&self, stream: &mut BufferedStream<Token>) -> Result<AstRef, ParseError> {
         let opening = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
         let closing_type = Self::closing_token_type(&opening.token_type);
 
+        if Self::peek_token(stream, &vec![&Identifier]).is_none() {
+            return self.destructuring_definition(stream, &closing_type);
+        }
+
         let name_token = Self::next_token(stream, &vec![&Identifier])?;
         let name = name_token.get_string_value().unwrap();
 
@@ -289,7 +627,12 @@ impl Parser {
 
         Self::next_token(stream, &vec![&closing_type])?; // consume closing token
 
-        Ok(new_astref(ast::Definition { name, value }))
+        Ok(new_astref(ast::Definition {
+            name,
+            value,
+            constant: false,
+            span: Some(Span::of(&name_token)),
+        }))
     }
 
     fn lambda(
@@ -299,32 +642,84 @@ impl Parser {
     ) -> Result<AstRef, ParseError> {
         let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
         let closing_token_type = Self::closing_token_type(&opening_token.token_type);
-        let mut variadic: Option<String> = None;
 
-        let mut parameters = Vec::new();
-        while Self::peek_token(stream, &vec![&Identifier]).is_some() {
-            let token = Self::next_token(stream, &vec![&Identifier])?;
-            if Self::peek_token(stream, &vec![&Dot3]).is_none() {
-                parameters.push(token.get_string_value().unwrap());
-            } else {
-                Self::next_token(stream, &vec![&Dot3])?; // consume variadic token
-                variadic = Some(token.get_string_value().unwrap());
-                break;
-            }
-        }
+        let mut parameters = ParameterList::default();
+        self.parameters(stream, &mut parameters)?;
 
         Self::next_token(stream, &vec![&closing_token_type])?;
 
+        let signature = parameters.signature(Self::return_type(stream)?);
         let body = self.block(stream, &end_token_type)?;
 
         Ok(new_astref(ast::Lambda {
             name: None,
-            parameters,
-            variadic,
+            parameters: parameters.names,
+            variadic: parameters.variadic,
             body,
+            signature,
+            slot_only: vec![],
         }))
     }
 
+    // Parameters are names or annotated names like [x : int]. The last one
+    // may collect the remaining arguments (xs... or [xs : int]...).
+    fn parameters(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        parameters: &mut ParameterList,
+    ) -> Result<(), ParseError> {
+        while let Some(token) = Self::peek_token(
+            stream,
+            &vec![&Identifier, &LeftParen, &LeftBrace, &LeftBracket],
+        ) {
+            let (name, type_name) = match token.token_type {
+                Identifier => {
+                    let token = Self::next_token(stream, &vec![&Identifier])?;
+                    (token.get_string_value().unwrap(), None)
+                }
+                _ => self.annotated_parameter(stream)?,
+            };
+            if Self::peek_token(stream, &vec![&Dot3]).is_some() {
+                Self::next_token(stream, &vec![&Dot3])?; // consume variadic token
+                parameters.variadic = Some(name);
+                parameters.variadic_type = type_name;
+                break;
+            }
+            parameters.names.push(name);
+            parameters.types.push(type_name);
+        }
+
+        Ok(())
+    }
+
+    fn annotated_parameter(
+        &self,
+        stream: &mut BufferedStream<Token>,
+    ) -> Result<(String, Option<String>), ParseError> {
+        let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
+        let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+
+        let name = Self::next_token(stream, &vec![&Identifier])?;
+        Self::next_token(stream, &vec![&Colon])?;
+        let type_name = Self::next_token(stream, &vec![&Identifier])?;
+
+        Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
+
+        Ok((
+            name.get_string_value().unwrap(),
+            type_name.get_string_value(),
+        ))
+    }
+
+    fn return_type(stream: &mut BufferedStream<Token>) -> Result<Option<String>, ParseError> {
+        if Self::peek_token(stream, &vec![&Colon]).is_none() {
+            return Ok(None);
+        }
+        Self::next_token(stream, &vec![&Colon])?;
+        let type_name = Self::next_token(stream, &vec![&Identifier])?;
+        Ok(type_name.get_string_value())
+    }
+
     fn call(
         &self,
         stream: &mut BufferedStream<Token>,
@@ -376,23 +771,53 @@ impl Parser {
         Ok(self.create_if_expr_from_disjunction(&mut children))
     }
 
+    // (or a b ...) evaluates to the first truthy operand or to the last
+    // operand. Each operand is evaluated once:
+    // (block (def tmp a) (if tmp tmp (or b ...)))
     fn create_if_expr_from_disjunction(&self, elements: &mut Vec<AstRef>) -> AstRef {
         if elements.is_empty() {
             return new_astref(ast::Bool { value: false });
         }
 
-        let condition = elements.remove(0);
+        let operand = elements.remove(0);
+        if elements.is_empty() {
+            return operand;
+        }
 
-        let if_expr = new_astref(ast::IfExpression {
-            condition: condition.clone(),
-            consequent: condition,
-            alternate: if elements.is_empty() {
-                new_astref(ast::Bool { value: false })
-            } else {
-                self.create_if_expr_from_disjunction(elements)
-            },
+        let rest = self.create_if_expr_from_disjunction(elements);
+        self.with_temporary("or", operand, |value| (value.clone(), rest))
+    }
+
+    // Binds the value to a temporary name ($or-1, $and-2, ...) and tests it: (block (def tmp value) (if tmp consequent alternate))
+    fn with_temporary<F>(&self, prefix: &str, value: AstRef, branches: F) -> AstRef
+    where
+        F: FnOnce(&AstRef) -> (AstRef, AstRef),
+    {
+        let index = self.temporaries.get() + 1;
+        self.temporaries.set(index);
+        let name = format!("${}-{}", prefix, index);
+
+        let temporary = new_astref(ast::Identifier {
+            value: name.clone(),
+            address: None,
+            span: None,
         });
-        if_expr
+        let (consequent, alternate) = branches(&temporary);
+        new_astref(ast::Block {
+            children: vec![
+                new_astref(ast::Definition {
+                    name,
+                    value,
+                    constant: false,
+                    span: None,
+                }),
+                new_astref(ast::IfExpression {
+                    condition: temporary,
+                    consequent,
+                    alternate,
+                }),
+            ],
+        })
     }
 
     fn conjunction(
@@ -415,23 +840,20 @@ impl Parser {
         Ok(self.create_if_expr_from_conjunction(&mut children))
     }
 
+    // (and a b ...) evaluates to the first falsy operand or to the last
+    // operand: (block (def tmp a) (if tmp (and b ...) tmp))
     fn create_if_expr_from_conjunction(&self, elements: &mut Vec<AstRef>) -> AstRef {
         if elements.is_empty() {
             return new_astref(ast::Bool { value: true });
         }
 
-        let condition = elements.remove(0);
+        let operand = elements.remove(0);
+        if elements.is_empty() {
+            return operand;
+        }
 
-        let if_expr = new_astref(ast::IfExpression {
-            condition: condition.clone(),
-            consequent: if elements.is_empty() {
-                condition
-            } else {
-                self.create_if_expr_from_conjunction(elements)
-            },
-            alternate: new_astref(ast::Bool { value: false }),
-        });
-        if_expr
+        let rest = self.create_if_expr_from_conjunction(elements);
+        self.with_temporary("and", operand, |value| (rest, value.clone()))
     }
 
     fn cond_expression(
@@ -476,17 +898,208 @@ impl Parser {
     ) -> Result<(AstRef, AstRef), ParseError> {
         let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
 
+        let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+
         let condition = self.expression(stream, false)?;
-        let consequent = self.expression(stream, false)?;
+        let consequent = self.clause_body(stream, &closing_token_type)?;
 
-        Self::next_token(
-            stream,
-            &vec![&Self::closing_token_type(&opening_token.token_type)],
-        )?; // consume closing token
+        Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
 
         Ok((condition, consequent))
     }
 
+    // Bodies of clauses and branches may consist of several expressions that
+    // are evaluated like a block. A single expression is used as it is.
+    fn clause_body(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: &TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let mut children = Vec::new();
+        while Self::peek_token(stream, &vec![end_token_type]).is_none() {
+            children.push(self.expression(stream, true)?);
+        }
+
+        match children.len() {
+            0 => Err(ParseError::new("At least one expression required")),
+            1 if !children[0].borrow().as_any().is::<ast::Definition>() => Ok(children.remove(0)),
+            _ => Ok(new_astref(ast::Block { children })),
+        }
+    }
+
+    fn case_expression(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let key = self.expression(stream, false)?;
+        let mut clauses = Vec::new();
+        let mut alternate = None;
+
+        while let Some(token) =
+            Self::peek_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])
+        {
+            if alternate.is_some() {
+                return Err(ParseError::with_token(
+                    "else clause must be the last clause",
+                    token,
+                ));
+            }
+            let opening_token = Self::next_token(stream, &vec![])?;
+            let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+
+            match Self::peek_token(stream, &vec![&Identifier]) {
+                Some(token) if token.get_string_value().unwrap() == "else" => {
+                    Self::next_token(stream, &vec![])?;
+                    alternate = Some(self.clause_body(stream, &closing_token_type)?);
+                }
+                _ => {
+                    let data = self.case_data(stream)?;
+                    clauses.push((data, self.clause_body(stream, &closing_token_type)?));
+                }
+            }
+
+            Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
+        }
+
+        if clauses.is_empty() && alternate.is_none() {
+            return Err(ParseError::new("At least one clause required"));
+        }
+
+        Self::next_token(stream, &vec![&end_token_type])?; // consume closing token
+
+        Ok(new_astref(ast::CaseExpression::new(
+            key,
+            clauses,
+            alternate.unwrap_or_else(|| new_astref(ast::Nil {})),
+        )))
+    }
+
+    // Data of a case clause are literals. Identifiers are taken as symbols,
+    // so (case x ((a b) ...)) is the same as (case x (('a 'b) ...)).
+    fn case_data(&self, stream: &mut BufferedStream<Token>) -> Result<Vec<AstRef>, ParseError> {
+        let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
+        let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+        let mut data = Vec::new();
+
+        while Self::peek_token(stream, &vec![&closing_token_type]).is_none() {
+            let token = Self::next_token(stream, &vec![])?;
+            let datum = match token.token_type {
+                Integer | Rational | Real | Bool | Str | Nil | Symbol => {
+                    stream.push_back(token);
+                    self.expression(stream, false)?
+                }
+                Identifier => new_astref(ast::Symbol {
+                    value: format!("'{}", token.get_string_value().unwrap()),
+                }),
+                _ => {
+                    return Err(ParseError::with_token(
+                        "Literal expected in case clause",
+                        token,
+                    ))
+                }
+            };
+            data.push(datum);
+        }
+
+        Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
+
+        Ok(data)
+    }
+
+    // (when c e1 e2) is parsed as (if c (block e1 e2) nil) and
+    // (unless c e1 e2) as (if c nil (block e1 e2))
+    fn when_expression(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+        is_when: bool,
+    ) -> Result<AstRef, ParseError> {
+        let condition = self.expression(stream, false)?;
+        let body = self.block(stream, &end_token_type)?;
+        let nil = new_astref(ast::Nil {});
+
+        let (consequent, alternate) = if is_when { (body, nil) } else { (nil, body) };
+
+        Ok(new_astref(ast::IfExpression {
+            condition,
+            consequent,
+            alternate,
+        }))
+    }
+
+    fn while_loop(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let condition = self.expression(stream, false)?;
+        let body = self.block(stream, &end_token_type)?;
+
+        Ok(new_astref(ast::WhileLoop { condition, body }))
+    }
+
+    // (for (i start end [step]) body...)
+    fn for_loop(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
+        let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+
+        let variable = Self::next_token(stream, &vec![&Identifier])?
+            .get_string_value()
+            .unwrap();
+        let start = self.expression(stream, false)?;
+        let end = self.expression(stream, false)?;
+        let step = match Self::peek_token(stream, &vec![&closing_token_type]) {
+            Some(_) => new_astref(ast::Integer { value: 1 }),
+            None => self.expression(stream, false)?,
+        };
+
+        Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
+
+        let body = self.block(stream, &end_token_type)?;
+
+        Ok(new_astref(ast::ForLoop {
+            variable,
+            start,
+            end,
+            step,
+            body,
+            slot_only: false,
+        }))
+    }
+
+    // (dotimes (i n) body...) is parsed as (for (i 0 n 1) body...)
+    fn dotimes(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
+        let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+
+        let variable = Self::next_token(stream, &vec![&Identifier])?
+            .get_string_value()
+            .unwrap();
+        let end = self.expression(stream, false)?;
+
+        Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
+
+        let body = self.block(stream, &end_token_type)?;
+
+        Ok(new_astref(ast::ForLoop {
+            variable,
+            start: new_astref(ast::Integer { value: 0 }),
+            end,
+            step: new_astref(ast::Integer { value: 1 }),
+            body,
+            slot_only: false,
+        }))
+    }
+
     fn if_expression(
         &self,
         stream: &mut BufferedStream<Token>,
@@ -494,7 +1107,8 @@ impl Parser {
     ) -> Result<AstRef, ParseError> {
         let condition = self.expression(stream, false)?;
         let consequent = self.expression(stream, false)?;
-        let alternate = self.expression(stream, false)?;
+        // (if c e1 e2 e3) evaluates e2 and e3 like a block if c is false
+        let alternate = self.clause_body(stream, &end_token_type)?;
 
         Self::next_token(stream, &vec![&end_token_type])?; // consume closing token
 
@@ -518,10 +1132,94 @@ impl Parser {
 
         match token.token_type {
             Identifier => self.definition_with_name(stream, &token, &end_token_type),
+            LeftBracket | LeftBrace => self.destructuring_definition(stream, &end_token_type),
+            _ if Self::is_list_pattern(stream) => {
+                self.destructuring_definition(stream, &end_token_type)
+            }
             _ => self.definition_with_lambda(stream, &token, &end_token_type),
         }
     }
 
+    // (def (a . b) pair) and (def ((a b) c) list) destructure, whereas
+    // (def (f a b) ...) defines a function
+    fn is_list_pattern(stream: &mut BufferedStream<Token>) -> bool {
+        let tokens = stream.peek_many(3);
+        match (tokens.get(1), tokens.get(2)) {
+            (Some(first), _) if matches!(first.token_type, LeftParen | LeftBrace | LeftBracket) => {
+                true
+            }
+            (_, Some(second)) => second.token_type == Dot,
+            _ => false,
+        }
+    }
+
+    fn destructuring_definition(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: &TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let pattern = self.pattern(stream)?;
+        let value = self.expression(stream, false)?;
+
+        Self::next_token(stream, &vec![end_token_type])?; // consume closing token
+
+        Ok(new_astref(ast::DestructuringDefinition { pattern, value }))
+    }
+
+    fn pattern(&self, stream: &mut BufferedStream<Token>) -> Result<ast::Pattern, ParseError> {
+        let opening_token = Self::next_token(
+            stream,
+            &vec![&Identifier, &LeftParen, &LeftBrace, &LeftBracket],
+        )?;
+        if opening_token.token_type == Identifier {
+            return Ok(ast::Pattern::Name(
+                opening_token.get_string_value().unwrap(),
+            ));
+        }
+        let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+
+        if opening_token.token_type == LeftBrace {
+            let mut fields = Vec::new();
+            while Self::peek_token(stream, &vec![&closing_token_type]).is_none() {
+                let field = Self::next_token(stream, &vec![&Identifier])?;
+                fields.push(field.get_string_value().unwrap());
+            }
+            Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
+            return Ok(ast::Pattern::Struct(fields));
+        }
+
+        let is_list = opening_token.token_type == LeftParen;
+        let mut elements = Vec::new();
+        let mut rest = None;
+
+        while Self::peek_token(stream, &vec![&closing_token_type]).is_none() {
+            if is_list && Self::peek_token(stream, &vec![&Dot]).is_some() {
+                Self::next_token(stream, &vec![&Dot])?; // consume dot
+                rest = Some(self.pattern(stream)?);
+                break;
+            }
+            let element = self.pattern(stream)?;
+            if Self::peek_token(stream, &vec![&Dot3]).is_some() {
+                Self::next_token(stream, &vec![&Dot3])?; // consume variadic token
+                match element {
+                    ast::Pattern::Name(_) => rest = Some(element),
+                    _ => return Err(ParseError::new("Only a name can collect the rest")),
+                }
+                break;
+            }
+            elements.push(element);
+        }
+
+        Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token
+
+        if is_list {
+            Ok(ast::Pattern::List(elements, rest.map(Box::new)))
+        } else {
+            let rest = rest.map(|rest| rest.to_string());
+            Ok(ast::Pattern::Vector(elements, rest))
+        }
+    }
+
     fn definition_with_lambda(
         &self,
         stream: &mut BufferedStream<Token>,
@@ -533,31 +1231,27 @@ impl Parser {
 
         let name_token = Self::next_token(stream, &vec![&Identifier])?;
         let name = name_token.get_string_value().unwrap();
-        let mut variadic: Option<String> = None;
 
-        let mut parameters = Vec::new();
-        while let Some(_) = Self::peek_token(stream, &vec![&Identifier]) {
-            let token = Self::next_token(stream, &vec![&Identifier])?;
-            if let Some(_) = Self::peek_token(stream, &vec![&Dot3]) {
-                Self::next_token(stream, &vec![&Dot3])?; // consume variadic token
-                variadic = Some(token.get_string_value().unwrap());
-                break;
-            }
-            parameters.push(token.get_string_value().unwrap());
-        }
+        let mut parameters = ParameterList::default();
+        self.parameters(stream, &mut parameters)?;
 
         Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token for parameters
 
+        let signature = parameters.signature(Self::return_type(stream)?);
         let body = self.block(stream, def_end_token_type)?;
 
         Ok(new_astref(ast::Definition {
             name: name.clone(),
             value: new_astref(ast::Lambda {
                 name: Some(name.clone()),
-                parameters,
-                variadic,
+                parameters: parameters.names,
+                variadic: parameters.variadic,
                 body,
+                signature,
+                slot_only: vec![],
             }),
+            constant: false,
+            span: Some(Span::of(&name_token)),
         }))
     }
 
@@ -574,7 +1268,32 @@ impl Parser {
 
         Self::next_token(stream, &vec![end_token_type])?; // consume closing token
 
-        Ok(new_astref(ast::Definition { name, value }))
+        Ok(new_astref(ast::Definition {
+            name,
+            value,
+            constant: false,
+            span: Some(Span::of(name_token)),
+        }))
+    }
+
+    fn constant_definition(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: &TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let name_token = Self::next_token(stream, &vec![&Identifier])?;
+        let name = name_token.get_string_value().unwrap();
+
+        let value = self.expression(stream, false)?;
+
+        Self::next_token(stream, &vec![end_token_type])?; // consume closing token
+
+        Ok(new_astref(ast::Definition {
+            name,
+            value,
+            constant: true,
+            span: Some(Span::of(&name_token)),
+        }))
     }
 
     fn struct_definition(
@@ -585,6 +1304,19 @@ impl Parser {
         let token = Self::next_token(stream, &vec![&Identifier])?;
         let name = token.get_string_value().unwrap();
 
+        let mut parent = None;
+        if let Some(token) = Self::peek_token(stream, &vec![&Identifier]) {
+            if token.get_string_value().unwrap() != "extends" {
+                return Err(ParseError::with_token(
+                    "Expected 'extends' or field list",
+                    token,
+                ));
+            }
+            Self::next_token(stream, &vec![&Identifier])?; // consume 'extends'
+            let parent_token = Self::next_token(stream, &vec![&Identifier])?;
+            parent = Some(parent_token.get_string_value().unwrap());
+        }
+
         let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
         let closing_token_type = Self::closing_token_type(&opening_token.token_type);
 
@@ -598,7 +1330,50 @@ impl Parser {
 
         Self::next_token(stream, &vec![&end_token_type])?; // consume closing token
 
-        Ok(new_astref(ast::StructDefinition { name, fields }))
+        Ok(new_astref(ast::StructDefinition {
+            name,
+            parent,
+            fields,
+        }))
+    }
+
+    fn method_definition(
+        &self,
+        stream: &mut BufferedStream<Token>,
+        end_token_type: TokenType,
+    ) -> Result<AstRef, ParseError> {
+        let opening_token = Self::next_token(stream, &vec![&LeftParen, &LeftBrace, &LeftBracket])?;
+        let closing_token_type = Self::closing_token_type(&opening_token.token_type);
+
+        let name_token = Self::next_token(stream, &vec![&Identifier])?;
+        let name = name_token.get_string_value().unwrap();
+
+        // The first parameter names the struct type the method is dispatched on
+        let type_token = Self::next_token(stream, &vec![&Identifier])?;
+        let struct_type = type_token.get_string_value().unwrap();
+
+        let mut parameters = ParameterList::default();
+        parameters.names.push(struct_type.clone());
+        parameters.types.push(None);
+        self.parameters(stream, &mut parameters)?;
+
+        Self::next_token(stream, &vec![&closing_token_type])?; // consume closing token for parameters
+
+        let signature = parameters.signature(Self::return_type(stream)?);
+        let body = self.block(stream, &end_token_type)?;
+
+        Ok(new_astref(ast::MethodDefinition {
+            name,
+            struct_type,
+            value: new_astref(ast::Lambda {
+                name: None, // no self tail calls: recursive calls must be dispatched again
+                parameters: parameters.names,
+                variadic: parameters.variadic,
+                body,
+                signature,
+                slot_only: vec![],
+            }),
+        }))
     }
 
     fn set_bang(
@@ -613,7 +1388,11 @@ impl Parser {
 
         Self::next_token(stream, &vec![&end_token_type])?; // consume closing token
 
-        Ok(new_astref(ast::SetBang { name, value }))
+        Ok(new_astref(ast::SetBang {
+            name,
+            value,
+            span: Some(Span::of(&name_token)),
+        }))
     }
 }
 
@@ -637,6 +1416,31 @@ impl ParseError {
             token: Some(token),
         }
     }
+
+    pub fn span(&self) -> Option<Span> {
+        self.token.as_ref().map(Span::of)
+    }
+}
+
+// Location of a token in the source code. Lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+impl Span {
+    pub fn of(token: &Token) -> Self {
+        Self {
+            line: token.line,
+            column: token.column,
+            length: match &token.lexeme {
+                Some(lexeme) => lexeme.chars().count().max(1),
+                None => atom_text(token).chars().count().max(1),
+            },
+        }
+    }
 }
 
 impl std::fmt::Display for ParseError {
@@ -753,6 +1557,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_struct_definition_w_parent() {
+        let parser = super::Parser::new();
+        let code = r#"
+            (def-struct employee extends person [salary])
+        "#;
+        let program = parser.parse(code).unwrap();
+
+        let struct_def = &borrow_ast(&program.children[0]);
+        let struct_def = downcast_ast::<StructDefinition>(struct_def).unwrap();
+        assert_eq!(struct_def.name, "employee");
+        assert_eq!(struct_def.parent, Some("person".to_string()));
+        assert_eq!(struct_def.fields, vec!["salary".to_string()]);
+    }
+
+    #[test]
+    fn test_method_definition() {
+        let parser = super::Parser::new();
+        let code = r#"
+            (def-method (area circle) (* 3 (circle-radius circle)))
+        "#;
+        let program = parser.parse(code).unwrap();
+
+        let method_def = &borrow_ast(&program.children[0]);
+        let method_def = downcast_ast::<MethodDefinition>(method_def).unwrap();
+        assert_eq!(method_def.name, "area");
+        assert_eq!(method_def.struct_type, "circle");
+
+        let lambda = &borrow_ast(&method_def.value);
+        let lambda = downcast_ast::<Lambda>(lambda).unwrap();
+        assert_eq!(lambda.parameters, vec!["circle".to_string()]);
+    }
+
     #[test]
     fn test_function_definition() {
         let parser = super::Parser::new();
@@ -787,6 +1624,71 @@ mod tests {
         assert_eq!(*lambda.parameters, vec!["a".to_string(), "b".to_string()]);
     }
 
+    #[test]
+    fn test_destructuring_definition() {
+        let parser = super::Parser::new();
+        let code = r#"
+            (def (a . [b c...]) value)
+            (let (({x y} p)) x)
+            (def (f a b) (+ a b))
+        "#;
+        let program = parser.parse(code).unwrap();
+
+        let def = &borrow_ast(&program.children[0]);
+        let def = downcast_ast::<DestructuringDefinition>(def).unwrap();
+        assert_eq!(def.pattern.to_string(), "(a . [b c...])");
+        assert_eq!(def.pattern.names(), vec!["a", "b", "c"]);
+
+        let block = &borrow_ast(&program.children[1]);
+        let block = downcast_ast::<Block>(block).unwrap();
+        let def = &borrow_ast(&block.children[0]);
+        let def = downcast_ast::<DestructuringDefinition>(def).unwrap();
+        assert_eq!(
+            def.pattern,
+            Pattern::Struct(vec!["x".to_string(), "y".to_string()])
+        );
+
+        // Function definitions are not affected
+        let def = &borrow_ast(&program.children[2]);
+        assert!(downcast_ast::<Definition>(def).is_some());
+
+        assert!(parser.parse("(def [a [b]...] v)").is_err());
+    }
+
+    #[test]
+    fn test_case_expression() {
+        let parser = super::Parser::new();
+        let code = r#"
+            (case n
+                ((1 2) "small")
+                ((3 2) "medium")
+                (else "large"))
+        "#;
+        let program = parser.parse(code).unwrap();
+        let case_expr = &borrow_ast(&program.children[0]);
+        let case_expr = downcast_ast::<CaseExpression>(case_expr).unwrap();
+        assert_eq!(case_expr.clauses.len(), 2);
+        assert_eq!(case_expr.dispatch.get(&CaseKey::Int(2)), Some(&0));
+        assert_eq!(case_expr.dispatch.get(&CaseKey::Int(3)), Some(&1));
+
+        let alternate = &borrow_ast(&case_expr.alternate);
+        let alternate = downcast_ast::<Str>(alternate).unwrap();
+        assert_eq!(alternate.value, "large");
+
+        // Identifiers in clause data are symbols
+        let program = parser.parse("(case x ((a) 1))").unwrap();
+        let case_expr = &borrow_ast(&program.children[0]);
+        let case_expr = downcast_ast::<CaseExpression>(case_expr).unwrap();
+        assert_eq!(
+            case_expr.dispatch.get(&CaseKey::Symbol("a".to_string())),
+            Some(&0)
+        );
+
+        assert!(parser.parse("(case x)").is_err());
+        assert!(parser.parse("(case x (else 1) ((2) 3))").is_err());
+        assert!(parser.parse("(case x (((+ 1 2)) 3))").is_err());
+    }
+
     #[test]
     fn test_if_expression() {
         let parser = super::Parser::new();
@@ -936,6 +1838,34 @@ mod tests {
         assert_eq!(ident.value.get_string_value().unwrap(), "a");
     }
 
+    #[test]
+    fn test_nested_quotation() {
+        let parser = super::Parser::new();
+        let code = r#"
+            '('(1 2) 3)
+        "#;
+        let program = parser.parse(code);
+        assert!(program.is_ok(), "{}", program.err().unwrap());
+
+        let program = program.unwrap();
+
+        let list = &borrow_ast(&program.children[0]);
+        let list = downcast_ast::<List>(list).unwrap();
+        assert_eq!(list.elements.len(), 2);
+
+        let nested = &borrow_ast(&list.elements[0]);
+        let nested = downcast_ast::<List>(nested).unwrap();
+        assert_eq!(nested.elements.len(), 2);
+
+        let quote = &borrow_ast(&nested.elements[0]);
+        let quote = downcast_ast::<Quote>(quote).unwrap();
+        assert_eq!(quote.value.get_string_value().unwrap(), "quote");
+
+        let quoted = &borrow_ast(&nested.elements[1]);
+        let quoted = downcast_ast::<List>(quoted).unwrap();
+        assert_eq!(quoted.elements.len(), 2);
+    }
+
     #[test]
     fn test_let_expression() {
         let parser = super::Parser::new();
@@ -1055,4 +1985,61 @@ mod tests {
         let value = downcast_ast::<Integer>(value).unwrap();
         assert_eq!(value.value, 1);
     }
+    #[test]
+    fn test_export() {
+        let parser = super::Parser::new();
+        let code = r#"
+        (export square cube)
+        "#;
+        let program = parser.parse(code);
+        assert!(program.is_ok(), "{}", program.err().unwrap());
+        let program = program.unwrap();
+
+        let child0 = &borrow_ast(&program.children[0]);
+        let call = downcast_ast::<Call>(child0).unwrap();
+
+        let callee = &borrow_ast(&call.callee);
+        let callee = downcast_ast::<Identifier>(callee).unwrap();
+        assert_eq!(callee.value, "provide");
+
+        let names = &borrow_ast(&call.arguments[0]);
+        let names = downcast_ast::<List>(names).unwrap();
+        assert_eq!(names.elements.len(), 2);
+
+        assert!(parser.parse("(export 42)").is_err());
+    }
+
+    #[test]
+    fn test_parse_with_recovery() {
+        let parser = super::Parser::new();
+        let code = r#"
+            (def a 1)
+            (def 42 2)
+            (+ a 1))
+            (def b (lambda (x) x))
+            (display (b a)
+        "#;
+        let (program, errors) = parser.parse_with_recovery(code);
+        assert_eq!(program.children.len(), 3);
+
+        let positions: Vec<(String, usize, usize)> = errors
+            .iter()
+            .map(|err| {
+                let span = err.span().unwrap();
+                (err.message.clone(), span.line, span.column)
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                ("Unexpected token in definition".to_string(), 3, 13),
+                ("Unexpected token".to_string(), 4, 20),
+                ("Unexpected end of input".to_string(), 6, 13),
+            ]
+        );
+
+        let (program, errors) = parser.parse_with_recovery("(def a 1) a");
+        assert_eq!(program.children.len(), 2);
+        assert!(errors.is_empty());
+    }
 }
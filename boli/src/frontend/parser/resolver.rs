@@ -0,0 +1,547 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ast::*;
+
+// Identifiers that can introduce or reassign bindings at runtime in the
+// frame they are evaluated in
+const DYNAMIC_IDENTIFIERS: [&str; 3] = ["eval", "require", "current-environment"];
+
+struct Scope {
+    slots: HashMap<String, usize>,
+    locals: HashSet<String>,
+    dynamic: bool,
+    // Slot variables that are (also) looked up by name
+    named: HashSet<String>,
+}
+
+impl Scope {
+    fn is_slot_only(&self, name: &str, slot: usize) -> bool {
+        !self.dynamic && !self.named.contains(name) && self.slots.get(name) == Some(&slot)
+    }
+}
+
+pub struct Resolver {
+    scopes: Vec<Scope>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver { scopes: Vec::new() }
+    }
+
+    pub fn resolve(&mut self, program: &mut Program) {
+        program.accept_mut(self);
+    }
+
+    fn lookup(&self, name: &str) -> Option<LexicalAddress> {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.dynamic || scope.locals.contains(name) {
+                return None;
+            }
+            if let Some(slot) = scope.slots.get(name) {
+                return Some(LexicalAddress { depth, slot: *slot });
+            }
+        }
+        None
+    }
+
+    // A name lookup at runtime ends at the innermost frame that binds the
+    // name, this frame has to provide the binding by name then
+    fn mark_named(&mut self, name: &str) {
+        if let Some(scope) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find(|scope| scope.slots.contains_key(name))
+        {
+            scope.named.insert(name.to_string());
+        }
+    }
+
+    fn lambda_scope(lambda: &Lambda) -> Scope {
+        let scanner = ScopeScanner::scan(std::slice::from_ref(&lambda.body));
+
+        let mut slots = HashMap::new();
+        let mut locals = HashSet::new();
+        let parameters = lambda.parameters.iter().chain(lambda.variadic.iter());
+
+        for (slot, name) in parameters.enumerate() {
+            if locals.contains(name) {
+                continue;
+            }
+            if scanner.assigned.contains(name) || slots.contains_key(name) {
+                slots.remove(name);
+                locals.insert(name.clone());
+            } else {
+                slots.insert(name.clone(), slot);
+            }
+        }
+
+        Scope {
+            slots,
+            locals,
+            dynamic: scanner.dynamic,
+            named: HashSet::new(),
+        }
+    }
+
+    // The loop variable is bound in an environment of its own per iteration
+    fn for_scope(for_loop: &ForLoop) -> Scope {
+        let scanner = ScopeScanner::scan(std::slice::from_ref(&for_loop.body));
+        let mut slots = HashMap::new();
+        let mut locals = HashSet::new();
+
+        if scanner.assigned.contains(&for_loop.variable) {
+            locals.insert(for_loop.variable.clone());
+        } else {
+            slots.insert(for_loop.variable.clone(), 0);
+        }
+
+        Scope {
+            slots,
+            locals,
+            dynamic: scanner.dynamic,
+            named: HashSet::new(),
+        }
+    }
+
+    fn block_scope(block: &Block) -> Scope {
+        let scanner = ScopeScanner::scan(&block.children);
+        let mut locals = HashSet::new();
+        let mut dynamic = scanner.dynamic;
+
+        for child in &block.children {
+            let child = &borrow_ast(child);
+            if let Some(def) = downcast_ast::<Definition>(child) {
+                locals.insert(def.name.clone());
+            } else if let Some(def) = downcast_ast::<DestructuringDefinition>(child) {
+                locals.extend(def.pattern.names());
+            } else if let Some(method_def) = downcast_ast::<MethodDefinition>(child) {
+                locals.insert(method_def.name.clone());
+            } else if downcast_ast::<StructDefinition>(child).is_some() {
+                dynamic = true;
+            }
+        }
+
+        Scope {
+            slots: HashMap::new(),
+            locals,
+            dynamic,
+            named: HashSet::new(),
+        }
+    }
+}
+
+impl AstMutVisitor for Resolver {
+    fn visit_program(&mut self, program: &mut Program) {
+        program.children.iter_mut().for_each(|child| {
+            child.borrow_mut().accept_mut(self);
+        });
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        self.scopes.push(Self::block_scope(block));
+        block.children.iter_mut().for_each(|child| {
+            child.borrow_mut().accept_mut(self);
+        });
+        self.scopes.pop();
+    }
+
+    fn visit_integer(&mut self, _integer: &mut Integer) {}
+
+    fn visit_rational(&mut self, _rational: &mut Rational) {}
+
+    fn visit_real(&mut self, _real: &mut Real) {}
+
+    fn visit_bool(&mut self, _bool: &mut Bool) {}
+
+    fn visit_str(&mut self, _str: &mut Str) {}
+
+    fn visit_nil(&mut self) {}
+
+    fn visit_identifier(&mut self, identifier: &mut Identifier) {
+        identifier.address = self.lookup(&identifier.value);
+        if identifier.address.is_none() {
+            self.mark_named(&identifier.value);
+        }
+    }
+
+    fn visit_absolute_name(&mut self, _absolute_name: &mut AbsoluteName) {}
+
+    fn visit_symbol(&mut self, _symbol: &mut Symbol) {}
+
+    fn visit_quote(&mut self, _quote: &mut Quote) {}
+
+    fn visit_operator(&mut self, _operator: &mut Operator) {}
+
+    fn visit_logical_operator(&mut self, _operator: &mut LogicalOperator) {}
+
+    fn visit_pair(&mut self, pair: &mut Pair) {
+        pair.left.borrow_mut().accept_mut(self);
+        pair.right.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_list(&mut self, list: &mut List) {
+        list.elements.iter_mut().for_each(|element| {
+            element.borrow_mut().accept_mut(self);
+        });
+    }
+
+    fn visit_def(&mut self, def: &mut Definition) {
+        def.value.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_destructuring_def(&mut self, def: &mut DestructuringDefinition) {
+        def.value.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_struct_def(&mut self, _struct_def: &mut StructDefinition) {}
+
+    fn visit_method_def(&mut self, method_def: &mut MethodDefinition) {
+        method_def.value.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &mut SetBang) {
+        set_bang.value.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_if(&mut self, if_expr: &mut IfExpression) {
+        if_expr.condition.borrow_mut().accept_mut(self);
+        if_expr.consequent.borrow_mut().accept_mut(self);
+        if_expr.alternate.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_case(&mut self, case_expr: &mut CaseExpression) {
+        case_expr.key.borrow_mut().accept_mut(self);
+        for (_, consequent) in case_expr.clauses.iter_mut() {
+            consequent.borrow_mut().accept_mut(self);
+        }
+        case_expr.alternate.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_while(&mut self, while_loop: &mut WhileLoop) {
+        while_loop.condition.borrow_mut().accept_mut(self);
+        while_loop.body.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_for(&mut self, for_loop: &mut ForLoop) {
+        for_loop.start.borrow_mut().accept_mut(self);
+        for_loop.end.borrow_mut().accept_mut(self);
+        for_loop.step.borrow_mut().accept_mut(self);
+        self.scopes.push(Self::for_scope(for_loop));
+        for_loop.body.borrow_mut().accept_mut(self);
+        let scope = self.scopes.pop().unwrap();
+        for_loop.slot_only = scope.is_slot_only(&for_loop.variable, 0);
+    }
+
+    fn visit_lambda(&mut self, lambda: &mut Lambda) {
+        self.scopes.push(Self::lambda_scope(lambda));
+        lambda.body.borrow_mut().accept_mut(self);
+        let scope = self.scopes.pop().unwrap();
+        lambda.slot_only = lambda
+            .parameters
+            .iter()
+            .chain(lambda.variadic.iter())
+            .enumerate()
+            .map(|(slot, name)| scope.is_slot_only(name, slot))
+            .collect();
+    }
+
+    fn visit_call(&mut self, call: &mut Call) {
+        call.callee.borrow_mut().accept_mut(self);
+        call.arguments.iter_mut().for_each(|arg| {
+            arg.borrow_mut().accept_mut(self);
+        });
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &mut SpreadExpr) {
+        spread_expr.expr.borrow_mut().accept_mut(self);
+    }
+}
+
+// Collects the names assigned via set! and whether dynamic binding
+// builtins are referenced anywhere within a subtree
+pub(crate) struct ScopeScanner {
+    pub(crate) assigned: HashSet<String>,
+    pub(crate) dynamic: bool,
+}
+
+impl ScopeScanner {
+    pub(crate) fn scan(nodes: &[AstRef]) -> Self {
+        let mut scanner = ScopeScanner {
+            assigned: HashSet::new(),
+            dynamic: false,
+        };
+        nodes
+            .iter()
+            .for_each(|node| node.borrow().accept(&mut scanner));
+        scanner
+    }
+}
+
+impl AstVisitor for ScopeScanner {
+    fn visit_program(&mut self, program: &Program) {
+        program.children.iter().for_each(|child| {
+            child.borrow().accept(self);
+        });
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        block.children.iter().for_each(|child| {
+            child.borrow().accept(self);
+        });
+    }
+
+    fn visit_integer(&mut self, _integer: &Integer) {}
+
+    fn visit_rational(&mut self, _rational: &Rational) {}
+
+    fn visit_real(&mut self, _real: &Real) {}
+
+    fn visit_bool(&mut self, _bool: &Bool) {}
+
+    fn visit_str(&mut self, _str: &Str) {}
+
+    fn visit_nil(&mut self) {}
+
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        if DYNAMIC_IDENTIFIERS.contains(&identifier.value.as_str()) {
+            self.dynamic = true;
+        }
+    }
+
+    fn visit_absolute_name(&mut self, _absolute_name: &AbsoluteName) {}
+
+    fn visit_symbol(&mut self, _symbol: &Symbol) {}
+
+    fn visit_quote(&mut self, _quote: &Quote) {}
+
+    fn visit_operator(&mut self, _operator: &Operator) {}
+
+    fn visit_logical_operator(&mut self, _operator: &LogicalOperator) {}
+
+    fn visit_pair(&mut self, pair: &Pair) {
+        pair.left.borrow().accept(self);
+        pair.right.borrow().accept(self);
+    }
+
+    fn visit_list(&mut self, list: &List) {
+        list.elements.iter().for_each(|element| {
+            element.borrow().accept(self);
+        });
+    }
+
+    fn visit_def(&mut self, def: &Definition) {
+        def.value.borrow().accept(self);
+    }
+
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) {
+        def.value.borrow().accept(self);
+    }
+
+    fn visit_struct_def(&mut self, _struct_def: &StructDefinition) {}
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) {
+        method_def.value.borrow().accept(self);
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) {
+        self.assigned.insert(set_bang.name.clone());
+        set_bang.value.borrow().accept(self);
+    }
+
+    fn visit_if(&mut self, if_expr: &IfExpression) {
+        if_expr.condition.borrow().accept(self);
+        if_expr.consequent.borrow().accept(self);
+        if_expr.alternate.borrow().accept(self);
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) {
+        case_expr.key.borrow().accept(self);
+        for (_, consequent) in &case_expr.clauses {
+            consequent.borrow().accept(self);
+        }
+        case_expr.alternate.borrow().accept(self);
+    }
+
+    fn visit_while(&mut self, while_loop: &WhileLoop) {
+        while_loop.condition.borrow().accept(self);
+        while_loop.body.borrow().accept(self);
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) {
+        for_loop.start.borrow().accept(self);
+        for_loop.end.borrow().accept(self);
+        for_loop.step.borrow().accept(self);
+        for_loop.body.borrow().accept(self);
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        lambda.body.borrow().accept(self);
+    }
+
+    fn visit_call(&mut self, call: &Call) {
+        call.callee.borrow().accept(self);
+        call.arguments.iter().for_each(|arg| {
+            arg.borrow().accept(self);
+        });
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+        spread_expr.expr.borrow().accept(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::parser::Parser;
+
+    fn collect(code: &str) -> AddressCollector {
+        let program = Parser::new().parse(code).unwrap();
+        let mut collector = AddressCollector {
+            found: vec![],
+            slot_only: vec![],
+        };
+        program.accept(&mut collector);
+        collector
+    }
+
+    fn addresses(code: &str) -> Vec<(String, Option<LexicalAddress>)> {
+        collect(code).found
+    }
+
+    fn slot_only(code: &str) -> Vec<Vec<bool>> {
+        collect(code).slot_only
+    }
+
+    struct AddressCollector {
+        found: Vec<(String, Option<LexicalAddress>)>,
+        slot_only: Vec<Vec<bool>>,
+    }
+
+    impl AstVisitor for AddressCollector {
+        fn visit_program(&mut self, program: &Program) {
+            program
+                .children
+                .iter()
+                .for_each(|c| c.borrow().accept(self));
+        }
+        fn visit_block(&mut self, block: &Block) {
+            block.children.iter().for_each(|c| c.borrow().accept(self));
+        }
+        fn visit_integer(&mut self, _integer: &Integer) {}
+        fn visit_rational(&mut self, _rational: &Rational) {}
+        fn visit_real(&mut self, _real: &Real) {}
+        fn visit_bool(&mut self, _bool: &Bool) {}
+        fn visit_str(&mut self, _str: &Str) {}
+        fn visit_nil(&mut self) {}
+        fn visit_identifier(&mut self, identifier: &Identifier) {
+            self.found
+                .push((identifier.value.clone(), identifier.address.clone()));
+        }
+        fn visit_absolute_name(&mut self, _absolute_name: &AbsoluteName) {}
+        fn visit_symbol(&mut self, _symbol: &Symbol) {}
+        fn visit_quote(&mut self, _quote: &Quote) {}
+        fn visit_operator(&mut self, _operator: &Operator) {}
+        fn visit_logical_operator(&mut self, _operator: &LogicalOperator) {}
+        fn visit_pair(&mut self, pair: &Pair) {
+            pair.left.borrow().accept(self);
+            pair.right.borrow().accept(self);
+        }
+        fn visit_list(&mut self, list: &List) {
+            list.elements.iter().for_each(|e| e.borrow().accept(self));
+        }
+        fn visit_def(&mut self, def: &Definition) {
+            def.value.borrow().accept(self);
+        }
+        fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) {
+            def.value.borrow().accept(self);
+        }
+        fn visit_struct_def(&mut self, _struct_def: &StructDefinition) {}
+        fn visit_method_def(&mut self, method_def: &MethodDefinition) {
+            method_def.value.borrow().accept(self);
+        }
+        fn visit_set_bang(&mut self, set_bang: &SetBang) {
+            set_bang.value.borrow().accept(self);
+        }
+        fn visit_if(&mut self, if_expr: &IfExpression) {
+            if_expr.condition.borrow().accept(self);
+            if_expr.consequent.borrow().accept(self);
+            if_expr.alternate.borrow().accept(self);
+        }
+        fn visit_case(&mut self, case_expr: &CaseExpression) {
+            case_expr.key.borrow().accept(self);
+            for (_, consequent) in &case_expr.clauses {
+                consequent.borrow().accept(self);
+            }
+            case_expr.alternate.borrow().accept(self);
+        }
+        fn visit_while(&mut self, while_loop: &WhileLoop) {
+            while_loop.condition.borrow().accept(self);
+            while_loop.body.borrow().accept(self);
+        }
+        fn visit_for(&mut self, for_loop: &ForLoop) {
+            for_loop.start.borrow().accept(self);
+            for_loop.end.borrow().accept(self);
+            for_loop.step.borrow().accept(self);
+            for_loop.body.borrow().accept(self);
+        }
+        fn visit_lambda(&mut self, lambda: &Lambda) {
+            self.slot_only.push(lambda.slot_only.clone());
+            lambda.body.borrow().accept(self);
+        }
+        fn visit_call(&mut self, call: &Call) {
+            call.callee.borrow().accept(self);
+            call.arguments.iter().for_each(|a| a.borrow().accept(self));
+        }
+        fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+            spread_expr.expr.borrow().accept(self);
+        }
+    }
+
+    fn address(depth: usize, slot: usize) -> Option<LexicalAddress> {
+        Some(LexicalAddress { depth, slot })
+    }
+
+    #[test]
+    fn test_parameter_addresses() {
+        let found = addresses("(def (f a b) (lambda (c) (+ a b c)))");
+        assert_eq!(
+            found,
+            vec![
+                ("a".to_string(), address(3, 0)),
+                ("b".to_string(), address(3, 1)),
+                ("c".to_string(), address(1, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unaddressed_identifiers() {
+        let found = addresses("(def (f a b) (def a 1) (set! b 2) (+ a b))");
+        assert_eq!(
+            found,
+            vec![("a".to_string(), None), ("b".to_string(), None)]
+        );
+
+        let found = addresses("(def (f a) (eval 'a) a)");
+        assert_eq!(found.last().unwrap(), &("a".to_string(), None));
+
+        let found = addresses("(def (f a rest...) rest)");
+        assert_eq!(found, vec![("rest".to_string(), address(1, 1))]);
+    }
+
+    #[test]
+    fn test_slot_only_parameters() {
+        let found = slot_only("(def (f a b rest...) (lambda (c) (+ a b c rest)))");
+        assert_eq!(found, vec![vec![true, true, true], vec![true]]);
+
+        // Assigned, defined later in the body or looked up dynamically
+        let found = slot_only("(def (f a b c) (set! a 1) (println b) (def b 2) c)");
+        assert_eq!(found, vec![vec![false, false, true]]);
+
+        let found = slot_only("(def (f a) (lambda () (eval 'a)))");
+        assert_eq!(found, vec![vec![false], vec![]]);
+    }
+}
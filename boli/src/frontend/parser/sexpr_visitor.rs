@@ -0,0 +1,356 @@
+use super::super::formatter::atom_text;
+use super::super::lexer::tokens::{LogicalOp, Op, Token};
+use super::ast::*;
+
+// Writes the AST as BOLI source. Derived forms (cond, let, and, ...) appear
+// in their expanded form, parsing the output yields the same AST again.
+pub struct SexprVisitor {
+    quoted: bool,
+}
+
+impl SexprVisitor {
+    pub fn new() -> Self {
+        Self { quoted: false }
+    }
+
+    pub fn to_sexpr(&mut self, ast: &dyn Ast) -> String {
+        fold_ast(self, ast)
+    }
+
+    fn fold_all(&mut self, asts: &[AstRef]) -> Vec<String> {
+        asts.iter().map(|ast| self.fold(ast)).collect()
+    }
+
+    fn parameters(lambda: &Lambda) -> String {
+        let signature = lambda.signature.clone().unwrap_or_default();
+        let annotate = |name: &str, type_name: Option<&String>| match type_name {
+            Some(type_name) => format!("[{} : {}]", name, type_name),
+            None => name.to_string(),
+        };
+
+        let mut parameters: Vec<String> = lambda
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                annotate(
+                    name,
+                    signature.parameter_types.get(i).and_then(|t| t.as_ref()),
+                )
+            })
+            .collect();
+        if let Some(variadic) = &lambda.variadic {
+            parameters.push(format!(
+                "{}...",
+                annotate(variadic, signature.variadic_type.as_ref())
+            ));
+        }
+        parameters.join(" ")
+    }
+
+    fn return_type(lambda: &Lambda) -> String {
+        match lambda
+            .signature
+            .as_ref()
+            .and_then(|s| s.return_type.as_ref())
+        {
+            Some(return_type) => format!(" : {}", return_type),
+            None => String::new(),
+        }
+    }
+
+    // Lambda bodies are blocks whose children are written without the
+    // surrounding block form
+    fn body(&mut self, body: &AstRef) -> String {
+        let children = match downcast_ast::<Block>(&borrow_ast(body)) {
+            Some(block) => self.fold_all(&block.children),
+            None => vec![self.fold(body)],
+        };
+        children
+            .into_iter()
+            .map(|child| format!(" {}", child))
+            .collect()
+    }
+}
+
+impl AstFolder<String> for SexprVisitor {
+    fn visit_program(&mut self, program: &Program) -> String {
+        self.fold_all(&program.children).join("\n")
+    }
+
+    fn visit_block(&mut self, block: &Block) -> String {
+        let children: String = self
+            .fold_all(&block.children)
+            .into_iter()
+            .map(|child| format!(" {}", child))
+            .collect();
+        format!("(block{})", children)
+    }
+
+    fn visit_integer(&mut self, integer: &Integer) -> String {
+        integer.value.to_string()
+    }
+
+    fn visit_rational(&mut self, rational: &Rational) -> String {
+        format!("{}/{}", rational.numerator, rational.denominator)
+    }
+
+    fn visit_real(&mut self, real: &Real) -> String {
+        atom_text(&Token::new_real(real.value, 0, 0))
+    }
+
+    fn visit_bool(&mut self, bool: &Bool) -> String {
+        atom_text(&Token::new_bool(bool.value, 0, 0))
+    }
+
+    fn visit_str(&mut self, str: &Str) -> String {
+        atom_text(&Token::new_str(str.value.clone(), 0, 0))
+    }
+
+    fn visit_nil(&mut self) -> String {
+        "nil".to_string()
+    }
+
+    fn visit_identifier(&mut self, identifier: &Identifier) -> String {
+        identifier.value.clone()
+    }
+
+    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) -> String {
+        absolute_name.segments.join("::")
+    }
+
+    fn visit_symbol(&mut self, symbol: &Symbol) -> String {
+        symbol.value.clone()
+    }
+
+    fn visit_quote(&mut self, quote: &Quote) -> String {
+        atom_text(&quote.value)
+    }
+
+    fn visit_operator(&mut self, operator: &Operator) -> String {
+        match operator.value {
+            Op::Plus => "+",
+            Op::Minus => "-",
+            Op::Asterisk => "*",
+            Op::Slash => "/",
+            Op::Caret => "^",
+            Op::Percent => "%",
+        }
+        .to_string()
+    }
+
+    fn visit_logical_operator(&mut self, operator: &LogicalOperator) -> String {
+        match operator.value {
+            LogicalOp::Eq => "=",
+            LogicalOp::Gt => ">",
+            LogicalOp::Ge => ">=",
+            LogicalOp::Lt => "<",
+            LogicalOp::Le => "<=",
+        }
+        .to_string()
+    }
+
+    fn visit_pair(&mut self, pair: &Pair) -> String {
+        format!("({} . {})", self.fold(&pair.left), self.fold(&pair.right))
+    }
+
+    fn visit_list(&mut self, list: &List) -> String {
+        let quoted = self.quoted;
+        self.quoted = true;
+        let elements = self.fold_all(&list.elements).join(" ");
+        self.quoted = quoted;
+
+        if quoted {
+            format!("({})", elements)
+        } else {
+            format!("'({})", elements)
+        }
+    }
+
+    fn visit_def(&mut self, def: &Definition) -> String {
+        if let Some(lambda) = downcast_ast::<Lambda>(&borrow_ast(&def.value)) {
+            if lambda.name.as_ref() == Some(&def.name) {
+                let parameters = Self::parameters(lambda);
+                let separator = if parameters.is_empty() { "" } else { " " };
+                return format!(
+                    "(def ({}{}{}){}{})",
+                    def.name,
+                    separator,
+                    parameters,
+                    Self::return_type(lambda),
+                    self.body(&lambda.body)
+                );
+            }
+        }
+        let keyword = if def.constant { "def-const" } else { "def" };
+        format!("({} {} {})", keyword, def.name, self.fold(&def.value))
+    }
+
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) -> String {
+        format!("(def {} {})", def.pattern, self.fold(&def.value))
+    }
+
+    fn visit_struct_def(&mut self, struct_def: &StructDefinition) -> String {
+        let parent = match &struct_def.parent {
+            Some(parent) => format!(" extends {}", parent),
+            None => String::new(),
+        };
+        format!(
+            "(def-struct {}{} ({}))",
+            struct_def.name,
+            parent,
+            struct_def.fields.join(" ")
+        )
+    }
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) -> String {
+        match downcast_ast::<Lambda>(&borrow_ast(&method_def.value)) {
+            Some(lambda) => format!(
+                "(def-method ({} {}){}{})",
+                method_def.name,
+                Self::parameters(lambda),
+                Self::return_type(lambda),
+                self.body(&lambda.body)
+            ),
+            None => format!(
+                "(def-method ({} {}) {})",
+                method_def.name,
+                method_def.struct_type,
+                self.fold(&method_def.value)
+            ),
+        }
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) -> String {
+        format!("(set! {} {})", set_bang.name, self.fold(&set_bang.value))
+    }
+
+    fn visit_if(&mut self, if_expr: &IfExpression) -> String {
+        format!(
+            "(if {} {} {})",
+            self.fold(&if_expr.condition),
+            self.fold(&if_expr.consequent),
+            self.fold(&if_expr.alternate)
+        )
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) -> String {
+        let mut text = format!("(case {}", self.fold(&case_expr.key));
+        for (data, consequent) in &case_expr.clauses {
+            text.push_str(&format!(
+                " (({}) {})",
+                self.fold_all(data).join(" "),
+                self.fold(consequent)
+            ));
+        }
+        if downcast_ast::<Nil>(&borrow_ast(&case_expr.alternate)).is_none() {
+            text.push_str(&format!(" (else {})", self.fold(&case_expr.alternate)));
+        }
+        text.push(')');
+        text
+    }
+
+    fn visit_while(&mut self, while_loop: &WhileLoop) -> String {
+        format!(
+            "(while {}{})",
+            self.fold(&while_loop.condition),
+            self.body(&while_loop.body)
+        )
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) -> String {
+        format!(
+            "(for ({} {} {} {}){})",
+            for_loop.variable,
+            self.fold(&for_loop.start),
+            self.fold(&for_loop.end),
+            self.fold(&for_loop.step),
+            self.body(&for_loop.body)
+        )
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> String {
+        format!(
+            "(λ ({}){}{})",
+            Self::parameters(lambda),
+            Self::return_type(lambda),
+            self.body(&lambda.body)
+        )
+    }
+
+    fn visit_call(&mut self, call: &Call) -> String {
+        let arguments: String = self
+            .fold_all(&call.arguments)
+            .into_iter()
+            .map(|argument| format!(" {}", argument))
+            .collect();
+        format!("({}{})", self.fold(&call.callee), arguments)
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) -> String {
+        format!("...{}", self.fold(&spread_expr.expr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{json_visitor::JsonData, Parser};
+    use super::*;
+
+    fn round_trip(code: &str) -> String {
+        let parser = Parser::new();
+        let sexpr = SexprVisitor::new().to_sexpr(&parser.parse(code).unwrap());
+        let reparsed = parser.parse(&sexpr).unwrap();
+        assert_eq!(
+            JsonData::from(reparsed),
+            JsonData::from(parser.parse(code).unwrap())
+        );
+        sexpr
+    }
+
+    #[test]
+    fn test_expressions() {
+        let sexpr = round_trip(r#"(displayln (+ 1 2,5 1/2) "a \"b\"" #t nil 'sym)"#);
+        assert_eq!(
+            sexpr,
+            r#"(displayln (+ 1 2,5 1/2) "a \"b\"" #true nil 'sym)"#
+        );
+    }
+
+    #[test]
+    fn test_definitions() {
+        let code = r#"
+        (def-struct point (x y))
+        (def-struct point3d extends point (z))
+        (def (sum first rest...) (+ first ...rest))
+        (def-method (describe point p prefix) (displayln prefix) p)
+        (def answer (λ () 42))
+        "#;
+        assert_eq!(
+            round_trip(code),
+            "(def-struct point (x y))
+(def-struct point3d extends point (z))
+(def (sum first rest...) (+ first ...rest))
+(def-method (describe point p prefix) (displayln prefix) p)
+(def answer (λ () 42))"
+        );
+    }
+
+    #[test]
+    fn test_derived_forms() {
+        let sexpr = round_trip("(let ([x 1]) (cond [(> x 0) 'pos] [#t (and x 'neg)]))");
+        assert_eq!(
+            sexpr,
+            "(block (def x 1) (if (> x 0) 'pos (if #true (block (def $and-1 x) (if $and-1 'neg $and-1)) nil)))"
+        );
+    }
+
+    #[test]
+    fn test_quoted_lists() {
+        let sexpr = round_trip("(def l '(1 \"two\" three (4 5) '(6)))\n(def p (1 . 2))");
+        assert_eq!(
+            sexpr,
+            "(def l '(1 \"two\" three (4 5) (quote (6))))\n(def p (1 . 2))"
+        );
+    }
+}
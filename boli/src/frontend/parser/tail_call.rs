@@ -64,8 +64,16 @@ impl AstMutVisitor for TailCallFinder {
         def.value.borrow_mut().accept_mut(self);
     }
 
+    fn visit_destructuring_def(&mut self, def: &mut DestructuringDefinition) {
+        def.value.borrow_mut().accept_mut(self);
+    }
+
     fn visit_struct_def(&mut self, _struct_def: &mut StructDefinition) {}
 
+    fn visit_method_def(&mut self, method_def: &mut MethodDefinition) {
+        method_def.value.borrow_mut().accept_mut(self);
+    }
+
     fn visit_set_bang(&mut self, set_bang: &mut SetBang) {
         set_bang.value.borrow_mut().accept_mut(self);
     }
@@ -76,6 +84,26 @@ impl AstMutVisitor for TailCallFinder {
         if_expr.alternate.borrow_mut().accept_mut(self);
     }
 
+    fn visit_case(&mut self, case_expr: &mut CaseExpression) {
+        case_expr.key.borrow_mut().accept_mut(self);
+        for (_, consequent) in case_expr.clauses.iter_mut() {
+            consequent.borrow_mut().accept_mut(self);
+        }
+        case_expr.alternate.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_while(&mut self, while_loop: &mut WhileLoop) {
+        while_loop.condition.borrow_mut().accept_mut(self);
+        while_loop.body.borrow_mut().accept_mut(self);
+    }
+
+    fn visit_for(&mut self, for_loop: &mut ForLoop) {
+        for_loop.start.borrow_mut().accept_mut(self);
+        for_loop.end.borrow_mut().accept_mut(self);
+        for_loop.step.borrow_mut().accept_mut(self);
+        for_loop.body.borrow_mut().accept_mut(self);
+    }
+
     fn visit_lambda(&mut self, lambda: &mut Lambda) {
         TailCallSetter::mark_tail_calls(lambda);
         lambda.body.borrow_mut().accept_mut(self);
@@ -144,8 +172,12 @@ impl AstMutVisitor for TailCallSetter {
 
     fn visit_def(&mut self, _def: &mut Definition) {}
 
+    fn visit_destructuring_def(&mut self, _def: &mut DestructuringDefinition) {}
+
     fn visit_struct_def(&mut self, _struct_def: &mut StructDefinition) {}
 
+    fn visit_method_def(&mut self, _method_def: &mut MethodDefinition) {}
+
     fn visit_set_bang(&mut self, _set_bang: &mut SetBang) {}
 
     fn visit_if(&mut self, if_expr: &mut IfExpression) {
@@ -153,6 +185,18 @@ impl AstMutVisitor for TailCallSetter {
         if_expr.alternate.borrow_mut().accept_mut(self);
     }
 
+    fn visit_case(&mut self, case_expr: &mut CaseExpression) {
+        for (_, consequent) in case_expr.clauses.iter_mut() {
+            consequent.borrow_mut().accept_mut(self);
+        }
+        case_expr.alternate.borrow_mut().accept_mut(self);
+    }
+
+    // Calls inside of loops are never in tail position
+    fn visit_while(&mut self, _while_loop: &mut WhileLoop) {}
+
+    fn visit_for(&mut self, _for_loop: &mut ForLoop) {}
+
     fn visit_lambda(&mut self, lambda: &mut Lambda) {
         lambda.body.borrow_mut().accept_mut(self);
     }
@@ -0,0 +1,716 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use super::{
+    lexer::tokens::{LogicalOp, Op},
+    parser::{ast::*, resolver::ScopeScanner, ParseError, Parser, Span},
+};
+
+// Gradual type check: the types of literals, arithmetic, annotated
+// parameters and of the functions defined by a program are inferred,
+// everything else has the unknown type and is never reported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Unknown,
+    Nil,
+    Bool,
+    Int,
+    Rational,
+    Real,
+    // Number whose exact type is not known (e.g. the result of a division)
+    Number,
+    Str,
+    Symbol,
+    Vector(Box<Type>),
+    Function(FunctionType),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub parameters: Vec<Type>,
+    pub variadic: Option<Box<Type>>,
+    pub result: Box<Type>,
+}
+
+impl FunctionType {
+    fn new(parameters: Vec<Type>, variadic: Option<Type>, result: Type) -> Self {
+        Self {
+            parameters,
+            variadic: variadic.map(Box::new),
+            result: Box::new(result),
+        }
+    }
+
+    fn parameter_type(&self, index: usize) -> Option<&Type> {
+        self.parameters.get(index).or(self.variadic.as_deref())
+    }
+}
+
+impl Type {
+    // Maps the type annotations of parameters and results. Annotations that
+    // cover several types (like number or list) or name struct types are
+    // not tracked.
+    pub fn from_annotation(annotation: &Option<String>) -> Type {
+        match annotation.as_deref() {
+            Some("nil") => Type::Nil,
+            Some("bool") => Type::Bool,
+            Some("int") => Type::Int,
+            Some("rational") => Type::Rational,
+            Some("real") => Type::Real,
+            Some("number") => Type::Number,
+            Some("str") | Some("string") => Type::Str,
+            Some("symbol") => Type::Symbol,
+            Some("vector") => Type::Vector(Box::new(Type::Unknown)),
+            _ => Type::Unknown,
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Type::Int | Type::Rational | Type::Real | Type::Number)
+    }
+
+    // Two types conflict if no value can have both of them
+    pub fn conflicts_with(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => false,
+            (Type::Number, other) | (other, Type::Number) => !other.is_number(),
+            (Type::Vector(a), Type::Vector(b)) => a.conflicts_with(b),
+            (Type::Function(_), Type::Function(_)) => false,
+            (a, b) => a != b,
+        }
+    }
+
+    fn join(&self, other: &Type) -> Type {
+        match (self, other) {
+            (a, b) if a == b => a.clone(),
+            (Type::Vector(a), Type::Vector(b)) => Type::Vector(Box::new(a.join(b))),
+            (a, b) if a.is_number() && b.is_number() => Type::Number,
+            _ => Type::Unknown,
+        }
+    }
+
+    fn join_all<'a, I: Iterator<Item = &'a Type>>(mut types: I) -> Type {
+        match types.next() {
+            Some(first) => types.fold(first.clone(), |joined, t| joined.join(t)),
+            None => Type::Unknown,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Unknown => write!(f, "any"),
+            Type::Nil => write!(f, "nil"),
+            Type::Bool => write!(f, "bool"),
+            Type::Int => write!(f, "int"),
+            Type::Rational => write!(f, "rational"),
+            Type::Real => write!(f, "real"),
+            Type::Number => write!(f, "number"),
+            Type::Str => write!(f, "str"),
+            Type::Symbol => write!(f, "symbol"),
+            Type::Vector(element) => write!(f, "(vector-of {})", element),
+            Type::Function(function) => {
+                write!(f, "(function")?;
+                for parameter in &function.parameters {
+                    write!(f, " {}", parameter)?;
+                }
+                if let Some(variadic) = &function.variadic {
+                    write!(f, " {}...", variadic)?;
+                }
+                write!(f, " -> {})", function.result)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeWarning {
+    pub message: String,
+    // Index (starting at 1) of the top-level form the warning was found in
+    pub form: usize,
+    // Location of the called function, or of the top-level form if the
+    // function has no location
+    pub span: Option<Span>,
+}
+
+pub fn typecheck_code(code: &str) -> Result<Vec<TypeWarning>, ParseError> {
+    let program = Parser::new().parse(code)?;
+    Ok(typecheck_program(&program))
+}
+
+pub fn typecheck_program(program: &Program) -> Vec<TypeWarning> {
+    let scanner = ScopeScanner::scan(&program.children);
+    let mut checker = TypeChecker {
+        assigned: scanner.assigned,
+        scopes: Vec::new(),
+        form: 0,
+        span: None,
+        warnings: Vec::new(),
+    };
+    checker.visit_program(program);
+    checker.warnings
+}
+
+// Types of the built-in functions whose arguments or results are worth
+// checking
+fn builtin_type(name: &str) -> Option<Type> {
+    let function = |parameters: Vec<Type>, result: Type| {
+        Some(Type::Function(FunctionType::new(parameters, None, result)))
+    };
+    match name {
+        "not" | "nil?" | "string?" | "vector?" | "procedure?" | "builtin?" | "eof-object?" => {
+            function(vec![Type::Unknown], Type::Bool)
+        }
+        "equal?" => function(vec![Type::Unknown, Type::Unknown], Type::Bool),
+        "value-hash" => function(vec![Type::Unknown], Type::Int),
+        "compare" => function(vec![Type::Unknown, Type::Unknown], Type::Int),
+        "sqrt" | "log" => function(vec![Type::Number], Type::Real),
+        "number->string" => function(vec![Type::Number], Type::Str),
+        "real->string" => function(vec![Type::Number, Type::Int], Type::Str),
+        "string->number" => function(vec![Type::Str], Type::Unknown),
+        "string-upper" | "string-lower" | "string-trim" | "string-trim-left"
+        | "string-trim-right" => function(vec![Type::Str], Type::Str),
+        "vector-count" => function(vec![Type::Vector(Box::new(Type::Unknown))], Type::Int),
+        _ => None,
+    }
+}
+
+struct TypeChecker {
+    assigned: HashSet<String>,
+    scopes: Vec<HashMap<String, Type>>,
+    form: usize,
+    span: Option<Span>,
+    warnings: Vec<TypeWarning>,
+}
+
+impl TypeChecker {
+    fn warn(&mut self, message: String) {
+        self.warnings.push(TypeWarning {
+            message,
+            form: self.form,
+            span: self.span,
+        });
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+
+    // The types of names that are assigned are not statically known
+    fn bind(&mut self, name: &str, value_type: Type) {
+        let value_type = if self.assigned.contains(name) {
+            Type::Unknown
+        } else {
+            value_type
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), value_type);
+    }
+
+    // Functions can be called before their definition has been checked, so
+    // their signatures are known up front
+    fn declare_definitions(&mut self, children: &[AstRef]) {
+        for child in children {
+            let child = &borrow_ast(child);
+            if let Some(def) = downcast_ast::<Definition>(child) {
+                let value = &borrow_ast(&def.value);
+                if let Some(lambda) = downcast_ast::<Lambda>(value) {
+                    let function = Self::declared_type(lambda);
+                    self.bind(&def.name, Type::Function(function));
+                }
+            }
+        }
+    }
+
+    fn declared_type(lambda: &Lambda) -> FunctionType {
+        let signature = lambda.signature.clone().unwrap_or_default();
+        let parameters = (0..lambda.parameters.len())
+            .map(|i| Type::from_annotation(signature.parameter_types.get(i).unwrap_or(&None)))
+            .collect();
+        let variadic = lambda
+            .variadic
+            .as_ref()
+            .map(|_| Type::from_annotation(&signature.variadic_type));
+        FunctionType::new(
+            parameters,
+            variadic,
+            Type::from_annotation(&signature.return_type),
+        )
+    }
+
+    fn check_block(&mut self, children: &[AstRef]) -> Type {
+        self.scopes.push(HashMap::new());
+        self.declare_definitions(children);
+        let mut result = Type::Nil;
+        for child in children {
+            result = self.fold(child);
+        }
+        self.scopes.pop();
+        result
+    }
+
+    fn check_numbers(&mut self, operator: &str, arguments: &[Type]) {
+        if let Some(argument) = arguments
+            .iter()
+            .find(|argument| argument.conflicts_with(&Type::Number))
+        {
+            self.warn(format!("{} expects numbers, got {}", operator, argument));
+        }
+    }
+
+    fn arithmetic(&mut self, operator: &Op, arguments: &[Type]) -> Type {
+        let name = match operator {
+            Op::Plus => "+",
+            Op::Minus => "-",
+            Op::Asterisk => "*",
+            Op::Slash => "/",
+            Op::Caret => "^",
+            Op::Percent => "%",
+        };
+        self.check_numbers(name, arguments);
+
+        if !arguments.iter().all(Type::is_number) {
+            Type::Unknown
+        } else if arguments.contains(&Type::Real) {
+            Type::Real
+        } else if matches!(operator, Op::Plus | Op::Minus | Op::Asterisk | Op::Percent)
+            && arguments.iter().all(|argument| *argument == Type::Int)
+        {
+            Type::Int
+        } else {
+            Type::Number
+        }
+    }
+
+    fn comparison(&mut self, operator: &LogicalOp, arguments: &[Type]) -> Type {
+        let name = match operator {
+            LogicalOp::Eq => "=",
+            LogicalOp::Gt => ">",
+            LogicalOp::Ge => ">=",
+            LogicalOp::Lt => "<",
+            LogicalOp::Le => "<=",
+        };
+        self.check_comparable(name, arguments);
+        Type::Bool
+    }
+
+    // Numbers, strings, symbols and vectors can be compared, but only with
+    // values of the same kind
+    fn check_comparable(&mut self, operator: &str, arguments: &[Type]) {
+        let known = arguments
+            .iter()
+            .filter(|argument| **argument != Type::Unknown)
+            .collect::<Vec<_>>();
+        if let Some(argument) = known
+            .iter()
+            .find(|argument| matches!(argument, Type::Nil | Type::Bool | Type::Function(_)))
+        {
+            self.warn(format!(
+                "{} expects comparable values, got {}",
+                operator, argument
+            ));
+        } else if let Some(first) = known.first() {
+            let kind = |argument: &Type| match argument {
+                argument if argument.is_number() => Type::Number,
+                argument => argument.clone(),
+            };
+            if let Some(other) = known
+                .iter()
+                .find(|argument| kind(argument).conflicts_with(&kind(first)))
+            {
+                self.warn(format!(
+                    "{} cannot compare {} and {}",
+                    operator, first, other
+                ));
+            }
+        }
+    }
+
+    fn apply(
+        &mut self,
+        name: &str,
+        function: &FunctionType,
+        arguments: &[Type],
+        has_spread: bool,
+    ) -> Type {
+        let count = arguments.len();
+        let parameters = function.parameters.len();
+        let arity_ok = count == parameters || (function.variadic.is_some() && count > parameters);
+        if !has_spread && !arity_ok {
+            let expected = if function.variadic.is_some() {
+                format!("at least {}", parameters)
+            } else {
+                parameters.to_string()
+            };
+            self.warn(format!(
+                "{} expects {} argument(s) but is called with {}",
+                name, expected, count
+            ));
+        }
+
+        // Positions are not known after a spread argument
+        for (i, argument) in arguments.iter().enumerate() {
+            if has_spread {
+                break;
+            }
+            if let Some(expected) = function.parameter_type(i) {
+                if expected.conflicts_with(argument) {
+                    self.warn(format!(
+                        "{} expects {} as argument {}, got {}",
+                        name,
+                        expected,
+                        i + 1,
+                        argument
+                    ));
+                }
+            }
+        }
+
+        (*function.result).clone()
+    }
+
+    // Type of the result of calling the function name
+    fn call_type(
+        &mut self,
+        name: &str,
+        callee_type: Type,
+        arguments: &[Type],
+        has_spread: bool,
+    ) -> Type {
+        match callee_type {
+            Type::Function(function) => self.apply(name, &function, arguments, has_spread),
+            Type::Unknown if name == "vector" && self.lookup(name).is_none() => {
+                let element = if has_spread {
+                    Type::Unknown
+                } else {
+                    Type::join_all(arguments.iter())
+                };
+                Type::Vector(Box::new(element))
+            }
+            Type::Unknown if name == "vector-ref" && self.lookup(name).is_none() => {
+                match arguments.first() {
+                    Some(Type::Vector(element)) if !has_spread => (**element).clone(),
+                    _ => Type::Unknown,
+                }
+            }
+            Type::Unknown => Type::Unknown,
+            callee_type => {
+                self.warn(format!(
+                    "{} of type {} is not a function",
+                    name, callee_type
+                ));
+                Type::Unknown
+            }
+        }
+    }
+}
+
+impl AstFolder<Type> for TypeChecker {
+    fn visit_program(&mut self, program: &Program) -> Type {
+        self.scopes.push(HashMap::new());
+        self.declare_definitions(&program.children);
+        for (i, child) in program.children.iter().enumerate() {
+            self.form = i + 1;
+            self.span = program.spans.get(i).copied();
+            self.fold(child);
+        }
+        self.scopes.pop();
+        Type::Unknown
+    }
+
+    fn visit_block(&mut self, block: &Block) -> Type {
+        self.check_block(&block.children)
+    }
+
+    fn visit_integer(&mut self, _integer: &Integer) -> Type {
+        Type::Int
+    }
+
+    fn visit_rational(&mut self, _rational: &Rational) -> Type {
+        Type::Rational
+    }
+
+    fn visit_real(&mut self, _real: &Real) -> Type {
+        Type::Real
+    }
+
+    fn visit_bool(&mut self, _bool: &Bool) -> Type {
+        Type::Bool
+    }
+
+    fn visit_str(&mut self, _str: &Str) -> Type {
+        Type::Str
+    }
+
+    fn visit_nil(&mut self) -> Type {
+        Type::Nil
+    }
+
+    fn visit_identifier(&mut self, identifier: &Identifier) -> Type {
+        let name = &identifier.value;
+        self.lookup(name)
+            .or_else(|| builtin_type(name))
+            .unwrap_or(Type::Unknown)
+    }
+
+    fn visit_absolute_name(&mut self, _absolute_name: &AbsoluteName) -> Type {
+        Type::Unknown
+    }
+
+    fn visit_symbol(&mut self, _symbol: &Symbol) -> Type {
+        Type::Symbol
+    }
+
+    fn visit_quote(&mut self, _quote: &Quote) -> Type {
+        Type::Unknown
+    }
+
+    fn visit_operator(&mut self, _operator: &Operator) -> Type {
+        Type::Unknown
+    }
+
+    fn visit_logical_operator(&mut self, _operator: &LogicalOperator) -> Type {
+        Type::Unknown
+    }
+
+    fn visit_pair(&mut self, _pair: &Pair) -> Type {
+        Type::Unknown
+    }
+
+    fn visit_list(&mut self, _list: &List) -> Type {
+        Type::Unknown
+    }
+
+    fn visit_def(&mut self, def: &Definition) -> Type {
+        let value_type = self.fold(&def.value);
+        self.bind(&def.name, value_type);
+        Type::Unknown
+    }
+
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) -> Type {
+        self.fold(&def.value);
+        for name in def.pattern.names() {
+            self.bind(&name, Type::Unknown);
+        }
+        Type::Unknown
+    }
+
+    fn visit_struct_def(&mut self, _struct_def: &StructDefinition) -> Type {
+        Type::Unknown
+    }
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) -> Type {
+        self.fold(&method_def.value);
+        Type::Unknown
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) -> Type {
+        self.fold(&set_bang.value);
+        Type::Unknown
+    }
+
+    fn visit_if(&mut self, if_expr: &IfExpression) -> Type {
+        self.fold(&if_expr.condition);
+        let consequent = self.fold(&if_expr.consequent);
+        let alternate = self.fold(&if_expr.alternate);
+        consequent.join(&alternate)
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) -> Type {
+        self.fold(&case_expr.key);
+        let mut types = Vec::new();
+        for (_, consequent) in &case_expr.clauses {
+            types.push(self.fold(consequent));
+        }
+        types.push(self.fold(&case_expr.alternate));
+        Type::join_all(types.iter())
+    }
+
+    fn visit_while(&mut self, while_loop: &WhileLoop) -> Type {
+        self.fold(&while_loop.condition);
+        self.fold(&while_loop.body);
+        Type::Nil
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) -> Type {
+        for bound in [&for_loop.start, &for_loop.end, &for_loop.step] {
+            let bound_type = self.fold(bound);
+            if bound_type.conflicts_with(&Type::Int) {
+                self.warn(format!(
+                    "for loop bounds must be integers, got {}",
+                    bound_type
+                ));
+            }
+        }
+        self.scopes.push(HashMap::new());
+        self.bind(&for_loop.variable, Type::Int);
+        self.fold(&for_loop.body);
+        self.scopes.pop();
+        Type::Nil
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> Type {
+        let mut function = Self::declared_type(lambda);
+
+        self.scopes.push(HashMap::new());
+        for (parameter, parameter_type) in lambda.parameters.iter().zip(&function.parameters) {
+            self.bind(parameter, parameter_type.clone());
+        }
+        if let (Some(variadic), Some(_)) = (&lambda.variadic, &function.variadic) {
+            // Variadic arguments are passed as a list
+            self.bind(variadic, Type::Unknown);
+        }
+        let body_type = self.fold(&lambda.body);
+        self.scopes.pop();
+
+        if *function.result == Type::Unknown {
+            function.result = Box::new(body_type);
+        } else if function.result.conflicts_with(&body_type) {
+            let name = lambda.name.as_deref().unwrap_or("λ");
+            self.warn(format!(
+                "{} should return {}, got {}",
+                name, function.result, body_type
+            ));
+        }
+
+        Type::Function(function)
+    }
+
+    fn visit_call(&mut self, call: &Call) -> Type {
+        let callee_type = self.fold(&call.callee);
+        let has_spread = call
+            .arguments
+            .iter()
+            .any(|arg| downcast_ast::<SpreadExpr>(&borrow_ast(arg)).is_some());
+        let arguments: Vec<Type> = call.arguments.iter().map(|arg| self.fold(arg)).collect();
+
+        let callee = &borrow_ast(&call.callee);
+        if let Some(operator) = downcast_ast::<Operator>(callee) {
+            return self.arithmetic(&operator.value, &arguments);
+        }
+        if let Some(operator) = downcast_ast::<LogicalOperator>(callee) {
+            return self.comparison(&operator.value, &arguments);
+        }
+        let (name, span) = match downcast_ast::<Identifier>(callee) {
+            Some(identifier) => (identifier.value.clone(), identifier.span),
+            None => ("λ".to_string(), None),
+        };
+
+        let outer_span = self.span;
+        self.span = span.or(outer_span);
+        let result = self.call_type(&name, callee_type, &arguments, has_spread);
+        self.span = outer_span;
+        result
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) -> Type {
+        self.fold(&spread_expr.expr);
+        Type::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(code: &str) -> Vec<(String, usize)> {
+        typecheck_code(code)
+            .unwrap()
+            .into_iter()
+            .map(|warning| (warning.message, warning.form))
+            .collect()
+    }
+
+    fn infer(code: &str) -> Type {
+        let program = Parser::new().parse(code).unwrap();
+        let mut checker = TypeChecker {
+            assigned: HashSet::new(),
+            scopes: vec![HashMap::new()],
+            form: 0,
+            span: None,
+            warnings: Vec::new(),
+        };
+        checker.check_block(&program.children)
+    }
+
+    #[test]
+    fn test_warning_locations() {
+        let code = "(def (f [x : int]) x)\n(displayln\n   (f \"a\"))\n  (+ 1 \"b\")";
+        let locations: Vec<(usize, usize)> = typecheck_code(code)
+            .unwrap()
+            .iter()
+            .map(|warning| {
+                let span = warning.span.unwrap();
+                (span.line, span.column)
+            })
+            .collect();
+        assert_eq!(locations, vec![(3, 5), (4, 3)]);
+    }
+
+    #[test]
+    fn test_infer_types() {
+        assert_eq!(infer("(+ 1 2)"), Type::Int);
+        assert_eq!(infer("(* 2 1,5)"), Type::Real);
+        assert_eq!(infer("(/ 1 2)"), Type::Number);
+        assert_eq!(infer("(< 1 2)"), Type::Bool);
+        assert_eq!(infer("(if (< 1 2) \"a\" \"b\")"), Type::Str);
+        assert_eq!(infer("(if (< 1 2) \"a\" 1)"), Type::Unknown);
+        assert_eq!(infer("(vector 1 2 3)"), Type::Vector(Box::new(Type::Int)));
+        assert_eq!(infer("(vector-ref (vector 1,0 2,0) 0)"), Type::Real);
+        assert_eq!(infer("(def (twice x) (* 2 x)) (twice 2)"), Type::Unknown);
+        assert_eq!(
+            infer("(def (half [x : real]) (/ x 2)) (half 2,0)"),
+            Type::Real
+        );
+        assert_eq!(
+            infer("(def (count-up n) : int (count-up n)) count-up").to_string(),
+            "(function any -> int)"
+        );
+    }
+
+    #[test]
+    fn test_type_warnings() {
+        let code = r#"
+        (def name "boli")
+        (+ name 1)
+        (def (area [w : int] [h : int]) (* w h))
+        (area 2 "3")
+        (area 2)
+        (area ...'(1 2))
+        (def (greet) : str 42)
+        (name 1)
+        (for (i 0 "10") i)
+        (def n 1)
+        (set! n "one")
+        (+ n 1)
+        (def (f g) (g 1))
+        (< "a" "b" name)
+        (< 1 "a")
+        (> nil 1)
+        "#;
+        assert_eq!(
+            warnings(code),
+            vec![
+                ("+ expects numbers, got str".to_string(), 2),
+                ("area expects int as argument 2, got str".to_string(), 4),
+                (
+                    "area expects 2 argument(s) but is called with 1".to_string(),
+                    5
+                ),
+                ("greet should return str, got int".to_string(), 7),
+                ("name of type str is not a function".to_string(), 8),
+                ("for loop bounds must be integers, got str".to_string(), 9),
+                ("< cannot compare int and str".to_string(), 15),
+                ("> expects comparable values, got nil".to_string(), 16),
+            ]
+        );
+    }
+}
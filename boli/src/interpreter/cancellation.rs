@@ -0,0 +1,94 @@
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::values::{EvalResult, InterpreterError};
+
+// Aborts a running evaluation from the outside, e.g. from a signal handler
+// or after a timeout. The interpreter checks the token on every function
+// call and while iterating streams.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: Some(Instant::now() + timeout),
+        }
+    }
+
+    // Can be called from any thread
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // Makes the token usable for another evaluation
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.check().is_err()
+    }
+
+    pub fn check(&self) -> Result<(), InterpreterError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(InterpreterError::new("evaluation cancelled"));
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                Err(InterpreterError::new("evaluation timed out"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+thread_local! {
+    static ACTIVE_TOKEN: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+pub fn current_token() -> Option<CancellationToken> {
+    ACTIVE_TOKEN.with(|token| token.borrow().clone())
+}
+
+// Evaluations that run in Rust loops (like stream traversals) call this to
+// stop early. Their caller then reports the cancellation.
+pub fn is_cancelled() -> bool {
+    ACTIVE_TOKEN.with(|token| {
+        token
+            .borrow()
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled())
+    })
+}
+
+struct ActiveToken {
+    previous: Option<CancellationToken>,
+}
+
+impl Drop for ActiveToken {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE_TOKEN.with(|token| *token.borrow_mut() = previous);
+    }
+}
+
+pub fn with_cancellation<F: FnOnce() -> EvalResult>(token: &CancellationToken, f: F) -> EvalResult {
+    let previous = ACTIVE_TOKEN.with(|active| active.borrow_mut().replace(token.clone()));
+    let _active_token = ActiveToken { previous };
+    f()
+}
@@ -0,0 +1,140 @@
+use crate::frontend::parser::ast::Signature;
+
+use super::values::*;
+
+// Besides the names of the value types, annotations can use a few names
+// that cover several types and the names of struct types
+pub fn has_type(value: &ValueRef, type_name: &str) -> bool {
+    let value = borrow_value(value);
+    let value_type = value.get_type();
+    match type_name {
+        "any" => true,
+        "number" => matches!(
+            value_type,
+            ValueType::Int | ValueType::Rational | ValueType::Real
+        ),
+        "string" => value_type == ValueType::Str,
+        "function" => matches!(value_type, ValueType::Lambda | ValueType::BuiltInFunction),
+        "list" => match downcast_value::<PairValue>(&value) {
+            Some(pair) => pair.is_list(),
+            None => value_type == ValueType::Nil,
+        },
+        _ if value_type.name() == type_name => true,
+        _ => match downcast_value::<StructValue>(&value) {
+            Some(struct_value) => is_struct_of_type(&struct_value.struct_type, type_name),
+            None => false,
+        },
+    }
+}
+
+// Instances of derived struct types also have the types of their parents
+fn is_struct_of_type(struct_type: &Option<ValueRef>, type_name: &str) -> bool {
+    let mut current = struct_type.clone();
+    while let Some(struct_type) = current {
+        let struct_type = borrow_value(&struct_type);
+        let Some(struct_type) = downcast_value::<StructTypeValue>(&struct_type) else {
+            return false;
+        };
+        if struct_type.name == type_name {
+            return true;
+        }
+        current = struct_type.parent.clone();
+    }
+    false
+}
+
+pub fn check_arguments(
+    function: &str,
+    parameters: &[String],
+    variadic: &Option<String>,
+    signature: &Signature,
+    args: &[ValueRef],
+) -> Result<(), InterpreterError> {
+    for (i, arg) in args.iter().enumerate() {
+        let (parameter, type_name) = match parameters.get(i) {
+            Some(parameter) => (parameter, signature.parameter_types.get(i)),
+            None => match variadic {
+                Some(variadic) => (variadic, Some(&signature.variadic_type)),
+                None => break,
+            },
+        };
+        if let Some(Some(type_name)) = type_name {
+            if !has_type(arg, type_name) {
+                return Err(InterpreterError::type_error(
+                    &format!(
+                        "{} expects {} of type {}, got {}",
+                        function,
+                        parameter,
+                        type_name,
+                        arg.borrow()
+                    ),
+                    arg,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn check_result(
+    function: &str,
+    signature: &Signature,
+    result: &ValueRef,
+) -> Result<(), InterpreterError> {
+    match &signature.return_type {
+        Some(type_name) if !has_type(result, type_name) => Err(InterpreterError::type_error(
+            &format!(
+                "{} should return {}, got {}",
+                function,
+                type_name,
+                result.borrow()
+            ),
+            result,
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{settings::Settings, Interpreter};
+
+    #[test]
+    fn test_contracts() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def-struct shape (name))
+            (def-struct circle extends shape (radius))
+            (def (add [x : int] [y : number]) : int (+ x y))
+            (def (area [s : shape]) : real (* 3,0 (circle-radius s)))
+            (def (count-all [xs : str]...) xs)
+        "#;
+        interpreter.eval(code).unwrap();
+
+        // Annotations are not checked by default
+        assert_eq!(
+            interpreter
+                .eval("(add 1 1,5)")
+                .unwrap()
+                .borrow()
+                .to_string(),
+            "2,5"
+        );
+
+        interpreter.set_settings(Settings::new().contracts(true));
+        assert_eq!(
+            interpreter.eval("(add 1 2)").unwrap().borrow().to_string(),
+            "3"
+        );
+        let err = interpreter.eval("(add \"1\" 2)").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Type);
+        assert_eq!(err.message, "add expects x of type int, got \"1\"");
+        let err = interpreter.eval("(add 1 1,5)").unwrap_err();
+        assert_eq!(err.message, "add should return int, got 2,5");
+        assert!(interpreter.eval("(area (create-circle \"c\" 2,0))").is_ok());
+        assert!(interpreter.eval("(area 2)").is_err());
+        let err = interpreter.eval("(count-all \"a\" 'b)").unwrap_err();
+        assert_eq!(err.message, "count-all expects xs of type str, got 'b");
+    }
+}
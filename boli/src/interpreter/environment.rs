@@ -1,23 +1,41 @@
+use super::eval_functions::{EvalFn, ReadFn};
+use super::freeze::{DeepCopy, Freeze, IsFrozen};
+use super::gc::{self, CollectCycles, MemoryReport};
+use super::introspection::{
+    Arities, Arity, CurrentEnvironment, EnvBindings, EnvLookup, IsBuiltin, IsProcedure, LambdaName,
+    LambdaParams, MakeEnvironment,
+};
+use super::limits::Limits;
 use super::misc_functions::*;
 use super::module_mgmt::file_system::new_directory;
+use super::module_mgmt::module_loader::ModuleCache;
+use super::module_mgmt::module_loader::ModuleCacheRef;
 use super::module_mgmt::module_loader::ProvideFn;
 use super::module_mgmt::module_loader::RequireFn;
 use super::module_mgmt::ModuleDirRef;
 use super::number_functions::*;
-use super::prelude;
+use super::parameters::{number_format_parameter, CallWithParameters, MakeParameter};
+use super::prelude::{self, Capabilities};
+use super::pretty_printer::PrettyPrint;
+use super::settings::Settings;
+use super::stats::{self, RuntimeStatsFn};
 use super::struct_functions::*;
 use super::values::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 pub struct EnvironmentBuilder {
     pub env: HashMap<String, EnvEntry>,
     module_search_dirs: Option<Vec<ModuleDirRef>>,
     input: Option<InputRef>,
     output: Option<OutputRef>,
+    error_output: Option<OutputRef>,
     parent: Option<EnvironmentRef>,
+    limits: Option<Limits>,
+    capabilities: Option<Capabilities>,
+    settings: Option<Settings>,
     with_prelude: bool,
 }
 
@@ -28,7 +46,11 @@ impl EnvironmentBuilder {
             module_search_dirs: None,
             input: None,
             output: None,
+            error_output: None,
             parent: None,
+            limits: None,
+            capabilities: None,
+            settings: None,
             with_prelude: false,
         }
     }
@@ -53,23 +75,115 @@ impl EnvironmentBuilder {
         self
     }
 
+    pub fn error_output(&mut self, error_output: &OutputRef) -> &mut Self {
+        self.error_output = Some(error_output.clone());
+        self
+    }
+
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn capabilities(&mut self, capabilities: Capabilities) -> &mut Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    pub fn settings(&mut self, settings: Settings) -> &mut Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        let settings = self.settings.unwrap_or_default();
+        self.settings(settings.strict(strict))
+    }
+
+    // Capabilities that are not set explicitly are allowed
+    fn capabilities_mut(&mut self) -> &mut Capabilities {
+        self.capabilities.get_or_insert_with(Capabilities::all)
+    }
+
+    pub fn allow_fs(&mut self, allow: bool) -> &mut Self {
+        self.capabilities_mut().fs = allow;
+        self
+    }
+
+    pub fn allow_net(&mut self, allow: bool) -> &mut Self {
+        self.capabilities_mut().net = allow;
+        self
+    }
+
+    pub fn allow_exec(&mut self, allow: bool) -> &mut Self {
+        self.capabilities_mut().exec = allow;
+        self
+    }
+
+    pub fn allow_native(&mut self, allow: bool) -> &mut Self {
+        self.capabilities_mut().native = allow;
+        self
+    }
+
+    // Takes over the module search dirs, the input and output, the limits,
+    // the capabilities and the settings of env. Environments that builtins
+    // create on behalf of a script must not have more rights than the script
+    // itself.
+    pub fn settings_from(&mut self, env: &Environment) -> &mut Self {
+        self.module_search_dirs = Some(env.get_module_search_dirs());
+        self.input = Some(env.get_input());
+        self.output = Some(env.get_output());
+        self.error_output = Some(env.get_error_output());
+        self.limits = env.get_limits();
+        self.capabilities = Some(env.get_capabilities());
+        self.settings = Some(env.get_settings());
+        self
+    }
+
     pub fn with_prelude(&mut self, with_prelude: bool) -> &mut Self {
         self.with_prelude = with_prelude;
         self
     }
 
     pub fn build(&self) -> EnvironmentRef {
+        let depth = match &self.parent {
+            Some(parent) => parent.borrow().depth + 1,
+            None => 0,
+        };
+        stats::record_environment_depth(depth);
+
         let ret = Rc::new(RefCell::new(Environment {
             env: self.env.clone(),
             module_search_dirs: self.module_search_dirs.clone(),
+            module_cache: match self.parent {
+                Some(_) => None,
+                None => Some(Rc::new(RefCell::new(ModuleCache::new()))),
+            },
             input: self.input.clone(),
             output: self.output.clone(),
+            error_output: self.error_output.clone(),
             parent: self.parent.clone(),
+            depth,
+            limits: self.limits,
+            capabilities: self.capabilities,
+            settings: self.settings,
             export_set: None,
+            methods: HashMap::new(),
+            slots: Vec::new(),
+            gc_registered: Cell::new(false),
         }));
-        Environment::init_builtins(&ret);
+        // Child environments find the builtins in their root environment
+        // unless they have input or output of their own
+        if self.parent.is_none() {
+            Environment::init_builtins(&ret);
+            gc::register_environment(&ret);
+        } else if self.input.is_some() || self.output.is_some() || self.error_output.is_some() {
+            Environment::init_input_builtins(&ret);
+            Environment::init_output_builtins(&ret);
+        }
         if self.with_prelude {
-            Environment::load_prelude(&ret);
+            let capabilities = ret.borrow().get_capabilities();
+            Environment::load_prelude_with(&ret, &capabilities);
         }
         ret
     }
@@ -84,19 +198,43 @@ impl Default for EnvironmentBuilder {
 pub struct Environment {
     pub env: HashMap<String, EnvEntry>,
     module_search_dirs: Option<Vec<ModuleDirRef>>,
+    module_cache: Option<ModuleCacheRef>,
     input: Option<InputRef>,
     output: Option<OutputRef>,
+    error_output: Option<OutputRef>,
     parent: Option<EnvironmentRef>,
+    // Number of ancestors
+    depth: usize,
+    limits: Option<Limits>,
+    capabilities: Option<Capabilities>,
+    settings: Option<Settings>,
     export_set: Option<HashSet<String>>,
+    methods: HashMap<String, HashMap<String, ValueRef>>,
+    slots: Vec<ValueRef>,
+    // Set once the cycle collector knows about the environment
+    gc_registered: Cell<bool>,
 }
 
 #[derive(Clone)]
 pub struct EnvEntry {
     value: ValueRef,
     owned: bool,
+    constant: bool,
+}
+
+impl EnvEntry {
+    pub fn get_value(&self) -> ValueRef {
+        self.value.clone()
+    }
 }
 
 pub type EnvironmentRef = Rc<RefCell<Environment>>;
+pub type WeakEnvironmentRef = Weak<RefCell<Environment>>;
+
+pub fn upgrade_env(env: &WeakEnvironmentRef) -> Result<EnvironmentRef, InterpreterError> {
+    env.upgrade()
+        .ok_or(InterpreterError::new("Environment is no longer available"))
+}
 
 impl Environment {
     pub fn set_module_search_dirs(env: &EnvironmentRef, dirs: &Vec<ModuleDirRef>) {
@@ -117,6 +255,54 @@ impl Environment {
         }
     }
 
+    // All environments of an interpreter share the module cache of the
+    // root environment
+    pub fn get_module_cache(&self) -> ModuleCacheRef {
+        match &self.module_cache {
+            Some(module_cache) => module_cache.clone(),
+            None => match &self.parent {
+                Some(parent) => parent.borrow().get_module_cache(),
+                None => Rc::new(RefCell::new(ModuleCache::new())),
+            },
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = Some(limits);
+    }
+
+    // Limits of the nearest environment that has any
+    pub fn get_limits(&self) -> Option<Limits> {
+        match (&self.limits, &self.parent) {
+            (Some(limits), _) => Some(*limits),
+            (None, Some(parent)) => parent.borrow().get_limits(),
+            (None, None) => None,
+        }
+    }
+
+    // Capabilities of the nearest environment that has any, everything is
+    // allowed if no environment restricts it
+    pub fn get_capabilities(&self) -> Capabilities {
+        match (&self.capabilities, &self.parent) {
+            (Some(capabilities), _) => *capabilities,
+            (None, Some(parent)) => parent.borrow().get_capabilities(),
+            (None, None) => Capabilities::all(),
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = Some(settings);
+    }
+
+    // Settings of the nearest environment that has any
+    pub fn get_settings(&self) -> Settings {
+        match (&self.settings, &self.parent) {
+            (Some(settings), _) => *settings,
+            (None, Some(parent)) => parent.borrow().get_settings(),
+            (None, None) => Settings::default(),
+        }
+    }
+
     pub fn get_input(&self) -> InputRef {
         match &self.input {
             Some(input) => input.clone(),
@@ -148,12 +334,30 @@ impl Environment {
         }
     }
 
+    pub fn set_error_output(env: &EnvironmentRef, error_output: &OutputRef) {
+        env.borrow_mut().error_output = Some(error_output.clone());
+        Self::init_output_builtins(env);
+    }
+
+    pub fn get_error_output(&self) -> OutputRef {
+        match &self.error_output {
+            Some(error_output) => error_output.clone(),
+            None => {
+                if let Some(parent) = &self.parent {
+                    return parent.borrow().get_error_output();
+                } else {
+                    Rc::new(RefCell::new(StdErrOutput::new()))
+                }
+            }
+        }
+    }
+
     pub fn get_parent(&self) -> Option<Rc<RefCell<Environment>>> {
         self.parent.clone()
     }
 
     pub fn get(&self, key: &str) -> Option<ValueRef> {
-        if let Some(EnvEntry { value, owned: _ }) = self.env.get(key) {
+        if let Some(EnvEntry { value, .. }) = self.env.get(key) {
             return Some(value.clone());
         }
 
@@ -164,6 +368,122 @@ impl Environment {
         None
     }
 
+    pub fn clear(&mut self) {
+        self.env.clear();
+        self.methods.clear();
+        self.slots.clear();
+        self.parent = None;
+    }
+
+    // Copies env and its ancestors for a task of the concurrent extension.
+    // The bindings are deep copies and lambdas defined in one of the copied
+    // environments are bound to the copies, so that the task cannot change
+    // what env sees. Closures defined elsewhere keep their environment.
+    pub fn isolated_copy(env: &EnvironmentRef) -> EnvironmentRef {
+        let mut chain = vec![env.clone()];
+        let mut current = env.borrow().get_parent();
+        while let Some(parent) = current {
+            current = parent.borrow().get_parent();
+            chain.push(parent);
+        }
+
+        let mut copies: HashMap<*const RefCell<Environment>, EnvironmentRef> = HashMap::new();
+        let mut parent: Option<EnvironmentRef> = None;
+        for original in chain.iter().rev() {
+            let original_env = original.borrow();
+            let copy = Rc::new(RefCell::new(Environment {
+                env: HashMap::new(),
+                module_search_dirs: original_env.module_search_dirs.clone(),
+                module_cache: original_env.module_cache.clone(),
+                input: original_env.input.clone(),
+                output: original_env.output.clone(),
+                error_output: original_env.error_output.clone(),
+                parent: parent.clone(),
+                depth: original_env.depth,
+                limits: original_env.limits,
+                capabilities: original_env.capabilities,
+                settings: original_env.settings,
+                export_set: original_env.export_set.clone(),
+                methods: HashMap::new(),
+                slots: Vec::new(),
+                gc_registered: Cell::new(false),
+            }));
+            gc::register_environment(&copy);
+            copies.insert(Rc::as_ptr(original), copy.clone());
+            parent = Some(copy);
+        }
+
+        let copy_value = |value: &ValueRef| {
+            let rebound = {
+                let value = borrow_value(value);
+                downcast_value::<LambdaValue>(&value).and_then(|lambda| {
+                    copies
+                        .get(&Rc::as_ptr(&lambda.env))
+                        .map(|env| lambda.with_env(env))
+                })
+            };
+            match rebound {
+                Some(lambda) => new_valueref(lambda),
+                None => deep_copy(value),
+            }
+        };
+
+        for original in &chain {
+            let copy = &copies[&Rc::as_ptr(original)];
+            let original = original.borrow();
+            let entries = original
+                .env
+                .iter()
+                .map(|(key, entry)| {
+                    let entry = EnvEntry {
+                        value: copy_value(&entry.value),
+                        ..entry.clone()
+                    };
+                    (key.clone(), entry)
+                })
+                .collect();
+            let methods = original
+                .methods
+                .iter()
+                .map(|(name, methods)| {
+                    let methods = methods
+                        .iter()
+                        .map(|(struct_type, method)| (struct_type.clone(), copy_value(method)))
+                        .collect();
+                    (name.clone(), methods)
+                })
+                .collect();
+            let slots = original.slots.iter().map(copy_value).collect();
+
+            let mut copy = copy.borrow_mut();
+            copy.env = entries;
+            copy.methods = methods;
+            copy.slots = slots;
+        }
+
+        copies[&Rc::as_ptr(env)].clone()
+    }
+
+    // Returns whether the environment had already been registered
+    pub fn mark_gc_registered(&self) -> bool {
+        self.gc_registered.replace(true)
+    }
+
+    pub fn get_slot(&self, depth: usize, slot: usize) -> Option<ValueRef> {
+        if depth == 0 {
+            return self.slots.get(slot).cloned();
+        }
+
+        match &self.parent {
+            Some(parent) => parent.borrow().get_slot(depth - 1, slot),
+            None => None,
+        }
+    }
+
+    pub fn push_slot(&mut self, value: &ValueRef) {
+        self.slots.push(value.clone());
+    }
+
     pub fn get_defining_env(env: &EnvironmentRef, key: &str) -> Option<EnvironmentRef> {
         if env.borrow().env.contains_key(key) {
             return Some(env.clone());
@@ -176,41 +496,115 @@ impl Environment {
         None
     }
 
+    pub fn get_method_env(env: &EnvironmentRef, name: &str) -> Option<EnvironmentRef> {
+        if env.borrow().methods.contains_key(name) {
+            return Some(env.clone());
+        }
+
+        if let Some(parent) = env.borrow().get_parent() {
+            return Self::get_method_env(&parent, name);
+        }
+
+        None
+    }
+
+    pub fn get_method(&self, name: &str, struct_type: &str) -> Option<ValueRef> {
+        self.methods
+            .get(name)
+            .and_then(|methods| methods.get(struct_type))
+            .cloned()
+    }
+
+    pub fn method_values(&self) -> Vec<ValueRef> {
+        self.methods
+            .values()
+            .flat_map(|methods| methods.values().cloned())
+            .collect()
+    }
+
+    pub fn set_method(&mut self, name: &str, struct_type: &str, method: ValueRef) {
+        self.methods
+            .entry(name.to_string())
+            .or_default()
+            .insert(struct_type.to_string(), method);
+    }
+
+    // A lambda that is defined under the name of a lambda owned by the
+    // environment is merged into it, unless one of them is variadic
     pub fn set(&mut self, key: String, value: ValueRef) {
-        let value_type = value.borrow().get_type();
-        match value_type {
-            ValueType::Lambda => {
-                if self.env.contains_key(&key) {
-                    let existing_entry = self.env.get(&key).unwrap();
-                    if !existing_entry.owned {
-                        self.set_owned(key.clone(), value);
-                    } else {
-                        let existing_value = existing_entry.value.clone();
-                        let existing_value = &mut borrow_mut_value(&existing_value);
-                        let existing_lambda = existing_value
-                            .as_any_mut()
-                            .downcast_mut::<LambdaValue>()
-                            .unwrap();
-                        let new_value = &borrow_value(&value);
-                        let new_lambda = new_value.as_any().downcast_ref::<LambdaValue>().unwrap();
-                        existing_lambda.merge_lambda(new_lambda).unwrap();
-                    }
-                } else {
-                    self.set_owned(key.clone(), value);
-                }
+        let is_lambda = |value: &ValueRef| value.borrow().get_type() == ValueType::Lambda;
+        let merged = match self.env.get(&key) {
+            Some(existing_entry)
+                if existing_entry.owned
+                    && is_lambda(&existing_entry.value)
+                    && is_lambda(&value)
+                    && !Rc::ptr_eq(&existing_entry.value, &value) =>
+            {
+                let existing_value = &mut borrow_mut_value(&existing_entry.value);
+                let existing_lambda = existing_value
+                    .as_any_mut()
+                    .downcast_mut::<LambdaValue>()
+                    .unwrap();
+                let new_value = &borrow_value(&value);
+                let new_lambda = downcast_value::<LambdaValue>(new_value).unwrap();
+                existing_lambda.merge_lambda(new_lambda).is_ok()
             }
-            _ => self.set_owned(key.clone(), value),
+            _ => false,
+        };
+        if !merged {
+            self.set_owned(key, value);
         }
     }
 
+    // Binds key to value replacing any previous binding, functions are not
+    // merged
+    pub fn bind(&mut self, key: String, value: ValueRef) {
+        self.set_owned(key, value);
+    }
+
+    // Describes the definitions of the function bound to key that a
+    // definition of value would replace
+    pub fn replaced_definitions(&self, key: &str, value: &ValueRef) -> Option<String> {
+        let existing_entry = self
+            .env
+            .get(key)
+            .filter(|entry| entry.owned && !Rc::ptr_eq(&entry.value, value))?;
+        let existing_value = &borrow_value(&existing_entry.value);
+        let new_value = &borrow_value(value);
+        let existing_lambda = downcast_value::<LambdaValue>(existing_value)?;
+        let new_lambda = downcast_value::<LambdaValue>(new_value)?;
+        existing_lambda.replaced_definitions(new_lambda)
+    }
+
+    // Constants (def-const) are owned by the environment like other
+    // definitions but cannot be assigned with set!
+    pub fn set_constant(&mut self, key: String, value: ValueRef) {
+        self.env.insert(
+            key,
+            EnvEntry {
+                value,
+                owned: true,
+                constant: true,
+            },
+        );
+    }
+
+    pub fn is_constant(&self, key: &str) -> bool {
+        self.env.get(key).is_some_and(|entry| entry.constant)
+    }
+
     pub fn load_prelude(env: &EnvironmentRef) {
-        let stdlib = prelude::create_prelude();
+        Self::load_prelude_with(env, &Capabilities::all());
+    }
 
-        Self::import_extension(env, &stdlib, "vector");
-        Self::import_extension(env, &stdlib, "list");
-        Self::import_extension(env, &stdlib, "stream");
-        Self::import_extension(env, &stdlib, "string");
-        Self::import_extension(env, &stdlib, "seqcol");
+    pub fn load_prelude_with(env: &EnvironmentRef, capabilities: &Capabilities) {
+        let stdlib = prelude::create_prelude(capabilities);
+
+        for name in prelude::PRELUDE_EXTENSIONS {
+            if capabilities.allows_extension(name) {
+                Self::import_extension(env, &stdlib, name);
+            }
+        }
     }
 
     fn import_extension(env: &EnvironmentRef, extension_dir: &ModuleDirRef, name: &str) {
@@ -220,7 +614,14 @@ impl Environment {
     }
 
     fn set_owned(&mut self, key: String, value: ValueRef) {
-        self.env.insert(key, EnvEntry { value, owned: true }); // true: value is owned by the environment
+        self.env.insert(
+            key,
+            EnvEntry {
+                value,
+                owned: true,
+                constant: false,
+            },
+        ); // true: value is owned by the environment
     }
 
     fn set_unowned(&mut self, key: String, value: ValueRef) {
@@ -229,6 +630,7 @@ impl Environment {
             EnvEntry {
                 value,
                 owned: false,
+                constant: false,
             },
         ); // false: value is not owned by the environment
     }
@@ -238,14 +640,14 @@ impl Environment {
             None => self
                 .env
                 .iter()
-                .filter(|(_, EnvEntry { value: _, owned })| *owned)
-                .map(|(key, EnvEntry { value, owned: _ })| (key.clone(), value.clone()))
+                .filter(|(_, EnvEntry { owned, .. })| *owned)
+                .map(|(key, EnvEntry { value, .. })| (key.clone(), value.clone()))
                 .collect::<HashMap<String, ValueRef>>(),
             Some(exp_set) => self
                 .env
                 .iter()
-                .filter(|(key, EnvEntry { value: _, owned })| *owned && exp_set.contains(*key))
-                .map(|(key, EnvEntry { value, owned: _ })| (key.clone(), value.clone()))
+                .filter(|(key, EnvEntry { owned, .. })| *owned && exp_set.contains(*key))
+                .map(|(key, EnvEntry { value, .. })| (key.clone(), value.clone()))
                 .collect::<HashMap<String, ValueRef>>(),
         }
     }
@@ -257,6 +659,7 @@ impl Environment {
                 EnvEntry {
                     value,
                     owned: false,
+                    constant: false,
                 },
             ); // false: value is not owned by the environment
         }
@@ -269,6 +672,7 @@ impl Environment {
                 EnvEntry {
                     value,
                     owned: false,
+                    constant: false,
                 },
             ); // false: value is not owned by the environment
         }
@@ -295,25 +699,114 @@ impl Environment {
         env.borrow_mut().set_builtin(">=", &Rc::new(Ge::new()));
         env.borrow_mut().set_builtin("<", &Rc::new(Lt::new()));
         env.borrow_mut().set_builtin("<=", &Rc::new(Le::new()));
+        env.borrow_mut().set_builtin("abs", &Rc::new(Abs::new()));
+        env.borrow_mut().set_builtin("sign", &Rc::new(Sign::new()));
+        env.borrow_mut()
+            .set_builtin("clamp", &Rc::new(Clamp::new()));
+        env.borrow_mut()
+            .set_builtin("between?", &Rc::new(Between::new()));
+        env.borrow_mut().set_builtin("sqrt", &Rc::new(Sqrt::new()));
+        env.borrow_mut().set_builtin("log", &Rc::new(Log::new()));
+        env.borrow_mut()
+            .set_builtin("atan2", &Rc::new(Atan2::new()));
+        for (name, function) in [
+            ("exp", f64::exp as fn(f64) -> f64),
+            ("sin", f64::sin),
+            ("cos", f64::cos),
+            ("tan", f64::tan),
+        ] {
+            env.borrow_mut()
+                .set_builtin(name, &Rc::new(MathFunction::new(name, function)));
+        }
+        for (name, mode) in [
+            ("floor", RoundingMode::Floor),
+            ("ceiling", RoundingMode::Ceiling),
+            ("round", RoundingMode::Round),
+            ("truncate", RoundingMode::Truncate),
+        ] {
+            env.borrow_mut()
+                .set_builtin(name, &Rc::new(Rounding::new(name, mode)));
+        }
+        for (name, mode) in [
+            ("quotient", IntDivisionMode::Quotient),
+            ("remainder", IntDivisionMode::Remainder),
+            ("modulo", IntDivisionMode::Modulo),
+        ] {
+            env.borrow_mut()
+                .set_builtin(name, &Rc::new(IntDivision::new(name, mode)));
+        }
+        env.borrow_mut().set_builtin("min", &Rc::new(Min::new()));
+        env.borrow_mut().set_builtin("max", &Rc::new(Max::new()));
+        env.borrow_mut().set_builtin("gcd", &Rc::new(Gcd::new()));
+        env.borrow_mut().set_builtin("lcm", &Rc::new(Lcm::new()));
+        env.borrow_mut()
+            .set_builtin("number->string", &Rc::new(NumberToStr::new()));
+        env.borrow_mut()
+            .set_builtin("real->string", &Rc::new(RealToStr::new()));
+        env.borrow_mut()
+            .set_builtin("string->number", &Rc::new(StrToNumber::new()));
 
         env.borrow_mut().set_builtin("not", &Rc::new(Not::new()));
         env.borrow_mut().set_builtin("nil?", &Rc::new(IsNil::new()));
 
         env.borrow_mut()
             .set_builtin("equal?", &Rc::new(IsEqual::new()));
+        env.borrow_mut()
+            .set_builtin("value-hash", &Rc::new(ValueHash::new()));
+        env.borrow_mut()
+            .set_builtin("compare", &Rc::new(Compare::new()));
+        env.borrow_mut()
+            .set_builtin("procedure?", &Rc::new(IsProcedure::new()));
+        env.borrow_mut()
+            .set_builtin("builtin?", &Rc::new(IsBuiltin::new()));
+        env.borrow_mut()
+            .set_builtin("arity", &Rc::new(Arity::new()));
+        env.borrow_mut()
+            .set_builtin("arities", &Rc::new(Arities::new()));
+        env.borrow_mut()
+            .set_builtin("lambda-name", &Rc::new(LambdaName::new()));
+        env.borrow_mut()
+            .set_builtin("lambda-params", &Rc::new(LambdaParams::new()));
 
-        let input = env.borrow().get_input().clone();
+        Self::init_input_builtins(env);
         env.borrow_mut()
-            .set_builtin("read-line", &Rc::new(ReadLine::new(&input)));
+            .set_builtin("eof-object?", &Rc::new(IsEof::new()));
 
         Self::init_output_builtins(env);
 
         Self::init_require_builtin(env);
 
+        env.borrow_mut()
+            .set_builtin("read", &Rc::new(ReadFn::new()));
+        env.borrow_mut()
+            .set_builtin("eval", &Rc::new(EvalFn::new(env)));
+        env.borrow_mut().set_builtin(
+            "current-environment",
+            &Rc::new(CurrentEnvironment::new(env)),
+        );
+        env.borrow_mut()
+            .set_builtin("env-bindings", &Rc::new(EnvBindings::new()));
+        env.borrow_mut()
+            .set_builtin("env-lookup", &Rc::new(EnvLookup::new()));
+        env.borrow_mut()
+            .set_builtin("make-environment", &Rc::new(MakeEnvironment::new(env)));
+        env.borrow_mut()
+            .set_builtin("memory-report", &Rc::new(MemoryReport::new()));
+        env.borrow_mut()
+            .set_builtin("collect-cycles", &Rc::new(CollectCycles::new()));
+        env.borrow_mut()
+            .set_builtin("runtime-stats", &Rc::new(RuntimeStatsFn::new()));
+
         env.borrow_mut()
             .set_builtin("struct-get", &Rc::new(StructGet::new()));
         env.borrow_mut()
             .set_builtin("struct-set!", &Rc::new(StructSet::new()));
+        env.borrow_mut()
+            .set_builtin("struct-with", &Rc::new(StructWith::new()));
+        env.borrow_mut()
+            .set_builtin("struct-update", &Rc::new(StructUpdate::new()));
+        env.borrow_mut()
+            .set_builtin("def-struct-printer", &Rc::new(DefStructPrinter::new()));
 
         env.borrow_mut()
             .set_builtin("create-hash-table", &Rc::new(CreateHashTable::new()));
@@ -329,6 +822,16 @@ impl Environment {
             .set_builtin("hash-set!", &Rc::new(HashSetBang::new()));
         env.borrow_mut()
             .set_builtin("hash-remove!", &Rc::new(HashRemoveBang::new()));
+        env.borrow_mut()
+            .set_builtin("hash-update!", &Rc::new(HashUpdateBang::new()));
+        env.borrow_mut()
+            .set_builtin("hash-get-or-insert!", &Rc::new(HashGetOrInsertBang::new()));
+        env.borrow_mut()
+            .set_builtin("hash-merge", &Rc::new(HashMerge::new()));
+        env.borrow_mut()
+            .set_builtin("alist->hash", &Rc::new(AlistToHash::new()));
+        env.borrow_mut()
+            .set_builtin("hash->alist", &Rc::new(HashToAlist::new()));
 
         env.borrow_mut()
             .set_builtin("create-set", &Rc::new(CreateSet::new()));
@@ -345,6 +848,52 @@ impl Environment {
 
         env.borrow_mut()
             .set_builtin("error", &Rc::new(ErrorFn::new()));
+        env.borrow_mut().set_builtin("exit", &Rc::new(Exit::new()));
+        env.borrow_mut()
+            .set_builtin("catch-error", &Rc::new(CatchError::new()));
+        env.borrow_mut()
+            .set_builtin("error?", &Rc::new(IsError::new()));
+        env.borrow_mut()
+            .set_builtin("error-kind", &Rc::new(ErrorInfo::kind()));
+        env.borrow_mut()
+            .set_builtin("error-message", &Rc::new(ErrorInfo::message()));
+        env.borrow_mut()
+            .set_builtin("error-value", &Rc::new(ErrorInfo::value()));
+        env.borrow_mut().set_builtin(
+            "with-exception-handler",
+            &Rc::new(WithExceptionHandler::new()),
+        );
+        env.borrow_mut()
+            .set_builtin("dynamic-wind", &Rc::new(DynamicWind::new()));
+
+        env.borrow_mut()
+            .set_builtin("deep-copy", &Rc::new(DeepCopy::new()));
+        env.borrow_mut()
+            .set_builtin("freeze!", &Rc::new(Freeze::new()));
+        env.borrow_mut()
+            .set_builtin("frozen?", &Rc::new(IsFrozen::new()));
+
+        env.borrow_mut()
+            .set_builtin("make-parameter", &Rc::new(MakeParameter::new()));
+        env.borrow_mut()
+            .set_builtin("call-with-parameters", &Rc::new(CallWithParameters::new()));
+        env.borrow_mut()
+            .set_builtin("number-format", &Rc::new(number_format_parameter()));
+
+        env.borrow_mut()
+            .set_builtin("boli-version", &Rc::new(BoliVersion::new()));
+        env.borrow_mut()
+            .set_builtin("features", &Rc::new(Features::new()));
+        env.borrow_mut()
+            .set_builtin("platform", &Rc::new(Platform::new()));
+    }
+
+    fn init_input_builtins(env: &EnvironmentRef) {
+        let input = env.borrow().get_input().clone();
+        env.borrow_mut()
+            .set_builtin("read-line", &Rc::new(ReadLine::new(&input)));
+        env.borrow_mut()
+            .set_builtin("read-lines", &Rc::new(ReadLines::new(&input)));
     }
 
     fn init_output_builtins(env: &EnvironmentRef) {
@@ -357,6 +906,22 @@ impl Environment {
             .set_builtin("display", &Rc::new(Display_::new(&output)));
         env.borrow_mut()
             .set_builtin("displayln", &Rc::new(DisplayLn::new(&output)));
+        env.borrow_mut()
+            .set_builtin("print", &Rc::new(Print::new(&output)));
+        env.borrow_mut()
+            .set_builtin("println", &Rc::new(PrintLn::new(&output)));
+        env.borrow_mut()
+            .set_builtin("newline", &Rc::new(NewLine::new(&output)));
+
+        let error_output = env.borrow().get_error_output();
+        env.borrow_mut()
+            .set_builtin("eprint", &Rc::new(EPrint::new(&error_output)));
+        env.borrow_mut()
+            .set_builtin("eprintln", &Rc::new(EPrintLn::new(&error_output)));
+        env.borrow_mut()
+            .set_builtin("pretty-print", &Rc::new(PrettyPrint::new(&output)));
+        env.borrow_mut()
+            .set_builtin("with-output-to-string", &Rc::new(WithOutputToString::new()));
     }
 
     fn init_require_builtin(env: &EnvironmentRef) {
@@ -0,0 +1,382 @@
+use crate::frontend::{
+    lexer::{
+        stream::Stream,
+        tokens::{Token, TokenType},
+        Lexer,
+    },
+    parser::{ast::new_astref, Parser},
+};
+
+use std::rc::Rc;
+
+use super::{
+    environment::{upgrade_env, EnvironmentBuilder, EnvironmentRef, WeakEnvironmentRef},
+    introspection::EnvironmentValue,
+    values::*,
+    Interpreter,
+};
+
+pub struct ReadFn {}
+
+impl ReadFn {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for ReadFn {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("read function expects exactly one argument");
+        }
+
+        let code = match downcast_value::<StrValue>(&args[0].borrow()) {
+            Some(code) => code.value.clone(),
+            None => return error("read function expects a string"),
+        };
+
+        let data = Parser::new()
+            .parse_data(&code)
+            .map_err(|e| InterpreterError::syntax_error(&e))?;
+
+        match data.first() {
+            Some(datum) => Interpreter::new().eval_ast(datum),
+            None => Ok(new_valueref(NilValue {})),
+        }
+    }
+}
+
+pub struct EvalFn {
+    env: WeakEnvironmentRef,
+}
+
+impl EvalFn {
+    pub fn new(env: &EnvironmentRef) -> Self {
+        Self {
+            env: Rc::downgrade(env),
+        }
+    }
+}
+
+// Code is evaluated in the environment eval is called in unless another
+// one is given
+impl Callable for EvalFn {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        self.call_in_environment(args, &upgrade_env(&self.env)?)
+    }
+
+    fn call_in_environment(
+        &self,
+        args: &Vec<ValueRef>,
+        current_env: &EnvironmentRef,
+    ) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("eval function expects one or two arguments");
+        }
+
+        let current_env = current_env.clone();
+        let env = match args.get(1) {
+            None => current_env,
+            Some(arg) => match downcast_value::<SymbolValue>(&arg.borrow()) {
+                Some(symbol) if symbol.value == "current" => current_env,
+                Some(symbol) if symbol.value == "fresh" => EnvironmentBuilder::new()
+                    .settings_from(&current_env.borrow())
+                    .with_prelude(true)
+                    .build(),
+                _ => match downcast_value::<EnvironmentValue>(&arg.borrow()) {
+                    Some(env) => env.env.clone(),
+                    None => {
+                        return error(
+                            "eval function expects 'current, 'fresh or an environment value",
+                        )
+                    }
+                },
+            },
+        };
+
+        let mut tokens = vec![];
+        value_to_tokens(&args[0], &mut tokens)?;
+
+        let program = Parser::new()
+            .parse_tokens(tokens)
+            .map_err(|e| InterpreterError::syntax_error(&e))?;
+
+        Interpreter::with_environment(&env).eval_ast(&new_astref(program))
+    }
+}
+
+// Turns data back into code: symbols become names again, lists become
+// calls and (quote x) becomes the quoted form of x
+pub fn value_to_tokens(value: &ValueRef, tokens: &mut Vec<Token>) -> Result<(), InterpreterError> {
+    data_to_tokens(value, false, tokens)
+}
+
+// Within quoted data names and lists stay data
+fn data_to_tokens(
+    value: &ValueRef,
+    quoted: bool,
+    tokens: &mut Vec<Token>,
+) -> Result<(), InterpreterError> {
+    let value = borrow_value(value);
+    let token = match value.get_type() {
+        ValueType::Nil => Token::new(TokenType::Nil, 0, 0),
+        ValueType::Bool => {
+            Token::new_bool(downcast_value::<BoolValue>(&value).unwrap().value, 0, 0)
+        }
+        ValueType::Int => Token::new_int(downcast_value::<IntValue>(&value).unwrap().value, 0, 0),
+        ValueType::Rational => {
+            let rational = downcast_value::<RationalValue>(&value).unwrap();
+            Token::new_rational(rational.numerator, rational.denominator, 0, 0)
+        }
+        ValueType::Real => {
+            Token::new_real(downcast_value::<RealValue>(&value).unwrap().value, 0, 0)
+        }
+        ValueType::Str => Token::new_str(
+            downcast_value::<StrValue>(&value)
+                .unwrap()
+                .value
+                .to_string(),
+            0,
+            0,
+        ),
+        ValueType::Symbol => {
+            let name = &downcast_value::<SymbolValue>(&value).unwrap().value;
+            return name_to_tokens(name, quoted, tokens);
+        }
+        ValueType::Quote => downcast_value::<QuoteValue>(&value).unwrap().token.clone(),
+        ValueType::Vector => {
+            let elements = &downcast_value::<VectorValue>(&value).unwrap().elements;
+            return elements_to_tokens(elements, quoted, tokens);
+        }
+        ValueType::Pair => {
+            let pair = downcast_value::<PairValue>(&value).unwrap();
+            if pair.is_list() {
+                return elements_to_tokens(&pair.get_elements(), quoted, tokens);
+            }
+            tokens.push(Token::new(TokenType::LeftParen, 0, 0));
+            data_to_tokens(&pair.left, quoted, tokens)?;
+            tokens.push(Token::new(TokenType::Dot, 0, 0));
+            data_to_tokens(&pair.right, quoted, tokens)?;
+            Token::new(TokenType::RightParen, 0, 0)
+        }
+        _ => {
+            return Err(InterpreterError::new(&format!(
+                "Cannot convert {} to code",
+                value
+            )))
+        }
+    };
+    tokens.push(token);
+
+    Ok(())
+}
+
+fn elements_to_tokens(
+    elements: &[ValueRef],
+    quoted: bool,
+    tokens: &mut Vec<Token>,
+) -> Result<(), InterpreterError> {
+    if !quoted {
+        if let Some(quoted_value) = quotation(elements) {
+            return quotation_to_tokens(&quoted_value, tokens);
+        }
+    }
+
+    tokens.push(Token::new(TokenType::LeftParen, 0, 0));
+    for element in elements {
+        data_to_tokens(element, quoted, tokens)?;
+    }
+    tokens.push(Token::new(TokenType::RightParen, 0, 0));
+
+    Ok(())
+}
+
+// The code for (quote value)
+fn quotation_to_tokens(value: &ValueRef, tokens: &mut Vec<Token>) -> Result<(), InterpreterError> {
+    let elements = {
+        let data = borrow_value(value);
+        if let Some(vector) = downcast_value::<VectorValue>(&data) {
+            Some(vector.elements.clone())
+        } else if let Some(pair) = downcast_value::<PairValue>(&data).filter(|p| p.is_list()) {
+            Some(pair.get_elements())
+        } else if let Some(name) = symbol_name(value) {
+            tokens.push(Token::new_symbol(format!("'{}", name), 0, 0));
+            return Ok(());
+        } else {
+            None
+        }
+    };
+
+    match elements {
+        Some(elements) => {
+            tokens.push(Token::new(TokenType::QuoteParen, 0, 0));
+            for element in &elements {
+                data_to_tokens(element, true, tokens)?;
+            }
+            tokens.push(Token::new(TokenType::RightParen, 0, 0));
+            Ok(())
+        }
+        // Other data evaluate to themselves
+        None => data_to_tokens(value, false, tokens),
+    }
+}
+
+// Names are scanned again, so that operators and keywords are recognized.
+// A name starting with a quote is itself quoted data.
+fn name_to_tokens(
+    name: &str,
+    quoted: bool,
+    tokens: &mut Vec<Token>,
+) -> Result<(), InterpreterError> {
+    if quoted {
+        if let Some(inner) = name.strip_prefix('\'') {
+            tokens.push(Token::new(TokenType::LeftParen, 0, 0));
+            tokens.push(Token::new_identifier("quote".to_string(), 0, 0));
+            name_to_tokens(inner, true, tokens)?;
+            tokens.push(Token::new(TokenType::RightParen, 0, 0));
+            return Ok(());
+        }
+    }
+
+    let mut lexer = Lexer::new(name);
+    let token = match (lexer.next(), lexer.next()) {
+        (Some(token), None) if token.token_type != TokenType::Error => token,
+        _ => Token::new_identifier(name.to_string(), 0, 0),
+    };
+    tokens.push(token);
+
+    Ok(())
+}
+
+fn quotation(elements: &[ValueRef]) -> Option<ValueRef> {
+    if elements.len() != 2 {
+        return None;
+    }
+
+    if symbol_name(&elements[0]) == Some("quote".to_string()) {
+        Some(elements[1].clone())
+    } else {
+        None
+    }
+}
+
+fn symbol_name(value: &ValueRef) -> Option<String> {
+    let value = borrow_value(value);
+    if let Some(symbol) = downcast_value::<SymbolValue>(&value) {
+        return Some(symbol.value.to_string());
+    }
+    downcast_value::<QuoteValue>(&value).and_then(|quote| quote.token.get_display_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_read() {
+        let mut interpreter = Interpreter::new();
+        let result = interpreter
+            .eval(r#"(read "(+ 1 (* 2 3)) ignored")"#)
+            .unwrap();
+
+        assert_eq!(result.borrow().to_string(), "(vector '+ 1 (vector '* 2 3))");
+    }
+
+    #[test]
+    fn test_eval_read_data() {
+        let code = r#"
+        (def answer 42)
+        (def (twice x) (* 2 x))
+        (vector (eval (read "(twice answer)"))
+                (eval '(if (> answer 40) "big" "small"))
+                (eval 'answer)
+                (eval (read "(def answer 0)"))
+                answer)
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "(vector 84 \"big\" 42 nil 0)");
+    }
+
+    #[test]
+    fn test_eval_fresh_environment() {
+        let code = r#"
+        (def answer 42)
+        (eval (read "answer") 'fresh)
+        "#;
+
+        let mut interpreter = Interpreter::new();
+
+        assert!(interpreter.eval(code).is_err());
+    }
+
+    #[test]
+    fn test_eval_nested_quotation() {
+        let code = r#"
+        (def (second xs) (head (tail xs)))
+        (vector '('(1 2) 3)
+                (eval '(second '(a b c))))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector (vector (vector 'quote (vector 1 2)) 3) 'b)"
+        );
+    }
+
+    #[test]
+    fn test_eval_quote_form() {
+        let code = r#"
+        (vector (eval '(quote a))
+                (eval (read "(quote a)"))
+                (eval '(quote (1 b)))
+                (eval (read "'a")))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector 'a 'a (vector 1 'b) 'a)"
+        );
+    }
+
+    #[test]
+    fn test_eval_nested_quote() {
+        let code = r#"
+        (vector ''a
+                '('a)
+                (eval ''a)
+                (eval '''a))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector (vector 'quote 'a) (vector (vector 'quote 'a)) 'a (vector 'quote 'a))"
+        );
+    }
+
+    #[test]
+    fn test_eval_built_list() {
+        let code = r#"
+        (def x 20)
+        (vector (eval (list '+ 1 2))
+                (eval (list 'if #true "yes" "no"))
+                (eval (list '- 'x 2)))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "(vector 3 \"yes\" 18)");
+    }
+}
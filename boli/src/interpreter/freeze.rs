@@ -0,0 +1,331 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use super::values::*;
+
+const MIN_PRUNE_SIZE: usize = 1024;
+
+thread_local! {
+    static FROZEN: RefCell<FrozenValues> = RefCell::new(FrozenValues::new());
+}
+
+// Frozen values are registered by address. The weak references keep the
+// allocations alive, so an address cannot be reused by another value as long
+// as its entry exists.
+struct FrozenValues {
+    values: HashMap<*const (), Weak<RefCell<dyn Value>>>,
+    prune_size: usize,
+}
+
+impl FrozenValues {
+    fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+            prune_size: MIN_PRUNE_SIZE,
+        }
+    }
+
+    fn add(&mut self, value: &ValueRef) -> bool {
+        if self.contains(value) {
+            return false;
+        }
+        self.values.insert(address(value), Rc::downgrade(value));
+        if self.values.len() >= self.prune_size {
+            self.values.retain(|_, value| value.strong_count() > 0);
+            self.prune_size = MIN_PRUNE_SIZE.max(2 * self.values.len());
+        }
+        true
+    }
+
+    fn contains(&self, value: &ValueRef) -> bool {
+        self.values
+            .get(&address(value))
+            .is_some_and(|value| value.strong_count() > 0)
+    }
+}
+
+fn address(value: &ValueRef) -> *const () {
+    Rc::as_ptr(value) as *const ()
+}
+
+pub fn is_frozen(value: &ValueRef) -> bool {
+    FROZEN.with(|frozen| frozen.borrow().contains(value))
+}
+
+// Must be called by every built-in that modifies a vector or struct in place
+pub fn check_mutable(value: &ValueRef, function: &str) -> Result<(), InterpreterError> {
+    if is_frozen(value) {
+        return Err(InterpreterError::type_error(
+            &format!("{} cannot modify frozen value {}", function, value.borrow()),
+            value,
+        ));
+    }
+    Ok(())
+}
+
+// Freezes vectors, structs, hash tables and sets including all values that
+// can be reached from them
+pub fn freeze(value: &ValueRef) {
+    let mut pending = vec![value.clone()];
+    while let Some(value) = pending.pop() {
+        let value_ref = borrow_value(&value);
+        if let Some(pair) = downcast_value::<PairValue>(&value_ref) {
+            pending.push(pair.left.clone());
+            pending.push(pair.right.clone());
+        } else if let Some(vector) = downcast_value::<VectorValue>(&value_ref) {
+            if FROZEN.with(|frozen| frozen.borrow_mut().add(&value)) {
+                pending.extend(vector.elements.iter().cloned());
+            }
+        } else if let Some(struct_value) = downcast_value::<StructValue>(&value_ref) {
+            if FROZEN.with(|frozen| frozen.borrow_mut().add(&value)) {
+                for entry in struct_value.values.values() {
+                    pending.push(entry.key.clone());
+                    pending.push(entry.value.clone());
+                }
+            }
+        }
+    }
+}
+
+// Copies pairs, vectors, structs, hash tables and sets. Values that cannot be
+// modified (numbers, strings, functions, ...) are shared. Shared structure
+// and cycles of the original are preserved, the copy is never frozen.
+pub fn deep_copy(value: &ValueRef) -> ValueRef {
+    Copier {
+        copies: HashMap::new(),
+    }
+    .copy(value)
+}
+
+struct Copier {
+    copies: HashMap<*const (), ValueRef>,
+}
+
+impl Copier {
+    fn copy(&mut self, value: &ValueRef) -> ValueRef {
+        if let Some(copy) = self.copies.get(&address(value)) {
+            return copy.clone();
+        }
+        let value_type = borrow_value(value).get_type();
+        match value_type {
+            ValueType::Pair => self.copy_list(value),
+            ValueType::Vector => self.copy_vector(value),
+            ValueType::Struct => self.copy_struct(value),
+            _ => value.clone(),
+        }
+    }
+
+    // Lists are copied iteratively to support long lists
+    fn copy_list(&mut self, value: &ValueRef) -> ValueRef {
+        let mut elements = Vec::new();
+        let mut current = value.clone();
+        loop {
+            let next = match downcast_value::<PairValue>(&borrow_value(&current)) {
+                Some(pair) => {
+                    elements.push(pair.left.clone());
+                    pair.right.clone()
+                }
+                None => break,
+            };
+            current = next;
+        }
+
+        let mut ret = self.copy(&current);
+        for element in elements.iter().rev() {
+            let element = self.copy(element);
+            ret = new_valueref(PairValue::new(&element, &ret));
+        }
+        ret
+    }
+
+    fn copy_vector(&mut self, value: &ValueRef) -> ValueRef {
+        let elements = match downcast_value::<VectorValue>(&borrow_value(value)) {
+            Some(vector) => vector.elements.clone(),
+            None => return value.clone(),
+        };
+        let ret = new_valueref(VectorValue { elements: vec![] });
+        self.copies.insert(address(value), ret.clone());
+
+        let elements = elements
+            .iter()
+            .map(|element| self.copy(element))
+            .collect::<Vec<_>>();
+        if let Some(vector) = borrow_mut_value(&ret)
+            .as_any_mut()
+            .downcast_mut::<VectorValue>()
+        {
+            vector.elements = elements;
+        }
+        ret
+    }
+
+    fn copy_struct(&mut self, value: &ValueRef) -> ValueRef {
+        let (copy, entries) = match downcast_value::<StructValue>(&borrow_value(value)) {
+            Some(struct_value) => {
+                let copy = match &struct_value.struct_type {
+                    Some(struct_type) => StructValue::new(struct_type, HashMap::new()),
+                    None if struct_value.is_set() => StructValue::new_set(),
+                    None => StructValue::new_hash_table(),
+                };
+                let entries = struct_value
+                    .values
+                    .iter()
+                    .map(|(name, entry)| (name.clone(), entry.key.clone(), entry.value.clone()))
+                    .collect::<Vec<_>>();
+                (copy, entries)
+            }
+            None => return value.clone(),
+        };
+        let ret = new_valueref(copy);
+        self.copies.insert(address(value), ret.clone());
+
+        let entries = entries
+            .iter()
+            .map(|(name, key, value)| {
+                let entry = StructEntry {
+                    key: self.copy(key),
+                    value: self.copy(value),
+                };
+                (name.clone(), entry)
+            })
+            .collect::<HashMap<_, _>>();
+        if let Some(struct_value) = borrow_mut_value(&ret)
+            .as_any_mut()
+            .downcast_mut::<StructValue>()
+        {
+            struct_value.values = entries;
+        }
+        ret
+    }
+}
+
+pub struct DeepCopy {}
+
+impl DeepCopy {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for DeepCopy {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("deep-copy function expects exactly one argument");
+        }
+        Ok(deep_copy(&args[0]))
+    }
+}
+
+pub struct Freeze {}
+
+impl Freeze {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Freeze {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("freeze! function expects exactly one argument");
+        }
+        freeze(&args[0]);
+        Ok(args[0].clone())
+    }
+}
+
+pub struct IsFrozen {}
+
+impl IsFrozen {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsFrozen {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("frozen? function expects exactly one argument");
+        }
+        Ok(new_valueref(BoolValue {
+            value: is_frozen(&args[0]),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_deep_copy() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def inner (vector 1 2))
+            (def outer (vector inner inner (list inner 3)))
+            (def copy (deep-copy outer))
+            (vector-set! (vector-ref copy 0) 0 42)
+            (list outer copy)
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(list (vector (vector 1 2) (vector 1 2) (list (vector 1 2) 3)) \
+             (vector (vector 42 2) (vector 42 2) (list (vector 42 2) 3)))"
+        );
+
+        // Cycles are copied as cycles
+        let code = r#"
+            (def v (vector 1 2))
+            (vector-set! v 1 v)
+            (def c (deep-copy v))
+            (vector-set! c 0 99)
+            (list (vector-ref v 0) (vector-ref (vector-ref c 1) 0))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(list 1 99)");
+
+        let code = r#"
+            (def-struct point (x y))
+            (def h (create-hash-table))
+            (hash-set! h 'p (create-point 1 2))
+            (def c (deep-copy h))
+            (point-set-x! (hash-get c 'p) 10)
+            (list (point-x (hash-get h 'p)) (point-x (hash-get c 'p)))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(list 1 10)");
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def-struct point (x y))
+            (def v (freeze! (vector (create-point 1 2) (create-hash-table))))
+            (list (frozen? v) (frozen? (vector-ref v 0)) (frozen? (deep-copy v)))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(list #true #true #false)");
+
+        for code in [
+            "(vector-set! v 0 1)",
+            "(vector-remove! v 0)",
+            "(point-set-x! (vector-ref v 0) 3)",
+            "(hash-set! (vector-ref v 1) 'a 1)",
+        ] {
+            let err = interpreter.eval(code).unwrap_err();
+            assert!(
+                err.message.contains("cannot modify frozen value"),
+                "{}",
+                code
+            );
+        }
+
+        let result = interpreter
+            .eval("(def c (deep-copy v)) (vector-set! c 0 1) (vector-ref c 0)")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "1");
+    }
+}
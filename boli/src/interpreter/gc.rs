@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::environment::{Environment, EnvironmentRef, WeakEnvironmentRef};
+use super::struct_functions::get_key;
+use super::values::*;
+
+const MIN_PRUNE_SIZE: usize = 1024;
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::new());
+}
+
+struct Registry {
+    environments: Vec<WeakEnvironmentRef>,
+    prune_size: usize,
+}
+
+impl Registry {
+    fn new() -> Self {
+        Self {
+            environments: Vec::new(),
+            prune_size: MIN_PRUNE_SIZE,
+        }
+    }
+
+    fn add(&mut self, env: &EnvironmentRef) {
+        self.environments.push(Rc::downgrade(env));
+        if self.environments.len() >= self.prune_size {
+            self.environments.retain(|env| env.strong_count() > 0);
+            self.prune_size = MIN_PRUNE_SIZE.max(2 * self.environments.len());
+        }
+    }
+
+    fn live_environments(&self) -> Vec<EnvironmentRef> {
+        self.environments
+            .iter()
+            .filter_map(|env| env.upgrade())
+            .collect()
+    }
+}
+
+// Only root environments and environments captured by lambdas are
+// registered: every reference cycle runs through a lambda and the
+// environment it captured, call frames that are not captured are never
+// part of one.
+pub fn register_environment(env: &EnvironmentRef) {
+    let registered = match env.try_borrow() {
+        Ok(env) => env.mark_gc_registered(),
+        Err(_) => false,
+    };
+    if !registered {
+        REGISTRY.with(|registry| registry.borrow_mut().add(env));
+    }
+}
+
+pub fn live_environments() -> usize {
+    REGISTRY.with(|registry| {
+        registry
+            .borrow()
+            .environments
+            .iter()
+            .filter(|env| env.strong_count() > 0)
+            .count()
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Node {
+    Env(usize),
+    Lambda(usize),
+}
+
+// Finds environments that are only kept alive by reference cycles between
+// environments and the lambdas defined in them. Strong references that are
+// not edges of this graph (Rust locals, vectors, hash tables, ...) make a
+// node a root, so unknown references only ever keep values alive.
+pub fn find_unreachable_environments() -> Vec<EnvironmentRef> {
+    let environments = REGISTRY.with(|registry| registry.borrow().live_environments());
+    let env_index: HashMap<*const RefCell<Environment>, usize> = environments
+        .iter()
+        .enumerate()
+        .map(|(i, env)| (Rc::as_ptr(env), i))
+        .collect();
+
+    let mut lambdas: Vec<ValueRef> = Vec::new();
+    let mut lambda_index: HashMap<*const (), usize> = HashMap::new();
+    let mut env_edges: Vec<Vec<Node>> = vec![Vec::new(); environments.len()];
+    let mut pinned = vec![false; environments.len()];
+
+    for (i, env) in environments.iter().enumerate() {
+        let Ok(env) = env.try_borrow() else {
+            pinned[i] = true;
+            continue;
+        };
+        if let Some(parent) = env.get_parent() {
+            if let Some(p) = env_index.get(&Rc::as_ptr(&parent)) {
+                env_edges[i].push(Node::Env(*p));
+            }
+        }
+        let values = env.env.values().map(|entry| entry.get_value());
+        for value in values.chain(env.method_values()) {
+            let is_lambda = value
+                .try_borrow()
+                .map(|v| v.get_type() == ValueType::Lambda)
+                .unwrap_or(false);
+            if !is_lambda {
+                continue;
+            }
+            let key = Rc::as_ptr(&value) as *const ();
+            let l = *lambda_index.entry(key).or_insert_with(|| {
+                lambdas.push(value.clone());
+                lambdas.len() - 1
+            });
+            env_edges[i].push(Node::Lambda(l));
+        }
+    }
+
+    let lambda_edges: Vec<Option<usize>> = lambdas
+        .iter()
+        .map(|lambda| {
+            let lambda = lambda.try_borrow().ok()?;
+            let lambda = downcast_value::<LambdaValue>(&lambda)?;
+            env_index.get(&Rc::as_ptr(&lambda.env)).copied()
+        })
+        .collect();
+
+    // Strong references not accounted for by the graph edges
+    let mut env_refs: Vec<isize> = environments
+        .iter()
+        .map(|env| Rc::strong_count(env) as isize - 1)
+        .collect();
+    let mut lambda_refs: Vec<isize> = lambdas
+        .iter()
+        .map(|lambda| Rc::strong_count(lambda) as isize - 1)
+        .collect();
+
+    for edges in &env_edges {
+        for edge in edges {
+            match edge {
+                Node::Env(e) => env_refs[*e] -= 1,
+                Node::Lambda(l) => lambda_refs[*l] -= 1,
+            }
+        }
+    }
+    for e in lambda_edges.iter().flatten() {
+        env_refs[*e] -= 1;
+    }
+
+    let mut env_reachable = vec![false; environments.len()];
+    let mut lambda_reachable = vec![false; lambdas.len()];
+    let mut pending: Vec<Node> = Vec::new();
+
+    for (i, refs) in env_refs.iter().enumerate() {
+        if *refs > 0 || pinned[i] {
+            pending.push(Node::Env(i));
+        }
+    }
+    for (l, refs) in lambda_refs.iter().enumerate() {
+        if *refs > 0 {
+            pending.push(Node::Lambda(l));
+        }
+    }
+
+    while let Some(node) = pending.pop() {
+        match node {
+            Node::Env(e) => {
+                if !env_reachable[e] {
+                    env_reachable[e] = true;
+                    pending.extend(env_edges[e].iter().copied());
+                }
+            }
+            Node::Lambda(l) => {
+                if !lambda_reachable[l] {
+                    lambda_reachable[l] = true;
+                    if let Some(e) = lambda_edges[l] {
+                        pending.push(Node::Env(e));
+                    }
+                }
+            }
+        }
+    }
+
+    environments
+        .into_iter()
+        .zip(env_reachable)
+        .filter(|(_, reachable)| !reachable)
+        .map(|(env, _)| env)
+        .collect()
+}
+
+pub fn collect_cycles() -> usize {
+    let unreachable = find_unreachable_environments();
+    for env in &unreachable {
+        env.borrow_mut().clear();
+    }
+    unreachable.len()
+}
+
+pub struct MemoryReport {}
+
+impl MemoryReport {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for MemoryReport {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("memory-report function expects no arguments");
+        }
+
+        let mut report = StructValue::new_hash_table();
+        let entries = [
+            ("environments", live_environments()),
+            ("unreachable", find_unreachable_environments().len()),
+        ];
+        for (name, count) in entries {
+            let key = new_valueref(SymbolValue::new(name));
+            let value = new_valueref(IntValue {
+                value: count as i64,
+            });
+            report
+                .values
+                .insert(get_key(&key), StructEntry { key, value });
+        }
+
+        Ok(new_valueref(report))
+    }
+}
+
+pub struct CollectCycles {}
+
+impl CollectCycles {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for CollectCycles {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("collect-cycles function expects no arguments");
+        }
+
+        Ok(new_valueref(IntValue {
+            value: collect_cycles() as i64,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_call_frames_are_released() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("(def (inc x) (+ x 1))").unwrap();
+        interpreter.eval("(inc 1)").unwrap();
+
+        let before = live_environments();
+        interpreter.eval("(inc (inc (inc 1)))").unwrap();
+        assert_eq!(live_environments(), before);
+    }
+
+    #[test]
+    fn test_frames_with_generic_functions_are_released() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval(
+                r#"
+                (def-struct shape (name))
+                (def (measure)
+                    (def-method (area shape) 1)
+                    (area (create-shape "s")))
+                (measure)
+                "#,
+            )
+            .unwrap();
+
+        // The method captures the call frame it is defined in
+        collect_cycles();
+        let before = live_environments();
+        interpreter.eval("(measure)").unwrap();
+        assert_eq!(collect_cycles(), 1);
+        assert_eq!(live_environments(), before);
+    }
+
+    #[test]
+    fn test_collect_cycles() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(def (make) (def (inner) inner) inner)")
+            .unwrap();
+        let kept = interpreter.eval("(def kept (make))");
+        assert!(kept.is_ok());
+
+        interpreter.eval("(make)").unwrap();
+        let report = interpreter.eval("(hash-get (memory-report) 'unreachable)");
+        // Only the scope captured by inner is registered, the call frame
+        // of make is released together with it
+        assert_eq!(report.unwrap().borrow().to_string(), "1");
+
+        let before = live_environments();
+        let collected = interpreter.eval("(collect-cycles)").unwrap();
+        assert_eq!(collected.borrow().to_string(), "1");
+        assert_eq!(live_environments(), before - 1);
+
+        let result = interpreter.eval("(kept)");
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,572 @@
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+
+use super::environment::{upgrade_env, EnvironmentBuilder, EnvironmentRef, WeakEnvironmentRef};
+use super::struct_functions::get_key;
+use super::values::*;
+
+fn list_of(elements: Vec<ValueRef>) -> ValueRef {
+    let mut list = new_valueref(NilValue {});
+    for element in elements.iter().rev() {
+        list = new_valueref(PairValue::new(element, &list));
+    }
+    list
+}
+
+fn is_function(value: &ValueRef) -> bool {
+    matches!(
+        value.borrow().get_type(),
+        ValueType::Lambda | ValueType::BuiltInFunction
+    )
+}
+
+fn expect_function(value: &ValueRef, function: &str) -> Result<(), InterpreterError> {
+    if !is_function(value) {
+        return Err(InterpreterError::type_error(
+            &format!("{} expects a function, got {}", function, value.borrow()),
+            value,
+        ));
+    }
+    Ok(())
+}
+
+fn known_arities(value: &ValueRef) -> Option<(Vec<usize>, bool)> {
+    let value = borrow_value(value);
+    if let Some(lambda) = downcast_value::<LambdaValue>(&value) {
+        return lambda.known_arities();
+    }
+    downcast_value::<BuiltInFunctionValue>(&value)?.known_arities()
+}
+
+// Fixed numbers of arguments are described by integers, the smallest number
+// of arguments n of a variadic function by (list 'at-least n)
+fn describe_arities(arities: &[usize], variadic: bool) -> Vec<ValueRef> {
+    let int_value = |value: usize| {
+        new_valueref(IntValue {
+            value: value as i64,
+        })
+    };
+    let mut elements: Vec<ValueRef> = arities.iter().copied().map(int_value).collect();
+    if variadic {
+        if let Some(at_least) = elements.pop() {
+            let at_least = vec![new_valueref(SymbolValue::new("at-least")), at_least];
+            elements.push(list_of(at_least));
+        }
+    }
+    elements
+}
+
+pub struct IsProcedure {}
+
+impl IsProcedure {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsProcedure {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("procedure? function expects exactly one argument");
+        }
+        Ok(new_valueref(BoolValue {
+            value: is_function(&args[0]),
+        }))
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
+pub struct IsBuiltin {}
+
+impl IsBuiltin {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsBuiltin {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("builtin? function expects exactly one argument");
+        }
+        Ok(new_valueref(BoolValue {
+            value: args[0].borrow().get_type() == ValueType::BuiltInFunction,
+        }))
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
+pub struct Arities {}
+
+impl Arities {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+// Returns the numbers of arguments a function accepts as a list, or nil if
+// they are not known (as for most built-in functions)
+impl Callable for Arities {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("arities function expects exactly one argument");
+        }
+        expect_function(&args[0], "arities")?;
+
+        Ok(match known_arities(&args[0]) {
+            Some((arities, variadic)) => list_of(describe_arities(&arities, variadic)),
+            None => new_valueref(NilValue {}),
+        })
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
+pub struct Arity {}
+
+impl Arity {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+// Like arities, but a function with a single arity is described by the
+// arity itself instead of a list
+impl Callable for Arity {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("arity function expects exactly one argument");
+        }
+        expect_function(&args[0], "arity")?;
+
+        let Some((arities, variadic)) = known_arities(&args[0]) else {
+            return Ok(new_valueref(NilValue {}));
+        };
+        let mut elements = describe_arities(&arities, variadic);
+        if elements.len() == 1 {
+            return Ok(elements.pop().unwrap());
+        }
+        Ok(list_of(elements))
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
+pub struct LambdaName {}
+
+impl LambdaName {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+// Anonymous lambdas have no name (nil)
+impl Callable for LambdaName {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("lambda-name function expects exactly one argument");
+        }
+        expect_function(&args[0], "lambda-name")?;
+
+        let value = borrow_value(&args[0]);
+        let name = match downcast_value::<LambdaValue>(&value) {
+            Some(lambda) => lambda.name.clone(),
+            None => downcast_value::<BuiltInFunctionValue>(&value).map(|f| f.name.clone()),
+        };
+        Ok(match name {
+            Some(name) => new_valueref(StrValue { value: name.into() }),
+            None => new_valueref(NilValue {}),
+        })
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
+pub struct LambdaParams {}
+
+impl LambdaParams {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn parameter_list(parameters: &[String], variadic: Option<&String>) -> ValueRef {
+        let elements = parameters
+            .iter()
+            .map(|parameter| new_valueref(SymbolValue::new(parameter)))
+            .chain(
+                variadic
+                    .map(|variadic| new_valueref(SymbolValue::new(&format!("{}...", variadic)))),
+            )
+            .collect();
+        list_of(elements)
+    }
+}
+
+// Returns the parameter names of a lambda as a list of symbols, the variadic
+// parameter with a trailing "...". For a lambda with several arities the
+// result is a list of parameter lists ordered by arity. The parameters of
+// built-in functions are not known (nil).
+impl Callable for LambdaParams {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("lambda-params function expects exactly one argument");
+        }
+        expect_function(&args[0], "lambda-params")?;
+
+        let value = borrow_value(&args[0]);
+        let Some(lambda) = downcast_value::<LambdaValue>(&value) else {
+            return Ok(new_valueref(NilValue {}));
+        };
+        match &lambda.variant {
+            LambdaVariant::Variadic(parameters, variadic, _) => {
+                Ok(Self::parameter_list(parameters, Some(variadic)))
+            }
+            LambdaVariant::Arities(arities) => {
+                let mut lists = arities.iter().collect::<Vec<_>>();
+                lists.sort_by_key(|(arity, _)| **arity);
+                let mut lists = lists
+                    .into_iter()
+                    .map(|(_, (parameters, _))| Self::parameter_list(parameters, None))
+                    .collect::<Vec<_>>();
+                if lists.len() == 1 {
+                    return Ok(lists.pop().unwrap());
+                }
+                Ok(list_of(lists))
+            }
+        }
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
+// First-class environments give code access to the scopes of the program.
+// They can be passed to eval as the environment to evaluate code in.
+pub struct EnvironmentValue {
+    pub env: EnvironmentRef,
+}
+
+impl EnvironmentValue {
+    pub fn new(env: &EnvironmentRef) -> Self {
+        Self { env: env.clone() }
+    }
+}
+
+impl Value for EnvironmentValue {
+    fn get_type(&self) -> ValueType {
+        ValueType::Environment
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Display for EnvironmentValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<environment {}>", self.env.borrow().env.len())
+    }
+}
+
+impl Debug for EnvironmentValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+pub fn environment_arg(
+    value: &ValueRef,
+    function: &str,
+) -> Result<EnvironmentRef, InterpreterError> {
+    match downcast_value::<EnvironmentValue>(&borrow_value(value)) {
+        Some(env) => Ok(env.env.clone()),
+        None => Err(InterpreterError::type_error(
+            &format!(
+                "{} expects an environment, got {}",
+                function,
+                value.borrow()
+            ),
+            value,
+        )),
+    }
+}
+
+pub struct CurrentEnvironment {
+    env: WeakEnvironmentRef,
+}
+
+impl CurrentEnvironment {
+    pub fn new(env: &EnvironmentRef) -> Self {
+        Self {
+            env: Rc::downgrade(env),
+        }
+    }
+}
+
+// Returns the environment current-environment is called in. If it is called
+// indirectly (e.g. by apply) the global environment is returned.
+impl Callable for CurrentEnvironment {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        self.call_in_environment(args, &upgrade_env(&self.env)?)
+    }
+
+    fn call_in_environment(&self, args: &Vec<ValueRef>, env: &EnvironmentRef) -> EvalResult {
+        if !args.is_empty() {
+            return error("current-environment function expects no arguments");
+        }
+        Ok(new_valueref(EnvironmentValue::new(env)))
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![0], false))
+    }
+}
+
+pub struct EnvBindings {}
+
+impl EnvBindings {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+// Returns the bindings of the environment itself (not those of its parents)
+// as a hash table with symbols as keys
+impl Callable for EnvBindings {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("env-bindings function expects exactly one argument");
+        }
+        let env = environment_arg(&args[0], "env-bindings")?;
+
+        let mut bindings = StructValue::new_hash_table();
+        for (name, entry) in &env.borrow().env {
+            let key = new_valueref(SymbolValue::new(name));
+            bindings.values.insert(
+                get_key(&key),
+                StructEntry {
+                    key,
+                    value: entry.get_value(),
+                },
+            );
+        }
+        Ok(new_valueref(bindings))
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
+pub struct EnvLookup {}
+
+impl EnvLookup {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+// Looks up a name in the environment and its parents. Without a default
+// value an unbound name is an error.
+impl Callable for EnvLookup {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 && args.len() != 3 {
+            return error("env-lookup function expects two or three arguments");
+        }
+        let env = environment_arg(&args[0], "env-lookup")?;
+        let name = match downcast_value::<SymbolValue>(&borrow_value(&args[1])) {
+            Some(symbol) => symbol.value.clone(),
+            None => {
+                return Err(InterpreterError::type_error(
+                    "env-lookup expects a symbol as name",
+                    &args[1],
+                ))
+            }
+        };
+
+        let value = env.borrow().get(&name);
+        match (value, args.get(2)) {
+            (Some(value), _) => Ok(value),
+            (None, Some(default)) => Ok(default.clone()),
+            (None, None) => Err(InterpreterError::name_error(&name)),
+        }
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![2, 3], false))
+    }
+}
+
+pub struct MakeEnvironment {
+    env: WeakEnvironmentRef,
+}
+
+impl MakeEnvironment {
+    pub fn new(env: &EnvironmentRef) -> Self {
+        Self {
+            env: Rc::downgrade(env),
+        }
+    }
+}
+
+// Creates an empty environment whose names are looked up in the parent
+// environment as well. Without a parent the new environment only contains
+// the prelude.
+impl Callable for MakeEnvironment {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        self.call_in_environment(args, &upgrade_env(&self.env)?)
+    }
+
+    fn call_in_environment(
+        &self,
+        args: &Vec<ValueRef>,
+        current_env: &EnvironmentRef,
+    ) -> EvalResult {
+        let env = match args.as_slice() {
+            [] => {
+                let env = current_env.borrow();
+                EnvironmentBuilder::new()
+                    .settings_from(&env)
+                    .with_prelude(true)
+                    .build()
+            }
+            [parent] => {
+                let parent = environment_arg(parent, "make-environment")?;
+                EnvironmentBuilder::new().parent(&parent).build()
+            }
+            _ => return error("make-environment function expects zero or one argument"),
+        };
+        Ok(new_valueref(EnvironmentValue::new(&env)))
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![0, 1], false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{settings::Settings, Interpreter};
+
+    #[test]
+    fn test_arities() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def (f x) x)
+            (def (f x y) y)
+            (def (g x rest...) x)
+            (def (h) 1)
+            (def (h) 2)
+            (list (arities f) (arities g) (arities (λ () 1)) (arities +) (h))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(list (list 1 2) (list (list 'at-least 1)) (list 0) nil 2)"
+        );
+        assert!(interpreter.eval("(arities 1)").is_err());
+
+        // A variadic definition replaces all others
+        let result = interpreter
+            .eval("(def (f xs...) xs) (list (arities f) (f 1 2 3))")
+            .unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(list (list (list 'at-least 0)) (vector 1 2 3))"
+        );
+
+        interpreter.set_settings(Settings::new().strict(true));
+        let result = interpreter.eval("(def (f x y) x)");
+        let added = interpreter.eval("(def (k) 1) (def (k x) x) (arities k)");
+        assert_eq!(
+            result.unwrap_err().message,
+            "redefinition of f replaces its definition for at least 0 arguments"
+        );
+        assert_eq!(added.unwrap().borrow().to_string(), "(list 0 1)");
+    }
+
+    #[test]
+    fn test_function_introspection() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def-struct point (x y))
+            (def (f x) x)
+            (def (f x y) y)
+            (def (g x rest...) x)
+            (list
+                (map procedure? (list f + (λ (a) a) 'f point-x))
+                (map builtin? (list f + 1))
+                (map arity (list f g (λ (a) a) create-point point-set-x! +))
+                (map lambda-name (list f + (λ (a) a)))
+                (map lambda-params (list f g (λ () 1) +)))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(list (vector #true #true #true #false #true) \
+             (vector #false #true #false) \
+             (vector (list 1 2) (list 'at-least 1) 1 2 2 nil) \
+             (vector \"f\" \"+\" nil) \
+             (vector (list (list 'x) (list 'x 'y)) (list 'x 'rest...) nil nil))"
+        );
+
+        let err = interpreter.eval("(lambda-params 42)").unwrap_err();
+        assert_eq!(err.message, "lambda-params expects a function, got 42");
+    }
+
+    #[test]
+    fn test_environments() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def answer 42)
+            (def (scope x)
+                (def y (* x 2))
+                (current-environment))
+            (def env (scope 21))
+            (def bindings (env-bindings env))
+            (list
+                (env-lookup env 'y)
+                (env-lookup env 'answer)
+                (env-lookup env 'missing 'none)
+                (hash-get bindings 'y)
+                (hash-contains? bindings 'answer)
+                (eval '(+ x y) env))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(list 42 42 'none 42 #false 63)"
+        );
+
+        // Definitions in a child environment do not affect its parent
+        let code = r#"
+            (def child (make-environment (current-environment)))
+            (eval '(def answer 0) child)
+            (list (env-lookup child 'answer) answer (env-lookup (make-environment) 'answer 'unbound))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(list 0 42 'unbound)");
+
+        let err = interpreter.eval("(env-lookup env 'missing)").unwrap_err();
+        assert_eq!(err.message, "Undefined identifier: missing");
+        let err = interpreter.eval("(env-bindings 1)").unwrap_err();
+        assert_eq!(err.message, "env-bindings expects an environment, got 1");
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use super::values::{EvalResult, InterpreterError};
+
+// Limits protect embedders from runaway scripts. They apply to each
+// evaluation separately, exceeding one of them aborts the evaluation with
+// an error.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    // Number of evaluated expressions
+    pub max_steps: Option<u64>,
+    // Number of nested function calls, tail calls do not count
+    pub max_call_depth: Option<usize>,
+    // Number of values that are allocated
+    pub max_heap_values: Option<u64>,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = Some(max_call_depth);
+        self
+    }
+
+    pub fn max_heap_values(mut self, max_heap_values: u64) -> Self {
+        self.max_heap_values = Some(max_heap_values);
+        self
+    }
+}
+
+thread_local! {
+    static ALLOCATED_VALUES: Cell<u64> = const { Cell::new(0) };
+    static ACTIVE_BUDGET: RefCell<Option<Rc<Budget>>> = const { RefCell::new(None) };
+}
+
+pub fn count_allocation() {
+    ALLOCATED_VALUES.with(|count| count.set(count.get() + 1));
+}
+
+fn allocated_values() -> u64 {
+    ALLOCATED_VALUES.with(|count| count.get())
+}
+
+// What is left of the limits of the running evaluation
+pub struct Budget {
+    limits: Limits,
+    steps: Cell<u64>,
+    call_depth: Cell<usize>,
+    allocated_at_start: u64,
+}
+
+impl Budget {
+    fn new(limits: &Limits) -> Self {
+        Self {
+            limits: *limits,
+            steps: Cell::new(0),
+            call_depth: Cell::new(0),
+            allocated_at_start: allocated_values(),
+        }
+    }
+
+    pub fn current() -> Option<Rc<Budget>> {
+        ACTIVE_BUDGET.with(|budget| budget.borrow().clone())
+    }
+
+    pub fn step(&self) -> Result<(), InterpreterError> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(max_steps) = self.limits.max_steps {
+            if steps > max_steps {
+                return Err(limit_exceeded("evaluation step", max_steps));
+            }
+        }
+        if let Some(max_heap_values) = self.limits.max_heap_values {
+            if allocated_values() - self.allocated_at_start > max_heap_values {
+                return Err(limit_exceeded("heap value", max_heap_values));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn enter_call(&self) -> Result<(), InterpreterError> {
+        let call_depth = self.call_depth.get() + 1;
+        if let Some(max_call_depth) = self.limits.max_call_depth {
+            if call_depth > max_call_depth {
+                return Err(limit_exceeded("call depth", max_call_depth as u64));
+            }
+        }
+        self.call_depth.set(call_depth);
+        Ok(())
+    }
+
+    pub fn leave_call(&self) {
+        self.call_depth.set(self.call_depth.get() - 1);
+    }
+}
+
+fn limit_exceeded(limit: &str, max: u64) -> InterpreterError {
+    InterpreterError::new(&format!("{} limit of {} exceeded", limit, max))
+}
+
+struct ActiveBudget {}
+
+impl Drop for ActiveBudget {
+    fn drop(&mut self) {
+        ACTIVE_BUDGET.with(|budget| budget.borrow_mut().take());
+    }
+}
+
+// Runs f with a fresh budget for the limits. Evaluations that are started
+// while another one is running share its budget.
+pub fn with_limits<F: FnOnce() -> EvalResult>(limits: Option<Limits>, f: F) -> EvalResult {
+    match limits {
+        Some(limits) if Budget::current().is_none() => {
+            ACTIVE_BUDGET.with(|budget| *budget.borrow_mut() = Some(Rc::new(Budget::new(&limits))));
+            let _active_budget = ActiveBudget {};
+            f()
+        }
+        _ => f(),
+    }
+}
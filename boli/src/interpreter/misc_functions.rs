@@ -1,11 +1,12 @@
 use std::{
-    cell::{Ref, RefCell},
+    cell::RefCell,
+    collections::VecDeque,
     fmt::Debug,
     io::{stdout, Write},
     rc::Rc,
 };
 
-use super::values::*;
+use super::{environment::EnvironmentRef, stream::StreamValue, values::*};
 
 pub struct IsEqual {}
 
@@ -27,27 +28,65 @@ impl Callable for IsEqual {
             return error("equal? function expects exactly two arguments");
         }
 
-        let arg0 = borrow_value(&args[0]);
-        let arg0 = downcast_compareable_eq(&arg0).ok_or(InterpreterError::new(
-            "equal? function expects a comparable value as the first argument",
-        ))?;
-
-        let is_equal = arg0.is_equal(&args[1]);
+        let is_equal = values_equal(&args[0], &args[1]);
         Ok(new_valueref(BoolValue { value: is_equal }))
     }
 }
 
-fn downcast_compareable_eq<'a>(value: &'a Ref<dyn Value>) -> Option<&'a dyn ComparableEq> {
-    match value.get_type() {
-        ValueType::Str => Some(value.as_any().downcast_ref::<StrValue>().unwrap()),
-        ValueType::Int => Some(value.as_any().downcast_ref::<IntValue>().unwrap()),
-        ValueType::Bool => Some(value.as_any().downcast_ref::<BoolValue>().unwrap()),
-        _ => None,
+pub struct ValueHash {}
+
+impl ValueHash {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for ValueHash {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("value-hash function expects exactly one argument");
+        }
+
+        Ok(new_valueref(IntValue {
+            value: value_hash(&args[0]) as i64,
+        }))
+    }
+}
+
+pub struct Compare {}
+
+impl Compare {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Compare {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("compare function expects exactly two arguments");
+        }
+
+        let value = match compare_values(&args[0], &args[1]) {
+            Some(ordering) => ordering as i64,
+            None => {
+                return Err(InterpreterError::type_error(
+                    &format!(
+                        "compare expects comparable values, got {} and {}",
+                        args[0].borrow(),
+                        args[1].borrow()
+                    ),
+                    &args[1],
+                ))
+            }
+        };
+        Ok(new_valueref(IntValue { value }))
     }
 }
 
 pub trait Input: Debug {
-    fn read_line(&mut self) -> String;
+    // Returns None at the end of the input
+    fn read_line(&mut self) -> Option<String>;
     fn as_any(&self) -> &dyn std::any::Any;
 }
 
@@ -69,10 +108,35 @@ impl Default for StdInput {
 }
 
 impl Input for StdInput {
-    fn read_line(&mut self) -> String {
+    fn read_line(&mut self) -> Option<String> {
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        input.trim_end().to_string()
+        match std::io::stdin().read_line(&mut input) {
+            Ok(n) if n > 0 => Some(input.trim_end_matches(['\n', '\r']).to_string()),
+            _ => None,
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StringInput {
+    lines: VecDeque<String>,
+}
+
+impl StringInput {
+    pub fn new(text: &str) -> Self {
+        Self {
+            lines: text.lines().map(|line| line.to_string()).collect(),
+        }
+    }
+}
+
+impl Input for StringInput {
+    fn read_line(&mut self) -> Option<String> {
+        self.lines.pop_front()
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
@@ -80,6 +144,36 @@ impl Input for StdInput {
     }
 }
 
+thread_local! {
+    static REDIRECTED_INPUTS: RefCell<Vec<InputRef>> = const { RefCell::new(Vec::new()) };
+}
+
+// Like current_output: a redirection of the input (e.g. by
+// with-input-from-socket) takes precedence over the environment's input
+pub fn current_input(input: &InputRef) -> InputRef {
+    REDIRECTED_INPUTS
+        .with(|inputs| inputs.borrow().last().cloned())
+        .unwrap_or_else(|| input.clone())
+}
+
+struct InputRedirection {}
+
+impl Drop for InputRedirection {
+    fn drop(&mut self) {
+        REDIRECTED_INPUTS.with(|inputs| inputs.borrow_mut().pop());
+    }
+}
+
+pub fn with_redirected_input<F: FnOnce() -> EvalResult>(input: &InputRef, f: F) -> EvalResult {
+    REDIRECTED_INPUTS.with(|inputs| inputs.borrow_mut().push(input.clone()));
+    let _redirection = InputRedirection {};
+    f()
+}
+
+fn line_value(line: String) -> ValueRef {
+    new_valueref(StrValue { value: line.into() })
+}
+
 pub struct ReadLine {
     input: InputRef,
 }
@@ -93,10 +187,58 @@ impl ReadLine {
 }
 
 impl Callable for ReadLine {
-    fn call(&self, _args: &Vec<ValueRef>) -> EvalResult {
-        Ok(new_valueref(StrValue {
-            value: self.input.borrow_mut().read_line(),
-        }))
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("read-line function expects no arguments");
+        }
+        match current_input(&self.input).borrow_mut().read_line() {
+            Some(line) => Ok(line_value(line)),
+            None => Ok(new_valueref(EofValue {})),
+        }
+    }
+}
+
+// Lines are read when the stream is traversed, so input that is piped into
+// a script does not have to fit into memory
+pub struct ReadLines {
+    input: InputRef,
+}
+
+impl ReadLines {
+    pub fn new(input: &InputRef) -> Self {
+        Self {
+            input: input.clone(),
+        }
+    }
+}
+
+impl Callable for ReadLines {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("read-lines function expects no arguments");
+        }
+        let input = current_input(&self.input);
+        Ok(new_valueref(StreamValue::new_buffered(Box::new(
+            move || Ok(input.borrow_mut().read_line().map(line_value)),
+        ))))
+    }
+}
+
+pub struct IsEof {}
+
+impl IsEof {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsEof {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("eof-object? function expects exactly one argument");
+        }
+        let value = args[0].borrow().get_type() == ValueType::Eof;
+        Ok(new_valueref(BoolValue { value }))
     }
 }
 
@@ -137,6 +279,106 @@ impl Output for StdOutput {
     }
 }
 
+#[derive(Debug)]
+pub struct StdErrOutput {}
+
+impl StdErrOutput {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Output for StdErrOutput {
+    fn print(&mut self, text: &str) {
+        eprint!("{}", text);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+// Collects the output in a buffer that is shared by all clones, so one clone
+// can be given to an environment and another one kept to read the text
+#[derive(Clone, Debug, Default)]
+pub struct BufferedOutput {
+    buffer: Rc<RefCell<String>>,
+}
+
+impl BufferedOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> String {
+        self.buffer.borrow().clone()
+    }
+
+    // Returns the text and empties the buffer
+    pub fn take(&self) -> String {
+        std::mem::take(&mut *self.buffer.borrow_mut())
+    }
+}
+
+impl Output for BufferedOutput {
+    fn print(&mut self, text: &str) {
+        self.buffer.borrow_mut().push_str(text);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+thread_local! {
+    static REDIRECTED_OUTPUTS: RefCell<Vec<OutputRef>> = const { RefCell::new(Vec::new()) };
+}
+
+// Output builtins capture their environment's output when they are created,
+// so a redirection is looked up at print time and takes precedence
+pub fn current_output(output: &OutputRef) -> OutputRef {
+    REDIRECTED_OUTPUTS
+        .with(|outputs| outputs.borrow().last().cloned())
+        .unwrap_or_else(|| output.clone())
+}
+
+struct Redirection {}
+
+impl Drop for Redirection {
+    fn drop(&mut self) {
+        REDIRECTED_OUTPUTS.with(|outputs| outputs.borrow_mut().pop());
+    }
+}
+
+pub fn with_redirected_output<F: FnOnce() -> EvalResult>(output: &OutputRef, f: F) -> EvalResult {
+    REDIRECTED_OUTPUTS.with(|outputs| outputs.borrow_mut().push(output.clone()));
+    let _redirection = Redirection {};
+    f()
+}
+
+pub struct WithOutputToString {}
+
+impl WithOutputToString {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for WithOutputToString {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("with-output-to-string function expects exactly one argument");
+        }
+
+        let buffered_output = BufferedOutput::new();
+        let output: OutputRef = Rc::new(RefCell::new(buffered_output.clone()));
+        with_redirected_output(&output, || call_value(&args[0], &[]))?;
+
+        let value = buffered_output.take().into();
+        Ok(new_valueref(StrValue { value }))
+    }
+}
+
 pub struct Write_ {
     output: OutputRef,
 }
@@ -221,12 +463,128 @@ impl Callable for DisplayLn {
     }
 }
 
+// Unlike display and displayln, print and println separate their arguments
+// by spaces and end with at most one line break
+pub struct Print {
+    output: OutputRef,
+}
+
+impl Print {
+    pub fn new(output: &OutputRef) -> Self {
+        Self {
+            output: output.clone(),
+        }
+    }
+}
+
+impl Callable for Print {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        print_values(args, false, &current_output(&self.output));
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+pub struct PrintLn {
+    output: OutputRef,
+}
+
+impl PrintLn {
+    pub fn new(output: &OutputRef) -> Self {
+        Self {
+            output: output.clone(),
+        }
+    }
+}
+
+impl Callable for PrintLn {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        print_values(args, true, &current_output(&self.output));
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+pub struct NewLine {
+    output: OutputRef,
+}
+
+impl NewLine {
+    pub fn new(output: &OutputRef) -> Self {
+        Self {
+            output: output.clone(),
+        }
+    }
+}
+
+impl Callable for NewLine {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("newline function expects no arguments");
+        }
+        current_output(&self.output).borrow_mut().print_line("");
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+// Like print and println, but for the error output. Redirections of the
+// output (e.g. by with-output-to-string) do not apply.
+pub struct EPrint {
+    error_output: OutputRef,
+}
+
+impl EPrint {
+    pub fn new(error_output: &OutputRef) -> Self {
+        Self {
+            error_output: error_output.clone(),
+        }
+    }
+}
+
+impl Callable for EPrint {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        print_values(args, false, &self.error_output);
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+pub struct EPrintLn {
+    error_output: OutputRef,
+}
+
+impl EPrintLn {
+    pub fn new(error_output: &OutputRef) -> Self {
+        Self {
+            error_output: error_output.clone(),
+        }
+    }
+}
+
+impl Callable for EPrintLn {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        print_values(args, true, &self.error_output);
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+fn print_values(values: &[ValueRef], line_break: bool, output: &OutputRef) {
+    let text = values
+        .iter()
+        .map(|value| value.borrow().display_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if line_break {
+        output.borrow_mut().print_line(&text);
+    } else {
+        output.borrow_mut().print(&text);
+    }
+}
+
 enum PrintMode {
     Write { line_break: bool },
     Display { line_break: bool },
 }
 
 fn print_value(value: &ValueRef, mode: PrintMode, output: &OutputRef) {
+    let output = &current_output(output);
     match mode {
         PrintMode::Write { line_break } => {
             output.borrow_mut().print(&format!("{}", value.borrow()));
@@ -235,13 +593,8 @@ fn print_value(value: &ValueRef, mode: PrintMode, output: &OutputRef) {
             }
         }
         PrintMode::Display { line_break } => {
-            let value_str = format!("{}", value.borrow());
-            let value_str = if value.borrow().get_type() == ValueType::Str {
-                remove_quotes(&value_str)
-            } else {
-                &value_str
-            };
-            output.borrow_mut().print(value_str);
+            let value_str = value.borrow().display_string();
+            output.borrow_mut().print(&value_str);
             if line_break {
                 output.borrow_mut().print_line("");
             }
@@ -249,13 +602,6 @@ fn print_value(value: &ValueRef, mode: PrintMode, output: &OutputRef) {
     }
 }
 
-fn remove_quotes(value: &str) -> &str {
-    let mut chars = value.chars();
-    chars.next();
-    chars.next_back();
-    chars.as_str()
-}
-
 pub struct Not {}
 
 impl Not {
@@ -323,14 +669,303 @@ impl Default for ErrorFn {
 }
 
 impl Callable for ErrorFn {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("error function expects a message and an optional value");
+        }
+
+        let message = match downcast_value::<StrValue>(&borrow_value(&args[0])) {
+            Some(message) => message.value.clone(),
+            None => return error("error function expects a string as message"),
+        };
+        let err = InterpreterError::user_raised(&message);
+        match args.get(1) {
+            Some(value) => Err(err.value(value)),
+            None => Err(err),
+        }
+    }
+}
+
+// Ends the program with the given status code (0 by default, #t and #f
+// stand for 0 and 1). Pending dynamic-wind after thunks still run.
+pub struct Exit {}
+
+impl Exit {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Exit {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() > 1 {
+            return error("exit function expects at most one argument");
+        }
+        let code = match args.first() {
+            None => 0,
+            Some(arg) => exit_code(arg).ok_or_else(|| {
+                InterpreterError::type_error("exit expects an integer or a boolean", arg)
+            })?,
+        };
+        Err(InterpreterError::exit(code))
+    }
+}
+
+// Status code for the result of a program or the argument of exit
+pub fn exit_code(value: &ValueRef) -> Option<i32> {
+    let value = borrow_value(value);
+    if let Some(int) = downcast_value::<IntValue>(&value) {
+        return i32::try_from(int.value).ok();
+    }
+    downcast_value::<BoolValue>(&value).map(|bool| if bool.value { 0 } else { 1 })
+}
+
+pub struct CatchError {}
+
+impl CatchError {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for CatchError {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("catch-error function expects exactly one argument");
+        }
+
+        match call_value(&args[0], &[]) {
+            Ok(value) => Ok(value),
+            Err(err) if err.exit_code().is_some() => Err(err),
+            Err(err) => Ok(new_valueref(ErrorValue::new(err))),
+        }
+    }
+}
+
+pub struct WithExceptionHandler {}
+
+impl WithExceptionHandler {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for WithExceptionHandler {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("with-exception-handler function expects exactly two arguments");
+        }
+
+        // The handler gets the error and its result replaces the one of the thunk
+        match call_value(&args[1], &[]) {
+            Ok(value) => Ok(value),
+            Err(err) if err.exit_code().is_some() => Err(err),
+            Err(err) => call_value(&args[0], &[new_valueref(ErrorValue::new(err))]),
+        }
+    }
+}
+
+pub struct DynamicWind {}
+
+impl DynamicWind {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for DynamicWind {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 3 {
+            return error("dynamic-wind function expects exactly three arguments");
+        }
+
+        call_value(&args[0], &[])?;
+        let result = call_value(&args[1], &[]);
+        // The after thunk also runs if the thunk fails. Its own error is only
+        // reported if the thunk succeeded.
+        let after = call_value(&args[2], &[]);
+
+        match (result, after) {
+            (Err(err), _) | (Ok(_), Err(err)) => Err(err),
+            (Ok(value), Ok(_)) => Ok(value),
+        }
+    }
+}
+
+pub struct IsError {}
+
+impl IsError {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsError {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
         if args.len() != 1 {
-            return error("error function expects exactly one argument");
+            return error("error? function expects exactly one argument");
         }
 
-        let error_message = &borrow_value(&args[0]);
-        let error_message = downcast_value::<StrValue>(error_message).unwrap();
-        Err(InterpreterError::new(&error_message.value))
+        let value = args[0].borrow().get_type() == ValueType::Error;
+        Ok(new_valueref(BoolValue { value }))
+    }
+}
+
+pub struct ErrorInfo {
+    name: String,
+    info: fn(&InterpreterError) -> ValueRef,
+}
+
+impl ErrorInfo {
+    fn new(name: &str, info: fn(&InterpreterError) -> ValueRef) -> Self {
+        Self {
+            name: name.to_string(),
+            info,
+        }
+    }
+
+    pub fn kind() -> Self {
+        Self::new("error-kind", |err| {
+            new_valueref(SymbolValue::new(err.kind.name()))
+        })
+    }
+
+    pub fn message() -> Self {
+        Self::new("error-message", |err| {
+            new_valueref(StrValue {
+                value: err.message.as_str().into(),
+            })
+        })
+    }
+
+    pub fn value() -> Self {
+        Self::new("error-value", |err| match &err.value {
+            Some(value) => value.clone(),
+            None => new_valueref(NilValue {}),
+        })
+    }
+}
+
+impl Callable for ErrorInfo {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error(&format!(
+                "{} function expects exactly one argument",
+                self.name
+            ));
+        }
+
+        match downcast_value::<ErrorValue>(&borrow_value(&args[0])) {
+            Some(error_value) => Ok((self.info)(&error_value.error)),
+            None => Err(InterpreterError::type_error(
+                &format!("{} function expects an error", self.name),
+                &args[0],
+            )),
+        }
+    }
+}
+
+pub struct BoliVersion {}
+
+impl BoliVersion {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for BoliVersion {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("boli-version function expects no arguments");
+        }
+
+        Ok(new_valueref(StrValue {
+            value: env!("CARGO_PKG_VERSION").into(),
+        }))
+    }
+}
+
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = vec![];
+    if cfg!(feature = "catch-panics") {
+        features.push("catch-panics");
+    }
+    if cfg!(feature = "exec") {
+        features.push("exec");
+    }
+    if cfg!(feature = "http") {
+        features.push("http");
+    }
+    features.extend(super::prelude::PRELUDE_EXTENSIONS);
+    features
+}
+
+pub struct Features {}
+
+impl Features {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Features {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("features function expects no arguments");
+        }
+
+        let elements = enabled_features()
+            .into_iter()
+            .map(|feature| {
+                new_valueref(StrValue {
+                    value: feature.into(),
+                })
+            })
+            .collect();
+
+        Ok(new_valueref(VectorValue { elements }))
+    }
+}
+
+pub struct Platform {}
+
+impl Platform {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Platform {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("platform function expects no arguments");
+        }
+
+        Ok(new_valueref(StrValue {
+            value: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH).into(),
+        }))
+    }
+}
+
+// In strict boolean mode conditions must be booleans, see Settings
+pub fn check_condition(
+    value: &ValueRef,
+    form: &str,
+    env: &EnvironmentRef,
+) -> Result<bool, InterpreterError> {
+    if let Some(bool_value) = downcast_value::<BoolValue>(&borrow_value(value)) {
+        return Ok(bool_value.value);
+    }
+    match env.borrow().get_settings().strict_bool {
+        false => Ok(is_truthy(value)),
+        true => Err(InterpreterError::type_error(
+            &format!(
+                "{} expects a boolean condition, got {}",
+                form,
+                value.borrow()
+            ),
+            value,
+        )),
     }
 }
 
@@ -1,12 +1,26 @@
+pub mod cancellation;
+pub mod contracts;
 pub mod environment;
+pub mod eval_functions;
+pub mod freeze;
+pub mod gc;
+pub mod introspection;
+pub mod limits;
 pub mod misc_functions;
 pub mod module_mgmt;
 pub mod number_functions;
+pub mod parameters;
 pub mod prelude;
+pub mod pretty_printer;
+pub mod settings;
+pub mod stats;
 pub mod struct_functions;
 pub mod values;
 
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::rc::Rc;
+use std::time::Duration;
 use std::vec;
 
 use crate::frontend::lexer::tokens::{LogicalOp, Op};
@@ -15,29 +29,30 @@ use environment::Environment;
 
 use values::*;
 
+use self::cancellation::{current_token, with_cancellation, CancellationToken};
 use self::environment::{EnvironmentBuilder, EnvironmentRef};
-use self::misc_functions::is_truthy;
+use self::limits::{with_limits, Budget, Limits};
+use self::misc_functions::{check_condition, BufferedOutput, OutputRef};
+use self::settings::Settings;
 
 pub struct Interpreter {
-    pub stack: Vec<EvalResult>,
     pub env: EnvironmentRef,
     call_nesting: u32,
+    budget: Option<Rc<Budget>>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {
-            stack: Vec::new(),
-            env: EnvironmentBuilder::new().build(),
-            call_nesting: 0,
-        }
+        Self::with_environment(&EnvironmentBuilder::new().build())
     }
 
     pub fn with_environment(env: &EnvironmentRef) -> Self {
         Self {
-            stack: Vec::new(),
             env: env.clone(),
             call_nesting: 0,
+            budget: Budget::current(),
+            cancellation: current_token(),
         }
     }
 
@@ -46,19 +61,106 @@ impl Interpreter {
         Self::with_environment(&env)
     }
 
+    // Interpreter with prelude whose output is collected in the returned
+    // buffer instead of being printed
+    pub fn with_captured_output() -> (Self, BufferedOutput) {
+        let buffered_output = BufferedOutput::new();
+        let output: OutputRef = Rc::new(RefCell::new(buffered_output.clone()));
+        let env = EnvironmentBuilder::new()
+            .with_prelude(true)
+            .output(&output)
+            .build();
+        (Self::with_environment(&env), buffered_output)
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.env.borrow_mut().set_limits(limits);
+    }
+
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.env.borrow_mut().set_settings(settings);
+    }
+
+    pub fn set_real_format(&mut self, format: RealFormat) {
+        let settings = self.env.borrow().get_settings().real_format(format);
+        self.env.borrow_mut().set_settings(settings);
+    }
+
+    // Converts a value to a string the way display does in this interpreter
+    pub fn value_to_string(&self, value: &ValueRef) -> String {
+        let real_format = self.env.borrow().get_settings().real_format;
+        with_real_format(real_format, || value.borrow().to_string())
+    }
+
     pub fn set_value(&mut self, key: String, value: ValueRef) {
         self.env.borrow_mut().set(key, value);
     }
 
     pub fn eval(&mut self, code: &str) -> EvalResult {
         let parser = Parser::new();
-        let program: AstRef = new_astref(
-            parser
-                .parse(code)
-                .map_err(|e| InterpreterError::new(&e.message))?,
-        );
+        let program = parser
+            .parse(code)
+            .map_err(|e| InterpreterError::syntax_error(&e))?;
+
+        self.eval_program(program)
+    }
 
-        self.eval_ast(&program)
+    // Aborts the evaluation with an error if it takes longer than the timeout
+    pub fn eval_with_timeout(&mut self, code: &str, timeout: Duration) -> EvalResult {
+        self.eval_with_cancellation(code, &CancellationToken::with_timeout(timeout))
+    }
+
+    pub fn eval_with_cancellation(&mut self, code: &str, token: &CancellationToken) -> EvalResult {
+        with_cancellation(token, || {
+            let cancellation = self.cancellation.replace(token.clone());
+            let result = self.eval(code);
+            self.cancellation = cancellation;
+            result
+        })
+    }
+
+    pub fn eval_program(&mut self, program: Program) -> EvalResult {
+        let program: AstRef = new_astref(program);
+        let limits = self.env.borrow().get_limits();
+        let real_format = self.env.borrow().get_settings().real_format;
+
+        with_real_format(real_format, || {
+            with_limits(limits, || {
+                let budget = std::mem::replace(&mut self.budget, Budget::current());
+                let call_nesting = self.call_nesting;
+                let result = catch_panic(|| self.eval_ast(&program));
+                if result.is_err() {
+                    self.call_nesting = call_nesting;
+                }
+                self.budget = budget;
+                result
+            })
+        })
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<ValueRef> {
+        self.env.borrow().get(name)
+    }
+
+    // Calls the function bound to name with already evaluated arguments
+    pub fn call(&mut self, name: &str, args: &[ValueRef]) -> EvalResult {
+        let function = match self.get_global(name) {
+            Some(function) => function,
+            None => return Err(InterpreterError::name_error(name)),
+        };
+        let function_type = function.borrow().get_type();
+        if function_type != ValueType::Lambda && function_type != ValueType::BuiltInFunction {
+            return Err(InterpreterError::type_error(
+                &format!("{} is not a function", name),
+                &function,
+            ));
+        }
+
+        let limits = self.env.borrow().get_limits();
+        let real_format = self.env.borrow().get_settings().real_format;
+        with_real_format(real_format, || {
+            with_limits(limits, || catch_panic(|| call_value(&function, args)))
+        })
     }
 
     fn new_eval_error(&mut self, message: &str) -> EvalResult {
@@ -66,17 +168,33 @@ impl Interpreter {
     }
 
     fn eval_ast(&mut self, ast: &AstRef) -> EvalResult {
-        ast.borrow().accept(self);
-        self.stack
-            .pop()
-            .unwrap_or(self.new_eval_error("No value on the stack"))
+        if let Some(budget) = &self.budget {
+            budget.step()?;
+        }
+        self.fold(ast)
     }
 
+    // The forms of a block are evaluated in order, a name that is used
+    // before its def is evaluated is undefined (or refers to a binding of
+    // an enclosing scope). Only with hoisting function definitions are
+    // evaluated first.
     fn eval_block(&mut self, children: &Vec<AstRef>) -> EvalResult {
         let mut result: EvalResult = Ok(new_valueref(NilValue {}));
+        let mut hoisted = vec![None; children.len()];
+
+        if self.env.borrow().get_settings().hoist_functions {
+            for (i, child) in children.iter().enumerate() {
+                if Self::is_function_definition(child) {
+                    hoisted[i] = Some(self.eval_ast(child)?);
+                }
+            }
+        }
 
-        for child in children {
-            result = self.eval_ast(child);
+        for (child, hoisted) in children.iter().zip(hoisted) {
+            result = match hoisted {
+                Some(value) => Ok(value),
+                None => self.eval_ast(child),
+            };
             if result.is_err() {
                 return result;
             }
@@ -84,195 +202,341 @@ impl Interpreter {
 
         result
     }
+
+    fn is_function_definition(ast: &AstRef) -> bool {
+        let ast = &borrow_ast(ast);
+        downcast_ast::<Definition>(ast)
+            .is_some_and(|def| downcast_ast::<Lambda>(&borrow_ast(&def.value)).is_some())
+    }
+
+    fn bind_pattern(
+        &mut self,
+        pattern: &Pattern,
+        value: &ValueRef,
+    ) -> Result<(), InterpreterError> {
+        let mismatch = || {
+            InterpreterError::type_error(
+                &format!("{} does not match pattern {}", value.borrow(), pattern),
+                value,
+            )
+        };
+
+        match pattern {
+            Pattern::Name(name) => {
+                if name != "_" {
+                    self.env.borrow_mut().set(name.clone(), value.clone());
+                }
+            }
+            Pattern::List(elements, tail) => {
+                let mut current = value.clone();
+                for element in elements {
+                    let pair = match downcast_value::<PairValue>(&borrow_value(&current)) {
+                        Some(pair) => (pair.left.clone(), pair.right.clone()),
+                        None => return Err(mismatch()),
+                    };
+                    self.bind_pattern(element, &pair.0)?;
+                    current = pair.1;
+                }
+                match tail {
+                    Some(tail) => self.bind_pattern(tail, &current)?,
+                    None if current.borrow().get_type() == ValueType::Nil => {}
+                    None => return Err(mismatch()),
+                }
+            }
+            Pattern::Vector(elements, rest) => {
+                let values = match downcast_value::<VectorValue>(&borrow_value(value)) {
+                    Some(vector) => vector.elements.clone(),
+                    None => return Err(mismatch()),
+                };
+                let matches_length = match rest {
+                    Some(_) => values.len() >= elements.len(),
+                    None => values.len() == elements.len(),
+                };
+                if !matches_length {
+                    return Err(mismatch());
+                }
+                for (element, value) in elements.iter().zip(values.iter()) {
+                    self.bind_pattern(element, value)?;
+                }
+                if let Some(rest) = rest {
+                    let rest_value = new_valueref(VectorValue {
+                        elements: values[elements.len()..].to_vec(),
+                    });
+                    self.bind_pattern(&Pattern::Name(rest.clone()), &rest_value)?;
+                }
+            }
+            Pattern::Struct(fields) => {
+                let mut field_values = Vec::new();
+                match downcast_value::<StructValue>(&borrow_value(value)) {
+                    Some(struct_value) => {
+                        for field in fields {
+                            match struct_value.values.get(field) {
+                                Some(entry) => {
+                                    field_values.push((field.clone(), entry.value.clone()))
+                                }
+                                None => return Err(mismatch()),
+                            }
+                        }
+                    }
+                    None => return Err(mismatch()),
+                }
+                for (field, field_value) in field_values {
+                    self.env.borrow_mut().set(field, field_value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn eval_loop_bound(&mut self, ast: &AstRef) -> Result<i64, InterpreterError> {
+        let value = self.eval_ast(ast)?;
+        let int_value = downcast_value::<IntValue>(&borrow_value(&value)).map(|int| int.value);
+        int_value
+            .ok_or_else(|| InterpreterError::type_error("for loop bounds must be integers", &value))
+    }
+
+    fn case_key(value: &ValueRef) -> Option<CaseKey> {
+        let value = borrow_value(value);
+        match value.get_type() {
+            ValueType::Int => Some(CaseKey::Int(downcast_value::<IntValue>(&value)?.value)),
+            ValueType::Rational => {
+                let rational = downcast_value::<RationalValue>(&value)?;
+                Some(CaseKey::rational(rational.numerator, rational.denominator))
+            }
+            ValueType::Real => Some(CaseKey::Real(
+                downcast_value::<RealValue>(&value)?.value.to_bits(),
+            )),
+            ValueType::Bool => Some(CaseKey::Bool(downcast_value::<BoolValue>(&value)?.value)),
+            ValueType::Str => Some(CaseKey::Str(
+                downcast_value::<StrValue>(&value)?.value.to_string(),
+            )),
+            ValueType::Symbol => Some(CaseKey::Symbol(
+                downcast_value::<SymbolValue>(&value)?.value.clone(),
+            )),
+            ValueType::Nil => Some(CaseKey::Nil),
+            _ => None,
+        }
+    }
 }
 
-impl AstVisitor for Interpreter {
-    fn visit_program(&mut self, program: &Program) {
-        let result = self.eval_block(&program.children);
-        self.stack.push(result);
+impl AstFolder<EvalResult> for Interpreter {
+    fn visit_program(&mut self, program: &Program) -> EvalResult {
+        self.eval_block(&program.children)
     }
 
-    fn visit_block(&mut self, block: &Block) {
+    fn visit_block(&mut self, block: &Block) -> EvalResult {
         let env = self.env.clone();
         self.env = EnvironmentBuilder::new().parent(&self.env).build();
 
         let result = self.eval_block(&block.children);
-        self.stack.push(result);
         self.env = env;
+        result
     }
 
-    fn visit_integer(&mut self, integer: &Integer) {
-        self.stack.push(Ok(new_valueref(IntValue {
+    fn visit_integer(&mut self, integer: &Integer) -> EvalResult {
+        Ok(new_valueref(IntValue {
             value: integer.value,
-        })));
+        }))
     }
 
-    fn visit_rational(&mut self, rational: &Rational) {
-        self.stack.push(Ok(new_valueref(RationalValue {
+    fn visit_rational(&mut self, rational: &Rational) -> EvalResult {
+        Ok(new_valueref(RationalValue {
             numerator: rational.numerator,
             denominator: rational.denominator,
-        })));
+        }))
     }
 
-    fn visit_real(&mut self, real: &Real) {
-        self.stack
-            .push(Ok(new_valueref(RealValue { value: real.value })));
+    fn visit_real(&mut self, real: &Real) -> EvalResult {
+        Ok(new_valueref(RealValue { value: real.value }))
     }
 
-    fn visit_bool(&mut self, bool: &Bool) {
-        self.stack
-            .push(Ok(new_valueref(BoolValue { value: bool.value })));
+    fn visit_bool(&mut self, bool: &Bool) -> EvalResult {
+        Ok(new_valueref(BoolValue { value: bool.value }))
     }
 
-    fn visit_str(&mut self, str: &Str) {
-        self.stack.push(Ok(new_valueref(StrValue {
-            value: str.value.clone(),
-        })));
+    fn visit_str(&mut self, str: &Str) -> EvalResult {
+        Ok(new_valueref(StrValue {
+            value: str.value.as_str().into(),
+        }))
     }
 
-    fn visit_nil(&mut self) {
-        self.stack.push(Ok(new_valueref(NilValue {})));
+    fn visit_nil(&mut self) -> EvalResult {
+        Ok(new_valueref(NilValue {}))
     }
 
-    fn visit_identifier(&mut self, identifier: &Identifier) {
-        let value = self.env.borrow().get(&identifier.value);
+    fn visit_identifier(&mut self, identifier: &Identifier) -> EvalResult {
+        let value = match &identifier.address {
+            Some(LexicalAddress { depth, slot }) => self.env.borrow().get_slot(*depth, *slot),
+            None => None,
+        }
+        .or_else(|| self.env.borrow().get(&identifier.value));
         if value.is_none() {
-            let err = self.new_eval_error(&format!("Undefined identifier: {}", identifier.value));
-            self.stack.push(err);
-            return;
+            return Err(InterpreterError::name_error(&identifier.value));
         }
 
-        self.stack.push(Ok(value.unwrap().clone()));
+        Ok(value.unwrap().clone())
     }
 
-    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) {
+    fn visit_absolute_name(&mut self, absolute_name: &AbsoluteName) -> EvalResult {
         let abs_name = absolute_name.segments.join("::");
         let value = self.env.borrow().get(&abs_name);
         if value.is_none() {
-            let err = self.new_eval_error(&format!("Undefined identifier: {}", abs_name));
-            self.stack.push(err);
-            return;
+            return Err(InterpreterError::name_error(&abs_name));
         }
 
-        self.stack.push(Ok(value.unwrap().clone()));
+        Ok(value.unwrap().clone())
     }
 
-    fn visit_symbol(&mut self, symbol: &Symbol) {
-        self.stack
-            .push(Ok(new_valueref(SymbolValue::new(&symbol.value[1..]))));
+    fn visit_symbol(&mut self, symbol: &Symbol) -> EvalResult {
+        Ok(new_valueref(SymbolValue::new(&symbol.value[1..])))
     }
 
-    fn visit_quote(&mut self, quote: &Quote) {
-        self.stack.push(Ok(new_valueref(QuoteValue {
+    fn visit_quote(&mut self, quote: &Quote) -> EvalResult {
+        Ok(new_valueref(QuoteValue {
             token: quote.value.clone(),
-        })));
+        }))
     }
 
-    fn visit_operator(&mut self, operator: &Operator) {
+    fn visit_operator(&mut self, operator: &Operator) -> EvalResult {
         match operator.value {
             Op::Plus => {
                 let add = self.env.borrow().get("+").unwrap();
-                self.stack.push(Ok(add.clone()));
+                Ok(add.clone())
             }
             Op::Minus => {
                 let sub = self.env.borrow().get("-").unwrap();
-                self.stack.push(Ok(sub.clone()));
+                Ok(sub.clone())
             }
             Op::Asterisk => {
                 let mul = self.env.borrow().get("*").unwrap();
-                self.stack.push(Ok(mul.clone()));
+                Ok(mul.clone())
             }
             Op::Slash => {
                 let div = self.env.borrow().get("/").unwrap();
-                self.stack.push(Ok(div.clone()));
+                Ok(div.clone())
             }
             Op::Caret => {
                 let pow = self.env.borrow().get("^").unwrap();
-                self.stack.push(Ok(pow.clone()));
+                Ok(pow.clone())
             }
             Op::Percent => {
                 let mod_ = self.env.borrow().get("%").unwrap();
-                self.stack.push(Ok(mod_.clone()));
+                Ok(mod_.clone())
             }
         }
     }
 
-    fn visit_logical_operator(&mut self, operator: &LogicalOperator) {
+    fn visit_logical_operator(&mut self, operator: &LogicalOperator) -> EvalResult {
         match operator.value {
             LogicalOp::Eq => {
                 let eq = self.env.borrow().get("=").unwrap();
-                self.stack.push(Ok(eq.clone()));
+                Ok(eq.clone())
             }
             LogicalOp::Gt => {
                 let gt = self.env.borrow().get(">").unwrap();
-                self.stack.push(Ok(gt.clone()));
+                Ok(gt.clone())
             }
             LogicalOp::Ge => {
                 let ge = self.env.borrow().get(">=").unwrap();
-                self.stack.push(Ok(ge.clone()));
+                Ok(ge.clone())
             }
             LogicalOp::Lt => {
                 let lt = self.env.borrow().get("<").unwrap();
-                self.stack.push(Ok(lt.clone()));
+                Ok(lt.clone())
             }
             LogicalOp::Le => {
                 let le = self.env.borrow().get("<=").unwrap();
-                self.stack.push(Ok(le.clone()));
+                Ok(le.clone())
             }
         }
     }
 
-    fn visit_pair(&mut self, pair: &Pair) {
-        let left = self.eval_ast(&pair.left);
-        if left.is_err() {
-            self.stack.push(left);
-            return;
-        }
-        let left = left.unwrap();
+    fn visit_pair(&mut self, pair: &Pair) -> EvalResult {
+        let left = self.eval_ast(&pair.left)?;
 
-        let right = self.eval_ast(&pair.right);
-        if right.is_err() {
-            self.stack.push(right);
-            return;
-        }
-        let right = right.unwrap();
+        let right = self.eval_ast(&pair.right)?;
 
-        self.stack.push(Ok(new_valueref(PairValue {
+        Ok(new_valueref(PairValue {
             left: left.clone(),
             right: right.clone(),
-        })));
+        }))
     }
 
-    fn visit_list(&mut self, list: &List) {
+    fn visit_list(&mut self, list: &List) -> EvalResult {
         let mut elements = vec![];
 
         for element in &list.elements {
-            let elem_result = self.eval_ast(element);
-            if elem_result.is_err() {
-                self.stack.push(elem_result);
-                return;
-            }
-            elements.push(elem_result.unwrap());
+            elements.push(self.eval_ast(element)?);
         }
 
-        self.stack.push(Ok(new_valueref(VectorValue { elements })));
+        Ok(new_valueref(VectorValue { elements }))
     }
 
-    fn visit_def(&mut self, def: &Definition) {
+    fn visit_def(&mut self, def: &Definition) -> EvalResult {
         let name = def.name.clone();
-        let value = self.eval_ast(&def.value);
+        let value = self.eval_ast(&def.value)?;
 
-        if value.is_err() {
-            self.stack.push(value);
-            return;
+        if def.constant {
+            self.env.borrow_mut().set_constant(name, value);
+        } else {
+            let replaced = self.env.borrow().replaced_definitions(&name, &value);
+            if let Some(replaced) = replaced {
+                let message = format!(
+                    "redefinition of {} replaces its definition for {}",
+                    name, replaced
+                );
+                if self.env.borrow().get_settings().strict {
+                    return self.new_eval_error(&message);
+                }
+                let error_output = self.env.borrow().get_error_output();
+                error_output
+                    .borrow_mut()
+                    .print_line(&format!("warning: {}", message));
+            }
+            self.env.borrow_mut().set(name, value);
         }
+        Ok(new_valueref(NilValue {}))
+    }
 
-        self.env.borrow_mut().set(name, value.unwrap().clone());
-        self.stack.push(Ok(new_valueref(NilValue {})));
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) -> EvalResult {
+        let value = self.eval_ast(&def.value)?;
+        self.bind_pattern(&def.pattern, &value)?;
+        Ok(new_valueref(NilValue {}))
     }
 
-    fn visit_struct_def(&mut self, struct_def: &StructDefinition) {
-        let struct_type = new_valueref(StructTypeValue::new(&struct_def.name, &struct_def.fields));
+    fn visit_struct_def(&mut self, struct_def: &StructDefinition) -> EvalResult {
+        let struct_type = match &struct_def.parent {
+            Some(parent) => {
+                let parent_type = self.env.borrow().get(parent);
+                let parent_type = match parent_type {
+                    Some(parent_type)
+                        if parent_type.borrow().get_type() == ValueType::StructType =>
+                    {
+                        parent_type
+                    }
+                    _ => {
+                        let err = self.new_eval_error(&format!("Unknown struct type: {}", parent));
+                        return err;
+                    }
+                };
+                StructTypeValue::new_derived(&struct_def.name, &struct_def.fields, &parent_type)
+            }
+            None => StructTypeValue::new(&struct_def.name, &struct_def.fields),
+        };
+        let all_fields = struct_type.fields.clone();
+
+        // A field must not repeat one of the struct or of its ancestors
+        let mut field_names = HashSet::new();
+        for field in &all_fields {
+            if !field_names.insert(field) {
+                return self.new_eval_error(&format!(
+                    "Field {} of struct {} is already defined",
+                    field, struct_def.name
+                ));
+            }
+        }
+        let struct_type = new_valueref(struct_type);
         self.env
             .borrow_mut()
             .set(struct_def.name.clone(), struct_type.clone());
@@ -288,7 +552,7 @@ impl AstVisitor for Interpreter {
             .borrow_mut()
             .set_callable(&type_query_name, &Rc::new(IsStructType::new(&struct_type)));
 
-        for field in &struct_def.fields {
+        for field in &all_fields {
             let getter_name = format!("{}-{}", &struct_def.name, &field);
             self.env
                 .borrow_mut()
@@ -300,68 +564,159 @@ impl AstVisitor for Interpreter {
                 .set_callable(&setter_name, &Rc::new(SetStructField::new(&field)));
         }
 
-        self.stack.push(Ok(new_valueref(NilValue {})));
+        Ok(new_valueref(NilValue {}))
+    }
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) -> EvalResult {
+        let struct_type = self.env.borrow().get(&method_def.struct_type);
+        let is_struct_type = match struct_type {
+            Some(struct_type) => struct_type.borrow().get_type() == ValueType::StructType,
+            None => false,
+        };
+        if !is_struct_type {
+            let err =
+                self.new_eval_error(&format!("Unknown struct type: {}", method_def.struct_type));
+            return err;
+        }
+
+        let method = self.eval_ast(&method_def.value)?;
+
+        let method_env = match Environment::get_method_env(&self.env, &method_def.name) {
+            Some(method_env) => method_env,
+            None => {
+                // A method must not silently replace a plain function
+                if self.env.borrow().get(&method_def.name).is_some() {
+                    return self.new_eval_error(&format!(
+                        "{} is already defined and is not a generic function",
+                        method_def.name
+                    ));
+                }
+                let generic_function = GenericFunction::new(&method_def.name, &self.env);
+                self.env
+                    .borrow_mut()
+                    .set_callable(&method_def.name, &Rc::new(generic_function));
+                self.env.clone()
+            }
+        };
+
+        method_env
+            .borrow_mut()
+            .set_method(&method_def.name, &method_def.struct_type, method);
+
+        Ok(new_valueref(NilValue {}))
     }
 
-    fn visit_set_bang(&mut self, set_bang: &SetBang) {
+    fn visit_set_bang(&mut self, set_bang: &SetBang) -> EvalResult {
         let defining_env = Environment::get_defining_env(&self.env, &set_bang.name);
         match defining_env {
+            Some(env) if env.borrow().is_constant(&set_bang.name) => {
+                self.new_eval_error(&format!("Cannot assign to constant {}", set_bang.name))
+            }
             Some(env) => {
-                let value = self.eval_ast(&set_bang.value);
-                if value.is_err() {
-                    self.stack.push(value);
-                    return;
-                }
+                let value = self.eval_ast(&set_bang.value)?;
 
-                env.borrow_mut()
-                    .set(set_bang.name.clone(), value.unwrap().clone());
+                env.borrow_mut().set(set_bang.name.clone(), value);
 
-                self.stack.push(Ok(new_valueref(NilValue {})));
-            }
-            None => {
-                let err = self.new_eval_error(&format!("Undefined identifier: {}", set_bang.name));
-                self.stack.push(err);
+                Ok(new_valueref(NilValue {}))
             }
+            None => Err(InterpreterError::name_error(&set_bang.name)),
         }
     }
 
-    fn visit_if(&mut self, if_expr: &IfExpression) {
-        let condition = self.eval_ast(&if_expr.condition);
-        if condition.is_err() {
-            self.stack.push(condition);
-            return;
-        }
-        let condition = condition.unwrap();
+    fn visit_if(&mut self, if_expr: &IfExpression) -> EvalResult {
+        let condition = self.eval_ast(&if_expr.condition)?;
 
-        let result = if is_truthy(&condition) {
+        if check_condition(&condition, "if", &self.env)? {
             self.eval_ast(&if_expr.consequent)
         } else {
             self.eval_ast(&if_expr.alternate)
-        };
+        }
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) -> EvalResult {
+        let key = self.eval_ast(&case_expr.key)?;
+        let clause = Self::case_key(&key).and_then(|key| case_expr.dispatch.get(&key));
 
-        self.stack.push(result);
+        match clause {
+            Some(index) => self.eval_ast(&case_expr.clauses[*index].1),
+            None => self.eval_ast(&case_expr.alternate),
+        }
     }
 
-    fn visit_lambda(&mut self, lambda: &Lambda) {
-        let lambda_value = new_valueref(LambdaValue::new(
-            lambda.name.clone(),
-            lambda.parameters.clone(),
-            lambda.variadic.clone(),
-            &lambda.body,
-            &self.env,
-        ));
-        self.stack.push(Ok(lambda_value));
+    fn visit_while(&mut self, while_loop: &WhileLoop) -> EvalResult {
+        loop {
+            if let Some(token) = &self.cancellation {
+                token.check()?;
+            }
+            let condition = self.eval_ast(&while_loop.condition)?;
+            if !check_condition(&condition, "while", &self.env)? {
+                break;
+            }
+            self.eval_ast(&while_loop.body)?;
+        }
+
+        Ok(new_valueref(NilValue {}))
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) -> EvalResult {
+        let start = self.eval_loop_bound(&for_loop.start)?;
+        let end = self.eval_loop_bound(&for_loop.end)?;
+        let step = self.eval_loop_bound(&for_loop.step)?;
+        if step == 0 {
+            return error("for loop step must not be zero");
+        }
+
+        let mut i = start;
+        while (step > 0 && i < end) || (step < 0 && i > end) {
+            if let Some(token) = &self.cancellation {
+                token.check()?;
+            }
+
+            let value = new_valueref(IntValue { value: i });
+            let loop_env = EnvironmentBuilder::new().parent(&self.env).build();
+            if !for_loop.slot_only {
+                loop_env
+                    .borrow_mut()
+                    .set(for_loop.variable.clone(), value.clone());
+            }
+            loop_env.borrow_mut().push_slot(&value);
+
+            let env = std::mem::replace(&mut self.env, loop_env);
+            let result = self.eval_ast(&for_loop.body);
+            self.env = env;
+            result?;
+
+            i = match i.checked_add(step) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(new_valueref(NilValue {}))
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) -> EvalResult {
+        let lambda_value = new_valueref(
+            LambdaValue::new(
+                lambda.name.clone(),
+                lambda.parameters.clone(),
+                lambda.variadic.clone(),
+                &lambda.body,
+                &self.env,
+            )
+            .with_signature(&lambda.signature)
+            .with_slot_only(&lambda.slot_only),
+        );
+        Ok(lambda_value)
     }
 
-    fn visit_call(&mut self, call: &Call) {
-        let callee = self.eval_ast(&call.callee);
-        if callee.is_err() {
-            self.stack.push(callee);
-            return;
+    fn visit_call(&mut self, call: &Call) -> EvalResult {
+        if let Some(token) = &self.cancellation {
+            token.check()?;
         }
 
-        let callee = callee.unwrap();
-        let callee = &borrow_value(&callee);
+        let callee_ref = self.eval_ast(&call.callee)?;
+        let callee = &borrow_value(&callee_ref);
         let callee_type = callee.get_type();
 
         let callable: &dyn Callable = match callee_type {
@@ -370,8 +725,7 @@ impl AstVisitor for Interpreter {
                 if let Some(name) = &lambda.name {
                     if name == "main" {
                         let err = self.new_eval_error("Cannot call main function");
-                        self.stack.push(err);
-                        return;
+                        return err;
                     } else {
                         lambda
                     }
@@ -381,9 +735,10 @@ impl AstVisitor for Interpreter {
             }
             ValueType::BuiltInFunction => downcast_value::<BuiltInFunctionValue>(&callee).unwrap(),
             _ => {
-                let err = self.new_eval_error("Callee is not a function");
-                self.stack.push(err);
-                return;
+                return Err(InterpreterError::type_error(
+                    "Callee is not a function",
+                    &callee_ref,
+                ))
             }
         };
 
@@ -393,9 +748,8 @@ impl AstVisitor for Interpreter {
         for arg in &call.arguments {
             let arg = self.eval_ast(arg);
             if arg.is_err() {
-                self.stack.push(arg);
                 self.call_nesting -= 1;
-                return;
+                return arg;
             }
             let arg = arg.unwrap();
             if arg.borrow().get_type() == ValueType::Spread {
@@ -413,13 +767,19 @@ impl AstVisitor for Interpreter {
             let tail_call = new_valueref(TailCallValue {
                 arguments: args.clone(),
             });
-            self.stack.push(Ok(tail_call));
             self.call_nesting -= 1;
-            return;
+            return Ok(tail_call);
         }
 
-        loop {
-            let result = callable.call(&args);
+        if let Some(budget) = &self.budget {
+            if let Err(err) = budget.enter_call() {
+                self.call_nesting -= 1;
+                return Err(err);
+            }
+        }
+
+        let result = loop {
+            let result = callable.call_in_environment(&args, &self.env);
 
             match result {
                 Ok(result) => {
@@ -431,40 +791,38 @@ impl AstVisitor for Interpreter {
                             continue;
                         }
                     }
-                    self.stack.push(Ok(result));
-                    self.call_nesting -= 1;
-                    return;
-                }
-                Err(_) => {
-                    self.stack.push(result);
-                    self.call_nesting -= 1;
-                    return;
+                    break Ok(result);
                 }
+                Err(_) => break result,
             }
+        };
+
+        self.call_nesting -= 1;
+        if let Some(budget) = &self.budget {
+            budget.leave_call();
+        }
+
+        // Built-in functions stop traversing streams when the evaluation is
+        // cancelled, so their result must not be used
+        match &self.cancellation {
+            Some(token) if result.is_ok() => token.check().and(result),
+            _ => result,
         }
     }
 
-    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) -> EvalResult {
         if self.call_nesting == 0 {
             let err = self.new_eval_error("Spread expression outside of function call");
-            self.stack.push(err);
-            return;
-        }
-
-        let spread_value = self.eval_ast(&spread_expr.expr);
-        if spread_value.is_err() {
-            self.stack.push(spread_value);
-            return;
+            return err;
         }
 
-        let spread_value = spread_value.unwrap();
+        let spread_value = self.eval_ast(&spread_expr.expr)?;
         let spread_value = &borrow_value(&spread_value);
         let spread_value = downcast_value::<VectorValue>(spread_value);
 
         if spread_value.is_none() {
             let err = self.new_eval_error("Spread expression must be a list");
-            self.stack.push(err);
-            return;
+            return err;
         }
 
         let spread_value = spread_value.unwrap();
@@ -473,7 +831,7 @@ impl AstVisitor for Interpreter {
             elements: spread_value.elements.clone(),
         };
 
-        self.stack.push(Ok(new_valueref(spread)));
+        Ok(new_valueref(spread))
     }
 }
 
@@ -491,6 +849,18 @@ mod tests {
         assert_eq!(result.to_string(), "#true");
     }
 
+    #[test]
+    fn test_and_or_values() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def calls 0)
+            (def (next!) (set! calls (+ calls 1)) calls)
+            (list (or nil (next!) 99) (and 1 "two" 'three) (and 1 nil (next!)) (or nil 0) calls)
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(list 1 'three nil 0 1)");
+    }
+
     #[test]
     fn test_eval_integer() {
         let mut interpreter = Interpreter::new();
@@ -663,6 +1033,180 @@ mod tests {
         assert_eq!(result.to_string(), "42");
     }
 
+    #[test]
+    fn test_eval_when_unless() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def counter 0)
+            (def (count-if flag)
+                (when flag
+                    (set! counter (+ counter 1))
+                    counter))
+            (def (count-unless flag)
+                (unless flag
+                    (set! counter (+ counter 10))
+                    counter))
+        "#;
+        interpreter.eval(code).unwrap();
+
+        let eval = |interpreter: &mut Interpreter, code: &str| {
+            interpreter.eval(code).unwrap().borrow().to_string()
+        };
+        assert_eq!(eval(&mut interpreter, "(count-if #t)"), "1");
+        assert_eq!(eval(&mut interpreter, "(count-if #f)"), "nil");
+        assert_eq!(eval(&mut interpreter, "(count-unless #f)"), "11");
+        assert_eq!(eval(&mut interpreter, "(count-unless #t)"), "nil");
+        assert_eq!(eval(&mut interpreter, "counter"), "11");
+    }
+
+    #[test]
+    fn test_eval_loops() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def (sum-while n)
+                (let ((i 0) (sum 0))
+                    (while (< i n)
+                        (set! i (+ i 1))
+                        (set! sum (+ sum i)))
+                    sum))
+            (def (sum-for start end step)
+                (let ((sum 0))
+                    (for (i start end step)
+                        (set! sum (+ sum i)))
+                    sum))
+        "#;
+        interpreter.eval(code).unwrap();
+
+        let eval = |interpreter: &mut Interpreter, code: &str| {
+            interpreter.eval(code).unwrap().borrow().to_string()
+        };
+        // Loops do not grow the stack
+        assert_eq!(eval(&mut interpreter, "(sum-while 10000)"), "50005000");
+        assert_eq!(eval(&mut interpreter, "(sum-for 0 5 1)"), "10");
+        assert_eq!(eval(&mut interpreter, "(sum-for 0 10 3)"), "18");
+        assert_eq!(eval(&mut interpreter, "(sum-for 5 0 -1)"), "15");
+        assert_eq!(eval(&mut interpreter, "(sum-for 5 0 1)"), "0");
+
+        assert_eq!(eval(&mut interpreter, "(while #f 1)"), "nil");
+
+        let err = interpreter.eval("(sum-for 0 5 0)").unwrap_err();
+        assert_eq!(err.message, "for loop step must not be zero");
+        let err = interpreter.eval("(sum-for 0 \"5\" 1)").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Type);
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def-struct point (x y))
+            (def (a . b) (1 . 2))
+            (def [p _ rest...] (vector 3 4 5 6))
+            (def {x y} (create-point 7 8))
+            (def ((h t...) c) (list (list 9 10 11) 12))
+            (let [((u . v) (13 . 14)) ([w] (vector 15))]
+                (vector a b p rest x y h t c u v w))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector 1 2 3 (vector 5 6) 7 8 9 (list 10 11) 12 13 14 15)"
+        );
+
+        let err = interpreter.eval("(def [a b] (vector 1 2 3))").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Type);
+        assert_eq!(err.message, "(vector 1 2 3) does not match pattern [a b]");
+        assert!(interpreter.eval("(def {z} (create-point 1 2))").is_err());
+        assert!(interpreter.eval("(def ((a b)) (list 1))").is_err());
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(def-const limit 10) (def (set-limit! n) (set! limit n))")
+            .unwrap();
+
+        let err = interpreter.eval("(set-limit! 20)").unwrap_err();
+        assert_eq!(err.message, "Cannot assign to constant limit");
+        assert_eq!(
+            interpreter.eval("limit").unwrap().borrow().to_string(),
+            "10"
+        );
+
+        // Local definitions with the same name are not affected
+        let result = interpreter
+            .eval("(def (f) (def limit 1) (set! limit 2) limit) (f)")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "2");
+    }
+
+    #[test]
+    fn test_multi_expression_branches() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def calls 0)
+            (def (classify n)
+                (cond
+                    [(< n 0) (set! calls (+ calls 1)) 'negative]
+                    [(= n 0) (def zero 'zero) zero]
+                    [#t 'positive]))
+            (def (size n)
+                (case n
+                    ((1 2) (set! calls (+ calls 10)) "small")
+                    (else (def s "large") s)))
+            (def (sign n)
+                (if (< n 0) -1 (set! calls (+ calls 100)) 1))
+            (+ (if (equal? (classify -5) 'negative) 1 0)
+               (if (equal? (classify 0) 'zero) 1 0)
+               (if (equal? (size 1) "small") 1 0)
+               (if (equal? (size 3) "large") 1 0)
+               (sign 5)
+               calls)
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "116");
+
+        // Definitions in clauses are local to the clause
+        assert!(interpreter.eval("zero").is_err());
+    }
+
+    #[test]
+    fn test_eval_case() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval(
+                r#"
+                (def (size n)
+                    (case n
+                        ((1 2) "small")
+                        ((3) "medium")
+                        (else "large")))
+                (def (kind x)
+                    (case x
+                        (('a 'b) "letter")
+                        (("a" 1/2 2,5 #t) "literal")
+                        ((nil) "nothing")))
+                "#,
+            )
+            .unwrap();
+
+        let eval = |interpreter: &mut Interpreter, code: &str| {
+            interpreter.eval(code).unwrap().borrow().to_string()
+        };
+        assert_eq!(eval(&mut interpreter, "(size 2)"), "\"small\"");
+        assert_eq!(eval(&mut interpreter, "(size 3)"), "\"medium\"");
+        assert_eq!(eval(&mut interpreter, "(size 4)"), "\"large\"");
+        assert_eq!(eval(&mut interpreter, "(kind 'b)"), "\"letter\"");
+        assert_eq!(eval(&mut interpreter, "(kind \"a\")"), "\"literal\"");
+        assert_eq!(eval(&mut interpreter, "(kind 2/4)"), "\"literal\"");
+        assert_eq!(eval(&mut interpreter, "(kind 2,5)"), "\"literal\"");
+        assert_eq!(eval(&mut interpreter, "(kind #t)"), "\"literal\"");
+        assert_eq!(eval(&mut interpreter, "(kind nil)"), "\"nothing\"");
+        // Without an else clause the result of an unmatched key is nil
+        assert_eq!(eval(&mut interpreter, "(kind 'c)"), "nil");
+    }
+
     #[test]
     fn test_eval_identifier() {
         let mut interpreter = Interpreter::new();
@@ -797,93 +1341,326 @@ mod tests {
     }
 
     #[test]
-    fn test_set_struct_field() {
+    fn test_derived_struct() {
         let mut interpreter = Interpreter::new();
         let code = r#"
             (def-struct person (name first-name))
-            (def ego (create-person "Bollmeier" "Thomas"))
-            (person-set-first-name! ego "Tom")
-            ego
+            (def-struct employee extends person (salary))
+            (def ego (create-employee "Bollmeier" "Thomas" 1000))
+            (employee-set-salary! ego 2000)
+            ((person? ego) . (employee-name ego))
         "#;
         let result = interpreter.eval(code).unwrap();
         let result = borrow_value(&result);
-        assert_eq!(result.get_type(), ValueType::Struct);
+        assert_eq!(result.to_string(), r#"(#true . "Bollmeier")"#);
+
+        let result = interpreter.eval("ego").unwrap();
+        let result = borrow_value(&result);
         assert_eq!(
             result.to_string(),
-            r#"(struct person 'name "Bollmeier" 'first-name "Tom")"#
+            r#"(struct employee 'name "Bollmeier" 'first-name "Thomas" 'salary 2000)"#
         );
+
+        let result = interpreter
+            .eval(r#"(employee? (create-person "Kant" "Immanuel"))"#)
+            .unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.to_string(), "#false");
     }
 
     #[test]
-    fn test_struct_getter() {
+    fn test_derived_struct_repeating_field() {
         let mut interpreter = Interpreter::new();
         let code = r#"
-            (def-struct date (year month day))
-            (def-struct person (name first-name birth-date))
-            (def ego (create-person "Bollmeier" "Thomas" (create-date 1966 7 11)))
-            (struct-get ego '(birth-date year))
+            (def-struct person (name first-name))
+            (def-struct employee extends person (salary))
+            (def-struct manager extends employee (staff name))
         "#;
-        let result = interpreter.eval(code).unwrap();
-        let result = borrow_value(&result);
-        assert_eq!(result.get_type(), ValueType::Int);
-        assert_eq!(result.to_string(), "1966");
+        let err = interpreter.eval(code).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Field name of struct manager is already defined"
+        );
+
+        let err = interpreter
+            .eval("(def-struct team (name members name))")
+            .unwrap_err();
+        assert_eq!(err.message, "Field name of struct team is already defined");
     }
-    #[test]
 
-    fn test_struct_setter() {
+    #[test]
+    fn test_generic_methods() {
         let mut interpreter = Interpreter::new();
         let code = r#"
-            (def-struct date (year month day))
-            (def-struct person (name first-name birth-date))
-            (def ego (create-person "Bollmeier" "Thomas" (create-date 1970 7 11)))
-            (struct-set! ego '(birth-date year) 1966)
-            ego
+            (def-struct shape (name))
+            (def-struct circle extends shape (radius))
+            (def-struct square extends shape (length))
+            (def-struct rect extends shape (width height))
+            (def-method (area circle) (* 3 (circle-radius circle) (circle-radius circle)))
+            (def-method (area square) (* (square-length square) (square-length square)))
+            (def-method (describe shape) (shape-name shape))
+            (area (create-circle "c" 2))
         "#;
         let result = interpreter.eval(code).unwrap();
-        let result = borrow_value(&result);
-        assert_eq!(result.get_type(), ValueType::Struct);
-        assert_eq!(result.to_string(), "(struct person 'name \"Bollmeier\" 'first-name \"Thomas\" 'birth-date (struct date 'year 1966 'month 7 'day 11))");
+        assert_eq!(borrow_value(&result).to_string(), "12");
+
+        let result = interpreter.eval("(area (create-square \"s\" 3))").unwrap();
+        assert_eq!(borrow_value(&result).to_string(), "9");
+
+        let result = interpreter
+            .eval("(describe (create-rect \"r\" 1 2))")
+            .unwrap();
+        assert_eq!(borrow_value(&result).to_string(), "\"r\"");
+
+        let result = interpreter.eval("(area (create-rect \"r\" 1 2))");
+        assert!(result.is_err());
     }
 
     #[test]
-    fn test_create_hash_table() {
+    fn test_method_does_not_replace_function() {
         let mut interpreter = Interpreter::new();
         let code = r#"
-            (def options (create-hash-table))
-            (struct-set! options '(action) "parse")
-            (struct-set! options '(input-file) "code.boli")
-            options
+            (def-struct circle (radius))
+            (def (area x) 0)
+            (def-method (area circle) (* 3 (circle-radius circle) (circle-radius circle)))
         "#;
-        let result = interpreter.eval(code).unwrap();
-        let result = borrow_value(&result);
-        assert_eq!(result.get_type(), ValueType::Struct);
+        let err = interpreter.eval(code).unwrap_err();
         assert_eq!(
-            result.to_string(),
-            "(hash-table 'action \"parse\" 'input-file \"code.boli\")"
+            err.message,
+            "area is already defined and is not a generic function"
         );
+
+        let result = interpreter.eval("(area (create-circle 2))").unwrap();
+        assert_eq!(borrow_value(&result).to_string(), "0");
     }
 
     #[test]
-    fn test_hash_length() {
-        let mut interpreter = Interpreter::new();
+    fn test_get_struct_field_of_collection() {
+        let mut interpreter = Interpreter::with_prelude();
         let code = r#"
-            (def options (create-hash-table))
-            (hash-set! options 'action "parse")
-            (hash-set! options 'input-file "code.boli")
-            (hash-length options)
+            (def-struct person (name first-name))
+            (def philosophers (vector (create-person "Nietzsche" "Friedrich")
+                                      (create-person "Kant" "Immanuel")))
+            (person-name philosophers)
         "#;
         let result = interpreter.eval(code).unwrap();
         let result = borrow_value(&result);
-        assert_eq!(result.get_type(), ValueType::Int);
-        assert_eq!(result.to_string(), "2");
-    }
+        assert_eq!(result.get_type(), ValueType::Vector);
+        assert_eq!(result.to_string(), r#"(vector "Nietzsche" "Kant")"#);
 
-    #[test]
-    fn test_hash_keys() {
-        let mut interpreter = Interpreter::new();
         let code = r#"
-            (def options (create-hash-table))
-            (hash-set! options 'action "parse")
+            (person-first-name (list (create-person "Nietzsche" "Friedrich")
+                                     (create-person "Kant" "Immanuel")))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Pair);
+        assert_eq!(result.to_string(), r#"(list "Friedrich" "Immanuel")"#);
+
+        let code = r#"
+            (stream->vector (take 2 (person-name (vector->stream philosophers))))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.to_string(), r#"(vector "Nietzsche" "Kant")"#);
+    }
+
+    #[test]
+    fn test_set_struct_field() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def-struct person (name first-name))
+            (def ego (create-person "Bollmeier" "Thomas"))
+            (person-set-first-name! ego "Tom")
+            ego
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Struct);
+        assert_eq!(
+            result.to_string(),
+            r#"(struct person 'name "Bollmeier" 'first-name "Tom")"#
+        );
+    }
+
+    #[test]
+    fn test_struct_getter() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def-struct date (year month day))
+            (def-struct person (name first-name birth-date))
+            (def ego (create-person "Bollmeier" "Thomas" (create-date 1966 7 11)))
+            (struct-get ego '(birth-date year))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Int);
+        assert_eq!(result.to_string(), "1966");
+    }
+    #[test]
+
+    fn test_struct_setter() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def-struct date (year month day))
+            (def-struct person (name first-name birth-date))
+            (def ego (create-person "Bollmeier" "Thomas" (create-date 1970 7 11)))
+            (struct-set! ego '(birth-date year) 1966)
+            ego
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Struct);
+        assert_eq!(result.to_string(), "(struct person 'name \"Bollmeier\" 'first-name \"Thomas\" 'birth-date (struct date 'year 1966 'month 7 'day 11))");
+    }
+
+    #[test]
+    fn test_struct_functional_update() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def-struct date (year month day))
+            (def-struct person (name birth-date))
+            (def ego (create-person "Tom" (create-date 1970 7 11)))
+        "#;
+        interpreter.eval(code).unwrap();
+
+        let code = r#"
+            (def older (struct-with ego '(birth-date year) 1966))
+            (def renamed (struct-update (create-person 41 nil) 'name (λ (n) (+ n 1))))
+            (vector (struct-get ego 'name) (struct-get ego '(birth-date year)) older renamed)
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector \"Tom\" 1970 \
+             (struct person 'name \"Tom\" 'birth-date (struct date 'year 1966 'month 7 'day 11)) \
+             (struct person 'name 42 'birth-date nil))"
+        );
+
+        // Mutations return the previous value of the field
+        let result = interpreter
+            .eval("(vector (struct-set! ego 'name \"Thomas\") (person-set-name! ego \"T.\") (person-name ego))")
+            .unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector \"Tom\" \"Thomas\" \"T.\")"
+        );
+
+        let err = interpreter.eval("(struct-with ego 'age 52)").unwrap_err();
+        assert_eq!(err.message, "field 'age' not found in struct");
+    }
+
+    #[test]
+    fn test_struct_printer() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def-struct person (name age))
+            (def-struct employee extends person (salary))
+            (def-struct-printer person
+                (λ (p) (string-concat "<person " (person-name p) ">")))
+            (def-struct-printer employee (λ (e) (list 'employee (person-name e) e)))
+            (vector (create-person "Tom" 52) (create-employee "Ann" 40 1000))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector <person Tom> \
+             (list 'employee \"Ann\" (struct employee 'name \"Ann\" 'age 40 'salary 1000)))"
+        );
+
+        // Failing printers fall back to the default format
+        let code = r#"
+            (def-struct point (x y))
+            (def-struct-printer point (λ (p) (+ p 1)))
+            (create-point 1 2)
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(struct point 'x 1 'y 2)");
+
+        assert!(interpreter.eval("(def-struct-printer point 1)").is_err());
+    }
+
+    #[test]
+    fn test_compound_keys() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def-struct point (x y))
+            (def-struct-printer point (λ (p) "<point>"))
+            (def table (create-hash-table))
+            (hash-set! table (vector 1 2) 'vector)
+            (hash-set! table (list 1 (vector 2)) 'list)
+            (hash-set! table (create-point 1 2) 'point)
+            (hash-set! table (create-point 1 3) 'other-point)
+            (def s (create-set (create-set 1 2) 1,5))
+            (list
+                (hash-get table (vector 1 2))
+                (hash-get table (list 1 (vector 2)))
+                (hash-get table (create-point 1 2))
+                (hash-length table)
+                (set-contains? s (create-set 2 1))
+                (set-contains? s 1,5))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(list 'vector 'list 'point 4 #true #true)"
+        );
+
+        let code = r#"
+            (list
+                (equal? (list 1 "a" (vector 'b 1/2)) (list 1 "a" (vector 'b 2/4)))
+                (equal? (create-point 1 2) (create-point 1 2))
+                (equal? (vector 1) (list 1))
+                (equal? nil nil)
+                (equal? 1 1,0)
+                (= (value-hash (vector 1 2)) (value-hash (vector 1 2))))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            "(list #true #true #false #true #false #true)"
+        );
+    }
+
+    #[test]
+    fn test_create_hash_table() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def options (create-hash-table))
+            (struct-set! options '(action) "parse")
+            (struct-set! options '(input-file) "code.boli")
+            options
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Struct);
+        assert_eq!(
+            result.to_string(),
+            "(hash-table 'action \"parse\" 'input-file \"code.boli\")"
+        );
+    }
+
+    #[test]
+    fn test_hash_length() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def options (create-hash-table))
+            (hash-set! options 'action "parse")
+            (hash-set! options 'input-file "code.boli")
+            (hash-length options)
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Int);
+        assert_eq!(result.to_string(), "2");
+    }
+
+    #[test]
+    fn test_hash_keys() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def options (create-hash-table))
+            (hash-set! options 'action "parse")
             (hash-set! options 'input-file "code.boli")
             (hash-remove! options 'input-file)
             (hash-keys options)
@@ -909,6 +1686,109 @@ mod tests {
         assert_eq!(result.to_string(), "#true");
     }
 
+    #[test]
+    fn test_hash_get_default() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def options (create-hash-table))
+            (hash-set! options 'action "parse")
+            (hash-get options 'input-file "stdin")
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Str);
+        assert_eq!(result.to_string(), "\"stdin\"");
+    }
+
+    #[test]
+    fn test_hash_update() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def counts (create-hash-table))
+            (hash-update! counts 'a (λ (n) (+ n 1)) 0)
+            (hash-update! counts 'a (λ (n) (+ n 1)) 0)
+            (hash-update! counts 'b (λ (n) (+ n 1)) 0)
+            counts
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.to_string(), "(hash-table 'a 2 'b 1)");
+    }
+
+    #[test]
+    fn test_hash_update_with_tail_call() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def (count-down n) (if (> n 0) (count-down (- n 1)) 'done))
+            (def h (create-hash-table))
+            (hash-set! h 'a 3)
+            (hash-update! h 'a count-down)
+            (hash-get-or-insert! h 'b (λ () (count-down 2)))
+            ((hash-get h 'a) . (hash-get h 'b))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.to_string(), "('done . 'done)");
+    }
+
+    #[test]
+    fn test_hash_get_or_insert() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def cache (create-hash-table))
+            (def calls 0)
+            (def (lookup key)
+                (hash-get-or-insert! cache key (λ () (set! calls (+ calls 1)) (* key key))))
+            (lookup 3)
+            (lookup 3)
+            (lookup 4)
+            (calls . (hash-get cache 3))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.to_string(), "(2 . 9)");
+    }
+
+    #[test]
+    fn test_hash_get_or_insert_thunk_modifies_table() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def memo (create-hash-table))
+            (def (fib n)
+                (hash-get-or-insert! memo n
+                    (λ () (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))))
+            (def cache (create-hash-table))
+            (def value
+                (hash-get-or-insert! cache 'a (λ () (hash-set! cache 'a 1) 2)))
+            (vector (fib 30) (hash-get memo 29) value (hash-get cache 'a))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.to_string(), "(vector 832040 514229 1 1)");
+
+        let code = r#"
+            (def frozen (freeze! (create-hash-table)))
+            (def calls 0)
+            (hash-get-or-insert! frozen 'a (λ () (set! calls 1) 1))
+        "#;
+        assert!(interpreter.eval(code).is_err());
+        let calls = interpreter.eval("calls").unwrap();
+        assert_eq!(borrow_value(&calls).to_string(), "0");
+    }
+
+    #[test]
+    fn test_alist_conversion() {
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+            (def h (alist->hash (list ('a . 1) ('b . 2))))
+            (hash->alist (hash-merge h (alist->hash (vector ('b . 3) ('c . 4)))))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result);
+        assert_eq!(result.get_type(), ValueType::Pair);
+        assert_eq!(result.to_string(), "(list ('a . 1) ('b . 3) ('c . 4))");
+    }
+
     #[test]
     fn test_create_set() {
         let mut interpreter = Interpreter::new();
@@ -1014,4 +1894,622 @@ mod tests {
         assert_eq!(result.borrow().get_type(), ValueType::Int);
         assert_eq!(result.borrow().to_string(), "128");
     }
+
+    #[cfg(feature = "catch-panics")]
+    #[test]
+    fn test_panic_becomes_internal_error() {
+        struct Panicking {}
+
+        impl Callable for Panicking {
+            fn call(&self, _args: &Vec<ValueRef>) -> EvalResult {
+                panic!("boom")
+            }
+        }
+
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .env
+            .borrow_mut()
+            .set_builtin("explode", &Rc::new(Panicking {}));
+
+        let err = interpreter.eval("(+ 1 (explode))").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Internal);
+        assert_eq!(err.to_string(), "Internal error: boom");
+
+        let result = interpreter.eval("(+ 1 2)").unwrap();
+        assert_eq!(result.borrow().to_string(), "3");
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut interpreter = Interpreter::new();
+        for (code, status) in [("(exit)", 0), ("(exit 3)", 3), ("(exit #f)", 1)] {
+            let err = interpreter.eval(code).unwrap_err();
+            assert_eq!(err.exit_code(), Some(status), "{}", code);
+        }
+        assert_eq!(
+            interpreter.eval("(exit \"1\")").unwrap_err().kind,
+            ErrorKind::Type
+        );
+
+        // Error handlers do not stop exit, after thunks still run
+        let code = r#"
+            (def cleaned-up #f)
+            (dynamic-wind
+                (lambda () nil)
+                (lambda () (catch-error (lambda () (exit 2))))
+                (lambda () (set! cleaned-up #t)))
+        "#;
+        let err = interpreter.eval(code).unwrap_err();
+        assert_eq!(err.exit_code(), Some(2));
+        let result = interpreter.eval("cleaned-up").unwrap();
+        assert_eq!(result.borrow().to_string(), "#true");
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let mut interpreter = Interpreter::new();
+
+        let err = interpreter.eval("(+ 1 undefined)").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Name);
+        assert_eq!(
+            err.to_string(),
+            "NameError: Undefined identifier: undefined"
+        );
+
+        let err = interpreter.eval("(+ 1 \"2\")").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Type);
+        assert_eq!(err.value.as_ref().unwrap().borrow().to_string(), "\"2\"");
+
+        let err = interpreter.eval("((lambda (x) x))").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Arity);
+
+        let err = interpreter.eval("(def x 1)\n(+ x 2))").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Syntax);
+        assert_eq!(err.span.unwrap().line, 2);
+
+        let err = interpreter.eval("(error \"boom\" 42)").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UserRaised);
+        assert_eq!(err.to_string(), "boom");
+
+        interpreter
+            .eval("(def e (catch-error (lambda () (error \"boom\" 42))))")
+            .unwrap();
+        for (code, expected) in [
+            ("(error? e)", "#true"),
+            ("(error-kind e)", "'user-error"),
+            ("(error-message e)", "\"boom\""),
+            ("(error-value e)", "42"),
+        ] {
+            let result = interpreter.eval(code).unwrap();
+            assert_eq!(result.borrow().to_string(), expected);
+        }
+
+        let result = interpreter
+            .eval("(error-kind (catch-error (lambda () (idiv 1))))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "'eval-error");
+
+        let result = interpreter.eval("(catch-error (lambda () 42))").unwrap();
+        assert_eq!(result.borrow().to_string(), "42");
+    }
+
+    #[test]
+    fn test_strict_bool() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("(def n 0)").unwrap();
+        let result = interpreter.eval("(if n 'yes 'no)").unwrap();
+        assert_eq!(result.borrow().to_string(), "'no");
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_settings(Settings::new().strict_bool(true));
+        interpreter.eval("(def n 0)").unwrap();
+        let result = interpreter.eval("(if (= n 0) 'yes 'no)").unwrap();
+        assert_eq!(result.borrow().to_string(), "'yes");
+        for code in [
+            "(if n 'yes 'no)",
+            "(cond (nil 1) (#t 2))",
+            "(and #t 1 #t)",
+            "(while n (set! n 1))",
+            "((lambda () (if n 'yes 'no)))",
+        ] {
+            let err = interpreter.eval(code).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Type, "{}", code);
+        }
+        let err = interpreter.eval("(if n 'yes 'no)").unwrap_err();
+        assert_eq!(err.message, "if expects a boolean condition, got 0");
+    }
+
+    #[test]
+    fn test_hoist_functions() {
+        let code = r#"
+            (def (f n)
+                (def r (even? n))
+                (def (even? n) (if (= n 0) #t (odd? (- n 1))))
+                (def (odd? n) (if (= n 0) #f (even? (- n 1))))
+                r)
+            (f 10)
+        "#;
+        let err = Interpreter::new().eval(code).unwrap_err();
+        assert_eq!(err.message, "Undefined identifier: even?");
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_settings(Settings::new().hoist_functions(true));
+        let result = interpreter.eval(code);
+        assert_eq!(result.unwrap().borrow().to_string(), "#true");
+
+        // Other definitions are still evaluated in order
+        let result = interpreter.eval("(block (def a b) (def b 1) a)");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cleanup_on_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(def closed 0) (def (close!) (set! closed (+ closed 1)))")
+            .unwrap();
+
+        let result = interpreter
+            .eval("(unwind-protect (+ 1 2) (close!))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "3");
+
+        let err = interpreter
+            .eval("(unwind-protect (error \"boom\") (close!) (close!))")
+            .unwrap_err();
+        assert_eq!(err.message, "boom");
+        assert_eq!(
+            interpreter.eval("closed").unwrap().borrow().to_string(),
+            "3"
+        );
+
+        let result = interpreter
+            .eval("(dynamic-wind close! (λ () (error \"boom\")) close!)")
+            .unwrap_err();
+        assert_eq!(result.kind, ErrorKind::UserRaised);
+        assert_eq!(
+            interpreter.eval("closed").unwrap().borrow().to_string(),
+            "5"
+        );
+
+        let result = interpreter
+            .eval("(with-exception-handler error-message (λ () (error \"boom\")))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "\"boom\"");
+        let result = interpreter
+            .eval("(with-exception-handler error-message (λ () 42))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "42");
+    }
+
+    #[test]
+    fn test_arity_errors() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def (f x) x)
+            (def (f x y z) x)
+            (def (g x ys...) x)
+            (def-struct point (x y))
+        "#;
+        interpreter.eval(code).unwrap();
+
+        for (code, expected) in [
+            ("(f 1 2)", "f expects 1 or 3 arguments, got 2"),
+            ("(g)", "g expects at least 1 argument, got 0"),
+            ("((lambda () 1) 1)", "lambda expects 0 arguments, got 1"),
+            (
+                "(create-point 1)",
+                "create-point expects 2 arguments, got 1",
+            ),
+        ] {
+            let err = interpreter.eval(code).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Arity);
+            assert_eq!(err.message, expected);
+        }
+    }
+
+    #[test]
+    fn test_real_format() {
+        let mut interpreter = Interpreter::new();
+        let format = RealFormat::new()
+            .precision(2)
+            .decimal_separator('.')
+            .scientific_threshold(1e6);
+        interpreter.set_real_format(format);
+
+        let texts: Vec<String> = ["1,0", "3,14159", "1234567,0"]
+            .iter()
+            .map(|code| {
+                let result = interpreter.eval(code).unwrap();
+                interpreter.value_to_string(&result)
+            })
+            .collect();
+        assert_eq!(texts, vec!["1.00", "3.14", "1.23e6"]);
+
+        let result = interpreter.eval("(number->string 2,5)").unwrap();
+        assert_eq!(result.borrow().to_string(), "\"2.50\"");
+
+        // Other interpreters on the same thread keep the default format
+        let mut other = Interpreter::new();
+        let result = other.eval("(number->string (/ 1,0 4))").unwrap();
+        assert_eq!(result.borrow().to_string(), "\"0,25\"");
+        assert_eq!(
+            interpreter.value_to_string(&other.eval("0,5").unwrap()),
+            "0.50"
+        );
+    }
+
+    #[test]
+    fn test_introspection() {
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.eval("(boli-version)").unwrap();
+        assert_eq!(
+            result.borrow().to_string(),
+            format!("\"{}\"", env!("CARGO_PKG_VERSION"))
+        );
+
+        let result = interpreter.eval("(features)").unwrap();
+        assert!(result.borrow().to_string().contains("\"stream\""));
+
+        let result = interpreter.eval("(platform)").unwrap();
+        assert!(result.borrow().to_string().contains(std::env::consts::OS));
+    }
+
+    #[test]
+    fn test_lexical_addressing() {
+        let mut interpreter = Interpreter::with_prelude();
+
+        let code = r#"
+            (def (make-adder a)
+                (lambda (b) (+ a b)))
+            (def (shadow a)
+                (def b a)
+                (let ((a 10))
+                    (+ a b)))
+            (def (counter n)
+                (set! n (+ n 1))
+                n)
+            (def (sum-all first rest...)
+                (if (nil? first) 0 (+ first (count rest))))
+            (+ ((make-adder 1) 2) (shadow 5) (counter 41) (sum-all 1 2 3))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "63");
+    }
+
+    #[test]
+    fn test_parameters_looked_up_by_name() {
+        let mut interpreter = Interpreter::with_prelude();
+
+        // The parameter is read by name before the block defines its own a
+        let code = r#"
+            (def (redefine a)
+                (def b a)
+                (def a (+ b 1))
+                (list a b))
+            (def (evaluate x)
+                (eval '(+ 1 2))
+                (env-lookup (current-environment) 'x))
+            (list (redefine 1) (evaluate 42))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(list (list 2 1) 42)");
+    }
+
+    #[test]
+    fn test_call_frames_use_builtins_of_root() {
+        let mut interpreter = Interpreter::with_prelude();
+
+        // Call frames contain only their own bindings, builtins that act on
+        // an environment get the one they are called in
+        let code = r#"
+            (def (frame x)
+                (def y (eval '(* x 2)))
+                (env-bindings (current-environment)))
+            (frame 21)
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(hash-table 'y 42)");
+    }
+
+    #[test]
+    fn test_with_output_to_string() {
+        let mut interpreter = Interpreter::with_prelude();
+
+        let code = r#"
+            (def (greet name)
+                (display "Hello, ")
+                (displayln name))
+            (def inner "")
+            (def outer
+                (with-output-to-string
+                    (lambda ()
+                        (write "a")
+                        (set! inner (with-output-to-string (lambda () (greet "World"))))
+                        (pretty-print (list 1 2)))))
+        "#;
+        interpreter.eval(code).unwrap();
+        let outer = interpreter.eval("outer").unwrap();
+        assert_eq!(outer.borrow().display_string(), "\"a\"(list 1 2)\n");
+        let inner = interpreter.eval("inner").unwrap();
+        assert_eq!(inner.borrow().display_string(), "Hello, World\n");
+
+        let result = interpreter.eval(r#"(with-output-to-string (lambda () (error "boom")))"#);
+        assert!(result.is_err());
+        let result = interpreter.eval(r#"(with-output-to-string (lambda () (display "ok")))"#);
+        assert_eq!(result.unwrap().borrow().to_string(), r#""ok""#);
+    }
+
+    #[test]
+    fn test_print() {
+        let buffered_output = BufferedOutput::new();
+        let output: OutputRef = Rc::new(std::cell::RefCell::new(buffered_output.clone()));
+        let env = EnvironmentBuilder::new()
+            .with_prelude(true)
+            .output(&output)
+            .build();
+        let code = r#"
+            (print "x =" 42)
+            (newline)
+            (println "list:" (list 1 "a") 'sym)
+            (println)
+            (display "a" "b")
+        "#;
+        Interpreter::with_environment(&env).eval(code).unwrap();
+        assert_eq!(
+            buffered_output.text(),
+            "x = 42\nlist: (list 1 \"a\") 'sym\n\nab"
+        );
+    }
+
+    #[test]
+    fn test_error_output() {
+        let buffered_output = BufferedOutput::new();
+        let error_output: OutputRef = Rc::new(std::cell::RefCell::new(buffered_output.clone()));
+        let env = EnvironmentBuilder::new()
+            .with_prelude(true)
+            .error_output(&error_output)
+            .build();
+
+        // Capturing the output does not capture the error output
+        let code = r#"(with-output-to-string (lambda () (eprint "a" 1) (eprintln) (display "b")))"#;
+        let result = Interpreter::with_environment(&env).eval(code).unwrap();
+        assert_eq!(result.borrow().display_string(), "b");
+        assert_eq!(buffered_output.text(), "a 1\n");
+    }
+
+    #[test]
+    fn test_redefinition_warning() {
+        let buffered_output = BufferedOutput::new();
+        let error_output: OutputRef = Rc::new(std::cell::RefCell::new(buffered_output.clone()));
+        let lenient = EnvironmentBuilder::new()
+            .error_output(&error_output)
+            .build();
+        let strict = EnvironmentBuilder::new()
+            .error_output(&error_output)
+            .strict(true)
+            .build();
+        let code = "(def (f x) x) (def (f y) y)";
+
+        Interpreter::with_environment(&lenient).eval(code).unwrap();
+        assert_eq!(
+            buffered_output.take(),
+            "warning: redefinition of f replaces its definition for 1 argument\n"
+        );
+
+        let err = Interpreter::with_environment(&strict)
+            .eval(code)
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "redefinition of f replaces its definition for 1 argument"
+        );
+        assert_eq!(buffered_output.text(), "");
+    }
+
+    #[test]
+    fn test_captured_output() {
+        let (mut interpreter, output) = Interpreter::with_captured_output();
+        interpreter
+            .eval("(def (greet name) (println \"Hello,\" name)) (greet \"World\")")
+            .unwrap();
+        assert_eq!(output.text(), "Hello, World\n");
+
+        interpreter.eval("(greet \"BOLI\") (display 42)").unwrap();
+        assert_eq!(output.take(), "Hello, World\nHello, BOLI\n42");
+        assert_eq!(output.text(), "");
+    }
+
+    #[test]
+    fn test_call() {
+        let mut interpreter = Interpreter::with_prelude();
+        interpreter
+            .eval("(def (add a b) (+ a b)) (def (count-down n) (if (> n 0) (count-down (- n 1)) 'done)) (def answer 42)")
+            .unwrap();
+
+        let args = [
+            new_valueref(IntValue { value: 1 }),
+            new_valueref(IntValue { value: 2 }),
+        ];
+        let result = interpreter.call("add", &args).unwrap();
+        assert_eq!(result.borrow().to_string(), "3");
+
+        let args = [new_valueref(IntValue { value: 10000 })];
+        let result = interpreter.call("count-down", &args).unwrap();
+        assert_eq!(result.borrow().to_string(), "'done");
+
+        let result = interpreter.call("max", &[new_valueref(IntValue { value: 7 })]);
+        assert_eq!(result.unwrap().borrow().to_string(), "7");
+
+        let answer = interpreter.get_global("answer").unwrap();
+        assert_eq!(answer.borrow().to_string(), "42");
+        assert!(interpreter.get_global("question").is_none());
+
+        let err = interpreter.call("question", &[]).unwrap_err();
+        assert_eq!(err.message, "Undefined identifier: question");
+        let err = interpreter.call("answer", &[]).unwrap_err();
+        assert_eq!(err.message, "answer is not a function");
+        let err = interpreter.call("add", &[]).unwrap_err();
+        assert!(!err.message.is_empty());
+    }
+
+    #[test]
+    fn test_limits() {
+        let env = EnvironmentBuilder::new()
+            .with_prelude(true)
+            .limits(Limits::new().max_steps(10000).max_call_depth(50))
+            .build();
+        let mut interpreter = Interpreter::with_environment(&env);
+        interpreter
+            .eval("(def (loop n) (if (> n 0) (loop (- n 1)) 'done)) (def (depth n) (if (> n 0) (+ 1 (depth (- n 1))) 0))")
+            .unwrap();
+
+        // Tail calls do not count towards the call depth
+        let result = interpreter.eval("(loop 100)").unwrap();
+        assert_eq!(result.borrow().to_string(), "'done");
+        let result = interpreter.eval("(depth 40)").unwrap();
+        assert_eq!(result.borrow().to_string(), "40");
+
+        let err = interpreter.eval("(depth 60)").unwrap_err();
+        assert_eq!(err.message, "call depth limit of 50 exceeded");
+        let err = interpreter.eval("(loop 100000)").unwrap_err();
+        assert_eq!(err.message, "evaluation step limit of 10000 exceeded");
+
+        // Every evaluation gets the full budget
+        let result = interpreter.eval("(loop 100)").unwrap();
+        assert_eq!(result.borrow().to_string(), "'done");
+
+        interpreter.set_limits(Limits::new().max_heap_values(1000));
+        let err = interpreter
+            .eval("(def (fill l n) (if (> n 0) (fill (cons n l) (- n 1)) l)) (fill nil 10000)")
+            .unwrap_err();
+        assert_eq!(err.message, "heap value limit of 1000 exceeded");
+        let err = interpreter
+            .call("loop", &[new_valueref(IntValue { value: 10000 })])
+            .unwrap_err();
+        assert_eq!(err.message, "heap value limit of 1000 exceeded");
+    }
+
+    #[test]
+    fn test_eval_with_timeout() {
+        let mut interpreter = Interpreter::with_prelude();
+        interpreter
+            .eval("(def (forever n) (forever (+ n 1)))")
+            .unwrap();
+
+        let timeout = Duration::from_millis(50);
+        let err = interpreter
+            .eval_with_timeout("(forever 0)", timeout)
+            .unwrap_err();
+        assert_eq!(err.message, "evaluation timed out");
+        let err = interpreter
+            .eval_with_timeout("(frequencies (iterator 0 (λ (n) (+ n 1))))", timeout)
+            .unwrap_err();
+        assert_eq!(err.message, "evaluation timed out");
+        let err = interpreter
+            .eval_with_timeout("(frequencies (repeat 1))", timeout)
+            .unwrap_err();
+        assert_eq!(err.message, "evaluation timed out");
+
+        let result = interpreter.eval_with_timeout("(+ 1 2)", timeout).unwrap();
+        assert_eq!(result.borrow().to_string(), "3");
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = interpreter
+            .eval_with_cancellation("(forever 0)", &token)
+            .unwrap_err();
+        assert_eq!(err.message, "evaluation cancelled");
+        token.reset();
+        let result = interpreter
+            .eval_with_cancellation("(+ 1 2)", &token)
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "3");
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let defined = |env: &EnvironmentRef, name: &str| env.borrow().get(name).is_some();
+
+        let env = EnvironmentBuilder::new().with_prelude(true).build();
+        assert!(defined(&env, "file-exists?"));
+        assert!(defined(&env, "getenv"));
+
+        let env = EnvironmentBuilder::new()
+            .with_prelude(true)
+            .allow_fs(false)
+            .allow_net(false)
+            .allow_exec(false)
+            .build();
+        assert!(!defined(&env, "file-exists?"));
+        assert!(!defined(&env, "process-run"));
+        assert!(defined(&env, "getenv"));
+        assert!(defined(&env, "map"));
+
+        let err = Interpreter::with_environment(&env)
+            .eval("(delete-file \"data.txt\")")
+            .unwrap_err();
+        assert_eq!(err.message, "Undefined identifier: delete-file");
+
+        // Environments created by the script get the same capabilities
+        let mut interpreter = Interpreter::with_environment(&env);
+        let mut eval = |code: &str| interpreter.eval(code).unwrap().borrow().to_string();
+        assert_eq!(
+            eval("(env-lookup (make-environment) 'system 'undefined)"),
+            "'undefined"
+        );
+        assert_eq!(
+            eval("(env-lookup (make-environment) 'file-exists? 'undefined)"),
+            "'undefined"
+        );
+        let err = Interpreter::with_environment(&env)
+            .eval("(eval '(file-exists? \"/etc/passwd\") 'fresh)")
+            .unwrap_err();
+        assert_eq!(err.message, "Undefined identifier: file-exists?");
+        let err = Interpreter::with_environment(&env)
+            .eval("(eval '(system \"echo escaped >&2\") 'fresh)")
+            .unwrap_err();
+        assert_eq!(err.message, "Undefined identifier: system");
+    }
+
+    #[test]
+    fn test_read_lines() {
+        let input: misc_functions::InputRef = Rc::new(std::cell::RefCell::new(
+            misc_functions::StringInput::new("alpha\nbeta\ngamma\n"),
+        ));
+        let env = EnvironmentBuilder::new()
+            .with_prelude(true)
+            .input(&input)
+            .build();
+        let mut interpreter = Interpreter::with_environment(&env);
+        let mut eval = |code: &str| interpreter.eval(code).unwrap().borrow().to_string();
+
+        assert_eq!(eval("(read-line)"), "\"alpha\"");
+        // Lines are only read when the stream is traversed
+        assert_eq!(eval("(def lines (read-lines)) (head lines)"), "\"beta\"");
+        assert_eq!(eval("(read-line)"), "\"gamma\"");
+        assert_eq!(eval("(stream-empty? (tail lines))"), "#true");
+        assert_eq!(eval("(eof-object? (read-line))"), "#true");
+        assert_eq!(eval("(eof-object? \"\")"), "#false");
+    }
+
+    #[test]
+    fn test_deep_mutual_recursion() {
+        // Test threads have a small stack, use the size of the main thread
+        let handle = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| {
+                let mut interpreter = Interpreter::with_prelude();
+                let code = r#"
+                    (def (even? n) (if (= n 0) #t (not (odd? (- n 1)))))
+                    (def (odd? n) (if (= n 0) #f (not (even? (- n 1)))))
+                    (even? 1100)
+                "#;
+                interpreter.eval(code).unwrap().borrow().to_string()
+            })
+            .unwrap();
+        assert_eq!(handle.join().unwrap(), "#true");
+    }
 }
@@ -1,15 +1,20 @@
 use std::{
     cell::RefCell,
     collections::HashMap,
-    fs::{read_dir, File as FsFile},
+    fs::{canonicalize, metadata, read, read_dir, File as FsFile},
     io::{BufReader, Read},
     rc::Rc,
+    time::SystemTime,
 };
 
 use super::{
     ModuleDirRef, ModuleDirectory, ModuleFile, ModuleFileRef, ModuleObject, ModuleObjectType,
 };
 
+pub fn join_path(path: &str, name: &str) -> String {
+    path.to_string() + std::path::MAIN_SEPARATOR_STR + name
+}
+
 pub fn new_directory(path: &str, name: &str) -> Rc<RefCell<Directory>> {
     Rc::new(RefCell::new(Directory::new(path, name)))
 }
@@ -33,10 +38,34 @@ impl Directory {
         }
     }
 
+    pub fn full_path(&self) -> String {
+        join_path(&self.path, &self.name)
+    }
+
+    pub fn dir_names(&self) -> Vec<String> {
+        if !*self.initialized.borrow() {
+            self.initialize();
+        }
+
+        let mut names: Vec<String> = self.dirs.borrow().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn file_names(&self) -> Vec<String> {
+        if !*self.initialized.borrow() {
+            self.initialize();
+        }
+
+        let mut names: Vec<String> = self.files.borrow().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
     fn initialize(&self) {
         *self.initialized.borrow_mut() = true;
 
-        let full_path = self.path.clone() + "/" + &self.name;
+        let full_path = join_path(&self.path, &self.name);
 
         let entries = read_dir(full_path.clone());
         if entries.is_err() {
@@ -145,7 +174,7 @@ impl ModuleObject for File {
 impl ModuleFile for File {
     fn read(&self) -> String {
         let mut buffer = String::new();
-        let file_path = self.path.clone() + std::path::MAIN_SEPARATOR_STR + &self.name;
+        let file_path = join_path(&self.path, &self.name);
         match FsFile::open(&file_path) {
             Ok(file) => {
                 let mut reader = BufReader::new(file);
@@ -157,4 +186,22 @@ impl ModuleFile for File {
             Err(_) => "".to_string(),
         }
     }
+
+    fn canonical_path(&self) -> String {
+        let file_path = join_path(&self.path, &self.name);
+        match canonicalize(&file_path) {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(_) => file_path,
+        }
+    }
+
+    fn read_bytes(&self) -> Vec<u8> {
+        read(join_path(&self.path, &self.name)).unwrap_or_default()
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        metadata(join_path(&self.path, &self.name))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
 }
@@ -0,0 +1,137 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::{
+    ExtensionModuleRef, ModuleDirRef, ModuleDirectory, ModuleFile, ModuleFileRef, ModuleObject,
+    ModuleObjectType,
+};
+
+pub fn new_memory_directory(name: &str) -> Rc<RefCell<MemoryDirectory>> {
+    Rc::new(RefCell::new(MemoryDirectory::new(name, name)))
+}
+
+// Module directory whose files are kept in memory instead of the file
+// system, e.g. for the WebAssembly build that has no file system
+pub struct MemoryDirectory {
+    path: String,
+    name: String,
+    dirs: HashMap<String, Rc<RefCell<MemoryDirectory>>>,
+    files: HashMap<String, ModuleFileRef>,
+}
+
+impl MemoryDirectory {
+    fn new(path: &str, name: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            name: name.to_string(),
+            dirs: HashMap::new(),
+            files: HashMap::new(),
+        }
+    }
+
+    // Adds or replaces a file, path is relative to the directory and uses /
+    // as separator (e.g. "util/strings.boli")
+    pub fn add_file(&mut self, path: &str, content: &str) {
+        match path.split_once('/') {
+            Some((dir_name, rest)) => {
+                let dir_path = format!("{}/{}", self.path, dir_name);
+                self.dirs
+                    .entry(dir_name.to_string())
+                    .or_insert_with(|| {
+                        Rc::new(RefCell::new(MemoryDirectory::new(&dir_path, dir_name)))
+                    })
+                    .borrow_mut()
+                    .add_file(rest, content);
+            }
+            None => {
+                let file = MemoryFile {
+                    path: format!("{}/{}", self.path, path),
+                    name: path.to_string(),
+                    content: content.to_string(),
+                };
+                self.files
+                    .insert(path.to_string(), Rc::new(RefCell::new(file)));
+            }
+        }
+    }
+}
+
+impl ModuleObject for MemoryDirectory {
+    fn get_type(&self) -> ModuleObjectType {
+        ModuleObjectType::Directory
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl ModuleDirectory for MemoryDirectory {
+    fn get_dir(&self, name: &str) -> Option<ModuleDirRef> {
+        self.dirs.get(name).map(|dir| dir.clone() as ModuleDirRef)
+    }
+
+    fn get_file(&self, name: &str) -> Option<ModuleFileRef> {
+        self.files.get(name).cloned()
+    }
+
+    fn get_extension(&self, _name: &str) -> Option<ExtensionModuleRef> {
+        None
+    }
+}
+
+struct MemoryFile {
+    path: String,
+    name: String,
+    content: String,
+}
+
+impl ModuleObject for MemoryFile {
+    fn get_type(&self) -> ModuleObjectType {
+        ModuleObjectType::File
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl ModuleFile for MemoryFile {
+    fn read(&self) -> String {
+        self.content.clone()
+    }
+
+    fn canonical_path(&self) -> String {
+        format!("memory:{}", self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{
+        environment::EnvironmentBuilder, module_mgmt::module_loader::ModuleLoader,
+    };
+
+    #[test]
+    fn test_load_module_from_memory() {
+        let modules = new_memory_directory("");
+        modules
+            .borrow_mut()
+            .add_file("util/math.boli", "(def (square x) (* x x))");
+        let env = EnvironmentBuilder::new()
+            .search_dirs(&vec![modules.clone()])
+            .build();
+
+        let values = ModuleLoader::new(&env).load_module("util::math").unwrap();
+        assert!(values.contains_key("square"));
+        assert!(ModuleLoader::new(&env).load_module("math").is_err());
+    }
+}
@@ -1,10 +1,14 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::SystemTime};
 
 use super::ValueRef;
 
 pub mod extension;
 pub mod file_system;
+pub mod memory;
 pub mod module_loader;
+#[cfg(feature = "native")]
+pub mod native;
+pub mod search_path;
 
 #[derive(PartialEq, Debug)]
 pub enum ModuleObjectType {
@@ -29,6 +33,17 @@ pub type ModuleDirRef = Rc<RefCell<dyn ModuleDirectory>>;
 
 pub trait ModuleFile: ModuleObject {
     fn read(&self) -> String;
+
+    // Identifies the file independent of the search directory it is found in
+    fn canonical_path(&self) -> String;
+
+    fn read_bytes(&self) -> Vec<u8> {
+        self.read().into_bytes()
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        None
+    }
 }
 
 pub type ModuleFileRef = Rc<RefCell<dyn ModuleFile>>;
@@ -1,15 +1,26 @@
+#[cfg(feature = "native")]
+use super::native;
 use crate::{
-    frontend::lexer::tokens::TokenType,
+    frontend::{
+        lexer::tokens::TokenType,
+        parser::{
+            ast::Program,
+            binary::{deserialize_program, COMPILED_EXTENSION},
+            Parser,
+        },
+    },
     interpreter::{
         borrow_value, downcast_value,
-        environment::{EnvironmentBuilder, EnvironmentRef},
+        environment::{upgrade_env, EnvironmentBuilder, EnvironmentRef, WeakEnvironmentRef},
         error,
-        module_mgmt::ModuleDirRef,
+        module_mgmt::{ModuleDirRef, ModuleFileRef},
         new_valueref, Callable, Interpreter, InterpreterError, NilValue, QuoteValue, SymbolValue,
         ValueRef, VectorValue,
     },
 };
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 pub struct ModuleLoader {
     env: EnvironmentRef,
@@ -21,33 +32,26 @@ impl ModuleLoader {
     }
 
     pub fn load_module(&self, path: &str) -> Result<HashMap<String, ValueRef>, InterpreterError> {
-        let mut load_error: Option<InterpreterError> = None;
         let module_search_dirs = self.env.borrow().get_module_search_dirs();
 
         for dir in &module_search_dirs {
-            match self.load_module_in_dir(dir, path) {
-                Ok(value_map) => return Ok(value_map),
-                Err(err) => {
-                    load_error = Some(err);
-                    continue;
-                }
+            if let Some(value_map) = self.load_module_in_dir(dir, path, path)? {
+                return Ok(value_map);
             }
         }
 
-        match load_error {
-            Some(err) => Err(err),
-            None => Err(InterpreterError::new(&format!(
-                "module '{}' not found",
-                path
-            ))),
-        }
+        Err(InterpreterError::new(&format!(
+            "module '{}' not found",
+            path
+        )))
     }
 
     fn load_module_in_dir(
         &self,
         dir: &ModuleDirRef,
         path: &str,
-    ) -> Result<HashMap<String, ValueRef>, InterpreterError> {
+        module_path: &str,
+    ) -> Result<Option<HashMap<String, ValueRef>>, InterpreterError> {
         let path_segments = path.split("::").collect::<Vec<&str>>();
 
         if path_segments.len() == 0 {
@@ -56,54 +60,236 @@ impl ModuleLoader {
 
         if path_segments.len() == 1 {
             let module_name = path_segments[0];
-            let module_file = module_name.to_string() + ".boli";
-
-            if let Some(module_file) = dir.borrow().get_file(&module_file) {
-                let module_code = module_file.borrow().read();
-                let module_env = EnvironmentBuilder::new().parent(&self.env).build();
-                let mut interpreter = Interpreter::with_environment(&module_env);
-                interpreter.eval(&module_code)?;
-                return Ok(interpreter.env.clone().borrow().get_exported_values());
+            let source_file = dir.borrow().get_file(&format!("{}.boli", module_name));
+            let compiled_file = dir
+                .borrow()
+                .get_file(&format!("{}.{}", module_name, COMPILED_EXTENSION));
+
+            if let Some(module_file) = source_file.as_ref().or(compiled_file.as_ref()) {
+                let canonical_path = module_file.borrow().canonical_path();
+                return self
+                    .load_module_file(&canonical_path, module_path, source_file, compiled_file)
+                    .map(Some);
             }
 
             if let Some(ext_module) = dir.borrow().get_extension(module_name) {
-                return Ok(ext_module.borrow().get_values());
+                return Ok(Some(ext_module.borrow().get_values()));
             }
 
-            return Err(InterpreterError::new(&format!(
-                "module '{}' not found",
-                module_name
-            )));
+            #[cfg(feature = "native")]
+            if let Some(library_file) = dir
+                .borrow()
+                .get_file(&native::library_file_name(module_name))
+            {
+                if !self.env.borrow().get_capabilities().native {
+                    return Err(InterpreterError::new(&format!(
+                        "loading native module '{}' is not allowed",
+                        module_path
+                    )));
+                }
+                let canonical_path = library_file.borrow().canonical_path();
+                return self
+                    .load_native_module(&canonical_path, module_name)
+                    .map(Some);
+            }
+
+            return Ok(None);
         }
 
         let dir_name = path_segments[0];
 
         match dir.borrow().get_dir(&dir_name) {
             Some(sub_dir) => {
-                return self.load_module_in_dir(&sub_dir, &path_segments[1..].join("::"));
+                self.load_module_in_dir(&sub_dir, &path_segments[1..].join("::"), module_path)
             }
-            None => {
-                return Err(InterpreterError::new(&format!(
-                    "directory '{}' not found",
-                    dir_name
-                )))
+            None => Ok(None),
+        }
+    }
+
+    // Every module file is evaluated only once, later requires get the
+    // exported values from the module cache
+    fn load_module_file(
+        &self,
+        canonical_path: &str,
+        module_path: &str,
+        source_file: Option<ModuleFileRef>,
+        compiled_file: Option<ModuleFileRef>,
+    ) -> Result<HashMap<String, ValueRef>, InterpreterError> {
+        let module_cache = self.env.borrow().get_module_cache();
+
+        if let Some(value_map) = module_cache.borrow().get(canonical_path) {
+            return Ok(value_map);
+        }
+        module_cache
+            .borrow_mut()
+            .start_loading(canonical_path, module_path)?;
+
+        let result = self
+            .read_module_program(source_file, compiled_file)
+            .and_then(|program| {
+                let module_env = EnvironmentBuilder::new().parent(&self.env).build();
+                let mut interpreter = Interpreter::with_environment(&module_env);
+                interpreter.eval_program(program)?;
+                let value_map = interpreter.env.borrow().get_exported_values();
+                Ok(value_map)
+            });
+
+        module_cache
+            .borrow_mut()
+            .finish_loading(canonical_path, result.as_ref().ok());
+        result
+    }
+
+    #[cfg(feature = "native")]
+    fn load_native_module(
+        &self,
+        canonical_path: &str,
+        module_name: &str,
+    ) -> Result<HashMap<String, ValueRef>, InterpreterError> {
+        let module_cache = self.env.borrow().get_module_cache();
+
+        if let Some(value_map) = module_cache.borrow().get(canonical_path) {
+            return Ok(value_map);
+        }
+        let value_map = native::load_module(canonical_path, module_name, &self.env)?;
+
+        module_cache
+            .borrow_mut()
+            .finish_loading(canonical_path, Some(&value_map));
+        Ok(value_map)
+    }
+
+    // A compiled module is preferred to its source if it is not older
+    // than the source. Unreadable compiled modules are ignored.
+    fn read_module_program(
+        &self,
+        source_file: Option<ModuleFileRef>,
+        compiled_file: Option<ModuleFileRef>,
+    ) -> Result<Program, InterpreterError> {
+        if let Some(compiled_file) = compiled_file {
+            let compiled_file = compiled_file.borrow();
+            let up_to_date = match &source_file {
+                Some(source_file) => {
+                    match (compiled_file.modified(), source_file.borrow().modified()) {
+                        (Some(compiled), Some(source)) => compiled >= source,
+                        _ => false,
+                    }
+                }
+                None => true,
+            };
+            if up_to_date {
+                match deserialize_program(&compiled_file.read_bytes()) {
+                    Ok(program) => return Ok(program),
+                    Err(err) if source_file.is_none() => {
+                        return Err(InterpreterError::syntax_error(&err))
+                    }
+                    Err(_) => {}
+                }
             }
         }
+
+        match source_file {
+            Some(source_file) => {
+                let module_code = source_file.borrow().read();
+                Parser::new()
+                    .parse(&module_code)
+                    .map_err(|err| InterpreterError::syntax_error(&err))
+            }
+            None => Err(InterpreterError::new("module file not found")),
+        }
+    }
+}
+
+// Exported values of the loaded modules keyed by the canonical path of the
+// module file. Modules that are still being loaded are kept on a stack to
+// detect circular imports.
+pub struct ModuleCache {
+    modules: HashMap<String, HashMap<String, ValueRef>>,
+    loading: Vec<(String, String)>,
+}
+
+pub type ModuleCacheRef = Rc<RefCell<ModuleCache>>;
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self {
+            modules: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, canonical_path: &str) -> Option<HashMap<String, ValueRef>> {
+        self.modules.get(canonical_path).cloned()
+    }
+
+    // Makes the next require of every module read its file again
+    pub fn clear(&mut self) {
+        self.modules.clear();
+    }
+
+    fn start_loading(
+        &mut self,
+        canonical_path: &str,
+        module_path: &str,
+    ) -> Result<(), InterpreterError> {
+        if let Some(start) = self
+            .loading
+            .iter()
+            .position(|(path, _)| path == canonical_path)
+        {
+            let cycle = self.loading[start..]
+                .iter()
+                .map(|(_, module_path)| module_path.as_str())
+                .chain(std::iter::once(module_path))
+                .collect::<Vec<&str>>()
+                .join(" -> ");
+            return Err(InterpreterError::new(&format!(
+                "circular import: {}",
+                cycle
+            )));
+        }
+
+        self.loading
+            .push((canonical_path.to_string(), module_path.to_string()));
+        Ok(())
+    }
+
+    fn finish_loading(
+        &mut self,
+        canonical_path: &str,
+        value_map: Option<&HashMap<String, ValueRef>>,
+    ) {
+        self.loading.retain(|(path, _)| path != canonical_path);
+        if let Some(value_map) = value_map {
+            self.modules
+                .insert(canonical_path.to_string(), value_map.clone());
+        }
     }
 }
 
 pub struct RequireFn {
-    env: EnvironmentRef,
+    env: WeakEnvironmentRef,
 }
 
 impl RequireFn {
     pub fn new(env: &EnvironmentRef) -> Self {
-        Self { env: env.clone() }
+        Self {
+            env: Rc::downgrade(env),
+        }
     }
 }
 
+// Values are imported into the environment require is called in
 impl Callable for RequireFn {
     fn call(&self, args: &Vec<ValueRef>) -> Result<ValueRef, InterpreterError> {
+        self.call_in_environment(args, &upgrade_env(&self.env)?)
+    }
+
+    fn call_in_environment(
+        &self,
+        args: &Vec<ValueRef>,
+        env: &EnvironmentRef,
+    ) -> Result<ValueRef, InterpreterError> {
         let num_args = args.len();
 
         if num_args != 1 && num_args != 2 {
@@ -121,7 +307,7 @@ impl Callable for RequireFn {
         }
         let module_path = module_path.unwrap().value.clone();
 
-        let module_loader = ModuleLoader::new(&self.env);
+        let module_loader = ModuleLoader::new(env);
         let module_imports = module_loader.load_module(&module_path)?;
 
         if num_args == 2 {
@@ -134,11 +320,10 @@ impl Callable for RequireFn {
             }
             let alias = alias.unwrap().value.clone();
 
-            self.env
-                .borrow_mut()
+            env.borrow_mut()
                 .import_values_with_alias(module_imports, &alias);
         } else {
-            self.env.borrow_mut().import_values(module_imports);
+            env.borrow_mut().import_values(module_imports);
         }
 
         Ok(new_valueref(NilValue {}))
@@ -146,17 +331,27 @@ impl Callable for RequireFn {
 }
 
 pub struct ProvideFn {
-    env: EnvironmentRef,
+    env: WeakEnvironmentRef,
 }
 
 impl ProvideFn {
     pub fn new(env: &EnvironmentRef) -> Self {
-        Self { env: env.clone() }
+        Self {
+            env: Rc::downgrade(env),
+        }
     }
 }
 
 impl Callable for ProvideFn {
     fn call(&self, args: &Vec<ValueRef>) -> Result<ValueRef, InterpreterError> {
+        self.call_in_environment(args, &upgrade_env(&self.env)?)
+    }
+
+    fn call_in_environment(
+        &self,
+        args: &Vec<ValueRef>,
+        env: &EnvironmentRef,
+    ) -> Result<ValueRef, InterpreterError> {
         let num_args = args.len();
 
         if num_args != 1 {
@@ -184,7 +379,7 @@ impl Callable for ProvideFn {
             }
 
             let export_name = export_token.get_string_value().unwrap();
-            self.env.borrow_mut().export(&export_name);
+            env.borrow_mut().export(&export_name);
         }
 
         Ok(new_valueref(NilValue {}))
@@ -194,6 +389,7 @@ impl Callable for ProvideFn {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::frontend::parser::binary::serialize_program;
     use crate::interpreter::{
         self,
         environment::EnvironmentBuilder,
@@ -203,7 +399,11 @@ mod tests {
         },
         new_valueref, IntValue,
     };
-    use std::{cell::RefCell, rc::Rc};
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        time::{Duration, SystemTime},
+    };
 
     type DirRef = Rc<RefCell<TestDir>>;
     type FileRef = Rc<RefCell<TestFile>>;
@@ -277,22 +477,45 @@ mod tests {
 
     struct TestFile {
         name: String,
-        content: String,
+        content: Vec<u8>,
+        modified: Option<SystemTime>,
     }
 
     impl TestFile {
         fn new(name: &str, content: &str) -> Self {
             Self {
                 name: name.to_string(),
-                content: content.to_string(),
+                content: content.as_bytes().to_vec(),
+                modified: None,
+            }
+        }
+
+        fn compiled(name: &str, code: &str, modified: SystemTime) -> Self {
+            let program = crate::frontend::parser::Parser::new().parse(code).unwrap();
+            Self {
+                name: name.to_string(),
+                content: serialize_program(&program),
+                modified: Some(modified),
             }
         }
     }
 
     impl ModuleFile for TestFile {
         fn read(&self) -> String {
+            String::from_utf8_lossy(&self.content).to_string()
+        }
+
+        fn canonical_path(&self) -> String {
+            self.name.clone()
+        }
+
+        fn read_bytes(&self) -> Vec<u8> {
             self.content.clone()
         }
+
+        fn modified(&self) -> Option<SystemTime> {
+            self.modified
+        }
     }
 
     impl ModuleObject for TestFile {
@@ -424,4 +647,144 @@ mod tests {
         let helper = env.borrow().get("helper");
         assert!(helper.is_none());
     }
+    fn load_answer(files: Vec<TestFile>) -> Result<i64, InterpreterError> {
+        let current_dir = Rc::new(RefCell::new(TestDir::new("")));
+        for file in files {
+            current_dir
+                .borrow_mut()
+                .add_file(&Rc::new(RefCell::new(file)));
+        }
+        let env = EnvironmentBuilder::new()
+            .search_dirs(&vec![current_dir])
+            .build();
+
+        let loaded_values = ModuleLoader::new(&env).load_module("answer")?;
+        let answer = loaded_values.get("answer").unwrap().borrow();
+        Ok(answer.as_any().downcast_ref::<IntValue>().unwrap().value)
+    }
+
+    #[test]
+    fn load_compiled_module() {
+        let now = SystemTime::now();
+        let older = now - Duration::from_secs(60);
+
+        let source = || TestFile {
+            modified: Some(now),
+            ..TestFile::new("answer.boli", "(def answer 1)")
+        };
+        let compiled = |modified| TestFile::compiled("answer.bolc", "(def answer 2)", modified);
+
+        assert_eq!(load_answer(vec![source(), compiled(now)]).unwrap(), 2);
+        assert_eq!(load_answer(vec![source(), compiled(older)]).unwrap(), 1);
+        assert_eq!(load_answer(vec![compiled(older)]).unwrap(), 2);
+
+        let broken = || TestFile {
+            modified: Some(now),
+            ..TestFile::new("answer.bolc", "BOLC")
+        };
+        assert_eq!(load_answer(vec![source(), broken()]).unwrap(), 1);
+        assert!(load_answer(vec![broken()]).is_err());
+    }
+    #[test]
+    fn load_module_once() {
+        let current_dir = Rc::new(RefCell::new(TestDir::new("")));
+        for (name, code) in [
+            ("base.boli", "(def (answer) 42)"),
+            ("user.boli", "(require 'base)"),
+        ] {
+            current_dir
+                .borrow_mut()
+                .add_file(&Rc::new(RefCell::new(TestFile::new(name, code))));
+        }
+        let env = EnvironmentBuilder::new()
+            .search_dirs(&vec![current_dir])
+            .build();
+
+        let loader = ModuleLoader::new(&env);
+        let first = loader.load_module("base").unwrap();
+        loader.load_module("user").unwrap();
+        let second = loader.load_module("base").unwrap();
+
+        assert!(Rc::ptr_eq(
+            first.get("answer").unwrap(),
+            second.get("answer").unwrap()
+        ));
+    }
+
+    #[test]
+    fn detect_circular_imports() {
+        let current_dir = Rc::new(RefCell::new(TestDir::new("")));
+        let util_dir = Rc::new(RefCell::new(TestDir::new("util")));
+        current_dir.borrow_mut().add_dir(&util_dir);
+        current_dir
+            .borrow_mut()
+            .add_file(&Rc::new(RefCell::new(TestFile::new(
+                "a.boli",
+                "(require 'util::b)",
+            ))));
+        util_dir
+            .borrow_mut()
+            .add_file(&Rc::new(RefCell::new(TestFile::new(
+                "b.boli",
+                "(require 'c)",
+            ))));
+        current_dir
+            .borrow_mut()
+            .add_file(&Rc::new(RefCell::new(TestFile::new(
+                "c.boli",
+                "(require 'a)",
+            ))));
+        let env = EnvironmentBuilder::new()
+            .search_dirs(&vec![current_dir])
+            .build();
+
+        let err = ModuleLoader::new(&env).load_module("a").unwrap_err();
+        assert_eq!(err.message, "circular import: a -> util::b -> c -> a");
+    }
+    #[test]
+    fn load_module_with_export_form() {
+        let current_dir = Rc::new(RefCell::new(TestDir::new("")));
+        current_dir
+            .borrow_mut()
+            .add_file(&Rc::new(RefCell::new(TestFile::new(
+                "shapes.boli",
+                r#"
+        (export area perimeter)
+
+        (def (square x) (* x x))
+        (def (area r) (* 3 (square r)))
+        (def (perimeter r) (* 6 r))
+        "#,
+            ))));
+        let env = EnvironmentBuilder::new()
+            .search_dirs(&vec![current_dir])
+            .build();
+
+        let loaded_values = ModuleLoader::new(&env).load_module("shapes").unwrap();
+
+        let mut names: Vec<&String> = loaded_values.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["area", "perimeter"]);
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn native_modules_need_capability() {
+        let current_dir = Rc::new(RefCell::new(TestDir::new("")));
+        let library = Rc::new(RefCell::new(TestFile::new(
+            &native::library_file_name("geometry"),
+            "",
+        )));
+        current_dir.borrow_mut().add_file(&library);
+
+        let env = EnvironmentBuilder::new()
+            .search_dirs(&vec![current_dir])
+            .allow_native(false)
+            .build();
+        let err = ModuleLoader::new(&env).load_module("geometry").unwrap_err();
+        assert_eq!(
+            err.message,
+            "loading native module 'geometry' is not allowed"
+        );
+    }
 }
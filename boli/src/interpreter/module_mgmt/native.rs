@@ -0,0 +1,104 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+};
+
+use libloading::{Library, Symbol};
+
+use crate::interpreter::{environment::EnvironmentRef, InterpreterError, ValueRef};
+
+use super::ExtensionModuleRef;
+
+pub const REGISTER_SYMBOL: &str = "boli_register";
+
+// Signature of the entry point of a native extension library:
+//
+//   #[no_mangle]
+//   pub fn boli_register(env: &EnvironmentRef) -> Vec<ExtensionModuleRef>
+//
+// The Rust ABI is not stable, so libraries must be built with the same
+// compiler and the same version of boli as the interpreter.
+pub type RegisterFn = fn(&EnvironmentRef) -> Vec<ExtensionModuleRef>;
+
+thread_local! {
+    // Values of an extension contain code of the library, so loaded
+    // libraries are never unloaded
+    static LIBRARIES: RefCell<Vec<Library>> = const { RefCell::new(Vec::new()) };
+}
+
+// File name of the native library that provides the module, e.g.
+// libgeometry.so for module geometry on Linux
+pub fn library_file_name(module_name: &str) -> String {
+    format!("{}{}{}", DLL_PREFIX, module_name, DLL_SUFFIX)
+}
+
+pub fn load_extensions(
+    path: &str,
+    env: &EnvironmentRef,
+) -> Result<Vec<ExtensionModuleRef>, InterpreterError> {
+    let library = unsafe { Library::new(path) }.map_err(|err| {
+        InterpreterError::new(&format!("cannot load native library {}: {}", path, err))
+    })?;
+
+    let extensions = {
+        let register: Symbol<RegisterFn> = unsafe { library.get(REGISTER_SYMBOL.as_bytes()) }
+            .map_err(|_| {
+                InterpreterError::new(&format!(
+                    "native library {} does not define {}",
+                    path, REGISTER_SYMBOL
+                ))
+            })?;
+        register(env)
+    };
+
+    LIBRARIES.with(|libraries| libraries.borrow_mut().push(library));
+    Ok(extensions)
+}
+
+// Loads the library of a native module. A library may register several
+// extensions, the values of the one named like the module are returned.
+pub fn load_module(
+    path: &str,
+    module_name: &str,
+    env: &EnvironmentRef,
+) -> Result<HashMap<String, ValueRef>, InterpreterError> {
+    let extensions = load_extensions(path, env)?;
+
+    let extension = match extensions.as_slice() {
+        [extension] => Some(extension),
+        _ => extensions
+            .iter()
+            .find(|extension| extension.borrow().get_name() == module_name),
+    };
+
+    match extension {
+        Some(extension) => Ok(extension.borrow().get_values()),
+        None => Err(InterpreterError::new(&format!(
+            "native library {} does not register module '{}'",
+            path, module_name
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::environment::EnvironmentBuilder;
+
+    #[test]
+    fn test_library_file_name() {
+        let file_name = library_file_name("geometry");
+        assert!(file_name.contains("geometry"));
+        assert!(file_name.ends_with(DLL_SUFFIX));
+    }
+
+    #[test]
+    fn test_load_invalid_library() {
+        let env = EnvironmentBuilder::new().build();
+        let err = load_module("Cargo.toml", "geometry", &env).unwrap_err();
+        assert!(err
+            .message
+            .starts_with("cannot load native library Cargo.toml"));
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    env,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+pub const BOLI_PATH_VAR: &str = "BOLI_PATH";
+pub const MANIFEST_FILE: &str = "boli.toml";
+
+// Project manifest (boli.toml) with directories relative to the manifest:
+//
+//   sources = ["src"]
+//   dependencies = ["../shared", "vendor/json"]
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub sources: Vec<String>,
+    pub dependencies: Vec<String>,
+}
+
+impl Manifest {
+    pub fn parse(content: &str, base_dir: &Path) -> Result<Self> {
+        let table = content
+            .parse::<toml::Table>()
+            .map_err(|err| invalid_manifest(&err.to_string()))?;
+
+        Ok(Self {
+            sources: Self::dirs(&table, "sources", base_dir)?,
+            dependencies: Self::dirs(&table, "dependencies", base_dir)?,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse(&content, base_dir)
+    }
+
+    // Searches the manifest in the start directory and its ancestors
+    pub fn find(start_dir: &Path) -> Option<PathBuf> {
+        start_dir
+            .ancestors()
+            .map(|dir| dir.join(MANIFEST_FILE))
+            .find(|path| path.is_file())
+    }
+
+    fn dirs(table: &toml::Table, key: &str, base_dir: &Path) -> Result<Vec<String>> {
+        let values = match table.get(key) {
+            Some(toml::Value::Array(values)) => values,
+            Some(_) => return Err(invalid_manifest(&format!("{} must be a list", key))),
+            None => return Ok(vec![]),
+        };
+
+        values
+            .iter()
+            .map(|value| match value.as_str() {
+                Some(dir) => Ok(base_dir.join(dir).to_string_lossy().to_string()),
+                None => Err(invalid_manifest(&format!(
+                    "{} must only contain strings",
+                    key
+                ))),
+            })
+            .collect()
+    }
+}
+
+fn invalid_manifest(message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid {}: {}", MANIFEST_FILE, message),
+    )
+}
+
+// Module search directories in lookup order: the given directories and the
+// sources of the project manifest (or the current directory if there are
+// none), followed by the dependencies of the manifest and the directories
+// in BOLI_PATH.
+pub fn module_search_dirs(module_dirs: &[String], start_dir: &Path) -> Result<Vec<String>> {
+    let manifest = match Manifest::find(start_dir) {
+        Some(path) => Manifest::read(&path)?,
+        None => Manifest::default(),
+    };
+    let boli_path: Vec<String> = match env::var_os(BOLI_PATH_VAR) {
+        Some(paths) => env::split_paths(&paths)
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(|path| path.to_string_lossy().to_string())
+            .collect(),
+        None => vec![],
+    };
+
+    Ok(merge_dirs(module_dirs, manifest, boli_path))
+}
+
+fn merge_dirs(module_dirs: &[String], manifest: Manifest, boli_path: Vec<String>) -> Vec<String> {
+    let mut dirs: Vec<String> = module_dirs.to_vec();
+    dirs.extend(manifest.sources);
+    if dirs.is_empty() {
+        dirs.push(".".to_string());
+    }
+    dirs.extend(manifest.dependencies);
+    dirs.extend(boli_path);
+
+    let mut merged: Vec<String> = Vec::new();
+    for dir in dirs {
+        if !merged.contains(&dir) {
+            merged.push(dir);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::parse(
+            r#"
+            sources = ["src", "scripts"]
+            dependencies = ["../shared"]
+            "#,
+            Path::new("project"),
+        )
+        .unwrap();
+
+        let path = |dir: &str| Path::new("project").join(dir).to_string_lossy().to_string();
+        assert_eq!(manifest.sources, vec![path("src"), path("scripts")]);
+        assert_eq!(manifest.dependencies, vec![path("../shared")]);
+
+        assert_eq!(
+            Manifest::parse("", Path::new(".")).unwrap(),
+            Manifest::default()
+        );
+        assert!(Manifest::parse("sources = \"src\"", Path::new(".")).is_err());
+        assert!(Manifest::parse("sources = [1]", Path::new(".")).is_err());
+        assert!(Manifest::parse("sources = [", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_merge_dirs() {
+        let dirs = |dirs: &[&str]| dirs.iter().map(|dir| dir.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(merge_dirs(&[], Manifest::default(), vec![]), dirs(&["."]));
+        assert_eq!(
+            merge_dirs(&[], Manifest::default(), dirs(&["/usr/lib/boli"])),
+            dirs(&[".", "/usr/lib/boli"])
+        );
+
+        let manifest = Manifest {
+            sources: dirs(&["src"]),
+            dependencies: dirs(&["lib", "/usr/lib/boli"]),
+        };
+        assert_eq!(
+            merge_dirs(&dirs(&["extra"]), manifest, dirs(&["/usr/lib/boli"])),
+            dirs(&["extra", "src", "lib", "/usr/lib/boli"])
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use std::cmp::Ordering;
+
+use crate::frontend::lexer::{stream::Stream, tokens::TokenType, Lexer};
+
 use super::values::*;
 
 pub struct Add {}
@@ -57,7 +61,16 @@ impl Callable for Div {
             let one = new_valueref(IntValue { value: 1 });
             args.insert(0, one);
         }
-        calculate_value(|a, b| a.div(b), &args, true)
+        // Division by real zero yields infinity or NaN instead
+        let numbers = values_to_numbers(&args)?;
+        if numbers
+            .iter()
+            .skip(1)
+            .any(|divisor| divisor.is_exact_zero())
+        {
+            return Err(InterpreterError::division_by_zero());
+        }
+        Ok(number_to_value(calculate(|a, b| a.div(b), &numbers, true)))
     }
 }
 
@@ -85,7 +98,15 @@ impl Rem {
 
 impl Callable for Rem {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        calculate_value(|a, b| a.rem(b), args, true)
+        let numbers = values_to_numbers(args)?;
+        if numbers
+            .iter()
+            .skip(1)
+            .any(|divisor| divisor.is_exact_zero())
+        {
+            return Err(InterpreterError::division_by_zero());
+        }
+        Ok(number_to_value(calculate(|a, b| a.rem(b), &numbers, true)))
     }
 }
 
@@ -123,7 +144,7 @@ impl Callable for IDiv {
         };
 
         if arg1 == 0 {
-            return Err(InterpreterError::new("division by zero"));
+            return Err(InterpreterError::division_by_zero());
         }
 
         Ok(new_valueref(IntValue { value: arg0 / arg1 }))
@@ -140,7 +161,7 @@ impl Eq {
 
 impl Callable for Eq {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        all_values(|a, b| a.eq(b), args)
+        all_values(|a, b| a.eq(b), |ordering| ordering.is_eq(), args)
     }
 }
 
@@ -154,7 +175,7 @@ impl Gt {
 
 impl Callable for Gt {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        all_values(|a, b| a.gt(b), args)
+        all_values(|a, b| a.gt(b), |ordering| ordering.is_gt(), args)
     }
 }
 
@@ -168,7 +189,7 @@ impl Ge {
 
 impl Callable for Ge {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        all_values(|a, b| a.ge(b), args)
+        all_values(|a, b| a.ge(b), |ordering| ordering.is_ge(), args)
     }
 }
 
@@ -182,7 +203,7 @@ impl Lt {
 
 impl Callable for Lt {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        all_values(|a, b| a.lt(b), args)
+        all_values(|a, b| a.lt(b), |ordering| ordering.is_lt(), args)
     }
 }
 
@@ -196,29 +217,691 @@ impl Le {
 
 impl Callable for Le {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        all_values(|a, b| a.le(b), args)
+        all_values(|a, b| a.le(b), |ordering| ordering.is_le(), args)
+    }
+}
+
+pub struct Abs {}
+
+impl Abs {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Abs {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return Err(InterpreterError::new("abs requires exactly one argument"));
+        }
+
+        let numbers = values_to_numbers(args)?;
+
+        match numbers[0].abs() {
+            Some(result) => Ok(number_to_value(result)),
+            None => Err(InterpreterError::with_kind(
+                ErrorKind::Arithmetic,
+                "abs: integer overflow",
+            )),
+        }
+    }
+}
+
+pub struct Sign {}
+
+impl Sign {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Sign {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return Err(InterpreterError::new("sign requires exactly one argument"));
+        }
+
+        let numbers = values_to_numbers(args)?;
+        let zero = Number::Int(0);
+        let value = if numbers[0].gt(&zero) {
+            1
+        } else if numbers[0].lt(&zero) {
+            -1
+        } else {
+            0
+        };
+
+        Ok(new_valueref(IntValue { value }))
+    }
+}
+
+pub struct Clamp {}
+
+impl Clamp {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Clamp {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 3 {
+            return Err(InterpreterError::new(
+                "clamp requires exactly three arguments",
+            ));
+        }
+
+        let numbers = values_to_numbers(args)?;
+        let (x, lo, hi) = (&numbers[0], &numbers[1], &numbers[2]);
+
+        if lo.gt(hi) {
+            return Err(InterpreterError::new(
+                "clamp requires the lower bound not to exceed the upper bound",
+            ));
+        }
+
+        if x.lt(lo) {
+            Ok(args[1].clone())
+        } else if x.gt(hi) {
+            Ok(args[2].clone())
+        } else {
+            Ok(args[0].clone())
+        }
+    }
+}
+
+pub struct Between {}
+
+impl Between {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Between {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 3 {
+            return Err(InterpreterError::new(
+                "between? requires exactly three arguments",
+            ));
+        }
+
+        let numbers = values_to_numbers(args)?;
+        let (x, lo, hi) = (&numbers[0], &numbers[1], &numbers[2]);
+
+        Ok(new_valueref(BoolValue {
+            value: x.ge(lo) && x.le(hi),
+        }))
+    }
+}
+
+pub struct MathFunction {
+    name: String,
+    function: fn(f64) -> f64,
+}
+
+impl MathFunction {
+    pub fn new(name: &str, function: fn(f64) -> f64) -> Self {
+        Self {
+            name: name.to_string(),
+            function,
+        }
+    }
+}
+
+impl Callable for MathFunction {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return Err(InterpreterError::new(&format!(
+                "{} requires exactly one argument",
+                self.name
+            )));
+        }
+
+        let numbers = values_to_numbers(args)?;
+
+        Ok(number_to_value(Number::Float((self.function)(
+            numbers[0].to_float(),
+        ))))
+    }
+}
+
+pub struct Sqrt {}
+
+impl Sqrt {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Sqrt {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return Err(InterpreterError::new("sqrt requires exactly one argument"));
+        }
+
+        let numbers = values_to_numbers(args)?;
+        let x = &numbers[0];
+
+        if x.lt(&Number::Int(0)) {
+            return Err(InterpreterError::new(
+                "sqrt requires a non-negative argument",
+            ));
+        }
+
+        let result = match x.normalize() {
+            Number::Int(a) => match exact_sqrt(a) {
+                Some(root) => Number::Int(root),
+                None => Number::Float((a as f64).sqrt()),
+            },
+            Number::Rational(n, d) => match (exact_sqrt(n), exact_sqrt(d)) {
+                (Some(n), Some(d)) => Number::Rational(n, d),
+                _ => Number::Float(x.to_float().sqrt()),
+            },
+            Number::Float(a) => Number::Float(a.sqrt()),
+        };
+
+        Ok(number_to_value(result))
+    }
+}
+
+fn exact_sqrt(n: i64) -> Option<i64> {
+    let root = (n as f64).sqrt().round() as i64;
+    if root.checked_mul(root) == Some(n) {
+        Some(root)
+    } else {
+        None
+    }
+}
+
+pub struct Log {}
+
+impl Log {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Log {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return Err(InterpreterError::new("log requires one or two arguments"));
+        }
+
+        let numbers = values_to_numbers(args)?;
+        if numbers.iter().any(|x| !x.gt(&Number::Int(0))) {
+            return Err(InterpreterError::new("log requires positive arguments"));
+        }
+
+        let mut value = numbers[0].to_float().ln();
+        if numbers.len() == 2 {
+            value /= numbers[1].to_float().ln();
+        }
+
+        Ok(number_to_value(Number::Float(value)))
+    }
+}
+
+pub struct Atan2 {}
+
+impl Atan2 {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Atan2 {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return Err(InterpreterError::new(
+                "atan2 requires exactly two arguments",
+            ));
+        }
+
+        let numbers = values_to_numbers(args)?;
+        let (y, x) = (numbers[0].to_float(), numbers[1].to_float());
+
+        Ok(number_to_value(Number::Float(y.atan2(x))))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum RoundingMode {
+    Floor,
+    Ceiling,
+    Round,
+    Truncate,
+}
+
+pub struct Rounding {
+    name: String,
+    mode: RoundingMode,
+}
+
+impl Rounding {
+    pub fn new(name: &str, mode: RoundingMode) -> Self {
+        Self {
+            name: name.to_string(),
+            mode,
+        }
+    }
+}
+
+impl Callable for Rounding {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return Err(InterpreterError::new(&format!(
+                "{} requires exactly one argument",
+                self.name
+            )));
+        }
+
+        let numbers = values_to_numbers(args)?;
+
+        Ok(number_to_value(numbers[0].round(self.mode)))
+    }
+}
+
+// Integer division with the semantics of Scheme: quotient truncates towards
+// zero, the remainder has the sign of the dividend and the modulo the sign
+// of the divisor
+#[derive(Clone, Copy)]
+pub enum IntDivisionMode {
+    Quotient,
+    Remainder,
+    Modulo,
+}
+
+pub struct IntDivision {
+    name: String,
+    mode: IntDivisionMode,
+}
+
+impl IntDivision {
+    pub fn new(name: &str, mode: IntDivisionMode) -> Self {
+        Self {
+            name: name.to_string(),
+            mode,
+        }
+    }
+}
+
+impl Callable for IntDivision {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return Err(InterpreterError::new(&format!(
+                "{} requires exactly two arguments",
+                self.name
+            )));
+        }
+
+        let ints = values_to_ints(args, &self.name)?;
+        let (a, b) = (ints[0], ints[1]);
+        if b == 0 {
+            return Err(InterpreterError::division_by_zero());
+        }
+
+        let value = match self.mode {
+            IntDivisionMode::Quotient => a.checked_div(b),
+            IntDivisionMode::Remainder => a.checked_rem(b),
+            IntDivisionMode::Modulo => a.checked_rem(b).map(|rest| {
+                if rest != 0 && (rest < 0) != (b < 0) {
+                    rest + b
+                } else {
+                    rest
+                }
+            }),
+        };
+
+        int_result(value, &self.name)
+    }
+}
+
+// None stands for a result that does not fit into an integer
+fn int_result(value: Option<i64>, name: &str) -> EvalResult {
+    match value {
+        Some(value) => Ok(new_valueref(IntValue { value })),
+        None => Err(InterpreterError::with_kind(
+            ErrorKind::Arithmetic,
+            &format!("{}: integer overflow", name),
+        )),
+    }
+}
+
+pub struct Min {}
+
+impl Min {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Min {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        select_number(args, "min", |a, b| a.lt(b))
+    }
+}
+
+pub struct Max {}
+
+impl Max {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Max {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        select_number(args, "max", |a, b| a.gt(b))
+    }
+}
+
+fn select_number<F>(args: &Vec<ValueRef>, name: &str, is_better: F) -> EvalResult
+where
+    F: Fn(&Number, &Number) -> bool,
+{
+    if args.is_empty() {
+        return Err(InterpreterError::new(&format!(
+            "{} requires at least one argument",
+            name
+        )));
+    }
+
+    let numbers = values_to_numbers(args)?;
+    let mut selected = 0;
+    for (i, number) in numbers.iter().enumerate().skip(1) {
+        if is_better(number, &numbers[selected]) {
+            selected = i;
+        }
+    }
+
+    if numbers.iter().any(|x| matches!(x, Number::Float(_))) {
+        Ok(number_to_value(Number::Float(numbers[selected].to_float())))
+    } else {
+        Ok(args[selected].clone())
+    }
+}
+
+pub struct Gcd {}
+
+impl Gcd {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Gcd {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let value = values_to_ints(args, "gcd")?
+            .into_iter()
+            .map(i64::unsigned_abs)
+            .fold(0, gcd);
+
+        int_result(i64::try_from(value).ok(), "gcd")
+    }
+}
+
+pub struct Lcm {}
+
+impl Lcm {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Lcm {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let value = values_to_ints(args, "lcm")?
+            .into_iter()
+            .map(i64::unsigned_abs)
+            .try_fold(1, |a, b| {
+                if a == 0 || b == 0 {
+                    Some(0)
+                } else {
+                    (a / gcd(a, b)).checked_mul(b)
+                }
+            });
+
+        int_result(value.and_then(|value| i64::try_from(value).ok()), "lcm")
+    }
+}
+
+// (number->string n) uses the same format as display. An optional second
+// argument is the radix (2 to 36). Real numbers can only be converted with
+// radix 10, use real->string for a fixed number of decimal places.
+pub struct NumberToStr {}
+
+impl NumberToStr {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn int_to_string(value: i64, radix: u32) -> String {
+        let mut digits = vec![];
+        let mut rest = value.unsigned_abs();
+        loop {
+            digits.push(std::char::from_digit((rest % radix as u64) as u32, radix).unwrap());
+            rest /= radix as u64;
+            if rest == 0 {
+                break;
+            }
+        }
+        if value < 0 {
+            digits.push('-');
+        }
+        digits.iter().rev().collect()
+    }
+}
+
+impl Callable for NumberToStr {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("number->string function expects one or two arguments");
+        }
+
+        let radix = match args.get(1) {
+            Some(arg) => match downcast_value::<IntValue>(&borrow_value(arg)) {
+                Some(int_value) if (2..=36).contains(&int_value.value) => int_value.value as u32,
+                _ => return error("number->string function expects a radix between 2 and 36"),
+            },
+            None => 10,
+        };
+
+        let number = borrow_value(&args[0]);
+        let value = match number.get_type() {
+            ValueType::Int => {
+                let int_value = downcast_value::<IntValue>(&number).unwrap();
+                Self::int_to_string(int_value.value, radix)
+            }
+            ValueType::Rational => {
+                let rational = downcast_value::<RationalValue>(&number).unwrap();
+                format!(
+                    "{}/{}",
+                    Self::int_to_string(rational.numerator, radix),
+                    Self::int_to_string(rational.denominator, radix)
+                )
+            }
+            ValueType::Real if radix == 10 => number.to_string(),
+            ValueType::Real => {
+                return error("number->string function expects radix 10 for real numbers")
+            }
+            _ => {
+                return Err(InterpreterError::type_error(
+                    "number->string function expects a number as first argument",
+                    &args[0],
+                ))
+            }
+        };
+
+        Ok(new_valueref(StrValue {
+            value: value.into(),
+        }))
+    }
+}
+
+// (real->string x places) converts a number to a string with the given
+// number of decimal places. Apart from the precision the format of display
+// is used.
+pub struct RealToStr {}
+
+impl RealToStr {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for RealToStr {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("real->string function expects exactly two arguments");
+        }
+
+        let places = match downcast_value::<IntValue>(&borrow_value(&args[1])) {
+            Some(int_value) if int_value.value >= 0 => int_value.value as usize,
+            _ => {
+                return error(
+                    "real->string function expects a non-negative integer as second argument",
+                )
+            }
+        };
+
+        let value = values_to_numbers(&vec![args[0].clone()])?[0].to_float();
+
+        Ok(new_valueref(StrValue {
+            value: real_format().precision(places).format(value).into(),
+        }))
     }
 }
 
+// (string->number s) accepts all number literals of BOLI. With a radix as
+// second argument the string must contain an integer or a rational number
+// in that radix. If the string is not a number, #false is returned.
+pub struct StrToNumber {}
+
+impl StrToNumber {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn parse_literal(text: &str) -> Option<ValueRef> {
+        let mut lexer = Lexer::new(text);
+        let token = lexer.next()?;
+        if lexer.next().is_some() {
+            return None;
+        }
+        match token.token_type {
+            TokenType::Integer => Some(new_valueref(IntValue {
+                value: token.get_int_value()?,
+            })),
+            TokenType::Rational => {
+                let (numerator, denominator) = token.get_rational_value()?;
+                Some(new_valueref(RationalValue::new(numerator, denominator)))
+            }
+            TokenType::Real => Some(new_valueref(RealValue {
+                value: token.get_real_value()?,
+            })),
+            _ => None,
+        }
+    }
+
+    fn parse_with_radix(text: &str, radix: u32) -> Option<ValueRef> {
+        match text.split_once('/') {
+            Some((numerator, denominator)) => {
+                let numerator = i64::from_str_radix(numerator, radix).ok()?;
+                let denominator = i64::from_str_radix(denominator, radix).ok()?;
+                if denominator == 0 {
+                    return None;
+                }
+                Some(new_valueref(RationalValue::new(numerator, denominator)))
+            }
+            None => Some(new_valueref(IntValue {
+                value: i64::from_str_radix(text, radix).ok()?,
+            })),
+        }
+    }
+}
+
+impl Callable for StrToNumber {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("string->number function expects one or two arguments");
+        }
+
+        let text = match downcast_value::<StrValue>(&borrow_value(&args[0])) {
+            Some(string) => string.value.trim().to_string(),
+            None => {
+                return Err(InterpreterError::type_error(
+                    "string->number function expects a string as first argument",
+                    &args[0],
+                ))
+            }
+        };
+
+        let number = match args.get(1) {
+            Some(radix) => match downcast_value::<IntValue>(&borrow_value(radix)) {
+                Some(radix) if (2..=36).contains(&radix.value) => {
+                    Self::parse_with_radix(&text, radix.value as u32)
+                }
+                _ => return error("string->number function expects a radix between 2 and 36"),
+            },
+            None => Self::parse_literal(&text),
+        };
+
+        Ok(number.unwrap_or_else(|| new_valueref(BoolValue { value: false })))
+    }
+}
+
+// Absolute values are unsigned, the gcd of i64::MIN and 0 does not fit into
+// an i64
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn values_to_ints(vals: &Vec<ValueRef>, name: &str) -> Result<Vec<i64>, InterpreterError> {
+    let mut ints = vec![];
+
+    for value in vals {
+        let val = &borrow_value(value);
+        match downcast_value::<IntValue>(val) {
+            Some(int_value) => ints.push(int_value.value),
+            None => {
+                return Err(InterpreterError::type_error(
+                    &format!("{} requires integer arguments", name),
+                    value,
+                ))
+            }
+        }
+    }
+
+    Ok(ints)
+}
+
 fn calculate_value<F>(op: F, values: &Vec<ValueRef>, left_associative: bool) -> EvalResult
 where
     F: Fn(&Number, &Number) -> Number,
 {
     let numbers = values_to_numbers(values)?;
     let calc_result = calculate(op, &numbers, left_associative);
-    match calc_result {
-        Number::Int(result) => Ok(new_valueref(IntValue { value: result })),
+    Ok(number_to_value(calc_result))
+}
+
+fn number_to_value(number: Number) -> ValueRef {
+    match number {
+        Number::Int(result) => new_valueref(IntValue { value: result }),
         Number::Rational(n, d) => {
             let rational_value = RationalValue::new(n, d);
             if rational_value.denominator != 1 {
-                Ok(new_valueref(rational_value))
+                new_valueref(rational_value)
             } else {
-                Ok(new_valueref(IntValue {
+                new_valueref(IntValue {
                     value: rational_value.numerator,
-                }))
+                })
             }
         }
-        Number::Float(result) => Ok(new_valueref(RealValue { value: result })),
+        Number::Float(result) => new_valueref(RealValue { value: result }),
     }
 }
 
@@ -249,13 +932,20 @@ where
     }
 }
 
-fn all_values<F>(op: F, values: &Vec<ValueRef>) -> EvalResult
+fn all_values<F, G>(op: F, ordered: G, values: &Vec<ValueRef>) -> EvalResult
 where
     F: Fn(&Number, &Number) -> bool,
+    G: Fn(Ordering) -> bool,
 {
+    // Values other than numbers are compared with their ordering
     let numbers = match values_to_numbers(values) {
         Ok(numbers) => numbers,
-        Err(e) => return Err(e),
+        Err(e) => {
+            return match all_ordered(ordered, values) {
+                Some(value) => Ok(new_valueref(BoolValue { value })),
+                None => Err(e),
+            }
+        }
     };
 
     let op_result = all_numbers(op, &numbers);
@@ -263,6 +953,20 @@ where
     Ok(new_valueref(BoolValue { value: op_result }))
 }
 
+fn all_ordered<G>(ordered: G, values: &[ValueRef]) -> Option<bool>
+where
+    G: Fn(Ordering) -> bool,
+{
+    if let [value] = values {
+        return compare_values(value, value).map(|_| true);
+    }
+    let orderings = values
+        .windows(2)
+        .map(|pair| compare_values(&pair[0], &pair[1]))
+        .collect::<Option<Vec<_>>>()?;
+    Some(!orderings.is_empty() && orderings.into_iter().all(ordered))
+}
+
 fn all_numbers<F>(op: F, numbers: &Vec<Number>) -> bool
 where
     F: Fn(&Number, &Number) -> bool,
@@ -297,8 +1001,8 @@ where
 fn values_to_numbers(vals: &Vec<ValueRef>) -> Result<Vec<Number>, InterpreterError> {
     let mut numbers = vec![];
 
-    for val in vals {
-        let val = &borrow_value(val);
+    for value in vals {
+        let val = &borrow_value(value);
         let number = match val.get_type() {
             ValueType::Int => {
                 let int_value = downcast_value::<IntValue>(val).unwrap();
@@ -312,7 +1016,12 @@ fn values_to_numbers(vals: &Vec<ValueRef>) -> Result<Vec<Number>, InterpreterErr
                 let real_value = downcast_value::<RealValue>(val).unwrap();
                 Number::Float(real_value.value)
             }
-            _ => return Err(InterpreterError::new(&format!("Invalid value: {:?}", val))),
+            _ => {
+                return Err(InterpreterError::type_error(
+                    &format!("Invalid value: {:?}", val),
+                    value,
+                ))
+            }
         };
         numbers.push(number);
     }
@@ -336,6 +1045,59 @@ impl Number {
         }
     }
 
+    fn normalize(&self) -> Number {
+        match self {
+            Number::Rational(n, d) if *d < 0 => Number::Rational(-n, -d),
+            _ => self.clone(),
+        }
+    }
+
+    fn round(&self, mode: RoundingMode) -> Number {
+        match self.normalize() {
+            Number::Int(a) => Number::Int(a),
+            Number::Rational(n, d) => {
+                let floor = n.div_euclid(d);
+                let rest = n.rem_euclid(d);
+                let value = match mode {
+                    RoundingMode::Floor => floor,
+                    RoundingMode::Ceiling if rest > 0 => floor + 1,
+                    RoundingMode::Ceiling => floor,
+                    RoundingMode::Truncate if n < 0 && rest > 0 => floor + 1,
+                    RoundingMode::Truncate => floor,
+                    RoundingMode::Round => match (2 * rest).cmp(&d) {
+                        std::cmp::Ordering::Less => floor,
+                        std::cmp::Ordering::Greater => floor + 1,
+                        std::cmp::Ordering::Equal => floor + floor.rem_euclid(2),
+                    },
+                };
+                Number::Int(value)
+            }
+            Number::Float(a) => Number::Float(match mode {
+                RoundingMode::Floor => a.floor(),
+                RoundingMode::Ceiling => a.ceil(),
+                RoundingMode::Round => a.round_ties_even(),
+                RoundingMode::Truncate => a.trunc(),
+            }),
+        }
+    }
+
+    fn is_exact_zero(&self) -> bool {
+        match self {
+            Number::Int(a) => *a == 0,
+            Number::Rational(n, _) => *n == 0,
+            Number::Float(_) => false,
+        }
+    }
+
+    // None if the absolute value does not fit into an integer
+    fn abs(&self) -> Option<Number> {
+        match self {
+            Number::Int(a) => Some(Number::Int(a.checked_abs()?)),
+            Number::Rational(n, d) => Some(Number::Rational(n.checked_abs()?, d.checked_abs()?)),
+            Number::Float(a) => Some(Number::Float(a.abs())),
+        }
+    }
+
     fn add(&self, other: &Number) -> Number {
         match (self, other) {
             (Number::Int(a), Number::Int(b)) => Number::Int(a + b),
@@ -475,6 +1237,90 @@ impl Number {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_number_string_conversion() {
+        let mut interpreter = crate::interpreter::Interpreter::new();
+        for (code, expected) in [
+            ("(number->string 255)", "\"255\""),
+            ("(number->string 255 16)", "\"ff\""),
+            ("(number->string -5 2)", "\"-101\""),
+            ("(number->string 3/4 2)", "\"11/100\""),
+            ("(number->string 3,14159)", "\"3,14159\""),
+            ("(number->string 3,14159 10)", "\"3,14159\""),
+            ("(real->string 3,14159 2)", "\"3,14\""),
+            ("(real->string 2,0 0)", "\"2\""),
+            ("(real->string 1/4 3)", "\"0,250\""),
+            ("(real->string 7 1)", "\"7,0\""),
+            ("(string->number \"1.000\")", "1000"),
+            ("(string->number \" -0x1F \")", "-31"),
+            ("(string->number \"2/4\")", "1/2"),
+            ("(string->number \"2,5\")", "2,5"),
+            ("(string->number \"ff\" 16)", "255"),
+            ("(string->number \"-101/11\" 2)", "-5/3"),
+            ("(string->number \"12\" 2)", "#false"),
+            ("(string->number \"abc\")", "#false"),
+            ("(string->number \"1 2\")", "#false"),
+            ("(string->number (number->string 1234 36) 36)", "1234"),
+        ] {
+            let result = interpreter.eval(code).unwrap();
+            assert_eq!(result.borrow().to_string(), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_generic_ordering() {
+        let mut interpreter = crate::interpreter::Interpreter::with_prelude();
+        for (code, expected) in [
+            ("(< 1 3/2 2,0)", "#true"),
+            ("(< 1/3 1/3)", "#false"),
+            ("(< \"apple\" \"banana\" \"cherry\")", "#true"),
+            ("(>= 'b 'a 'a)", "#true"),
+            ("(< (vector 1 \"a\") (vector 1 \"b\") (vector 2))", "#true"),
+            ("(> (list 1 2) (list 1))", "#true"),
+            ("(= \"a\" \"a\")", "#true"),
+            ("(compare 1 2,5)", "-1"),
+            ("(compare \"b\" \"b\")", "0"),
+            ("(compare (vector 'b) (vector 'a 'z))", "1"),
+        ] {
+            let result = interpreter.eval(code).unwrap();
+            assert_eq!(result.borrow().to_string(), expected, "{}", code);
+        }
+
+        for code in ["(< 1 \"a\")", "(> 'a \"a\")", "(compare nil 1)"] {
+            let err = interpreter.eval(code).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Type, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_integer_division() {
+        let mut interpreter = crate::interpreter::Interpreter::new();
+        for (code, expected) in [
+            ("(quotient 17 5)", "3"),
+            ("(quotient -17 5)", "-3"),
+            ("(remainder 17 -5)", "2"),
+            ("(remainder -17 5)", "-2"),
+            ("(modulo 17 -5)", "-3"),
+            ("(modulo -17 5)", "3"),
+            ("(modulo 15 -5)", "0"),
+            ("(/ 1,0 0,0)", "inf"),
+        ] {
+            let result = interpreter.eval(code).unwrap();
+            assert_eq!(result.borrow().to_string(), expected, "{}", code);
+        }
+
+        for code in ["(/ 1 0)", "(/ 0)", "(/ 1 2 0/3)", "(% 7 0)", "(modulo 7 0)"] {
+            let err = interpreter.eval(code).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Arithmetic, "{}", code);
+            assert_eq!(err.to_string(), "ArithmeticError: division by zero");
+        }
+
+        let result = interpreter
+            .eval("(error-kind (catch-error (lambda () (idiv 1 0))))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "'arithmetic-error");
+    }
+
     #[test]
     fn test_add() {
         let add = Add::new();
@@ -555,4 +1401,115 @@ mod tests {
         let result = downcast_value::<IntValue>(&result).unwrap();
         assert_eq!(result.value, 8);
     }
+
+    #[test]
+    fn test_abs_and_sign() {
+        let args = vec![new_valueref(RationalValue::new(-3, 4))];
+        let result = Abs::new().call(&args).unwrap();
+        assert_eq!(borrow_value(&result).to_string(), "3/4");
+
+        let result = Sign::new().call(&args).unwrap();
+        let result = borrow_value(&result);
+        let result = downcast_value::<IntValue>(&result).unwrap();
+        assert_eq!(result.value, -1);
+    }
+
+    #[test]
+    fn test_abs_overflow() {
+        let args = vec![new_valueref(IntValue { value: i64::MIN })];
+        let err = Abs::new().call(&args).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Arithmetic);
+        assert_eq!(err.to_string(), "ArithmeticError: abs: integer overflow");
+    }
+
+    #[test]
+    fn test_clamp() {
+        let clamp = Clamp::new();
+        let args = vec![
+            new_valueref(IntValue { value: 42 }),
+            new_valueref(IntValue { value: 0 }),
+            new_valueref(RealValue { value: 10.5 }),
+        ];
+        let result = clamp.call(&args).unwrap();
+        let result = borrow_value(&result);
+        let result = downcast_value::<RealValue>(&result).unwrap();
+        assert_eq!(result.value, 10.5);
+    }
+
+    #[test]
+    fn test_between() {
+        let between = Between::new();
+        let args = vec![
+            new_valueref(IntValue { value: 10 }),
+            new_valueref(IntValue { value: 0 }),
+            new_valueref(IntValue { value: 10 }),
+        ];
+        let result = between.call(&args).unwrap();
+        let result = borrow_value(&result);
+        let result = downcast_value::<BoolValue>(&result).unwrap();
+        assert!(result.value);
+    }
+
+    fn eval_number(code: &str) -> String {
+        let mut interpreter = crate::interpreter::Interpreter::new();
+        let result = interpreter.eval(code).unwrap();
+        let result = borrow_value(&result).to_string();
+        result
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(eval_number("(sqrt 16)"), "4");
+        assert_eq!(eval_number("(sqrt (/ 4 9))"), "2/3");
+        assert_eq!(eval_number("(sqrt 2,25)"), "1,5");
+        assert_eq!(eval_number("(sqrt 2)"), "1,4142135623730951");
+        assert_eq!(
+            eval_number("(sqrt 9223372036854775807)"),
+            "3037000499,97605"
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(eval_number("(floor (/ -7 2))"), "-4");
+        assert_eq!(eval_number("(ceiling (/ -7 2))"), "-3");
+        assert_eq!(eval_number("(truncate (/ -7 2))"), "-3");
+        assert_eq!(eval_number("(round (/ 7 2))"), "4");
+        assert_eq!(eval_number("(round (/ 5 2))"), "2");
+        assert_eq!(eval_number("(round 2,5)"), "2,0");
+        assert_eq!(eval_number("(floor 42)"), "42");
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(eval_number("(min 3 1 2)"), "1");
+        assert_eq!(eval_number("(max 3 (/ 7 2) 2)"), "7/2");
+        assert_eq!(eval_number("(max 1 2,0 3)"), "3,0");
+    }
+
+    #[test]
+    fn test_gcd_lcm() {
+        assert_eq!(eval_number("(gcd 12 -18)"), "6");
+        assert_eq!(eval_number("(lcm 4 6)"), "12");
+        assert_eq!(eval_number("(gcd)"), "0");
+        assert_eq!(eval_number("(lcm)"), "1");
+        assert_eq!(eval_number("(gcd (- 0 9223372036854775807 1) 6)"), "2");
+
+        let mut interpreter = crate::interpreter::Interpreter::new();
+        for code in [
+            "(gcd (- 0 9223372036854775807 1))",
+            "(lcm 9223372036854775807 2)",
+        ] {
+            let err = interpreter.eval(code).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::Arithmetic, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_transcendental() {
+        assert_eq!(eval_number("(exp 0)"), "1,0");
+        assert_eq!(eval_number("(log 8 2)"), "3,0");
+        assert_eq!(eval_number("(cos 0)"), "1,0");
+        assert_eq!(eval_number("(atan2 0 1)"), "0,0");
+    }
 }
@@ -0,0 +1,265 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::frontend::lexer::number_format::{number_format, set_number_format, NumberFormat};
+
+use super::values::*;
+
+type Getter = Box<dyn Fn() -> ValueRef>;
+type Setter = Box<dyn Fn(&ValueRef) -> Result<(), InterpreterError>>;
+
+enum Storage {
+    Value(RefCell<ValueRef>),
+    // Parameters that expose a setting of the interpreter (like the number
+    // format) read and write the setting itself
+    Native { get: Getter, set: Setter },
+}
+
+// Parameter objects are called without arguments to get their current
+// value. parameterize rebinds them for the dynamic extent of its body.
+pub struct Parameter {
+    name: String,
+    storage: Storage,
+    converter: Option<ValueRef>,
+}
+
+impl Parameter {
+    pub fn new(name: &str, value: &ValueRef, converter: Option<ValueRef>) -> Self {
+        Self {
+            name: name.to_string(),
+            storage: Storage::Value(RefCell::new(value.clone())),
+            converter,
+        }
+    }
+
+    pub fn native(name: &str, get: Getter, set: Setter) -> Self {
+        Self {
+            name: name.to_string(),
+            storage: Storage::Native { get, set },
+            converter: None,
+        }
+    }
+
+    pub fn get(&self) -> ValueRef {
+        match &self.storage {
+            Storage::Value(value) => value.borrow().clone(),
+            Storage::Native { get, .. } => get(),
+        }
+    }
+
+    pub fn set(&self, value: &ValueRef) -> Result<(), InterpreterError> {
+        match &self.storage {
+            Storage::Value(current) => {
+                *current.borrow_mut() = value.clone();
+                Ok(())
+            }
+            Storage::Native { set, .. } => set(value),
+        }
+    }
+
+    pub fn convert(&self, value: &ValueRef) -> EvalResult {
+        match &self.converter {
+            Some(converter) => call_value(converter, std::slice::from_ref(value)),
+            None => Ok(value.clone()),
+        }
+    }
+}
+
+impl Callable for Parameter {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error(&format!("{} function expects no arguments", self.name));
+        }
+        Ok(self.get())
+    }
+
+    fn as_parameter(&self) -> Option<&Parameter> {
+        Some(self)
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![0], false))
+    }
+}
+
+fn parameter_function(value: &ValueRef) -> Option<Rc<dyn Callable>> {
+    let value = borrow_value(value);
+    let function = downcast_value::<BuiltInFunctionValue>(&value)?;
+    function
+        .function
+        .as_parameter()
+        .map(|_| function.function.clone())
+}
+
+pub fn number_format_parameter() -> Parameter {
+    Parameter::native(
+        "number-format",
+        Box::new(|| {
+            let name = match number_format() {
+                NumberFormat::De => "de",
+                NumberFormat::En => "en",
+            };
+            new_valueref(SymbolValue::new(name))
+        }),
+        Box::new(|value| {
+            let format = match downcast_value::<SymbolValue>(&borrow_value(value)) {
+                Some(symbol) => symbol.value.parse::<NumberFormat>().ok(),
+                None => None,
+            };
+            match format {
+                Some(format) => {
+                    set_number_format(format);
+                    Ok(())
+                }
+                None => Err(InterpreterError::new("number-format expects 'de or 'en")),
+            }
+        }),
+    )
+}
+
+pub struct MakeParameter {}
+
+impl MakeParameter {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for MakeParameter {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("make-parameter function expects one or two arguments");
+        }
+
+        // The converter is applied to the initial value as well
+        let converter = args.get(1).cloned();
+        let parameter = Parameter::new("parameter", &args[0], converter);
+        let value = parameter.convert(&args[0])?;
+        parameter.set(&value)?;
+
+        let function: Rc<dyn Callable> = Rc::new(parameter);
+        Ok(new_valueref(BuiltInFunctionValue::new(
+            "parameter",
+            &function,
+        )))
+    }
+}
+
+// Restores the previous values of the rebound parameters when the body of
+// parameterize is left, also if it is left with an error
+struct Parameterization {
+    previous: Vec<(Rc<dyn Callable>, ValueRef)>,
+}
+
+impl Drop for Parameterization {
+    fn drop(&mut self) {
+        for (function, value) in self.previous.iter().rev() {
+            if let Some(parameter) = function.as_parameter() {
+                let _ = parameter.set(value);
+            }
+        }
+    }
+}
+
+pub struct CallWithParameters {}
+
+impl CallWithParameters {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for CallWithParameters {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len().is_multiple_of(2) {
+            return error(
+                "call-with-parameters function expects a function and parameter value pairs",
+            );
+        }
+
+        // All values are converted before the first parameter is rebound
+        let mut bindings = Vec::new();
+        for binding in args[1..].chunks(2) {
+            let Some(function) = parameter_function(&binding[0]) else {
+                return Err(InterpreterError::type_error(
+                    "parameterize expects parameter objects",
+                    &binding[0],
+                ));
+            };
+            let value = function.as_parameter().unwrap().convert(&binding[1])?;
+            bindings.push((function, value));
+        }
+
+        let mut parameterization = Parameterization {
+            previous: Vec::new(),
+        };
+        for (function, value) in bindings {
+            let parameter = function.as_parameter().unwrap();
+            let previous = parameter.get();
+            parameter.set(&value)?;
+            parameterization.previous.push((function.clone(), previous));
+        }
+
+        call_value(&args[0], &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_parameterize() {
+        let mut interpreter = Interpreter::new();
+        let code = r#"
+            (def indent (make-parameter 2))
+            (def (show) (indent))
+            (def (nested)
+                (parameterize ([indent 4])
+                    (+ (show) (parameterize ((indent 8)) (show)))))
+            (+ (nested) (show))
+        "#;
+        let result = interpreter.eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "14");
+
+        // The previous value is restored if the body fails
+        let err = interpreter.eval("(parameterize ((indent 3)) (error \"failed\"))");
+        assert!(err.is_err());
+        assert_eq!(
+            interpreter.eval("(indent)").unwrap().borrow().to_string(),
+            "2"
+        );
+
+        // Converters apply to the initial and to rebound values
+        let code = r#"
+            (def width (make-parameter 1 (λ (n) (* n 10))))
+            (list (width) (parameterize ((width 5)) (width)))
+        "#;
+        let result = Interpreter::with_prelude().eval(code).unwrap();
+        assert_eq!(result.borrow().to_string(), "(list 10 50)");
+
+        let err = interpreter.eval("(parameterize ((show 1)) 1)").unwrap_err();
+        assert_eq!(err.message, "parameterize expects parameter objects");
+    }
+
+    #[test]
+    fn test_number_format_parameter() {
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter
+            .eval(
+                "(parameterize ((number-format 'en)) (list (number-format) (number->string 1,5)))",
+            )
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "(list 'en \"1.5\")");
+        assert_eq!(
+            interpreter
+                .eval("(number-format)")
+                .unwrap()
+                .borrow()
+                .to_string(),
+            "'de"
+        );
+        assert!(interpreter
+            .eval("(parameterize ((number-format 'fr)) 1)")
+            .is_err());
+    }
+}
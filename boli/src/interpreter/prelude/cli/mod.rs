@@ -0,0 +1,115 @@
+use crate::interpreter::{
+    environment::{Environment, EnvironmentBuilder},
+    module_mgmt::extension::{new_extension, new_extension_dir, ExtensionRef},
+};
+
+use super::load_module_code;
+
+pub fn create_cli_extension(
+    vector_ext: &ExtensionRef,
+    string_ext: &ExtensionRef,
+    seqcol_ext: &ExtensionRef,
+) -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+
+    let deps = new_extension_dir("deps");
+    for dep in [vector_ext, string_ext, seqcol_ext] {
+        deps.borrow_mut().add_extension(dep);
+    }
+
+    let mut search_dirs = env.borrow().get_module_search_dirs();
+    search_dirs.push(deps);
+    Environment::set_module_search_dirs(&env, &search_dirs);
+
+    let values = load_module_code(&env, include_str!("cli.boli")).unwrap_or_default();
+
+    new_extension("cli", values)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    const SPEC: &str = r#"
+        (def spec '((flag "verbose" "print more details")
+                    (option "out" "result.txt" "file to write the result to")
+                    (positional "input" "file to read")))
+    "#;
+
+    #[test]
+    fn test_parse_args() {
+        let mut interpreter = Interpreter::with_prelude();
+        interpreter.eval(SPEC).unwrap();
+        let mut parse = |args: &str| {
+            let code = format!(
+                "(let ([opts (parse-args spec {})]) \
+                 (list (hash-get opts \"verbose\") (hash-get opts \"out\") (hash-get opts \"input\")))",
+                args
+            );
+            interpreter
+                .eval(&code)
+                .map(|result| result.borrow().to_string())
+                .map_err(|err| err.message.clone())
+        };
+
+        assert_eq!(
+            parse("(vector \"data.txt\")").unwrap(),
+            "(list #false \"result.txt\" \"data.txt\")"
+        );
+        assert_eq!(
+            parse("(vector \"--out\" \"a.txt\" \"data.txt\" \"--verbose\")").unwrap(),
+            "(list #true \"a.txt\" \"data.txt\")"
+        );
+        assert_eq!(
+            parse("(list \"--out=b.txt\" \"--\" \"--data\")").unwrap(),
+            "(list #false \"b.txt\" \"--data\")"
+        );
+
+        assert_eq!(parse("(vector)").unwrap_err(), "missing argument input");
+        assert_eq!(
+            parse("(vector \"--quiet\" \"data.txt\")").unwrap_err(),
+            "unknown option --quiet"
+        );
+        assert_eq!(
+            parse("(vector \"data.txt\" \"--out\")").unwrap_err(),
+            "option --out expects a value"
+        );
+        assert_eq!(
+            parse("(vector \"--verbose=yes\" \"data.txt\")").unwrap_err(),
+            "option --verbose does not take a value"
+        );
+        assert_eq!(
+            parse("(vector \"a.txt\" \"b.txt\")").unwrap_err(),
+            "unexpected argument b.txt"
+        );
+    }
+
+    #[test]
+    fn test_args_help() {
+        let mut interpreter = Interpreter::with_prelude();
+        interpreter.eval(SPEC).unwrap();
+
+        let result = interpreter.eval("(args-help spec)").unwrap();
+        assert_eq!(
+            result.borrow().display_string(),
+            "usage: [options] <input>\n\
+             \n\
+             arguments:\n  \
+               input               file to read\n\
+             \n\
+             options:\n  \
+               --verbose           print more details\n  \
+               --out VALUE         file to write the result to (default: result.txt)\n  \
+               -h, --help          show this help\n"
+        );
+
+        let result = interpreter
+            .eval("(with-output-to-string (λ () (hash-get (parse-args spec (vector \"-h\")) \"help\")))")
+            .unwrap();
+        assert!(result
+            .borrow()
+            .display_string()
+            .starts_with("usage: [options] <input>\n"));
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::interpreter::{
+    environment::{Environment, EnvironmentBuilder},
+    module_mgmt::extension::{new_extension, ExtensionRef},
+    values::*,
+};
+
+type SchedulerRef = Rc<RefCell<Scheduler>>;
+
+pub fn create_concurrent_extension() -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+    let scheduler = Rc::new(RefCell::new(Scheduler::new()));
+
+    env.borrow_mut()
+        .set_callable("spawn", &Rc::new(Spawn::new(&scheduler)));
+    env.borrow_mut()
+        .set_callable("join", &Rc::new(Join::new(&scheduler)));
+    env.borrow_mut()
+        .set_callable("task?", &Rc::new(IsTask::new()));
+    env.borrow_mut()
+        .set_callable("make-channel", &Rc::new(MakeChannel::new()));
+    env.borrow_mut()
+        .set_callable("channel?", &Rc::new(IsChannel::new()));
+    env.borrow_mut()
+        .set_callable("send!", &Rc::new(SendBang::new()));
+    env.borrow_mut()
+        .set_callable("recv", &Rc::new(Recv::new(&scheduler)));
+
+    let values = env.borrow().get_exported_values();
+
+    new_extension("concurrent", values)
+}
+
+// Tasks are green threads: they run to completion, one at a time, whenever
+// a join or a recv on an empty channel needs them to make progress. A task
+// cannot be suspended, so a recv within a task only waits for other pending
+// tasks, never for the code that spawned it. A task runs a copy of its
+// function bound to a copy of the function's environment taken by spawn, so
+// it shares no bindings with the spawning code. Messages and the results of
+// join are deep copies as well.
+struct Scheduler {
+    pending: VecDeque<ValueRef>,
+    next_id: usize,
+}
+
+impl Scheduler {
+    fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            next_id: 1,
+        }
+    }
+
+    fn run_next(scheduler: &SchedulerRef) -> bool {
+        let next = scheduler.borrow_mut().pending.pop_front();
+        match next {
+            Some(task) => {
+                run_task(&task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, task: &ValueRef) {
+        self.pending.retain(|pending| !Rc::ptr_eq(pending, task));
+    }
+}
+
+enum TaskState {
+    Pending(ValueRef),
+    Running,
+    Done(EvalResult),
+}
+
+pub struct TaskValue {
+    id: usize,
+    state: TaskState,
+}
+
+impl Value for TaskValue {
+    fn get_type(&self) -> ValueType {
+        ValueType::Task
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Display for TaskValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self.state {
+            TaskState::Pending(_) => "pending",
+            TaskState::Running => "running",
+            TaskState::Done(_) => "done",
+        };
+        write!(f, "<task {} {}>", self.id, state)
+    }
+}
+
+impl Debug for TaskValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+fn run_task(task: &ValueRef) {
+    let function = {
+        let mut task = borrow_mut_value(task);
+        let task = task.as_any_mut().downcast_mut::<TaskValue>().unwrap();
+        match std::mem::replace(&mut task.state, TaskState::Running) {
+            TaskState::Pending(function) => function,
+            state => {
+                task.state = state;
+                return;
+            }
+        }
+    };
+
+    let result = call_value(&function, &[]).map(|value| deep_copy(&value));
+
+    let mut task = borrow_mut_value(task);
+    let task = task.as_any_mut().downcast_mut::<TaskValue>().unwrap();
+    task.state = TaskState::Done(result);
+}
+
+pub struct ChannelValue {
+    messages: VecDeque<ValueRef>,
+}
+
+impl Value for ChannelValue {
+    fn get_type(&self) -> ValueType {
+        ValueType::Channel
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Display for ChannelValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<channel {}>", self.messages.len())
+    }
+}
+
+impl Debug for ChannelValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+struct Spawn {
+    scheduler: SchedulerRef,
+}
+
+impl Spawn {
+    fn new(scheduler: &SchedulerRef) -> Self {
+        Self {
+            scheduler: scheduler.clone(),
+        }
+    }
+}
+
+impl Callable for Spawn {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("spawn function expects exactly one argument");
+        }
+
+        let function_type = args[0].borrow().get_type();
+        if function_type != ValueType::Lambda && function_type != ValueType::BuiltInFunction {
+            return error("spawn function expects a function argument");
+        }
+
+        let function = {
+            let function = borrow_value(&args[0]);
+            match downcast_value::<LambdaValue>(&function) {
+                Some(lambda) => {
+                    let env = Environment::isolated_copy(&lambda.env);
+                    new_valueref(lambda.with_env(&env))
+                }
+                None => args[0].clone(),
+            }
+        };
+
+        let mut scheduler = self.scheduler.borrow_mut();
+        let task = new_valueref(TaskValue {
+            id: scheduler.next_id,
+            state: TaskState::Pending(function),
+        });
+        scheduler.next_id += 1;
+        scheduler.pending.push_back(task.clone());
+
+        Ok(task)
+    }
+}
+
+struct Join {
+    scheduler: SchedulerRef,
+}
+
+impl Join {
+    fn new(scheduler: &SchedulerRef) -> Self {
+        Self {
+            scheduler: scheduler.clone(),
+        }
+    }
+}
+
+impl Callable for Join {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("join function expects exactly one argument");
+        }
+
+        let is_pending = match downcast_value::<TaskValue>(&borrow_value(&args[0])) {
+            Some(task) => matches!(task.state, TaskState::Pending(_)),
+            None => return error("join function expects a task"),
+        };
+
+        if is_pending {
+            self.scheduler.borrow_mut().remove(&args[0]);
+            run_task(&args[0]);
+        }
+
+        let task = borrow_value(&args[0]);
+        match &downcast_value::<TaskValue>(&task).unwrap().state {
+            TaskState::Done(Ok(value)) => Ok(deep_copy(value)),
+            TaskState::Done(Err(err)) => Err(err.clone()),
+            _ => error("join function cannot wait for a task that is already running"),
+        }
+    }
+}
+
+struct IsTask {}
+
+impl IsTask {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsTask {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("task? function expects exactly one argument");
+        }
+
+        Ok(new_valueref(BoolValue {
+            value: args[0].borrow().get_type() == ValueType::Task,
+        }))
+    }
+}
+
+struct MakeChannel {}
+
+impl MakeChannel {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for MakeChannel {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("make-channel function expects no arguments");
+        }
+
+        Ok(new_valueref(ChannelValue {
+            messages: VecDeque::new(),
+        }))
+    }
+}
+
+struct IsChannel {}
+
+impl IsChannel {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsChannel {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("channel? function expects exactly one argument");
+        }
+
+        Ok(new_valueref(BoolValue {
+            value: args[0].borrow().get_type() == ValueType::Channel,
+        }))
+    }
+}
+
+struct SendBang {}
+
+impl SendBang {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for SendBang {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("send! function expects exactly two arguments");
+        }
+
+        let mut channel = borrow_mut_value(&args[0]);
+        match channel.as_any_mut().downcast_mut::<ChannelValue>() {
+            Some(channel) => channel.messages.push_back(deep_copy(&args[1])),
+            None => return error("send! function expects a channel as the first argument"),
+        }
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct Recv {
+    scheduler: SchedulerRef,
+}
+
+impl Recv {
+    fn new(scheduler: &SchedulerRef) -> Self {
+        Self {
+            scheduler: scheduler.clone(),
+        }
+    }
+}
+
+impl Callable for Recv {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("recv function expects exactly one argument");
+        }
+
+        loop {
+            {
+                let mut channel = borrow_mut_value(&args[0]);
+                match channel.as_any_mut().downcast_mut::<ChannelValue>() {
+                    Some(channel) => {
+                        if let Some(message) = channel.messages.pop_front() {
+                            return Ok(message);
+                        }
+                    }
+                    None => return error("recv function expects a channel"),
+                }
+            }
+
+            if !Scheduler::run_next(&self.scheduler) {
+                return error(
+                    "recv function would block forever: channel is empty and no tasks are pending",
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    fn eval(code: &str) -> String {
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+        let result = result.borrow().to_string();
+        result
+    }
+
+    #[test]
+    fn test_spawn_join() {
+        let code = r#"
+        (def (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+        (def t1 (spawn (lambda () (fib 10))))
+        (def t2 (spawn (lambda () (fib 12))))
+        (+ (join t1) (join t2) (join t1))
+        "#;
+        assert_eq!(eval(code), "254");
+    }
+
+    #[test]
+    fn test_channels() {
+        let code = r#"
+        (def ch (make-channel))
+        (def results (make-channel))
+        (def worker
+            (spawn (lambda ()
+                (def v (recv ch))
+                (vector-set! v 0 42)
+                (send! results v))))
+        (def data (vector 1 2 3))
+        (send! ch data)
+        (def received (recv results))
+        (vector data received (task? worker) (channel? ch))
+        "#;
+        assert_eq!(
+            eval(code),
+            "(vector (vector 1 2 3) (vector 42 2 3) #true #true)"
+        );
+    }
+
+    #[test]
+    fn test_recv_without_tasks_fails() {
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval("(recv (make-channel))");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_tasks_cannot_change_bindings() {
+        let code = r#"
+        (def counter 0)
+        (def data (vector 1 2 3))
+        (def (count-up) (set! counter (+ counter 1)) counter)
+        (def (run)
+            (def local 10)
+            (spawn (lambda ()
+                (set! local 0)
+                (vector-set! data 0 42)
+                (vector (count-up) (count-up) local data))))
+        (def task (run))
+        (vector (join task) counter data)
+        "#;
+        assert_eq!(
+            eval(code),
+            "(vector (vector 1 2 0 (vector 42 2 3)) 0 (vector 1 2 3))"
+        );
+    }
+
+    #[test]
+    fn test_tasks_run_to_completion() {
+        // The task runs on join and cannot wait for the later send!
+        let mut interpreter = Interpreter::with_prelude();
+        let code = r#"
+        (def ch (make-channel))
+        (def task (spawn (lambda () (recv ch))))
+        (join task)
+        (send! ch 42)
+        "#;
+        let err = interpreter.eval(code).unwrap_err();
+        assert!(err.message.contains("would block forever"));
+    }
+}
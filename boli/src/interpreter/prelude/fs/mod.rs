@@ -0,0 +1,245 @@
+use std::{fs, path::Path, rc::Rc};
+
+use crate::interpreter::{
+    environment::EnvironmentBuilder,
+    module_mgmt::{
+        extension::{new_extension, ExtensionRef},
+        file_system::join_path,
+    },
+    values::*,
+};
+
+pub fn create_fs_extension() -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+
+    env.borrow_mut()
+        .set_callable("file-exists?", &Rc::new(FileExists::new()));
+    env.borrow_mut()
+        .set_callable("directory?", &Rc::new(IsDirectory::new()));
+    env.borrow_mut()
+        .set_callable("list-directory", &Rc::new(ListDirectory::new()));
+    env.borrow_mut()
+        .set_callable("make-directory", &Rc::new(MakeDirectory::new()));
+    env.borrow_mut()
+        .set_callable("delete-file", &Rc::new(DeleteFile::new()));
+    env.borrow_mut()
+        .set_callable("copy-file", &Rc::new(CopyFile::new()));
+    env.borrow_mut()
+        .set_callable("file-size", &Rc::new(FileSize::new()));
+
+    let values = env.borrow().get_exported_values();
+
+    new_extension("fs", values)
+}
+
+struct FileExists {}
+
+impl FileExists {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for FileExists {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let path = path_arg(args, "file-exists?")?;
+
+        Ok(new_valueref(BoolValue {
+            value: Path::new(&path).exists(),
+        }))
+    }
+}
+
+struct IsDirectory {}
+
+impl IsDirectory {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsDirectory {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let path = path_arg(args, "directory?")?;
+
+        Ok(new_valueref(BoolValue {
+            value: Path::new(&path).is_dir(),
+        }))
+    }
+}
+
+struct ListDirectory {}
+
+impl ListDirectory {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for ListDirectory {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let path = path_arg(args, "list-directory")?;
+        let entries = fs::read_dir(&path).map_err(|e| io_error("list-directory", &path, e))?;
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+
+        let elements = names
+            .into_iter()
+            .map(|value| {
+                new_valueref(StrValue {
+                    value: value.into(),
+                })
+            })
+            .collect();
+
+        Ok(new_valueref(VectorValue { elements }))
+    }
+}
+
+struct MakeDirectory {}
+
+impl MakeDirectory {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for MakeDirectory {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let path = path_arg(args, "make-directory")?;
+        fs::create_dir_all(&path).map_err(|e| io_error("make-directory", &path, e))?;
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct DeleteFile {}
+
+impl DeleteFile {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for DeleteFile {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let path = path_arg(args, "delete-file")?;
+        fs::remove_file(&path).map_err(|e| io_error("delete-file", &path, e))?;
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct CopyFile {}
+
+impl CopyFile {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for CopyFile {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("copy-file function expects exactly two arguments");
+        }
+
+        let source = str_value(&args[0], "copy-file")?;
+        let mut target = str_value(&args[1], "copy-file")?;
+
+        if Path::new(&target).is_dir() {
+            let file_name = Path::new(&source)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            target = join_path(&target, file_name);
+        }
+
+        let size = fs::copy(&source, &target).map_err(|e| io_error("copy-file", &source, e))?;
+
+        Ok(new_valueref(IntValue { value: size as i64 }))
+    }
+}
+
+struct FileSize {}
+
+impl FileSize {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for FileSize {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let path = path_arg(args, "file-size")?;
+        let metadata = fs::metadata(&path).map_err(|e| io_error("file-size", &path, e))?;
+
+        Ok(new_valueref(IntValue {
+            value: metadata.len() as i64,
+        }))
+    }
+}
+
+fn path_arg(args: &[ValueRef], function_name: &str) -> Result<String, InterpreterError> {
+    if args.len() != 1 {
+        return Err(InterpreterError::new(&format!(
+            "{} function expects exactly one argument",
+            function_name
+        )));
+    }
+
+    str_value(&args[0], function_name)
+}
+
+fn str_value(arg: &ValueRef, function_name: &str) -> Result<String, InterpreterError> {
+    match downcast_value::<StrValue>(&arg.borrow()) {
+        Some(str_value) => Ok(str_value.value.to_string()),
+        None => Err(InterpreterError::new(&format!(
+            "{} function expects a path string",
+            function_name
+        ))),
+    }
+}
+
+fn io_error(function_name: &str, path: &str, err: std::io::Error) -> InterpreterError {
+    InterpreterError::io_error(&format!("{}: {}: {}", function_name, path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_file_operations() {
+        let dir = std::env::temp_dir().join(format!("boli-fs-test-{}", std::process::id()));
+        let dir = dir.to_str().unwrap().replace('\\', "/");
+        let code = format!(
+            r#"
+            (def dir "{dir}")
+            (make-directory (string-concat dir "/sub"))
+            (copy-file "Cargo.toml" dir)
+            (def copied (string-concat dir "/Cargo.toml"))
+            (def result (vector (directory? dir)
+                                (file-exists? copied)
+                                (= (file-size copied) (file-size "Cargo.toml"))
+                                (list-directory dir)))
+            (delete-file copied)
+            (vector result (file-exists? copied))
+            "#
+        );
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(&code);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(
+            result.unwrap().borrow().to_string(),
+            "(vector (vector #true #true #true (vector \"Cargo.toml\" \"sub\")) #false)"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::interpreter::{
+    environment::EnvironmentBuilder,
+    misc_functions::{OutputRef, StdErrOutput},
+    module_mgmt::extension::{new_extension, ExtensionRef},
+    parameters::Parameter,
+    values::*,
+};
+
+type LoggerRef = Rc<RefCell<Logger>>;
+
+pub fn create_log_extension() -> ExtensionRef {
+    let output: OutputRef = Rc::new(RefCell::new(StdErrOutput::new()));
+    create_log_extension_with_output(&output)
+}
+
+fn create_log_extension_with_output(output: &OutputRef) -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+    let logger = Rc::new(RefCell::new(Logger::new(output)));
+
+    for level in [Level::Debug, Level::Info, Level::Warn, Level::Error] {
+        let name = format!("log-{}", level.name());
+        env.borrow_mut()
+            .set_callable(&name, &Rc::new(LogMessage::new(&logger, level)));
+    }
+    env.borrow_mut()
+        .set_callable("log-level", &Rc::new(log_level_parameter(&logger)));
+    env.borrow_mut()
+        .set_callable("set-log-level!", &Rc::new(SetLogLevel::new(&logger)));
+    env.borrow_mut()
+        .set_callable("set-log-format!", &Rc::new(SetLogFormat::new(&logger)));
+
+    let values = env.borrow().get_exported_values();
+
+    new_extension("log", values)
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+}
+
+enum Format {
+    Text,
+    Json,
+}
+
+struct Logger {
+    level: Level,
+    format: Format,
+    output: OutputRef,
+}
+
+impl Logger {
+    fn new(output: &OutputRef) -> Self {
+        Self {
+            level: Level::Info,
+            format: Format::Text,
+            output: output.clone(),
+        }
+    }
+
+    fn log(&self, level: Level, message: &str) {
+        if level < self.level {
+            return;
+        }
+
+        let timestamp = format_timestamp(SystemTime::now());
+        let line = match self.format {
+            Format::Text => format!(
+                "{} {:<5} {}",
+                timestamp,
+                level.name().to_uppercase(),
+                message
+            ),
+            Format::Json => format!(
+                "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"message\":\"{}\"}}",
+                timestamp,
+                level.name(),
+                json_escape(message)
+            ),
+        };
+        self.output.borrow_mut().print_line(&line);
+    }
+}
+
+// RFC 3339 timestamp in UTC with millisecond precision
+fn format_timestamp(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = duration.as_secs() as i64;
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        duration.subsec_millis()
+    )
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn symbol_arg(arg: &ValueRef, function: &str) -> Result<String, InterpreterError> {
+    match downcast_value::<SymbolValue>(&borrow_value(arg)) {
+        Some(symbol) => Ok(symbol.value.clone()),
+        None => Err(InterpreterError::new(&format!(
+            "{} function expects a symbol as argument",
+            function
+        ))),
+    }
+}
+
+struct LogMessage {
+    logger: LoggerRef,
+    level: Level,
+}
+
+impl LogMessage {
+    fn new(logger: &LoggerRef, level: Level) -> Self {
+        Self {
+            logger: logger.clone(),
+            level,
+        }
+    }
+}
+
+impl Callable for LogMessage {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() {
+            return error(&format!(
+                "log-{} function expects at least one argument",
+                self.level.name()
+            ));
+        }
+
+        let message = args
+            .iter()
+            .map(|arg| arg.borrow().display_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.logger.borrow().log(self.level, &message);
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+// log-level is a parameter, so (parameterize ((log-level 'debug)) ...)
+// changes the level temporarily
+fn log_level_parameter(logger: &LoggerRef) -> Parameter {
+    let get_logger = logger.clone();
+    let set_logger = logger.clone();
+    Parameter::native(
+        "log-level",
+        Box::new(move || new_valueref(SymbolValue::new(get_logger.borrow().level.name()))),
+        Box::new(move |value| {
+            let name = symbol_arg(value, "log-level")?;
+            match Level::from_name(&name) {
+                Some(level) => {
+                    set_logger.borrow_mut().level = level;
+                    Ok(())
+                }
+                None => Err(InterpreterError::new(
+                    "log-level expects 'debug, 'info, 'warn or 'error",
+                )),
+            }
+        }),
+    )
+}
+
+struct SetLogLevel {
+    logger: LoggerRef,
+}
+
+impl SetLogLevel {
+    fn new(logger: &LoggerRef) -> Self {
+        Self {
+            logger: logger.clone(),
+        }
+    }
+}
+
+impl Callable for SetLogLevel {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("set-log-level! function expects exactly one argument");
+        }
+
+        let name = symbol_arg(&args[0], "set-log-level!")?;
+        match Level::from_name(&name) {
+            Some(level) => self.logger.borrow_mut().level = level,
+            None => return error("set-log-level! function expects 'debug, 'info, 'warn or 'error"),
+        }
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct SetLogFormat {
+    logger: LoggerRef,
+}
+
+impl SetLogFormat {
+    fn new(logger: &LoggerRef) -> Self {
+        Self {
+            logger: logger.clone(),
+        }
+    }
+}
+
+impl Callable for SetLogFormat {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("set-log-format! function expects exactly one argument");
+        }
+
+        let format = match symbol_arg(&args[0], "set-log-format!")?.as_str() {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            _ => return error("set-log-format! function expects 'text or 'json"),
+        };
+        self.logger.borrow_mut().format = format;
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{
+        misc_functions::BufferedOutput, module_mgmt::ExtensionModule, Interpreter,
+    };
+
+    fn run(code: &str) -> Vec<String> {
+        let buffered_output = BufferedOutput::new();
+        let output: OutputRef = Rc::new(RefCell::new(buffered_output.clone()));
+        let extension = create_log_extension_with_output(&output);
+
+        let env = EnvironmentBuilder::new().build();
+        env.borrow_mut()
+            .import_values(extension.borrow().get_values());
+        Interpreter::with_environment(&env).eval(code).unwrap();
+
+        let lines = buffered_output.take();
+        lines.lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_levels() {
+        let lines = run(r#"
+            (log-debug "hidden")
+            (log-info "started" 42)
+            (set-log-level! 'warn)
+            (log-info "hidden")
+            (log-error "failed:" 'oops)
+            (parameterize ((log-level 'debug)) (log-debug "shown"))
+            (log-debug "hidden")
+        "#);
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("Z INFO  started 42"));
+        assert!(lines[1].ends_with("Z ERROR failed: 'oops"));
+        assert!(lines[2].ends_with("Z DEBUG shown"));
+    }
+
+    #[test]
+    fn test_json_format() {
+        let lines = run(r#"
+            (set-log-format! 'json)
+            (log-warn "say \"hi\"")
+        "#);
+
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("{\"timestamp\":\""));
+        assert!(lines[0].ends_with("\"level\":\"warn\",\"message\":\"say \\\"hi\\\"\"}"));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let time = UNIX_EPOCH + std::time::Duration::from_millis(1_709_164_800_123);
+        assert_eq!(format_timestamp(time), "2024-02-29T00:00:00.123Z");
+    }
+}
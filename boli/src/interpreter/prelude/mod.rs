@@ -4,13 +4,86 @@ use super::{
 use crate::interpreter::module_mgmt::extension::new_extension_dir;
 use std::collections::HashMap;
 
+mod cli;
+mod concurrent;
+mod fs;
 mod list;
+mod log;
+mod net;
+mod os;
+mod random;
 mod seq_collection;
 mod stream;
 mod string;
+mod test;
 mod vector;
 
-pub fn create_prelude() -> ModuleDirRef {
+pub const PRELUDE_EXTENSIONS: [&str; 13] = [
+    "vector",
+    "list",
+    "stream",
+    "string",
+    "seqcol",
+    "random",
+    "os",
+    "fs",
+    "net",
+    "concurrent",
+    "log",
+    "test",
+    "cli",
+];
+
+// Capabilities of the prelude that give scripts access to the system.
+// Untrusted scripts can be run with some of them disabled, the functions
+// of disabled capabilities are then not defined at all.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capabilities {
+    // File system functions (fs extension)
+    pub fs: bool,
+    // Network functions (net extension)
+    pub net: bool,
+    // Running external processes (process functions of the os extension)
+    pub exec: bool,
+    // Loading native libraries by require (native feature)
+    pub native: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self {
+            fs: true,
+            net: true,
+            exec: true,
+            native: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            fs: false,
+            net: false,
+            exec: false,
+            native: false,
+        }
+    }
+
+    pub fn allows_extension(&self, name: &str) -> bool {
+        match name {
+            "fs" => self.fs,
+            "net" => self.net,
+            _ => true,
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+pub fn create_prelude(capabilities: &Capabilities) -> ModuleDirRef {
     let ret = new_extension_dir("");
 
     let vector_ext = vector::create_vector_extension();
@@ -33,6 +106,34 @@ pub fn create_prelude() -> ModuleDirRef {
     );
     ret.borrow_mut().add_extension(&seq_collection);
 
+    let random_ext = random::create_random_extension();
+    ret.borrow_mut().add_extension(&random_ext);
+
+    let os_ext = os::create_os_extension(capabilities.exec);
+    ret.borrow_mut().add_extension(&os_ext);
+
+    if capabilities.fs {
+        let fs_ext = fs::create_fs_extension();
+        ret.borrow_mut().add_extension(&fs_ext);
+    }
+
+    if capabilities.net {
+        let net_ext = net::create_net_extension();
+        ret.borrow_mut().add_extension(&net_ext);
+    }
+
+    let concurrent_ext = concurrent::create_concurrent_extension();
+    ret.borrow_mut().add_extension(&concurrent_ext);
+
+    let log_ext = log::create_log_extension();
+    ret.borrow_mut().add_extension(&log_ext);
+
+    let test_ext = test::create_test_extension(&list_ext, &seq_collection);
+    ret.borrow_mut().add_extension(&test_ext);
+
+    let cli_ext = cli::create_cli_extension(&vector_ext, &string_ext, &seq_collection);
+    ret.borrow_mut().add_extension(&cli_ext);
+
     ret
 }
 
@@ -0,0 +1,222 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::interpreter::{environment::EnvironmentRef, struct_functions::get_key, values::*};
+
+use super::str_arg;
+
+const RESPONSE_FIELDS: [&str; 3] = ["status", "headers", "body"];
+
+pub fn register_http_functions(env: &EnvironmentRef) {
+    let fields: Vec<String> = RESPONSE_FIELDS.iter().map(|f| f.to_string()).collect();
+    let response_type = new_valueref(StructTypeValue::new("http-response", &fields));
+
+    env.borrow_mut()
+        .set("http-response".to_string(), response_type.clone());
+    env.borrow_mut().set_callable(
+        "http-response?",
+        &Rc::new(IsStructType::new(&response_type)),
+    );
+    for field in RESPONSE_FIELDS {
+        env.borrow_mut().set_callable(
+            &format!("http-response-{}", field),
+            &Rc::new(GetStructField::new(field)),
+        );
+    }
+
+    env.borrow_mut()
+        .set_callable("http-get", &Rc::new(HttpGet::new(&response_type)));
+    env.borrow_mut()
+        .set_callable("http-post", &Rc::new(HttpPost::new(&response_type)));
+}
+
+struct HttpGet {
+    response_type: ValueRef,
+}
+
+impl HttpGet {
+    fn new(response_type: &ValueRef) -> Self {
+        Self {
+            response_type: response_type.clone(),
+        }
+    }
+}
+
+impl Callable for HttpGet {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("http-get function expects one or two arguments");
+        }
+
+        let url = str_arg(&args[0], "http-get")?;
+        let mut request = ureq::get(&url);
+        if let Some(headers) = args.get(1) {
+            for (name, value) in header_args(headers)? {
+                request = request.set(&name, &value);
+            }
+        }
+
+        new_response(request.call(), &self.response_type)
+    }
+}
+
+struct HttpPost {
+    response_type: ValueRef,
+}
+
+impl HttpPost {
+    fn new(response_type: &ValueRef) -> Self {
+        Self {
+            response_type: response_type.clone(),
+        }
+    }
+}
+
+impl Callable for HttpPost {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() < 2 || args.len() > 3 {
+            return error("http-post function expects two or three arguments");
+        }
+
+        let url = str_arg(&args[0], "http-post")?;
+        let body = str_arg(&args[1], "http-post")?;
+        let mut request = ureq::post(&url);
+        if let Some(headers) = args.get(2) {
+            for (name, value) in header_args(headers)? {
+                request = request.set(&name, &value);
+            }
+        }
+
+        new_response(request.send_string(&body), &self.response_type)
+    }
+}
+
+fn new_response(
+    result: Result<ureq::Response, ureq::Error>,
+    response_type: &ValueRef,
+) -> EvalResult {
+    let response = match result {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => return error(&format!("HTTP request failed: {}", err)),
+    };
+
+    let status = new_valueref(IntValue {
+        value: response.status() as i64,
+    });
+
+    let mut headers = StructValue::new_hash_table();
+    for name in response.headers_names() {
+        if let Some(value) = response.header(&name) {
+            let key = new_valueref(StrValue { value: name.into() });
+            let entry = StructEntry {
+                key: key.clone(),
+                value: new_valueref(StrValue {
+                    value: value.into(),
+                }),
+            };
+            headers.values.insert(get_key(&key), entry);
+        }
+    }
+
+    let body = response
+        .into_string()
+        .map_err(|e| InterpreterError::new(&format!("Cannot read HTTP response: {}", e)))?;
+
+    CreateStructValue::new(response_type).call(&vec![
+        status,
+        new_valueref(headers),
+        new_valueref(StrValue { value: body.into() }),
+    ])
+}
+
+fn header_args(headers: &ValueRef) -> Result<HashMap<String, String>, InterpreterError> {
+    let headers = borrow_value(headers);
+    match downcast_value::<StructValue>(&headers) {
+        Some(hash_table) if hash_table.struct_type.is_none() => Ok(hash_table
+            .values
+            .values()
+            .map(|entry| {
+                (
+                    entry.key.borrow().display_string(),
+                    entry.value.borrow().display_string(),
+                )
+            })
+            .collect()),
+        _ => Err(InterpreterError::new("HTTP headers must be a hash table")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use crate::interpreter::Interpreter;
+
+    fn serve_once(response_body: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/echo", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+
+            write!(
+                stream,
+                "HTTP/1.1 201 Created\r\nX-Answer: 42\r\nContent-Length: {}\r\n\r\n{}",
+                response_body.len(),
+                response_body
+            )
+            .unwrap();
+            request
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn test_http_post() {
+        let (url, server) = serve_once("created");
+        let code = format!(
+            r#"
+            (def headers (create-hash-table))
+            (hash-set! headers "Content-Type" "text/plain")
+            (def response (http-post "{url}" "hello" headers))
+            (vector (http-response-status response)
+                    (hash-get (http-response-headers response) "x-answer")
+                    (http-response-body response))
+            "#
+        );
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(&code).unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector 201 \"42\" \"created\")"
+        );
+        assert!(request.starts_with("POST /echo"));
+        assert!(request.contains("Content-Type: text/plain"));
+        assert!(request.ends_with("hello"));
+    }
+}
@@ -0,0 +1,33 @@
+use crate::interpreter::{
+    environment::EnvironmentBuilder,
+    module_mgmt::extension::{new_extension, ExtensionRef},
+    values::*,
+};
+
+#[cfg(feature = "http")]
+mod http;
+mod tcp;
+
+pub fn create_net_extension() -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+
+    tcp::register_tcp_functions(&env);
+
+    #[cfg(feature = "http")]
+    http::register_http_functions(&env);
+
+    let values = env.borrow().get_exported_values();
+
+    new_extension("net", values)
+}
+
+fn str_arg(arg: &ValueRef, function_name: &str) -> Result<String, InterpreterError> {
+    match downcast_value::<StrValue>(&arg.borrow()) {
+        Some(str_value) => Ok(str_value.value.to_string()),
+        None => Err(InterpreterError::new(&format!(
+            "{} function expects string arguments",
+            function_name
+        ))),
+    }
+}
@@ -0,0 +1,524 @@
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Display},
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    rc::Rc,
+};
+
+use crate::interpreter::{
+    environment::EnvironmentRef,
+    misc_functions::{
+        with_redirected_input, with_redirected_output, Input, InputRef, Output, OutputRef,
+    },
+    values::*,
+};
+
+use super::str_arg;
+
+pub fn register_tcp_functions(env: &EnvironmentRef) {
+    env.borrow_mut()
+        .set_callable("tcp-listen", &Rc::new(TcpListen::new()));
+    env.borrow_mut()
+        .set_callable("tcp-accept", &Rc::new(TcpAccept::new()));
+    env.borrow_mut()
+        .set_callable("tcp-connect", &Rc::new(TcpConnect::new()));
+    env.borrow_mut()
+        .set_callable("socket?", &Rc::new(IsSocket::new()));
+    env.borrow_mut()
+        .set_callable("socket-address", &Rc::new(SocketAddress::new()));
+    env.borrow_mut()
+        .set_callable("socket-read-line", &Rc::new(SocketReadLine::new()));
+    env.borrow_mut()
+        .set_callable("socket-write", &Rc::new(SocketWrite::new()));
+    env.borrow_mut()
+        .set_callable("socket-close", &Rc::new(SocketClose::new()));
+    env.borrow_mut().set_callable(
+        "with-input-from-socket",
+        &Rc::new(WithInputFromSocket::new()),
+    );
+    env.borrow_mut()
+        .set_callable("with-output-to-socket", &Rc::new(WithOutputToSocket::new()));
+}
+
+// The stream of a connection is shared with the input and output ports it
+// is installed as by with-input-from-socket and with-output-to-socket
+#[derive(Debug)]
+pub struct SocketStream {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    // Output cannot fail, so the first write error is kept until the
+    // redirection of the output ends
+    write_error: Option<std::io::Error>,
+}
+
+impl Input for SocketStream {
+    // A read error ends the input like a closed connection does
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(n) if n > 0 => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+            _ => None,
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl Output for SocketStream {
+    fn print(&mut self, text: &str) {
+        if self.write_error.is_some() {
+            return;
+        }
+        if let Err(err) = self
+            .writer
+            .write_all(text.as_bytes())
+            .and_then(|_| self.writer.flush())
+        {
+            self.write_error = Some(err);
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+pub enum SocketValue {
+    Listener(TcpListener),
+    Connection(Rc<RefCell<SocketStream>>),
+    Closed,
+}
+
+impl SocketValue {
+    fn new_connection(stream: TcpStream) -> Result<Self, InterpreterError> {
+        let reader = stream.try_clone().map_err(io_error)?;
+        Ok(Self::Connection(Rc::new(RefCell::new(SocketStream {
+            reader: BufReader::new(reader),
+            writer: stream,
+            write_error: None,
+        }))))
+    }
+
+    fn address(&self) -> Option<String> {
+        match self {
+            Self::Listener(listener) => listener.local_addr().ok(),
+            Self::Connection(stream) => stream.borrow().writer.peer_addr().ok(),
+            Self::Closed => None,
+        }
+        .map(|addr| addr.to_string())
+    }
+}
+
+impl Value for SocketValue {
+    fn get_type(&self) -> ValueType {
+        ValueType::Socket
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Display for SocketValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.address() {
+            Some(address) => write!(f, "<socket {}>", address),
+            None => write!(f, "<socket closed>"),
+        }
+    }
+}
+
+impl Debug for SocketValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+struct TcpListen {}
+
+impl TcpListen {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for TcpListen {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let address = address_arg(args, "tcp-listen")?;
+        let listener = TcpListener::bind(address).map_err(io_error)?;
+
+        Ok(new_valueref(SocketValue::Listener(listener)))
+    }
+}
+
+struct TcpAccept {}
+
+impl TcpAccept {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for TcpAccept {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("tcp-accept function expects exactly one argument");
+        }
+
+        let socket = borrow_value(&args[0]);
+        let stream = match downcast_value::<SocketValue>(&socket) {
+            Some(SocketValue::Listener(listener)) => listener.accept().map_err(io_error)?.0,
+            _ => return error("tcp-accept function expects a listening socket"),
+        };
+
+        Ok(new_valueref(SocketValue::new_connection(stream)?))
+    }
+}
+
+struct TcpConnect {}
+
+impl TcpConnect {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for TcpConnect {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let address = address_arg(args, "tcp-connect")?;
+        let stream = TcpStream::connect(address).map_err(io_error)?;
+
+        Ok(new_valueref(SocketValue::new_connection(stream)?))
+    }
+}
+
+struct IsSocket {}
+
+impl IsSocket {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsSocket {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("socket? function expects exactly one argument");
+        }
+
+        Ok(new_valueref(BoolValue {
+            value: args[0].borrow().get_type() == ValueType::Socket,
+        }))
+    }
+}
+
+struct SocketAddress {}
+
+impl SocketAddress {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for SocketAddress {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("socket-address function expects exactly one argument");
+        }
+
+        let socket = borrow_value(&args[0]);
+        let address = match downcast_value::<SocketValue>(&socket) {
+            Some(socket) => socket.address(),
+            None => return error("socket-address function expects a socket"),
+        };
+
+        match address {
+            Some(value) => Ok(new_valueref(StrValue {
+                value: value.into(),
+            })),
+            None => Ok(new_valueref(NilValue {})),
+        }
+    }
+}
+
+struct SocketReadLine {}
+
+impl SocketReadLine {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for SocketReadLine {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("socket-read-line function expects exactly one argument");
+        }
+
+        let stream = stream_arg(&args[0], "socket-read-line")?;
+        let mut line = String::new();
+        let read = stream.borrow_mut().reader.read_line(&mut line);
+        if read.map_err(io_error)? == 0 {
+            return Ok(new_valueref(NilValue {}));
+        }
+
+        Ok(new_valueref(StrValue {
+            value: line.trim_end_matches(['\n', '\r']).into(),
+        }))
+    }
+}
+
+struct SocketWrite {}
+
+impl SocketWrite {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for SocketWrite {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() < 2 {
+            return error("socket-write function expects a socket and at least one value");
+        }
+
+        let text: String = args[1..]
+            .iter()
+            .map(|arg| arg.borrow().display_string())
+            .collect();
+
+        let stream = stream_arg(&args[0], "socket-write")?;
+        let writer = &mut stream.borrow_mut().writer;
+        writer.write_all(text.as_bytes()).map_err(io_error)?;
+        writer.flush().map_err(io_error)?;
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct SocketClose {}
+
+impl SocketClose {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for SocketClose {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("socket-close function expects exactly one argument");
+        }
+
+        let mut socket = borrow_mut_value(&args[0]);
+        let socket = match socket.as_any_mut().downcast_mut::<SocketValue>() {
+            Some(socket) => socket,
+            None => return error("socket-close function expects a socket"),
+        };
+
+        if let SocketValue::Connection(stream) = socket {
+            let _ = stream.borrow().writer.shutdown(Shutdown::Both);
+        }
+        *socket = SocketValue::Closed;
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct WithInputFromSocket {}
+
+impl WithInputFromSocket {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+// Calls the function with the socket as input, so read-line and read-lines
+// read from the connection
+impl Callable for WithInputFromSocket {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("with-input-from-socket function expects exactly two arguments");
+        }
+
+        let input: InputRef = stream_arg(&args[0], "with-input-from-socket")?;
+        with_redirected_input(&input, || call_value(&args[1], &[]))
+    }
+}
+
+struct WithOutputToSocket {}
+
+impl WithOutputToSocket {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+// Calls the function with the socket as output, so display, print and the
+// other output functions write to the connection
+impl Callable for WithOutputToSocket {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("with-output-to-socket function expects exactly two arguments");
+        }
+
+        let stream = stream_arg(&args[0], "with-output-to-socket")?;
+        let output: OutputRef = stream.clone();
+        let result = with_redirected_output(&output, || call_value(&args[1], &[]));
+
+        let write_error = stream.borrow_mut().write_error.take();
+        match write_error {
+            Some(err) => Err(io_error(err)),
+            None => result,
+        }
+    }
+}
+
+fn stream_arg(
+    value: &ValueRef,
+    function_name: &str,
+) -> Result<Rc<RefCell<SocketStream>>, InterpreterError> {
+    match downcast_value::<SocketValue>(&borrow_value(value)) {
+        Some(SocketValue::Connection(stream)) => Ok(stream.clone()),
+        _ => Err(InterpreterError::new(&format!(
+            "{} function expects a connected socket",
+            function_name
+        ))),
+    }
+}
+
+fn address_arg(args: &[ValueRef], function_name: &str) -> Result<String, InterpreterError> {
+    match args.len() {
+        1 => {
+            if let Some(port) = downcast_value::<IntValue>(&args[0].borrow()) {
+                return Ok(format!("127.0.0.1:{}", port.value));
+            }
+            str_arg(&args[0], function_name)
+        }
+        2 => {
+            let host = str_arg(&args[0], function_name)?;
+            match downcast_value::<IntValue>(&args[1].borrow()) {
+                Some(port) => Ok(format!("{}:{}", host, port.value)),
+                None => Err(InterpreterError::new(&format!(
+                    "{} function expects an integer port",
+                    function_name
+                ))),
+            }
+        }
+        _ => Err(InterpreterError::new(&format!(
+            "{} function expects an address or a host and a port",
+            function_name
+        ))),
+    }
+}
+
+fn io_error(err: std::io::Error) -> InterpreterError {
+    InterpreterError::io_error(&format!("Socket error: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpStream,
+        thread,
+    };
+
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_echo_server() {
+        let mut interpreter = Interpreter::with_prelude();
+        let address = interpreter
+            .eval(
+                r#"
+                (def server (tcp-listen 0))
+                (socket-address server)
+                "#,
+            )
+            .unwrap()
+            .borrow()
+            .display_string();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"hello\nworld\n").unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut lines = vec![];
+            for line in BufReader::new(stream).lines() {
+                lines.push(line.unwrap());
+            }
+            lines
+        });
+
+        let code = r#"
+        (def conn (tcp-accept server))
+        (def (echo n)
+            (let ([line (socket-read-line conn)])
+                (if (nil? line)
+                    n
+                    (block
+                        (socket-write conn "echo: " line "\n")
+                        (echo (+ n 1))))))
+        (def n (echo 0))
+        (socket-close conn)
+        n
+        "#;
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "2");
+        assert_eq!(client.join().unwrap(), vec!["echo: hello", "echo: world"]);
+    }
+
+    #[test]
+    fn test_socket_as_port() {
+        let mut interpreter = Interpreter::with_prelude();
+        let address = interpreter
+            .eval(
+                r#"
+                (def server (tcp-listen 0))
+                (socket-address server)
+                "#,
+            )
+            .unwrap()
+            .borrow()
+            .display_string();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"hello\nworld\n").unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut lines = vec![];
+            for line in BufReader::new(stream).lines() {
+                lines.push(line.unwrap());
+            }
+            lines
+        });
+
+        let code = r#"
+        (def conn (tcp-accept server))
+        (def (echo n)
+            (let ([line (read-line)])
+                (if (eof-object? line)
+                    n
+                    (block
+                        (displayln (string-concat "echo: " line))
+                        (echo (+ n 1))))))
+        (def n (with-input-from-socket conn
+            (λ () (with-output-to-socket conn (λ () (echo 0))))))
+        (socket-close conn)
+        n
+        "#;
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "2");
+        assert_eq!(client.join().unwrap(), vec!["echo: hello", "echo: world"]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::rc::Rc;
+
+use crate::interpreter::{
+    environment::EnvironmentBuilder,
+    module_mgmt::extension::{new_extension, ExtensionRef},
+    values::*,
+};
+
+#[cfg(feature = "exec")]
+mod process;
+
+pub fn create_os_extension(allow_exec: bool) -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+
+    env.borrow_mut()
+        .set_callable("getenv", &Rc::new(GetEnv::new()));
+    env.borrow_mut()
+        .set_callable("setenv!", &Rc::new(SetEnvBang::new()));
+    env.borrow_mut()
+        .set_callable("command-line", &Rc::new(CommandLine::new()));
+
+    #[cfg(feature = "exec")]
+    if allow_exec {
+        process::register_process_functions(&env);
+    }
+    #[cfg(not(feature = "exec"))]
+    let _ = allow_exec;
+
+    let values = env.borrow().get_exported_values();
+
+    new_extension("os", values)
+}
+
+struct GetEnv {}
+
+impl GetEnv {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for GetEnv {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("getenv function expects exactly one argument");
+        }
+
+        let name = str_arg(&args[0], "getenv")?;
+
+        match std::env::var(name) {
+            Ok(value) => Ok(new_valueref(StrValue {
+                value: value.into(),
+            })),
+            Err(_) => Ok(new_valueref(NilValue {})),
+        }
+    }
+}
+
+struct SetEnvBang {}
+
+impl SetEnvBang {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for SetEnvBang {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("setenv! function expects exactly two arguments");
+        }
+
+        let name = str_arg(&args[0], "setenv!")?;
+        if name.is_empty() || name.contains('=') {
+            return error("setenv! function expects a valid variable name");
+        }
+
+        if args[1].borrow().get_type() == ValueType::Nil {
+            std::env::remove_var(name);
+        } else {
+            let value = str_arg(&args[1], "setenv!")?;
+            std::env::set_var(name, value);
+        }
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct CommandLine {}
+
+impl CommandLine {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for CommandLine {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("command-line function expects no arguments");
+        }
+
+        let elements = std::env::args()
+            .map(|value| {
+                new_valueref(StrValue {
+                    value: value.into(),
+                })
+            })
+            .collect();
+
+        Ok(new_valueref(VectorValue { elements }))
+    }
+}
+
+fn str_arg(arg: &ValueRef, function_name: &str) -> Result<String, InterpreterError> {
+    match downcast_value::<StrValue>(&arg.borrow()) {
+        Some(str_value) => Ok(str_value.value.to_string()),
+        None => Err(InterpreterError::new(&format!(
+            "{} function expects string arguments",
+            function_name
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_environment_variables() {
+        let code = r#"
+        (setenv! "BOLI_TEST_OS_EXT" "42")
+        (def value (getenv "BOLI_TEST_OS_EXT"))
+        (setenv! "BOLI_TEST_OS_EXT" nil)
+        (vector value (getenv "BOLI_TEST_OS_EXT"))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "(vector \"42\" nil)");
+    }
+
+    #[test]
+    fn test_command_line() {
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval("(> (count (command-line)) 0)").unwrap();
+
+        assert_eq!(result.borrow().to_string(), "#true");
+    }
+}
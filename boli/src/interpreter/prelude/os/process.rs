@@ -0,0 +1,202 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, ChildStdout, Command, Stdio},
+    rc::Rc,
+};
+
+use crate::interpreter::{environment::EnvironmentRef, stream::StreamValue, values::*};
+
+use super::str_arg;
+
+const RESULT_FIELDS: [&str; 3] = ["exit-code", "stdout", "stderr"];
+
+pub fn register_process_functions(env: &EnvironmentRef) {
+    let fields: Vec<String> = RESULT_FIELDS.iter().map(|f| f.to_string()).collect();
+    let result_type = new_valueref(StructTypeValue::new("process-result", &fields));
+
+    env.borrow_mut()
+        .set("process-result".to_string(), result_type.clone());
+    env.borrow_mut()
+        .set_callable("process-result?", &Rc::new(IsStructType::new(&result_type)));
+    for field in RESULT_FIELDS {
+        env.borrow_mut().set_callable(
+            &format!("process-result-{}", field),
+            &Rc::new(GetStructField::new(field)),
+        );
+    }
+
+    env.borrow_mut()
+        .set_callable("system", &Rc::new(System::new(&result_type)));
+    env.borrow_mut()
+        .set_callable("process-run", &Rc::new(ProcessRun::new(&result_type)));
+    env.borrow_mut()
+        .set_callable("process-lines", &Rc::new(ProcessLines::new()));
+}
+
+struct System {
+    result_type: ValueRef,
+}
+
+impl System {
+    fn new(result_type: &ValueRef) -> Self {
+        Self {
+            result_type: result_type.clone(),
+        }
+    }
+}
+
+impl Callable for System {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("system function expects exactly one argument");
+        }
+
+        let command_line = str_arg(&args[0], "system")?;
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.args(["/C", &command_line]);
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.args(["-c", &command_line]);
+            command
+        };
+
+        run_command(&mut command, &self.result_type)
+    }
+}
+
+struct ProcessRun {
+    result_type: ValueRef,
+}
+
+impl ProcessRun {
+    fn new(result_type: &ValueRef) -> Self {
+        Self {
+            result_type: result_type.clone(),
+        }
+    }
+}
+
+impl Callable for ProcessRun {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let mut command = new_command(args, "process-run")?;
+        run_command(&mut command, &self.result_type)
+    }
+}
+
+struct ProcessLines {}
+
+impl ProcessLines {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for ProcessLines {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let mut command = new_command(args, "process-lines")?;
+        let mut child = command
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| InterpreterError::new(&format!("process-lines: {}", e)))?;
+        let stdout = child.stdout.take().unwrap();
+
+        let mut reader = LineReader {
+            reader: BufReader::new(stdout),
+            child,
+        };
+
+        Ok(new_valueref(StreamValue::new_buffered(Box::new(
+            move || Ok(reader.read_line()),
+        ))))
+    }
+}
+
+struct LineReader {
+    reader: BufReader<ChildStdout>,
+    child: Child,
+}
+
+impl LineReader {
+    fn read_line(&mut self) -> Option<ValueRef> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(n) if n > 0 => {
+                let value = line.trim_end_matches(['\n', '\r']).to_string();
+                Some(new_valueref(StrValue {
+                    value: value.into(),
+                }))
+            }
+            _ => {
+                let _ = self.child.wait();
+                None
+            }
+        }
+    }
+}
+
+fn new_command(args: &[ValueRef], function_name: &str) -> Result<Command, InterpreterError> {
+    if args.is_empty() {
+        return Err(InterpreterError::new(&format!(
+            "{} function expects at least one argument",
+            function_name
+        )));
+    }
+
+    let mut command = Command::new(str_arg(&args[0], function_name)?);
+    for arg in &args[1..] {
+        command.arg(str_arg(arg, function_name)?);
+    }
+
+    Ok(command)
+}
+
+fn run_command(command: &mut Command, result_type: &ValueRef) -> EvalResult {
+    let output = command
+        .output()
+        .map_err(|e| InterpreterError::new(&format!("Cannot run process: {}", e)))?;
+
+    let exit_code = new_valueref(IntValue {
+        value: output.status.code().unwrap_or(-1) as i64,
+    });
+    let stdout = new_valueref(StrValue {
+        value: String::from_utf8_lossy(&output.stdout).as_ref().into(),
+    });
+    let stderr = new_valueref(StrValue {
+        value: String::from_utf8_lossy(&output.stderr).as_ref().into(),
+    });
+
+    CreateStructValue::new(result_type).call(&vec![exit_code, stdout, stderr])
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_system() {
+        let code = r#"
+        (def result (system "echo hello; exit 3"))
+        (vector (process-result-exit-code result) (process-result-stdout result))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "(vector 3 \"hello\n\")");
+    }
+
+    #[test]
+    fn test_process_lines() {
+        let code = r#"
+        (def lines (process-lines "printf" "a\nb\nc\n"))
+        (stream->vector (take 5 lines))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "(vector \"a\" \"b\" \"c\")");
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::interpreter::{
+    environment::EnvironmentBuilder,
+    module_mgmt::extension::{new_extension, ExtensionRef},
+    values::*,
+};
+
+pub fn create_random_extension() -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+    let generator = Rc::new(RefCell::new(Generator::from_time()));
+
+    env.borrow_mut()
+        .set_callable("random", &Rc::new(Random::new(&generator)));
+    env.borrow_mut()
+        .set_callable("random-int", &Rc::new(RandomInt::new(&generator)));
+    env.borrow_mut()
+        .set_callable("random-range", &Rc::new(RandomRange::new(&generator)));
+    env.borrow_mut()
+        .set_callable("random-seed!", &Rc::new(RandomSeed::new(&generator)));
+    env.borrow_mut()
+        .set_callable("shuffle", &Rc::new(Shuffle::new(&generator)));
+
+    let values = env.borrow().get_exported_values();
+
+    new_extension("random", values)
+}
+
+type GeneratorRef = Rc<RefCell<Generator>>;
+
+struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos)
+    }
+
+    // splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_real(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn next_below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+struct Random {
+    generator: GeneratorRef,
+}
+
+impl Random {
+    fn new(generator: &GeneratorRef) -> Self {
+        Self {
+            generator: generator.clone(),
+        }
+    }
+}
+
+impl Callable for Random {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("random function expects no arguments");
+        }
+
+        Ok(new_valueref(RealValue {
+            value: self.generator.borrow_mut().next_real(),
+        }))
+    }
+}
+
+struct RandomInt {
+    generator: GeneratorRef,
+}
+
+impl RandomInt {
+    fn new(generator: &GeneratorRef) -> Self {
+        Self {
+            generator: generator.clone(),
+        }
+    }
+}
+
+impl Callable for RandomInt {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("random-int function expects exactly one argument");
+        }
+
+        let n = int_arg(&args[0], "random-int")?;
+        if n <= 0 {
+            return error("random-int function expects a positive integer");
+        }
+
+        Ok(new_valueref(IntValue {
+            value: self.generator.borrow_mut().next_below(n as u64) as i64,
+        }))
+    }
+}
+
+struct RandomRange {
+    generator: GeneratorRef,
+}
+
+impl RandomRange {
+    fn new(generator: &GeneratorRef) -> Self {
+        Self {
+            generator: generator.clone(),
+        }
+    }
+}
+
+impl Callable for RandomRange {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("random-range function expects exactly two arguments");
+        }
+
+        let types = (args[0].borrow().get_type(), args[1].borrow().get_type());
+
+        if types == (ValueType::Int, ValueType::Int) {
+            let start = int_arg(&args[0], "random-range")?;
+            let end = int_arg(&args[1], "random-range")?;
+            if start >= end {
+                return error("random-range function expects start to be less than end");
+            }
+            let offset = self.generator.borrow_mut().next_below(end.abs_diff(start));
+            return Ok(new_valueref(IntValue {
+                value: start.wrapping_add(offset as i64),
+            }));
+        }
+
+        let start = real_arg(&args[0])?;
+        let end = real_arg(&args[1])?;
+        if start >= end {
+            return error("random-range function expects start to be less than end");
+        }
+
+        Ok(new_valueref(RealValue {
+            value: start + (end - start) * self.generator.borrow_mut().next_real(),
+        }))
+    }
+}
+
+struct RandomSeed {
+    generator: GeneratorRef,
+}
+
+impl RandomSeed {
+    fn new(generator: &GeneratorRef) -> Self {
+        Self {
+            generator: generator.clone(),
+        }
+    }
+}
+
+impl Callable for RandomSeed {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("random-seed! function expects exactly one argument");
+        }
+
+        let seed = int_arg(&args[0], "random-seed!")?;
+        *self.generator.borrow_mut() = Generator::new(seed as u64);
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct Shuffle {
+    generator: GeneratorRef,
+}
+
+impl Shuffle {
+    fn new(generator: &GeneratorRef) -> Self {
+        Self {
+            generator: generator.clone(),
+        }
+    }
+}
+
+impl Callable for Shuffle {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("shuffle function expects exactly one argument");
+        }
+
+        let mut elements = match downcast_value::<VectorValue>(&args[0].borrow()) {
+            Some(vector) => vector.elements.clone(),
+            None => return error("shuffle function expects a vector"),
+        };
+
+        let mut generator = self.generator.borrow_mut();
+        for i in (1..elements.len()).rev() {
+            let j = generator.next_below(i as u64 + 1) as usize;
+            elements.swap(i, j);
+        }
+
+        Ok(new_valueref(VectorValue { elements }))
+    }
+}
+
+fn int_arg(arg: &ValueRef, function_name: &str) -> Result<i64, InterpreterError> {
+    match downcast_value::<IntValue>(&arg.borrow()) {
+        Some(int_value) => Ok(int_value.value),
+        None => Err(InterpreterError::new(&format!(
+            "{} function expects an integer argument",
+            function_name
+        ))),
+    }
+}
+
+fn real_arg(arg: &ValueRef) -> Result<f64, InterpreterError> {
+    let arg = arg.borrow();
+    match arg.get_type() {
+        ValueType::Int => Ok(downcast_value::<IntValue>(&arg).unwrap().value as f64),
+        ValueType::Real => Ok(downcast_value::<RealValue>(&arg).unwrap().value),
+        ValueType::Rational => {
+            let rational = downcast_value::<RationalValue>(&arg).unwrap();
+            Ok(rational.numerator as f64 / rational.denominator as f64)
+        }
+        _ => Err(InterpreterError::new(
+            "random-range function expects numeric arguments",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_seeded_random_is_reproducible() {
+        let code = r#"
+        (random-seed! 42)
+        (def a (vector (random-int 100) (random-range 10 20) (shuffle (vector 1 2 3 4 5))))
+        (random-seed! 42)
+        (def b (vector (random-int 100) (random-range 10 20) (shuffle (vector 1 2 3 4 5))))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        interpreter.eval(code).unwrap();
+        let a = interpreter.eval("a").unwrap();
+        let b = interpreter.eval("b").unwrap();
+
+        assert_eq!(a.borrow().to_string(), b.borrow().to_string());
+    }
+
+    #[test]
+    fn test_random_bounds() {
+        let code = r#"
+        (def r (random))
+        (def n (random-range -3 3))
+        (and (>= r 0) (< r 1) (>= n -3) (< n 3) (< (random-int 5) 5))
+        "#;
+
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "#true");
+    }
+}
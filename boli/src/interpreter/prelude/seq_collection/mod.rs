@@ -4,14 +4,16 @@ use std::rc::Rc;
 use std::vec;
 
 use crate::interpreter::environment::{Environment, EnvironmentBuilder};
+use crate::interpreter::misc_functions::is_truthy;
 use crate::interpreter::module_mgmt::extension::{new_extension, new_extension_dir, ExtensionRef};
 use crate::interpreter::module_mgmt::ExtensionModule;
 use crate::interpreter::prelude::load_module_code;
 use crate::interpreter::stream::StreamValue;
+use crate::interpreter::struct_functions::get_key;
 use crate::interpreter::{
-    borrow_value, downcast_value, error, new_valueref, BoolValue, BuiltInFunctionValue, Callable,
-    EvalResult, IntValue, InterpreterError, LambdaValue, NilValue, PairValue, ValueRef, ValueType,
-    VectorValue,
+    borrow_mut_value, borrow_value, call_value, downcast_value, error, new_valueref, BoolValue,
+    BuiltInFunctionValue, Callable, EvalResult, IntValue, InterpreterError, LambdaValue, NilValue,
+    PairValue, StructEntry, StructValue, ValueRef, ValueType, VectorValue,
 };
 
 use super::list::{Car, Cdr};
@@ -35,6 +37,20 @@ pub fn create_seq_collection_extension(
     env.borrow_mut()
         .set_callable("filter", &Rc::new(Filter::new(list_ext)));
     env.borrow_mut().set_callable("map", &Rc::new(Map::new()));
+    env.borrow_mut()
+        .set_callable("flatten", &Rc::new(Flatten::new(false)));
+    env.borrow_mut()
+        .set_callable("flatten-deep", &Rc::new(Flatten::new(true)));
+    env.borrow_mut()
+        .set_callable("flat-map", &Rc::new(FlatMap::new()));
+    env.borrow_mut()
+        .set_callable("mapcat", &Rc::new(FlatMap::new()));
+    env.borrow_mut()
+        .set_callable("group-by", &Rc::new(GroupBy::new()));
+    env.borrow_mut()
+        .set_callable("partition", &Rc::new(Partition::new()));
+    env.borrow_mut()
+        .set_callable("frequencies", &Rc::new(Frequencies::new()));
 
     let deps = new_extension_dir("deps");
     for dep in vec![vector_ext, list_ext, string_ext, stream_ext] {
@@ -73,7 +89,7 @@ impl Callable for Head {
                 let stream = args[0].clone();
                 let stream = borrow_value(&stream);
                 let mut stream = downcast_value::<StreamValue>(&stream).unwrap().clone();
-                match stream.next_value() {
+                match stream.next_value()? {
                     Some(head) => Ok(head),
                     None => Ok(new_valueref(NilValue {})),
                 }
@@ -112,7 +128,7 @@ impl Callable for Tail {
                 let sequence = args[0].clone();
                 let sequence = borrow_value(&sequence);
                 let mut sequence = downcast_value::<StreamValue>(&sequence).unwrap().clone();
-                sequence.next_value();
+                sequence.next_value()?;
                 Ok(new_valueref(sequence))
             }
             ValueType::Str => {
@@ -393,7 +409,7 @@ impl Callable for Map {
                         let mut stream = downcast_value::<StreamValue>(&col.borrow())
                             .unwrap()
                             .clone();
-                        match stream.next_value() {
+                        match stream.next_value()? {
                             Some(head) => {
                                 let tail = StreamValue::new_dropped(
                                     new_valueref(IntValue { value: 1 }),
@@ -432,6 +448,256 @@ impl Callable for Map {
     }
 }
 
+// Streams are flattened lazily, vectors and lists eagerly into a collection
+// of the given result type
+fn flatten(collection: &ValueRef, deep: bool, result_type: ValueType) -> EvalResult {
+    let stream = match collection.borrow().get_type() {
+        ValueType::Stream => collection.clone(),
+        ValueType::Vector => new_valueref(StreamValue::new_list(collection.clone())?),
+        ValueType::Pair | ValueType::Nil => {
+            let elements = match downcast_value::<PairValue>(&borrow_value(collection)) {
+                Some(pair) => pair.get_elements(),
+                None => Vec::new(),
+            };
+            new_valueref(StreamValue::new_list(new_valueref(VectorValue {
+                elements,
+            }))?)
+        }
+        _ => return error("flatten function expects a vector, list or stream"),
+    };
+
+    let mut flattened = StreamValue::new_flattened(stream, deep)?;
+    if result_type == ValueType::Stream {
+        return Ok(new_valueref(flattened));
+    }
+
+    let mut elements = Vec::new();
+    while let Some(value) = flattened.next_value()? {
+        elements.push(value);
+    }
+
+    if result_type == ValueType::Vector {
+        return Ok(new_valueref(VectorValue { elements }));
+    }
+
+    Ok(list_of(elements))
+}
+
+struct Flatten {
+    deep: bool,
+}
+
+impl Flatten {
+    fn new(deep: bool) -> Self {
+        Self { deep }
+    }
+}
+
+impl Callable for Flatten {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("flatten function expects exactly one argument");
+        }
+
+        let result_type = args[0].borrow().get_type();
+        flatten(&args[0], self.deep, result_type)
+    }
+}
+
+struct FlatMap {}
+
+impl FlatMap {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for FlatMap {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() < 2 {
+            return error("flat-map function expects at least two arguments");
+        }
+
+        let mapped = Map::new().call(args)?;
+        let result_type = match args[1].borrow().get_type() {
+            ValueType::Pair | ValueType::Nil => ValueType::Pair,
+            _ => mapped.borrow().get_type(),
+        };
+
+        flatten(&mapped, false, result_type)
+    }
+}
+
+fn collection_elements(collection: &ValueRef) -> Result<Vec<ValueRef>, InterpreterError> {
+    let collection = borrow_value(collection);
+    match collection.get_type() {
+        ValueType::Vector => Ok(downcast_value::<VectorValue>(&collection)
+            .unwrap()
+            .elements
+            .clone()),
+        ValueType::Pair => Ok(downcast_value::<PairValue>(&collection)
+            .unwrap()
+            .get_elements()),
+        ValueType::Nil => Ok(Vec::new()),
+        ValueType::Stream => {
+            let mut stream = downcast_value::<StreamValue>(&collection).unwrap().clone();
+            let mut elements = Vec::new();
+            while let Some(value) = stream.next_value()? {
+                elements.push(value);
+            }
+            Ok(elements)
+        }
+        _ => Err(InterpreterError::new(
+            "expected a vector, list or stream as collection",
+        )),
+    }
+}
+
+fn list_of(elements: Vec<ValueRef>) -> ValueRef {
+    let mut list = new_valueref(NilValue {});
+    for element in elements.iter().rev() {
+        list = new_valueref(PairValue::new(element, &list));
+    }
+    list
+}
+
+struct GroupBy {}
+
+impl GroupBy {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for GroupBy {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("group-by function expects exactly two arguments");
+        }
+
+        let mut groups = StructValue::new_hash_table();
+        for element in collection_elements(&args[1])? {
+            let key = call_value(&args[0], std::slice::from_ref(&element))?;
+            let entry = groups
+                .values
+                .entry(get_key(&key))
+                .or_insert_with(|| StructEntry {
+                    key,
+                    value: new_valueref(VectorValue { elements: vec![] }),
+                });
+            borrow_mut_value(&entry.value)
+                .as_any_mut()
+                .downcast_mut::<VectorValue>()
+                .unwrap()
+                .elements
+                .push(element);
+        }
+
+        Ok(new_valueref(groups))
+    }
+}
+
+struct Complement {
+    predicate: ValueRef,
+}
+
+impl Callable for Complement {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let result = call_value(&self.predicate, args)?;
+        Ok(new_valueref(BoolValue {
+            value: !is_truthy(&result),
+        }))
+    }
+}
+
+struct Partition {}
+
+impl Partition {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Partition {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("partition function expects exactly two arguments");
+        }
+
+        let predicate = &args[0];
+        let collection_type = args[1].borrow().get_type();
+
+        // Streams are split into two lazy streams
+        if collection_type == ValueType::Stream {
+            let complement: Rc<dyn Callable> = Rc::new(Complement {
+                predicate: predicate.clone(),
+            });
+            let complement = new_valueref(BuiltInFunctionValue::new("complement", &complement));
+            let matching = StreamValue::new_filtered(predicate.clone(), args[1].clone())?;
+            let rest = StreamValue::new_filtered(complement, args[1].clone())?;
+            return Ok(new_valueref(PairValue::new(
+                &new_valueref(matching),
+                &new_valueref(rest),
+            )));
+        }
+
+        let mut matching = Vec::new();
+        let mut rest = Vec::new();
+        for element in collection_elements(&args[1])? {
+            let result = call_value(predicate, std::slice::from_ref(&element))?;
+            if is_truthy(&result) {
+                matching.push(element);
+            } else {
+                rest.push(element);
+            }
+        }
+
+        let (matching, rest) = if collection_type == ValueType::Vector {
+            (
+                new_valueref(VectorValue { elements: matching }),
+                new_valueref(VectorValue { elements: rest }),
+            )
+        } else {
+            (list_of(matching), list_of(rest))
+        };
+
+        Ok(new_valueref(PairValue::new(&matching, &rest)))
+    }
+}
+
+struct Frequencies {}
+
+impl Frequencies {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Frequencies {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("frequencies function expects exactly one argument");
+        }
+
+        let mut counts = StructValue::new_hash_table();
+        for element in collection_elements(&args[0])? {
+            let entry = counts
+                .values
+                .entry(get_key(&element))
+                .or_insert_with(|| StructEntry {
+                    key: element,
+                    value: new_valueref(IntValue { value: 0 }),
+                });
+            let count = downcast_value::<IntValue>(&borrow_value(&entry.value))
+                .unwrap()
+                .value;
+            entry.value = new_valueref(IntValue { value: count + 1 });
+        }
+
+        Ok(new_valueref(counts))
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -521,8 +787,8 @@ mod tests {
     fn test_take_seq() {
         let code = r#"
         (def naturals (iterator 0 (lambda (x) (+ x 1))))
-        (take 3 (drop 1 naturals))
-        (take 3 naturals)
+        (stream->vector (take 3 (drop 1 naturals)))
+        (stream->vector (take 3 naturals))
         "#;
 
         let mut interpreter = interpreter::Interpreter::with_prelude();
@@ -539,7 +805,7 @@ mod tests {
                   (b (cdr p))]
                 (b . (+ a b))))
         (def fib (map head (iterator (0 . 1) next-pair)))
-        (take-while (λ (n) (< n 100)) fib)
+        (stream->vector (take-while (λ (n) (< n 100)) fib))
         "#;
 
         let mut interpreter = interpreter::Interpreter::with_prelude();
@@ -551,6 +817,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_range() {
+        let mut interpreter = interpreter::Interpreter::with_prelude();
+
+        let result = interpreter
+            .eval("(stream->vector (take 5 (range 1000000000)))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "(vector 0 1 2 3 4)");
+
+        let result = interpreter
+            .eval("(stream->vector (take 10 (range 2 5)))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "(vector 2 3 4)");
+
+        let result = interpreter
+            .eval("(stream->vector (take 10 (range 10 0 -3)))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "(vector 10 7 4 1)");
+
+        let result = interpreter
+            .eval("(stream->vector (take 10 (range 5 2)))")
+            .unwrap();
+        assert_eq!(result.borrow().to_string(), "(vector )");
+
+        assert!(interpreter.eval("(range 1 5 0)").is_err());
+    }
+
     #[test]
     fn test_vector_ref() {
         let code = r#"
@@ -590,4 +883,115 @@ mod tests {
         assert_eq!(result.borrow().get_type(), ValueType::Pair);
         assert_eq!(result.borrow().to_string(), "(list 1 2 3 4 5 6)");
     }
+
+    #[test]
+    fn test_flatten() {
+        let code = r#"
+        (vector
+            (flatten (vector 1 (vector 2 (vector 3)) (list 4 5) (list) "ab"))
+            (flatten-deep (vector 1 (vector 2 (vector 3 (list 4)))))
+            (flatten (list (vector 1 2) 3 (list 4)))
+            (stream->vector (take 5 (flatten (map (λ (n) (vector n n)) (iterator 1 (λ (n) (+ n 1))))))))
+        "#;
+
+        let mut interpreter = interpreter::Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector (vector 1 2 (vector 3) 4 5 \"ab\") (vector 1 2 3 4) \
+             (list 1 2 3 4) (vector 1 1 2 2 3))"
+        );
+    }
+
+    #[test]
+    fn test_flat_map() {
+        let code = r#"
+        (def naturals (iterator 0 (λ (n) (+ n 1))))
+        (vector
+            (flat-map (λ (n) (vector n (* n 10))) (vector 1 2))
+            (mapcat (λ (n) (list n n)) (list 1 2))
+            (stream? (flat-map (λ (n) (vector n)) naturals))
+            (stream->vector (take 4 (flat-map (λ (n) (repeat n n)) naturals))))
+        "#;
+
+        let mut interpreter = interpreter::Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector (vector 1 10 2 20) (list 1 1 2 2) #true (vector 1 2 2 3))"
+        );
+    }
+
+    #[test]
+    fn test_group_by() {
+        let code = r#"
+        (def groups (group-by (λ (n) (% n 3)) (list 1 2 3 4 5 6 7)))
+        (vector (hash-get groups 0) (hash-get groups 1) (hash-get groups 2))
+        "#;
+
+        let mut interpreter = interpreter::Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector (vector 3 6) (vector 1 4 7) (vector 2 5))"
+        );
+    }
+
+    #[test]
+    fn test_partition() {
+        let code = r#"
+        (def (even? n) (= (% n 2) 0))
+        (def streams (partition even? (iterator 0 (λ (n) (+ n 1)))))
+        (vector
+            (partition even? (vector 1 2 3 4 5))
+            (partition even? (list 1 2 3))
+            (stream->vector (take 3 (car streams)))
+            (stream->vector (take 3 (cdr streams))))
+        "#;
+
+        let mut interpreter = interpreter::Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector ((vector 2 4) . (vector 1 3 5)) (list (list 2) 1 3) \
+             (vector 0 2 4) (vector 1 3 5))"
+        );
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let code = r#"
+        (def counts (frequencies (vector 'a "b" 'a 1 'a 1)))
+        (vector (hash-get counts 'a) (hash-get counts "b") (hash-get counts 1))
+        "#;
+
+        let mut interpreter = interpreter::Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(result.borrow().to_string(), "(vector 3 1 2)");
+    }
+
+    #[test]
+    fn test_for_each_indexed() {
+        let code = r#"
+        (def indexed nil)
+        (for-each-indexed (vector 'a 'b 'c)
+            (λ (i x) (set! indexed (cons (i . x) indexed))))
+        (def sum 0)
+        (dotimes (i 4) (set! sum (+ sum i)))
+        (vector (reverse indexed) sum)
+        "#;
+
+        let mut interpreter = interpreter::Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+
+        assert_eq!(
+            result.borrow().to_string(),
+            "(vector (list (0 . 'a) (1 . 'b) (2 . 'c)) 6)"
+        );
+    }
 }
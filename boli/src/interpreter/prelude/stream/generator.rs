@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+
+use corosensei::{stack::DefaultStack, Coroutine, CoroutineResult, Yielder};
+
+use crate::interpreter::{stream::StreamValue, values::*};
+
+const GENERATOR_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+type GeneratorYielder = Yielder<(), ValueRef>;
+
+thread_local! {
+    // Yielders of the generators that are currently running, innermost last
+    static ACTIVE_YIELDERS: RefCell<Vec<*const GeneratorYielder>> = const { RefCell::new(Vec::new()) };
+}
+
+// Runs the body on its own stack and suspends it at every yield, so the
+// generator produces its values lazily as the stream is consumed
+pub fn new_generator(body: ValueRef) -> Result<StreamValue, InterpreterError> {
+    if !matches!(
+        body.borrow().get_type(),
+        ValueType::BuiltInFunction | ValueType::Lambda
+    ) {
+        return Err(InterpreterError::new(
+            "Generator requires a function as body.",
+        ));
+    }
+
+    let stack = DefaultStack::new(GENERATOR_STACK_SIZE)
+        .map_err(|e| InterpreterError::new(&format!("Cannot create generator: {}", e)))?;
+
+    let coroutine = Coroutine::with_stack(stack, move |yielder: &GeneratorYielder, ()| {
+        ACTIVE_YIELDERS.with(|active| active.borrow_mut().push(yielder));
+        let result = call_value(&body, &[]);
+        ACTIVE_YIELDERS.with(|active| active.borrow_mut().pop());
+        result
+    });
+    let mut coroutine = Some(coroutine);
+
+    // An error of the body ends the stream, the consumer gets it when it
+    // asks for the next value
+    Ok(StreamValue::new_buffered(Box::new(move || {
+        let Some(running) = coroutine.as_mut() else {
+            return Ok(None);
+        };
+        match running.resume(()) {
+            CoroutineResult::Yield(value) => Ok(Some(value)),
+            CoroutineResult::Return(result) => {
+                coroutine = None;
+                result.map(|_| None)
+            }
+        }
+    })))
+}
+
+pub fn yield_value(value: &ValueRef) -> Result<(), InterpreterError> {
+    let yielder = ACTIVE_YIELDERS
+        .with(|active| active.borrow_mut().pop())
+        .ok_or(InterpreterError::new(
+            "yield function can only be called inside a generator",
+        ))?;
+
+    // The yielder belongs to the innermost running generator, which stays
+    // alive until this call returns or its stack is unwound
+    unsafe { (*yielder).suspend(value.clone()) };
+
+    ACTIVE_YIELDERS.with(|active| active.borrow_mut().push(yielder));
+    Ok(())
+}
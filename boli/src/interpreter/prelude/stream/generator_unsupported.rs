@@ -0,0 +1,16 @@
+use crate::interpreter::{stream::StreamValue, values::*};
+
+// Generators run on a stack of their own, which cannot be created on
+// WebAssembly targets
+
+pub fn new_generator(_body: ValueRef) -> Result<StreamValue, InterpreterError> {
+    Err(unsupported())
+}
+
+pub fn yield_value(_value: &ValueRef) -> Result<(), InterpreterError> {
+    Err(unsupported())
+}
+
+fn unsupported() -> InterpreterError {
+    InterpreterError::new("generators are not supported on this platform")
+}
@@ -1,28 +1,55 @@
 use std::{collections::HashMap, rc::Rc};
 
 use crate::interpreter::{
+    borrow_value, downcast_value,
     environment::EnvironmentBuilder,
     error,
     module_mgmt::extension::{new_extension, ExtensionRef},
     new_valueref,
     stream::StreamValue,
-    BoolValue, Callable, EvalResult, ValueRef, ValueType,
+    BoolValue, Callable, EvalResult, NilValue, ValueRef, ValueType, VectorValue,
 };
 
 use super::load_module_code;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod generator;
+#[cfg(target_arch = "wasm32")]
+#[path = "generator_unsupported.rs"]
+mod generator;
+
 pub fn create_stream_extension() -> ExtensionRef {
     let core_env = EnvironmentBuilder::new().build();
     let env = EnvironmentBuilder::new().parent(&core_env).build();
 
     env.borrow_mut()
         .set_callable("stream?", &Rc::new(IsStream::new()));
+    env.borrow_mut()
+        .set_callable("stream-empty?", &Rc::new(IsStreamEmpty::new()));
     env.borrow_mut()
         .set_callable("vector->stream", &Rc::new(VectorToStream::new()));
+    env.borrow_mut()
+        .set_callable("stream->vector", &Rc::new(StreamToVector::new()));
     env.borrow_mut()
         .set_callable("iterator", &Rc::new(Iterator::new()));
     env.borrow_mut()
         .set_callable("stream-map", &Rc::new(StreamMap::new()));
+    env.borrow_mut()
+        .set_callable("stream-take", &Rc::new(StreamTake::new()));
+    env.borrow_mut()
+        .set_callable("stream-take-while", &Rc::new(StreamTakeWhile::new()));
+    env.borrow_mut()
+        .set_callable("interleave", &Rc::new(Interleave::new()));
+    env.borrow_mut()
+        .set_callable("cycle", &Rc::new(Cycle::new()));
+    env.borrow_mut()
+        .set_callable("repeat", &Rc::new(Repeat::new()));
+    env.borrow_mut()
+        .set_callable("make-stream-cons", &Rc::new(MakeStreamCons::new()));
+    env.borrow_mut()
+        .set_callable("make-generator", &Rc::new(MakeGenerator::new()));
+    env.borrow_mut()
+        .set_callable("yield", &Rc::new(Yield::new()));
 
     let values = load_module_code(&env, include_str!("stream.boli")).unwrap_or(HashMap::new());
 
@@ -49,6 +76,32 @@ impl Callable for IsStream {
     }
 }
 
+struct IsStreamEmpty {}
+
+impl IsStreamEmpty {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for IsStreamEmpty {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("stream-empty? function expects one argument");
+        }
+
+        let stream = borrow_value(&args[0]);
+        let mut stream = match downcast_value::<StreamValue>(&stream) {
+            Some(stream) => stream.clone(),
+            None => return error("stream-empty? function expects a stream as the argument"),
+        };
+
+        Ok(new_valueref(BoolValue {
+            value: stream.next_value()?.is_none(),
+        }))
+    }
+}
+
 struct VectorToStream {}
 
 impl VectorToStream {
@@ -71,6 +124,36 @@ impl Callable for VectorToStream {
     }
 }
 
+// Collects the remaining values of a finite stream
+struct StreamToVector {}
+
+impl StreamToVector {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for StreamToVector {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("stream->vector function expects one argument");
+        }
+
+        let stream = borrow_value(&args[0]);
+        let mut stream = match downcast_value::<StreamValue>(&stream) {
+            Some(stream) => stream.clone(),
+            None => return error("stream->vector function expects a stream as the argument"),
+        };
+
+        let mut elements = Vec::new();
+        while let Some(value) = stream.next_value()? {
+            elements.push(value);
+        }
+
+        Ok(new_valueref(VectorValue { elements }))
+    }
+}
+
 pub struct Iterator {}
 
 impl Iterator {
@@ -113,3 +196,301 @@ impl Callable for StreamMap {
         Ok(new_valueref(mapped))
     }
 }
+
+// Vectors are accepted wherever a stream is expected by the lazy combinators
+fn to_stream(value: &ValueRef) -> EvalResult {
+    match value.borrow().get_type() {
+        ValueType::Stream => Ok(value.clone()),
+        ValueType::Vector => Ok(new_valueref(StreamValue::new_list(value.clone())?)),
+        _ => error("expected a stream or a vector"),
+    }
+}
+
+struct StreamTake {}
+
+impl StreamTake {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for StreamTake {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("stream-take function expects two arguments");
+        }
+
+        let taken = StreamValue::new_taken(args[0].clone(), to_stream(&args[1])?)?;
+
+        Ok(new_valueref(taken))
+    }
+}
+
+struct StreamTakeWhile {}
+
+impl StreamTakeWhile {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for StreamTakeWhile {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("stream-take-while function expects two arguments");
+        }
+
+        let taken = StreamValue::new_taken_while(args[0].clone(), to_stream(&args[1])?)?;
+
+        Ok(new_valueref(taken))
+    }
+}
+
+struct Interleave {}
+
+impl Interleave {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Interleave {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() {
+            return error("interleave function expects at least one argument");
+        }
+
+        let streams = args.iter().map(to_stream).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(new_valueref(StreamValue::new_interleaved(streams)?))
+    }
+}
+
+struct Cycle {}
+
+impl Cycle {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Cycle {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("cycle function expects one argument");
+        }
+
+        Ok(new_valueref(StreamValue::new_cycled(to_stream(&args[0])?)?))
+    }
+}
+
+struct Repeat {}
+
+impl Repeat {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Repeat {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let repeated = match args.len() {
+            1 => StreamValue::new_repeated(args[0].clone(), None)?,
+            2 => StreamValue::new_repeated(args[1].clone(), Some(args[0].clone()))?,
+            _ => return error("repeat function expects one or two arguments"),
+        };
+
+        Ok(new_valueref(repeated))
+    }
+}
+
+struct MakeStreamCons {}
+
+impl MakeStreamCons {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for MakeStreamCons {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("make-stream-cons function expects two arguments");
+        }
+
+        let cons = StreamValue::new_cons(args[0].clone(), args[1].clone())?;
+
+        Ok(new_valueref(cons))
+    }
+}
+
+struct MakeGenerator {}
+
+impl MakeGenerator {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for MakeGenerator {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("make-generator function expects one argument");
+        }
+
+        Ok(new_valueref(generator::new_generator(args[0].clone())?))
+    }
+}
+
+struct Yield {}
+
+impl Yield {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Yield {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("yield function expects one argument");
+        }
+
+        generator::yield_value(&args[0])?;
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    fn eval(code: &str) -> String {
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+        let result = result.borrow().to_string();
+        result
+    }
+
+    #[test]
+    fn test_stream_cons() {
+        let code = r#"
+        (def (ints-from n) (stream-cons n (ints-from (+ n 1))))
+        (def s (ints-from 3))
+        (vector (stream->vector (take 3 s)) (head (tail (tail s))) (stream->vector (take 2 (stream-cons 1 nil))))
+        "#;
+        assert_eq!(eval(code), "(vector (vector 3 4 5) 5 (vector 1))");
+    }
+
+    #[test]
+    fn test_generator() {
+        let code = r#"
+        (def squares
+            (generator
+                (def (next i)
+                    (yield (* i i))
+                    (next (+ i 1)))
+                (next 1)))
+        (def pair (generator (yield 1) (yield 2)))
+        (vector (stream->vector (take 4 squares)) (stream->vector (take 2 squares)) (stream->vector (take 5 pair)) (head (tail pair)))
+        "#;
+        assert_eq!(
+            eval(code),
+            "(vector (vector 1 4 9 16) (vector 1 4) (vector 1 2) 2)"
+        );
+    }
+
+    #[test]
+    fn test_lazy_combinators() {
+        let code = r#"
+        (def naturals (iterator 0 (λ (n) (+ n 1))))
+        (def small (stream-take-while (λ (n) (< n 4)) naturals))
+        (vector
+            (stream->vector (take 10 (stream-take 3 naturals)))
+            (stream->vector (take 10 small))
+            (stream->vector (take 10 small))
+            (stream->vector (take 7 (interleave naturals (repeat 'x))))
+            (stream->vector (take 5 (cycle (vector 1 2))))
+            (stream->vector (take 10 (repeat 3 'y)))
+            (stream-empty? (cycle (vector))))
+        "#;
+        assert_eq!(
+            eval(code),
+            "(vector (vector 0 1 2) (vector 0 1 2 3) (vector 0 1 2 3) \
+             (vector 0 'x 1 'x 2 'x 3) (vector 1 2 1 2 1) (vector 'y 'y 'y) #true)"
+        );
+    }
+
+    #[test]
+    fn test_lazy_pipeline() {
+        let code = r#"
+        (def calls 0)
+        (def (tracked n)
+            (set! calls (+ calls 1))
+            n)
+        (def pipeline
+            (stream-take 3 (stream-map tracked (iterator 0 (λ (n) (+ n 1))))))
+        (def before calls)
+        (vector before (stream->vector (take 10 pipeline)) (> calls 0))
+        "#;
+        assert_eq!(eval(code), "(vector 0 (vector 0 1 2) #true)");
+    }
+
+    #[test]
+    fn test_take_of_stream_is_lazy() {
+        let code = r#"
+        (def calls 0)
+        (def (tracked n)
+            (set! calls (+ calls 1))
+            n)
+        (def naturals (stream-map tracked (iterator 0 (λ (n) (+ n 1)))))
+        (def first-three (take 3 naturals))
+        (def small (take-while (λ (n) (< n 2)) naturals))
+        (def before calls)
+        (vector (stream? first-three)
+                (stream? small)
+                before
+                (stream->vector first-three)
+                (stream->vector small))
+        "#;
+        assert_eq!(
+            eval(code),
+            "(vector #true #true 0 (vector 0 1 2) (vector 0 1))"
+        );
+    }
+
+    #[test]
+    fn test_errors_reach_the_consumer() {
+        let mut interpreter = Interpreter::with_prelude();
+        let mut eval_err = |code: &str| interpreter.eval(code).unwrap_err().message.clone();
+
+        assert_eq!(
+            eval_err(r#"(stream->vector (take 3 (generator (yield 1) (error "boom") (yield 2))))"#),
+            "boom"
+        );
+        assert_eq!(
+            eval_err(r#"(stream->vector (take 3 (stream-cons 1 (error "x"))))"#),
+            "x"
+        );
+
+        // The error is kept and returned on every later access
+        assert_eq!(
+            eval_err(r#"(def g (generator (yield 1) (error "boom"))) (stream->vector (take 3 g))"#),
+            "boom"
+        );
+        assert_eq!(eval_err("(head (tail g))"), "boom");
+        assert_eq!(
+            eval_err(r#"(def s (stream-cons 1 (error "x"))) (stream->vector (take 3 s))"#),
+            "x"
+        );
+        assert_eq!(eval_err("(head (tail s))"), "x");
+        assert_eq!(eval("(head (generator (yield 1) (error \"boom\")))"), "1");
+    }
+
+    #[test]
+    fn test_yield_outside_generator() {
+        let mut interpreter = Interpreter::with_prelude();
+        assert!(interpreter.eval("(yield 1)").is_err());
+    }
+}
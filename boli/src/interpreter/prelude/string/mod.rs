@@ -1,10 +1,17 @@
-use crate::interpreter::{
-    environment::EnvironmentBuilder,
-    module_mgmt::extension::{new_extension, ExtensionRef},
-    values::*,
+use crate::{
+    frontend::lexer::number_format::number_format,
+    interpreter::{
+        environment::EnvironmentBuilder,
+        module_mgmt::extension::{new_extension, ExtensionRef},
+        values::*,
+    },
 };
+use port::OutputStringPort;
+use regex::Regex;
 use std::rc::Rc;
 
+mod port;
+
 pub fn create_string_extension() -> ExtensionRef {
     let core_env = EnvironmentBuilder::new().build();
     let env = EnvironmentBuilder::new().parent(&core_env).build();
@@ -27,6 +34,34 @@ pub fn create_string_extension() -> ExtensionRef {
         .set_callable("string->real", &Rc::new(StrToReal::new()));
     env.borrow_mut()
         .set_callable("string-count", &Rc::new(StrCount::new()));
+    env.borrow_mut()
+        .set_callable("string-split", &Rc::new(StrSplit::new()));
+    env.borrow_mut()
+        .set_callable("string-trim", &Rc::new(StrTrim::new(Trim::Both)));
+    env.borrow_mut()
+        .set_callable("string-trim-left", &Rc::new(StrTrim::new(Trim::Left)));
+    env.borrow_mut()
+        .set_callable("string-trim-right", &Rc::new(StrTrim::new(Trim::Right)));
+    env.borrow_mut()
+        .set_callable("string-join", &Rc::new(StrJoin::new()));
+    env.borrow_mut().set_callable(
+        "string-starts-with?",
+        &Rc::new(StrMatches::new("string-starts-with?", |s, p| {
+            s.starts_with(p)
+        })),
+    );
+    env.borrow_mut().set_callable(
+        "string-ends-with?",
+        &Rc::new(StrMatches::new("string-ends-with?", |s, p| s.ends_with(p))),
+    );
+    env.borrow_mut().set_callable(
+        "string-contains?",
+        &Rc::new(StrMatches::new("string-contains?", |s, p| s.contains(p))),
+    );
+    env.borrow_mut()
+        .set_callable("string-index-of", &Rc::new(StrIndexOf::new()));
+
+    port::register_port_functions(&env);
 
     let values = env.borrow().get_exported_values();
 
@@ -82,26 +117,38 @@ impl Callable for StrSub {
             _ => return error("string-sub function expects an integer as the second argument"),
         };
 
-        if num_args == 2 {
-            let new_string = string.value.chars().skip(start).collect::<String>();
-            Ok(new_valueref(StrValue { value: new_string }))
-        } else {
+        let length = if num_args == 3 {
             let arg2 = borrow_value(&args[2]);
-            let length = match arg2.get_type() {
-                ValueType::Int => downcast_value::<IntValue>(&arg2).unwrap().value as usize,
+            match arg2.get_type() {
+                ValueType::Int => Some(downcast_value::<IntValue>(&arg2).unwrap().value as usize),
                 _ => return error("string-sub function expects an integer as the third argument"),
-            };
-            let new_string = string
-                .value
-                .chars()
-                .skip(start)
-                .take(length)
-                .collect::<String>();
-            Ok(new_valueref(StrValue { value: new_string }))
-        }
+            }
+        } else {
+            None
+        };
+
+        let value = &string.value;
+        let start = char_offset(value, start);
+        let end = match length {
+            Some(length) => start + char_offset(&value[start..], length),
+            None => value.len(),
+        };
+
+        // The substring shares the text of the string instead of copying it
+        Ok(new_valueref(StrValue {
+            value: value.slice(start..end),
+        }))
     }
 }
 
+fn char_offset(value: &str, num_chars: usize) -> usize {
+    value
+        .char_indices()
+        .nth(num_chars)
+        .map(|(offset, _)| offset)
+        .unwrap_or(value.len())
+}
+
 struct StrReplace {}
 
 impl StrReplace {
@@ -135,7 +182,10 @@ impl Callable for StrReplace {
         };
 
         Ok(new_valueref(StrValue {
-            value: string.value.replace(&pattern.value, &replacement.value),
+            value: string
+                .value
+                .replace(&*pattern.value, &replacement.value)
+                .into(),
         }))
     }
 }
@@ -150,20 +200,36 @@ impl StrConcat {
 
 impl Callable for StrConcat {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        let mut result = String::new();
+        let mut parts: Vec<SharedStr> = Vec::with_capacity(args.len());
 
         for arg in args {
             let value = borrow_value(&arg);
             match value.get_type() {
                 ValueType::Str => {
                     let string = downcast_value::<StrValue>(&value).unwrap();
-                    result.push_str(&string.value);
+                    if !string.value.is_empty() {
+                        parts.push(string.value.clone());
+                    }
                 }
                 _ => return error("string-concat function expects only strings as arguments"),
             }
         }
 
-        Ok(new_valueref(StrValue { value: result }))
+        // A single non-empty part is shared, otherwise the parts are copied
+        // into one buffer of the final length
+        let value = match parts.len() {
+            0 => "".into(),
+            1 => parts.pop().unwrap(),
+            _ => {
+                let mut text = String::with_capacity(parts.iter().map(|part| part.len()).sum());
+                for part in &parts {
+                    text.push_str(part);
+                }
+                text.into()
+            }
+        };
+
+        Ok(new_valueref(StrValue { value }))
     }
 }
 
@@ -188,7 +254,7 @@ impl Callable for StrUpper {
         };
 
         Ok(new_valueref(StrValue {
-            value: string.value.to_uppercase(),
+            value: string.value.to_uppercase().into(),
         }))
     }
 }
@@ -214,7 +280,7 @@ impl Callable for StrLower {
         };
 
         Ok(new_valueref(StrValue {
-            value: string.value.to_lowercase(),
+            value: string.value.to_lowercase().into(),
         }))
     }
 }
@@ -239,7 +305,8 @@ impl Callable for StrToInt {
             _ => return error("string->int function expects a string as the first argument"),
         };
 
-        let string_val = str::replace(&string.value, ".", "");
+        let grouping_separator = number_format().grouping_separator();
+        let string_val = string.value.replace(grouping_separator, "");
 
         match string_val.parse::<i64>() {
             Ok(value) => Ok(new_valueref(IntValue { value })),
@@ -268,12 +335,9 @@ impl Callable for StrToReal {
             _ => return error("string->real function expects a string as the first argument"),
         };
 
-        let mut string_val = str::replace(&string.value, ".", "");
-        string_val = string_val.replace(",", ".");
-
-        match string_val.parse::<f64>() {
-            Ok(value) => Ok(new_valueref(RealValue { value })),
-            Err(_) => Ok(new_valueref(BoolValue { value: false })),
+        match number_format().parse_real(&string.value) {
+            Some(value) => Ok(new_valueref(RealValue { value })),
+            None => Ok(new_valueref(BoolValue { value: false })),
         }
     }
 }
@@ -303,3 +367,319 @@ impl Callable for StrCount {
         }))
     }
 }
+
+fn string_arg(
+    args: &[ValueRef],
+    index: usize,
+    function: &str,
+    position: &str,
+) -> Result<SharedStr, InterpreterError> {
+    match downcast_value::<StrValue>(&borrow_value(&args[index])) {
+        Some(string) => Ok(string.value.clone()),
+        None => Err(InterpreterError::new(&format!(
+            "{} function expects a string as the {} argument",
+            function, position
+        ))),
+    }
+}
+
+fn string_vector<'a>(parts: impl Iterator<Item = &'a str>) -> ValueRef {
+    new_valueref(VectorValue {
+        elements: parts
+            .map(|part| new_valueref(StrValue { value: part.into() }))
+            .collect(),
+    })
+}
+
+struct StrSplit {}
+
+impl StrSplit {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for StrSplit {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 3 {
+            return error("string-split function expects one to three arguments");
+        }
+
+        let string = string_arg(args, 0, "string-split", "first")?;
+        if args.len() == 1 {
+            return Ok(string_vector(string.split_whitespace()));
+        }
+
+        let delimiter = string_arg(args, 1, "string-split", "second")?;
+        if args.len() == 2 {
+            return Ok(string_vector(string.split(&*delimiter)));
+        }
+
+        let is_regex = match downcast_value::<SymbolValue>(&borrow_value(&args[2])) {
+            Some(mode) => mode.value == "regex",
+            None => false,
+        };
+        if !is_regex {
+            return error("string-split function expects 'regex as the third argument");
+        }
+
+        match Regex::new(&delimiter) {
+            Ok(regex) => Ok(string_vector(regex.split(&string))),
+            Err(err) => error(&format!(
+                "string-split: invalid regular expression: {}",
+                err
+            )),
+        }
+    }
+}
+
+enum Trim {
+    Both,
+    Left,
+    Right,
+}
+
+struct StrTrim {
+    trim: Trim,
+}
+
+impl StrTrim {
+    pub fn new(trim: Trim) -> Self {
+        Self { trim }
+    }
+
+    fn name(&self) -> &str {
+        match self.trim {
+            Trim::Both => "string-trim",
+            Trim::Left => "string-trim-left",
+            Trim::Right => "string-trim-right",
+        }
+    }
+}
+
+impl Callable for StrTrim {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error(&format!(
+                "{} function expects exactly one argument",
+                self.name()
+            ));
+        }
+
+        let string = string_arg(args, 0, self.name(), "first")?;
+        let start = match self.trim {
+            Trim::Right => 0,
+            _ => string.len() - string.trim_start().len(),
+        };
+        let end = match self.trim {
+            Trim::Left => string.len(),
+            _ => string.trim_end().len().max(start),
+        };
+
+        Ok(new_valueref(StrValue {
+            value: string.slice(start..end),
+        }))
+    }
+}
+
+struct StrJoin {}
+
+impl StrJoin {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for StrJoin {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("string-join function expects one or two arguments");
+        }
+
+        let separator = if args.len() == 2 {
+            string_arg(args, 1, "string-join", "second")?
+        } else {
+            "".into()
+        };
+
+        let elements = {
+            let strings = borrow_value(&args[0]);
+            match strings.get_type() {
+                ValueType::Vector => downcast_value::<VectorValue>(&strings)
+                    .unwrap()
+                    .elements
+                    .clone(),
+                ValueType::Pair => downcast_value::<PairValue>(&strings)
+                    .unwrap()
+                    .get_elements(),
+                ValueType::Nil => Vec::new(),
+                _ => return error("string-join function expects a vector or list of strings"),
+            }
+        };
+
+        let mut port = OutputStringPort::new();
+        for (i, element) in elements.iter().enumerate() {
+            let element = borrow_value(element);
+            let Some(string) = downcast_value::<StrValue>(&element) else {
+                return error("string-join function expects a vector or list of strings");
+            };
+            if i > 0 {
+                port.write(&separator);
+            }
+            port.write(&string.value);
+        }
+
+        Ok(new_valueref(StrValue {
+            value: port.contents().into(),
+        }))
+    }
+}
+
+struct StrMatches {
+    name: &'static str,
+    predicate: fn(&str, &str) -> bool,
+}
+
+impl StrMatches {
+    pub fn new(name: &'static str, predicate: fn(&str, &str) -> bool) -> Self {
+        Self { name, predicate }
+    }
+}
+
+impl Callable for StrMatches {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error(&format!(
+                "{} function expects exactly two arguments",
+                self.name
+            ));
+        }
+
+        let string = string_arg(args, 0, self.name, "first")?;
+        let pattern = string_arg(args, 1, self.name, "second")?;
+
+        Ok(new_valueref(BoolValue {
+            value: (self.predicate)(&string, &pattern),
+        }))
+    }
+}
+
+struct StrIndexOf {}
+
+impl StrIndexOf {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for StrIndexOf {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("string-index-of function expects exactly two arguments");
+        }
+
+        let string = string_arg(args, 0, "string-index-of", "first")?;
+        let pattern = string_arg(args, 1, "string-index-of", "second")?;
+
+        match string.find(&*pattern) {
+            Some(offset) => Ok(new_valueref(IntValue {
+                value: string[..offset].chars().count() as i64,
+            })),
+            None => Ok(new_valueref(BoolValue { value: false })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    fn eval_string(code: &str) -> String {
+        let mut interpreter = Interpreter::with_prelude();
+        let result = interpreter.eval(code).unwrap();
+        let result = result.borrow().to_string();
+        result
+    }
+
+    #[test]
+    fn test_string_sub() {
+        assert_eq!(eval_string(r#"(string-sub "Grüße" 2)"#), "\"üße\"");
+        assert_eq!(eval_string(r#"(string-sub "Grüße" 1 3)"#), "\"rüß\"");
+        assert_eq!(eval_string(r#"(string-sub "Grüße" 3 10)"#), "\"ße\"");
+        assert_eq!(eval_string(r#"(string-sub "Grüße" 10)"#), "\"\"");
+        assert_eq!(
+            eval_string(r#"(string-sub (string-sub "Grüße" 1) 1 2)"#),
+            "\"üß\""
+        );
+    }
+
+    #[test]
+    fn test_string_concat() {
+        assert_eq!(eval_string(r#"(string-concat)"#), "\"\"");
+        assert_eq!(eval_string(r#"(string-concat "" "abc" "")"#), "\"abc\"");
+        assert_eq!(eval_string(r#"(string-concat "a" "ü" "c")"#), "\"aüc\"");
+    }
+
+    #[test]
+    fn test_string_split() {
+        assert_eq!(
+            eval_string(r#"(string-split "  a b\tc ")"#),
+            r#"(vector "a" "b" "c")"#
+        );
+        assert_eq!(
+            eval_string(r#"(string-split "a,b,,c" ",")"#),
+            r#"(vector "a" "b" "" "c")"#
+        );
+        assert_eq!(
+            eval_string(r#"(string-split "a1b22c" "[0-9]+" 'regex)"#),
+            r#"(vector "a" "b" "c")"#
+        );
+    }
+
+    #[test]
+    fn test_string_trim() {
+        assert_eq!(eval_string(r#"(string-trim "  ab c ")"#), "\"ab c\"");
+        assert_eq!(eval_string(r#"(string-trim-left "  ab ")"#), "\"ab \"");
+        assert_eq!(eval_string(r#"(string-trim-right "  ab ")"#), "\"  ab\"");
+        assert_eq!(eval_string(r#"(string-trim "   ")"#), "\"\"");
+    }
+
+    #[test]
+    fn test_string_join() {
+        assert_eq!(
+            eval_string(r#"(string-join (vector "a" "b" "c") ", ")"#),
+            "\"a, b, c\""
+        );
+        assert_eq!(eval_string(r#"(string-join (list "a" "b"))"#), "\"ab\"");
+        assert_eq!(eval_string(r#"(string-join (list) "-")"#), "\"\"");
+    }
+
+    #[test]
+    fn test_string_search() {
+        assert_eq!(
+            eval_string(r#"(string-starts-with? "Grüße" "Gr")"#),
+            "#true"
+        );
+        assert_eq!(eval_string(r#"(string-ends-with? "Grüße" "ß")"#), "#false");
+        assert_eq!(eval_string(r#"(string-contains? "Grüße" "üß")"#), "#true");
+        assert_eq!(eval_string(r#"(string-index-of "Grüße" "ße")"#), "3");
+        assert_eq!(eval_string(r#"(string-index-of "Grüße" "x")"#), "#false");
+    }
+
+    #[test]
+    fn test_output_string_port() {
+        let code = r#"
+        (def port (open-output-string))
+        (def (fill n)
+            (if (> n 0)
+                (block
+                    (write-string port "ab")
+                    (fill (- n 1)))
+                nil))
+        (fill 3)
+        (write-string port "!")
+        (get-output-string port)
+        "#;
+        assert_eq!(eval_string(code), "\"ababab!\"");
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::interpreter::{environment::EnvironmentRef, values::*};
+
+use super::string_arg;
+
+pub fn register_port_functions(env: &EnvironmentRef) {
+    env.borrow_mut()
+        .set_callable("open-output-string", &Rc::new(OpenOutputString::new()));
+    env.borrow_mut()
+        .set_callable("write-string", &Rc::new(WriteString::new()));
+    env.borrow_mut()
+        .set_callable("get-output-string", &Rc::new(GetOutputString::new()));
+}
+
+// Collects text in a growable buffer, so that building a string piece by
+// piece is linear instead of quadratic as with repeated string-concat
+#[derive(Default)]
+pub struct OutputStringPort {
+    buffer: String,
+}
+
+impl OutputStringPort {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, text: &str) {
+        self.buffer.push_str(text);
+    }
+
+    pub fn contents(&self) -> &str {
+        &self.buffer
+    }
+}
+
+impl Value for OutputStringPort {
+    fn get_type(&self) -> ValueType {
+        ValueType::OutputPort
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Display for OutputStringPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<output-string-port {}>", self.buffer.chars().count())
+    }
+}
+
+impl Debug for OutputStringPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+struct OpenOutputString {}
+
+impl OpenOutputString {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for OpenOutputString {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("open-output-string function expects no arguments");
+        }
+
+        Ok(new_valueref(OutputStringPort::new()))
+    }
+}
+
+struct WriteString {}
+
+impl WriteString {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for WriteString {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("write-string function expects exactly two arguments");
+        }
+
+        let text = string_arg(args, 1, "write-string", "second")?;
+
+        let mut port = borrow_mut_value(&args[0]);
+        match port.as_any_mut().downcast_mut::<OutputStringPort>() {
+            Some(port) => port.write(&text),
+            None => return error("write-string function expects a port as the first argument"),
+        }
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct GetOutputString {}
+
+impl GetOutputString {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for GetOutputString {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("get-output-string function expects exactly one argument");
+        }
+
+        match downcast_value::<OutputStringPort>(&borrow_value(&args[0])) {
+            Some(port) => Ok(new_valueref(StrValue {
+                value: port.contents().into(),
+            })),
+            None => error("get-output-string function expects a port"),
+        }
+    }
+}
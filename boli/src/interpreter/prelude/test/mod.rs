@@ -0,0 +1,170 @@
+use std::rc::Rc;
+
+use crate::interpreter::{
+    environment::{Environment, EnvironmentBuilder},
+    misc_functions::is_truthy,
+    module_mgmt::extension::{new_extension, new_extension_dir, ExtensionRef},
+    values::*,
+};
+
+use super::load_module_code;
+
+pub fn create_test_extension(list_ext: &ExtensionRef, seqcol_ext: &ExtensionRef) -> ExtensionRef {
+    let core_env = EnvironmentBuilder::new().build();
+    let env = EnvironmentBuilder::new().parent(&core_env).build();
+
+    env.borrow_mut()
+        .set_callable("assert", &Rc::new(Assert::new()));
+    env.borrow_mut()
+        .set_callable("assert-equal", &Rc::new(AssertEqual::new()));
+    env.borrow_mut()
+        .set_callable("test-outcome", &Rc::new(TestOutcome::new()));
+
+    let deps = new_extension_dir("deps");
+    for dep in [list_ext, seqcol_ext] {
+        deps.borrow_mut().add_extension(dep);
+    }
+
+    let mut search_dirs = env.borrow().get_module_search_dirs();
+    search_dirs.push(deps);
+    Environment::set_module_search_dirs(&env, &search_dirs);
+
+    let values = load_module_code(&env, include_str!("test.boli")).unwrap_or_default();
+
+    new_extension("test", values)
+}
+
+struct Assert {}
+
+impl Assert {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for Assert {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("assert function expects one or two arguments");
+        }
+
+        if is_truthy(&args[0]) {
+            return Ok(new_valueref(NilValue {}));
+        }
+
+        match args.get(1) {
+            Some(message) => error(&format!(
+                "Assertion failed: {}",
+                message.borrow().display_string()
+            )),
+            None => error("Assertion failed"),
+        }
+    }
+}
+
+struct AssertEqual {}
+
+impl AssertEqual {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for AssertEqual {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("assert-equal function expects exactly two arguments");
+        }
+
+        // Values are compared by their written representation, so that
+        // collections are compared structurally
+        let actual = args[0].borrow().to_string();
+        let expected = args[1].borrow().to_string();
+        if actual != expected {
+            return error(&format!(
+                "Assertion failed: expected {} but got {}",
+                expected, actual
+            ));
+        }
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+struct TestOutcome {}
+
+impl TestOutcome {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for TestOutcome {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("test-outcome function expects exactly one argument");
+        }
+
+        match catch_panic(|| call_value(&args[0], &[])) {
+            Ok(_) => Ok(new_valueref(NilValue {})),
+            Err(err) if err.exit_code().is_some() => Err(err),
+            Err(err) => Ok(new_valueref(StrValue {
+                value: err.to_string().into(),
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_asserts() {
+        let mut interpreter = Interpreter::with_prelude();
+
+        assert!(interpreter.eval("(assert (= 1 1))").is_ok());
+        let result = interpreter.eval(r#"(assert (= 1 2) "one is not two")"#);
+        assert_eq!(
+            result.err().unwrap().message,
+            "Assertion failed: one is not two"
+        );
+        assert!(interpreter
+            .eval("(assert-equal (list 1 (vector 2)) (list 1 (vector 2)))")
+            .is_ok());
+        let result = interpreter.eval("(assert-equal (+ 1 1) 3)");
+        assert_eq!(
+            result.err().unwrap().message,
+            "Assertion failed: expected 3 but got 2"
+        );
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let mut interpreter = Interpreter::with_prelude();
+
+        let code = r#"
+        (def-test "addition"
+            (assert-equal (+ 1 2) 3))
+        (def-test "subtraction"
+            (def x 5)
+            (assert-equal (- x 2) 2))
+        (def-test "error"
+            (head nil))
+        (run-tests)
+        "#;
+        let result = interpreter.eval(code);
+        assert_eq!(result.err().unwrap().message, "2 of 3 tests failed");
+
+        let code = r#"
+        (clear-tests!)
+        (def-test "ok" (assert #t))
+        (with-output-to-string run-tests)
+        "#;
+        let output = interpreter.eval(code).unwrap();
+        assert_eq!(
+            output.borrow().display_string(),
+            "PASS ok\ntests: 1, passed: 1, failed: 0\n"
+        );
+    }
+}
@@ -4,6 +4,7 @@ use crate::interpreter::{
     borrow_mut_value, borrow_value, downcast_value,
     environment::EnvironmentBuilder,
     error,
+    freeze::check_mutable,
     module_mgmt::extension::{new_extension, ExtensionRef},
     new_valueref, BoolValue, Callable, EvalResult, IntValue, ValueRef, ValueType, VectorValue,
 };
@@ -264,6 +265,7 @@ impl Callable for VecSetBang {
             return error("vector-set! function expects exactly three arguments");
         }
 
+        check_mutable(&args[0], "vector-set!")?;
         let arg0 = &mut borrow_mut_value(&args[0]);
         let list = match arg0.as_any_mut().downcast_mut::<VectorValue>() {
             Some(list) => list,
@@ -299,6 +301,7 @@ impl Callable for VecRemoveBang {
             return error("vector-remove! function expects exactly two arguments");
         }
 
+        check_mutable(&args[0], "vector-remove!")?;
         let arg0 = &mut borrow_mut_value(&args[0]);
         let vector = match arg0.as_any_mut().downcast_mut::<VectorValue>() {
             Some(vector) => vector,
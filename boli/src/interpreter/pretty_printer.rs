@@ -0,0 +1,262 @@
+use super::misc_functions::{current_output, OutputRef};
+use super::values::*;
+
+pub const DEFAULT_WIDTH: usize = 80;
+const INDENT: usize = 2;
+
+// Formats a value in its re-readable form. Collections that do not fit into
+// the remaining width are broken up into one element per line.
+pub fn pretty_format(value: &ValueRef, width: usize) -> String {
+    pretty_format_elided(value, 0, width, None)
+}
+
+// Like pretty_format for a value that starts at column indent. Of every
+// collection at most max_elements elements are shown, followed by ... if
+// there are more.
+pub fn pretty_format_elided(
+    value: &ValueRef,
+    indent: usize,
+    width: usize,
+    max_elements: Option<usize>,
+) -> String {
+    let mut out = String::new();
+    format_value(value, indent, width, max_elements, &mut out);
+    out
+}
+
+enum Item {
+    Value(ValueRef),
+    Entry(ValueRef, ValueRef),
+}
+
+fn format_value(
+    value: &ValueRef,
+    indent: usize,
+    width: usize,
+    max_elements: Option<usize>,
+    out: &mut String,
+) {
+    let flat = flat_format(value, max_elements);
+    if indent + flat.chars().count() <= width {
+        out.push_str(&flat);
+        return;
+    }
+
+    match layout(value) {
+        Some((header, items)) if !items.is_empty() => {
+            out.push('(');
+            out.push_str(&header);
+            let item_indent = indent + INDENT;
+            let elided = max_elements.is_some_and(|max| items.len() > max);
+            let shown = max_elements.unwrap_or(items.len());
+            for item in items.into_iter().take(shown) {
+                out.push('\n');
+                out.push_str(&" ".repeat(item_indent));
+                match item {
+                    Item::Value(value) => {
+                        format_value(&value, item_indent, width, max_elements, out)
+                    }
+                    Item::Entry(key, value) => {
+                        let key = key.borrow().to_string();
+                        out.push_str(&key);
+                        out.push(' ');
+                        let value_indent = item_indent + key.chars().count() + 1;
+                        format_value(&value, value_indent, width, max_elements, out);
+                    }
+                }
+            }
+            if elided {
+                out.push('\n');
+                out.push_str(&" ".repeat(item_indent));
+                out.push_str("...");
+            }
+            out.push(')');
+        }
+        _ => out.push_str(&flat),
+    }
+}
+
+// The printed form of a value on one line with elided collections
+fn flat_format(value: &ValueRef, max_elements: Option<usize>) -> String {
+    let Some(max_elements) = max_elements else {
+        return value.borrow().to_string();
+    };
+    match layout(value) {
+        Some((header, items)) if !items.is_empty() => {
+            let mut out = format!("({}", header);
+            let elided = items.len() > max_elements;
+            for item in items.into_iter().take(max_elements) {
+                out.push(' ');
+                match item {
+                    Item::Value(value) => out.push_str(&flat_format(&value, Some(max_elements))),
+                    Item::Entry(key, value) => {
+                        out.push_str(&key.borrow().to_string());
+                        out.push(' ');
+                        out.push_str(&flat_format(&value, Some(max_elements)));
+                    }
+                }
+            }
+            if elided {
+                out.push_str(" ...");
+            }
+            out.push(')');
+            out
+        }
+        _ => value.borrow().to_string(),
+    }
+}
+
+fn layout(value: &ValueRef) -> Option<(String, Vec<Item>)> {
+    let value = borrow_value(value);
+    match value.get_type() {
+        ValueType::Vector => {
+            let vector = downcast_value::<VectorValue>(&value).unwrap();
+            let items = vector.elements.iter().cloned().map(Item::Value).collect();
+            Some(("vector".to_string(), items))
+        }
+        ValueType::Pair => {
+            let pair = downcast_value::<PairValue>(&value).unwrap();
+            if !pair.is_list() {
+                return None;
+            }
+            let items = pair.get_elements().into_iter().map(Item::Value).collect();
+            Some(("list".to_string(), items))
+        }
+        ValueType::Struct => {
+            let value = downcast_value::<StructValue>(&value).unwrap();
+            match &value.struct_type {
+                Some(struct_type) => {
+                    let struct_type = borrow_value(struct_type);
+                    let struct_type = downcast_value::<StructTypeValue>(&struct_type).unwrap();
+                    if struct_type.find_printer().is_some() {
+                        return None;
+                    }
+                    let items = struct_type
+                        .fields
+                        .iter()
+                        .filter_map(|field| value.values.get(field))
+                        .map(|entry| Item::Entry(entry.key.clone(), entry.value.clone()))
+                        .collect();
+                    Some((format!("struct {}", struct_type.name), items))
+                }
+                None => {
+                    let mut keys = value.values.keys().collect::<Vec<_>>();
+                    keys.sort();
+                    let entries = keys.into_iter().map(|key| &value.values[key]);
+                    if value.is_set() {
+                        let items = entries.map(|entry| Item::Value(entry.key.clone()));
+                        Some(("set".to_string(), items.collect()))
+                    } else {
+                        let items = entries
+                            .map(|entry| Item::Entry(entry.key.clone(), entry.value.clone()));
+                        Some(("hash-table".to_string(), items.collect()))
+                    }
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+pub struct PrettyPrint {
+    output: OutputRef,
+}
+
+impl PrettyPrint {
+    pub fn new(output: &OutputRef) -> Self {
+        Self {
+            output: output.clone(),
+        }
+    }
+}
+
+impl Callable for PrettyPrint {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() || args.len() > 2 {
+            return error("pretty-print function expects one or two arguments");
+        }
+
+        let width = match args.get(1) {
+            Some(width) => match downcast_value::<IntValue>(&borrow_value(width)) {
+                Some(width) if width.value > 0 => width.value as usize,
+                _ => return error("pretty-print function expects a positive width"),
+            },
+            None => DEFAULT_WIDTH,
+        };
+
+        current_output(&self.output)
+            .borrow_mut()
+            .print_line(&pretty_format(&args[0], width));
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    fn pretty(code: &str, width: usize) -> String {
+        let mut interpreter = Interpreter::with_prelude();
+        let value = interpreter.eval(code).unwrap();
+        pretty_format(&value, width)
+    }
+
+    #[test]
+    fn test_fits_on_one_line() {
+        assert_eq!(
+            pretty(r#"(vector 1 "two" (list 3))"#, 80),
+            r#"(vector 1 "two" (list 3))"#
+        );
+    }
+
+    #[test]
+    fn test_nested_collections() {
+        let code = r#"(vector 1 (list 2 3 4) (vector "five" "six"))"#;
+        assert_eq!(
+            pretty(code, 24),
+            "(vector\n  1\n  (list 2 3 4)\n  (vector \"five\" \"six\"))"
+        );
+        assert_eq!(
+            pretty(code, 14),
+            "(vector\n  1\n  (list 2 3 4)\n  (vector\n    \"five\"\n    \"six\"))"
+        );
+    }
+
+    #[test]
+    fn test_structs() {
+        let code = r#"
+        (def-struct point [x y])
+        (create-point 1 (vector 10 20 30))
+        "#;
+        assert_eq!(
+            pretty(code, 16),
+            "(struct point\n  'x 1\n  'y (vector\n       10\n       20\n       30))"
+        );
+    }
+
+    #[test]
+    fn test_elided_collections() {
+        let mut interpreter = Interpreter::with_prelude();
+        let value = interpreter
+            .eval("(vector 1 2 3 (list 4 5 6 7) (create-hash-table))")
+            .unwrap();
+        assert_eq!(
+            pretty_format_elided(&value, 0, 80, Some(4)),
+            "(vector 1 2 3 (list 4 5 6 7) ...)"
+        );
+        assert_eq!(
+            pretty_format_elided(&value, 0, 80, Some(2)),
+            "(vector 1 2 ...)"
+        );
+        assert_eq!(
+            pretty_format_elided(&value, 6, 22, Some(4)),
+            "(vector\n        1\n        2\n        3\n        (list 4 5 6 7)\n        ...)"
+        );
+        assert_eq!(
+            pretty_format_elided(&value, 0, 80, None),
+            value.borrow().to_string()
+        );
+    }
+}
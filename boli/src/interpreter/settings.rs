@@ -0,0 +1,55 @@
+use super::values::RealFormat;
+
+// Settings change how programs are evaluated. Like limits and capabilities
+// they are kept by the environment of an interpreter, so interpreters on
+// the same thread can use different settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Settings {
+    // A function definition that replaces an existing definition of the
+    // function for the same number of arguments is an error (--strict),
+    // otherwise it is reported as a warning
+    pub strict: bool,
+    // Conditions must be booleans (--strict-bool), otherwise nil, 0 and
+    // empty vectors count as false and all other values as true
+    pub strict_bool: bool,
+    // The function definitions of a block are evaluated before its other
+    // forms (--hoist-functions), so that local functions can refer to each
+    // other and be called before the def that introduces them
+    pub hoist_functions: bool,
+    // Type annotations of functions are checked (--contracts), otherwise
+    // they serve as documentation
+    pub contracts: bool,
+    // How real numbers are displayed and converted to strings
+    pub real_format: RealFormat,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn strict_bool(mut self, strict_bool: bool) -> Self {
+        self.strict_bool = strict_bool;
+        self
+    }
+
+    pub fn hoist_functions(mut self, hoist_functions: bool) -> Self {
+        self.hoist_functions = hoist_functions;
+        self
+    }
+
+    pub fn contracts(mut self, contracts: bool) -> Self {
+        self.contracts = contracts;
+        self
+    }
+
+    pub fn real_format(mut self, real_format: RealFormat) -> Self {
+        self.real_format = real_format;
+        self
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Display,
+};
+
+use super::struct_functions::get_key;
+use super::values::*;
+
+// Counters of the resources used by the current thread. They only depend on
+// the evaluated code, so runs of the same program yield the same numbers.
+struct Counters {
+    allocations: [u64; ValueType::ALL.len()],
+    peak_environment_depth: usize,
+    builtin_calls: HashMap<String, u64>,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Self {
+            allocations: [0; ValueType::ALL.len()],
+            peak_environment_depth: 0,
+            builtin_calls: HashMap::new(),
+        }
+    }
+}
+
+thread_local! {
+    static COUNTERS: RefCell<Counters> = RefCell::new(Counters::new());
+    static COUNT_BUILTIN_CALLS: Cell<bool> = const { Cell::new(false) };
+}
+
+// Counting the calls of built-in functions costs time on every call, so it
+// is only done with --stats or once runtime-stats has been called
+pub fn count_builtin_calls(enabled: bool) {
+    COUNT_BUILTIN_CALLS.with(|count| count.set(enabled));
+}
+
+pub fn record_allocation(value_type: ValueType) {
+    COUNTERS.with(|counters| counters.borrow_mut().allocations[value_type as usize] += 1);
+}
+
+pub fn record_environment_depth(depth: usize) {
+    COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        counters.peak_environment_depth = counters.peak_environment_depth.max(depth);
+    });
+}
+
+pub fn record_builtin_call(name: &str) {
+    if !COUNT_BUILTIN_CALLS.with(|count| count.get()) {
+        return;
+    }
+    COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        match counters.builtin_calls.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                counters.builtin_calls.insert(name.to_string(), 1);
+            }
+        }
+    });
+}
+
+pub fn reset_stats() {
+    COUNTERS.with(|counters| *counters.borrow_mut() = Counters::new());
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RuntimeStats {
+    // Value types that were allocated at least once
+    pub allocations: Vec<(ValueType, u64)>,
+    pub peak_environment_depth: usize,
+    // Most frequently called functions first
+    pub builtin_calls: Vec<(String, u64)>,
+}
+
+pub fn runtime_stats() -> RuntimeStats {
+    COUNTERS.with(|counters| {
+        let counters = counters.borrow();
+
+        let allocations = ValueType::ALL
+            .iter()
+            .map(|value_type| (*value_type, counters.allocations[*value_type as usize]))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+
+        let mut builtin_calls: Vec<(String, u64)> = counters
+            .builtin_calls
+            .iter()
+            .map(|(name, count)| (name.clone(), *count))
+            .collect();
+        builtin_calls.sort_by(|(name_a, count_a), (name_b, count_b)| {
+            count_b.cmp(count_a).then(name_a.cmp(name_b))
+        });
+
+        RuntimeStats {
+            allocations,
+            peak_environment_depth: counters.peak_environment_depth,
+            builtin_calls,
+        }
+    })
+}
+
+impl Display for RuntimeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Allocations:")?;
+        for (value_type, count) in &self.allocations {
+            writeln!(f, "  {:<20} {:>10}", value_type.name(), count)?;
+        }
+        writeln!(f, "Peak environment depth: {}", self.peak_environment_depth)?;
+        writeln!(f, "Built-in function calls:")?;
+        for (name, count) in &self.builtin_calls {
+            writeln!(f, "  {:<20} {:>10}", name, count)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct RuntimeStatsFn {}
+
+impl RuntimeStatsFn {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn hash_table<'a, I: Iterator<Item = (&'a str, u64)>>(entries: I) -> ValueRef {
+        let mut hash_table = StructValue::new_hash_table();
+        for (name, count) in entries {
+            let key = new_valueref(SymbolValue::new(name));
+            let value = new_valueref(IntValue {
+                value: count as i64,
+            });
+            hash_table
+                .values
+                .insert(get_key(&key), StructEntry { key, value });
+        }
+        new_valueref(hash_table)
+    }
+}
+
+impl Callable for RuntimeStatsFn {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if !args.is_empty() {
+            return error("runtime-stats function expects no arguments");
+        }
+        count_builtin_calls(true);
+
+        let stats = runtime_stats();
+        let allocations = Self::hash_table(
+            stats
+                .allocations
+                .iter()
+                .map(|(value_type, count)| (value_type.name(), *count)),
+        );
+        let builtin_calls = Self::hash_table(
+            stats
+                .builtin_calls
+                .iter()
+                .map(|(name, count)| (name.as_str(), *count)),
+        );
+        let peak_environment_depth = new_valueref(IntValue {
+            value: stats.peak_environment_depth as i64,
+        });
+
+        let mut report = StructValue::new_hash_table();
+        for (name, value) in [
+            ("allocations", allocations),
+            ("peak-environment-depth", peak_environment_depth),
+            ("builtin-calls", builtin_calls),
+        ] {
+            let key = new_valueref(SymbolValue::new(name));
+            report
+                .values
+                .insert(get_key(&key), StructEntry { key, value });
+        }
+
+        Ok(new_valueref(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_runtime_stats() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(def (sum n) (if (> n 0) (+ n (sum (- n 1))) 0))")
+            .unwrap();
+
+        count_builtin_calls(true);
+        reset_stats();
+        interpreter.eval("(sum 10)").unwrap();
+        let stats = runtime_stats();
+        assert!(stats.peak_environment_depth >= 1);
+        let calls = |name: &str| {
+            stats
+                .builtin_calls
+                .iter()
+                .find(|(function, _)| function == name)
+                .map(|(_, count)| *count)
+        };
+        assert_eq!(calls(">"), Some(11));
+        assert_eq!(calls("+"), Some(10));
+        assert_eq!(calls("-"), Some(10));
+        assert_eq!(calls("*"), None);
+        assert!(stats
+            .allocations
+            .iter()
+            .any(|(value_type, count)| *value_type == ValueType::Int && *count >= 20));
+
+        // Repeated runs are counted the same way
+        reset_stats();
+        interpreter.eval("(sum 10)").unwrap();
+        assert_eq!(runtime_stats(), stats);
+
+        let calls = interpreter
+            .eval("(hash-get (hash-get (runtime-stats) 'builtin-calls) '>)")
+            .unwrap();
+        assert_eq!(calls.borrow().to_string(), "11");
+    }
+
+    #[test]
+    fn test_builtin_calls_not_counted_by_default() {
+        let mut interpreter = Interpreter::new();
+
+        count_builtin_calls(false);
+        reset_stats();
+        interpreter.eval("(+ 1 2)").unwrap();
+        assert!(runtime_stats().builtin_calls.is_empty());
+
+        // From the first call of runtime-stats on calls are counted
+        interpreter.eval("(runtime-stats)").unwrap();
+        interpreter.eval("(+ 1 2)").unwrap();
+        let stats = runtime_stats();
+        assert!(stats.builtin_calls.contains(&("+".to_string(), 1)));
+    }
+}
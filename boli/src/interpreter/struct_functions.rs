@@ -1,6 +1,105 @@
+use std::collections::hash_map::Entry;
+
+use super::freeze::check_mutable;
 use super::values::*;
 use crate::frontend::lexer::tokens::TokenType;
 
+// Fields are given as a quoted identifier or as a list of quoted identifiers
+// that is a path into nested structs
+fn field_path(value: &ValueRef, function: &str) -> Result<Vec<String>, InterpreterError> {
+    let fields = match downcast_value::<VectorValue>(&borrow_value(value)) {
+        Some(path) => path.elements.clone(),
+        None => vec![value.clone()],
+    };
+    let path = fields
+        .iter()
+        .map(|field| {
+            let field = &borrow_value(field);
+            if let Some(symbol) = downcast_value::<SymbolValue>(field) {
+                return Some(symbol.value.clone());
+            }
+            match downcast_value::<QuoteValue>(field) {
+                Some(quoted) if quoted.token.token_type == TokenType::Identifier => {
+                    quoted.token.get_string_value()
+                }
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<_>>>();
+
+    match path {
+        Some(path) if !path.is_empty() => Ok(path),
+        _ => Err(InterpreterError::new(&format!(
+            "{} expects a quoted identifier or a list of quoted identifiers as the second argument",
+            function
+        ))),
+    }
+}
+
+fn get_field(value: &ValueRef, field_name: &str, function: &str) -> EvalResult {
+    let value = &borrow_value(value);
+    let Some(struct_value) = downcast_value::<StructValue>(value) else {
+        return error(&format!(
+            "{} expects a struct as the first argument",
+            function
+        ));
+    };
+    match struct_value.values.get(field_name) {
+        Some(entry) => Ok(entry.value.clone()),
+        None => error(&format!("field '{}' not found in struct", field_name)),
+    }
+}
+
+fn get_path(value: &ValueRef, path: &[String], function: &str) -> EvalResult {
+    let mut current = value.clone();
+    for field_name in path {
+        current = get_field(&current, field_name, function)?;
+    }
+    Ok(current)
+}
+
+// Returns a copy of the struct in which the field at the end of the path is
+// replaced. The structs along the path are copied as well, all other values
+// are shared with the original.
+fn with_path(
+    value: &ValueRef,
+    path: &[String],
+    new_value: &ValueRef,
+    function: &str,
+) -> EvalResult {
+    let mut copy = match downcast_value::<StructValue>(&borrow_value(value)) {
+        Some(struct_value) => struct_value.clone(),
+        None => {
+            return error(&format!(
+                "{} expects a struct as the first argument",
+                function
+            ))
+        }
+    };
+
+    let (field_name, rest) = path.split_first().unwrap();
+    let new_value = if rest.is_empty() {
+        new_value.clone()
+    } else {
+        let inner = get_field(value, field_name, function)?;
+        with_path(&inner, rest, new_value, function)?
+    };
+
+    match copy.values.get_mut(field_name) {
+        Some(entry) => entry.value = new_value,
+        None if copy.struct_type.is_none() => {
+            let entry = StructEntry {
+                key: new_valueref(SymbolValue::new(field_name)),
+                value: new_value,
+            };
+            copy.values.insert(field_name.clone(), entry);
+        }
+        None => return error(&format!("field '{}' not found in struct", field_name)),
+    }
+
+    Ok(new_valueref(copy))
+}
+
 pub struct StructGet {}
 
 impl StructGet {
@@ -15,52 +114,8 @@ impl Callable for StructGet {
             return error("struct-get expects 2 arguments");
         }
 
-        let arg0 = &borrow_value(&args[0]);
-        let arg0 = downcast_value::<StructValue>(arg0);
-        if arg0.is_none() {
-            return error("struct-get expects a struct as the first argument");
-        }
-        let struct_value = arg0.unwrap();
-
-        let arg1 = &borrow_value(&args[1]);
-        let arg1 = downcast_value::<VectorValue>(arg1);
-        if arg1.is_none() {
-            return error("struct-get expects a list as the second argument");
-        }
-        let path = arg1.unwrap();
-
-        let field = path.elements[0].clone();
-        let field = &borrow_value(&field);
-        let field = downcast_value::<QuoteValue>(field);
-        if field.is_none() {
-            return error("struct-get expects a list of quoted identifiers as the second argument");
-        }
-
-        let quoted_field = field.unwrap();
-        let field_token = quoted_field.token.clone();
-
-        if field_token.token_type != TokenType::Identifier {
-            return error("struct-get expects a list of quoted identifiers as the second argument");
-        }
-
-        let field_name = field_token.get_string_value().unwrap();
-        let entry = struct_value.values.get(&field_name);
-
-        if entry.is_none() {
-            return error(&format!("field '{}' not found in struct", field_name));
-        }
-
-        let entry = entry.unwrap();
-
-        if path.elements.len() == 1 {
-            return Ok(entry.value.clone());
-        }
-
-        let new_path = new_valueref(VectorValue {
-            elements: path.elements[1..].to_vec(),
-        });
-
-        self.call(&vec![entry.value.clone(), new_path])
+        let path = field_path(&args[1], "struct-get")?;
+        get_path(&args[0], &path, "struct-get")
     }
 }
 
@@ -78,59 +133,100 @@ impl Callable for StructSet {
             return error("struct-set expects 3 arguments");
         }
 
-        let mut arg0 = borrow_mut_value(&args[0]);
-        let arg0 = arg0.as_any_mut().downcast_mut::<StructValue>();
-        if arg0.is_none() {
+        let path = field_path(&args[1], "struct-set!")?;
+        let (field_name, parents) = path.split_last().unwrap();
+        let target = get_path(&args[0], parents, "struct-set!")?;
+
+        check_mutable(&target, "struct-set!")?;
+        let mut target = borrow_mut_value(&target);
+        let Some(struct_value) = target.as_any_mut().downcast_mut::<StructValue>() else {
             return error("struct-set expects a struct as the first argument");
-        }
-        let struct_value = arg0.unwrap();
+        };
 
-        let arg1 = &borrow_value(&args[1]);
-        let arg1 = downcast_value::<VectorValue>(arg1);
-        if arg1.is_none() {
-            return error("struct-set expects a list as the second argument");
+        // The previous value of the field is returned
+        let new_entry = StructEntry {
+            key: new_valueref(SymbolValue::new(field_name)),
+            value: args[2].clone(),
+        };
+        match struct_value.values.insert(field_name.clone(), new_entry) {
+            Some(old_entry) => Ok(old_entry.value),
+            None => Ok(new_valueref(NilValue {})),
         }
-        let path = arg1.unwrap();
+    }
+}
+
+pub struct StructWith {}
+
+impl StructWith {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
 
-        let field = path.elements[0].clone();
-        let field = &borrow_value(&field);
-        let field = downcast_value::<QuoteValue>(field);
-        if field.is_none() {
-            return error("struct-set expects a list of quoted identifiers as the second argument");
+impl Callable for StructWith {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 3 {
+            return error("struct-with expects 3 arguments");
         }
 
-        let quoted_field = field.unwrap();
-        let field_token = quoted_field.token.clone();
+        let path = field_path(&args[1], "struct-with")?;
+        with_path(&args[0], &path, &args[2], "struct-with")
+    }
+}
+
+pub struct StructUpdate {}
 
-        if field_token.token_type != TokenType::Identifier {
-            return error("struct-set expects a list of quoted identifiers as the second argument");
+impl StructUpdate {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for StructUpdate {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 3 {
+            return error("struct-update expects 3 arguments");
         }
 
-        let field_name = field_token.get_string_value().unwrap();
+        let path = field_path(&args[1], "struct-update")?;
+        let old_value = get_path(&args[0], &path, "struct-update")?;
+        let new_value = call_function(&args[2], &vec![old_value], "struct-update")?;
+        with_path(&args[0], &path, &new_value, "struct-update")
+    }
+}
 
-        let new_entry = StructEntry {
-            key: new_valueref(SymbolValue {
-                value: field_name.clone(),
-            }),
-            value: args[2].clone(),
-        };
+pub struct DefStructPrinter {}
 
-        if path.elements.len() == 1 {
-            struct_value.values.insert(field_name, new_entry);
-            return Ok(new_valueref(NilValue {}));
+impl DefStructPrinter {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for DefStructPrinter {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 2 {
+            return error("def-struct-printer expects 2 arguments");
         }
 
-        let field_value = struct_value.values.get(&field_name);
-        if field_value.is_none() {
-            return error(&format!("field '{}' not found in struct", field_name));
+        let function_type = args[1].borrow().get_type();
+        if !matches!(
+            function_type,
+            ValueType::Lambda | ValueType::BuiltInFunction
+        ) {
+            return Err(InterpreterError::type_error(
+                "def-struct-printer expects a function as the second argument",
+                &args[1],
+            ));
         }
-        let inner_struct = field_value.unwrap().value.clone();
 
-        let new_path = new_valueref(VectorValue {
-            elements: path.elements[1..].to_vec(),
-        });
+        let mut arg0 = borrow_mut_value(&args[0]);
+        let Some(struct_type) = arg0.as_any_mut().downcast_mut::<StructTypeValue>() else {
+            return error("def-struct-printer expects a struct type as the first argument");
+        };
+        struct_type.printer = Some(args[1].clone());
 
-        self.call(&vec![inner_struct, new_path, new_entry.value])
+        Ok(new_valueref(NilValue {}))
     }
 }
 
@@ -216,10 +312,8 @@ impl Callable for HashKeys {
     }
 }
 
-fn get_key(value: &ValueRef) -> String {
-    let value = borrow_value(value);
-
-    format!("{:?}", value.to_string())
+pub fn get_key(value: &ValueRef) -> String {
+    format!("{:?}", value_key(value))
 }
 
 pub struct HashContains {}
@@ -266,8 +360,8 @@ impl HashGet {
 
 impl Callable for HashGet {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        if args.len() != 2 {
-            return error("hash-get expects 2 arguments");
+        if args.len() != 2 && args.len() != 3 {
+            return error("hash-get expects 2 or 3 arguments");
         }
 
         let arg0 = &borrow_value(&args[0]);
@@ -285,6 +379,9 @@ impl Callable for HashGet {
         let entry = hash_table.values.get(&key);
 
         if entry.is_none() {
+            if args.len() == 3 {
+                return Ok(args[2].clone());
+            }
             return error(&format!("key '{}' not found in hash table", key));
         }
 
@@ -306,6 +403,7 @@ impl Callable for HashSetBang {
             return error("hash-set! expects 3 arguments");
         }
 
+        check_mutable(&args[0], "hash-set!")?;
         let mut arg0 = borrow_mut_value(&args[0]);
         let arg0 = arg0.as_any_mut().downcast_mut::<StructValue>();
         if arg0.is_none() {
@@ -343,6 +441,7 @@ impl Callable for HashRemoveBang {
             return error("hash-remove! expects 2 arguments");
         }
 
+        check_mutable(&args[0], "hash-remove!")?;
         let mut arg0 = borrow_mut_value(&args[0]);
         let arg0 = arg0.as_any_mut().downcast_mut::<StructValue>();
         if arg0.is_none() {
@@ -361,6 +460,240 @@ impl Callable for HashRemoveBang {
     }
 }
 
+pub struct HashUpdateBang {}
+
+impl HashUpdateBang {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for HashUpdateBang {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 3 && args.len() != 4 {
+            return error("hash-update! expects 3 or 4 arguments");
+        }
+
+        let key = get_key(&args[1]);
+
+        let current_value = {
+            let arg0 = &borrow_value(&args[0]);
+            let arg0 = downcast_value::<StructValue>(arg0);
+            if arg0.is_none() {
+                return error("hash-update! expects a hash table as the first argument");
+            }
+            let hash_table = arg0.unwrap();
+            if hash_table.struct_type.is_some() {
+                return error("hash-update! expects a hash table as the first argument");
+            }
+
+            match hash_table.values.get(&key) {
+                Some(entry) => entry.value.clone(),
+                None => {
+                    if args.len() == 4 {
+                        args[3].clone()
+                    } else {
+                        return error(&format!("key '{}' not found in hash table", key));
+                    }
+                }
+            }
+        };
+
+        let new_value = call_function(&args[2], &vec![current_value], "hash-update!")?;
+
+        check_mutable(&args[0], "hash-update!")?;
+        let mut arg0 = borrow_mut_value(&args[0]);
+        let hash_table = arg0.as_any_mut().downcast_mut::<StructValue>().unwrap();
+
+        let new_entry = StructEntry {
+            key: args[1].clone(),
+            value: new_value,
+        };
+
+        hash_table.values.insert(key, new_entry);
+
+        Ok(new_valueref(NilValue {}))
+    }
+}
+
+pub struct HashGetOrInsertBang {}
+
+impl HashGetOrInsertBang {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for HashGetOrInsertBang {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 3 {
+            return error("hash-get-or-insert! expects 3 arguments");
+        }
+
+        // A present key costs a single lookup. For a missing key the entry
+        // cannot be kept while the thunk runs, since the table must not be
+        // borrowed then: the thunk may itself read or modify the table, e.g.
+        // to memoize recursive calls. If the thunk inserted the key, its
+        // value wins and the thunk's result is discarded.
+        let key = {
+            let mut arg0 = borrow_mut_value(&args[0]);
+            let hash_table = match arg0.as_any_mut().downcast_mut::<StructValue>() {
+                Some(hash_table) if hash_table.struct_type.is_none() => hash_table,
+                _ => {
+                    return error("hash-get-or-insert! expects a hash table as the first argument")
+                }
+            };
+            match hash_table.values.entry(get_key(&args[1])) {
+                Entry::Occupied(entry) => return Ok(entry.get().value.clone()),
+                Entry::Vacant(entry) => entry.into_key(),
+            }
+        };
+
+        check_mutable(&args[0], "hash-get-or-insert!")?;
+        let new_value = call_function(&args[2], &vec![], "hash-get-or-insert!")?;
+
+        let mut arg0 = borrow_mut_value(&args[0]);
+        let hash_table = arg0.as_any_mut().downcast_mut::<StructValue>().unwrap();
+
+        let entry = hash_table.values.entry(key).or_insert_with(|| StructEntry {
+            key: args[1].clone(),
+            value: new_value,
+        });
+
+        Ok(entry.value.clone())
+    }
+}
+
+pub struct HashMerge {}
+
+impl HashMerge {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for HashMerge {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        let mut ret = StructValue::new_hash_table();
+
+        for arg in args {
+            let arg = &borrow_value(arg);
+            let arg = downcast_value::<StructValue>(arg);
+            if arg.is_none() {
+                return error("hash-merge expects hash tables as arguments");
+            }
+            let hash_table = arg.unwrap();
+            if hash_table.struct_type.is_some() {
+                return error("hash-merge expects hash tables as arguments");
+            }
+
+            for (key, entry) in &hash_table.values {
+                let new_entry = StructEntry {
+                    key: entry.key.clone(),
+                    value: entry.value.clone(),
+                };
+                ret.values.insert(key.clone(), new_entry);
+            }
+        }
+
+        Ok(new_valueref(ret))
+    }
+}
+
+pub struct AlistToHash {}
+
+impl AlistToHash {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for AlistToHash {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("alist->hash expects 1 argument");
+        }
+
+        let elements = {
+            let arg0 = &borrow_value(&args[0]);
+            match arg0.get_type() {
+                ValueType::Nil => vec![],
+                ValueType::Pair => downcast_value::<PairValue>(arg0).unwrap().get_elements(),
+                ValueType::Vector => downcast_value::<VectorValue>(arg0)
+                    .unwrap()
+                    .elements
+                    .clone(),
+                _ => return error("alist->hash expects a list or vector of pairs"),
+            }
+        };
+
+        let mut ret = StructValue::new_hash_table();
+
+        for element in &elements {
+            let element = &borrow_value(element);
+            let pair = downcast_value::<PairValue>(element);
+            if pair.is_none() {
+                return error("alist->hash expects a list or vector of pairs");
+            }
+            let pair = pair.unwrap();
+
+            let new_entry = StructEntry {
+                key: pair.left.clone(),
+                value: pair.right.clone(),
+            };
+            ret.values.insert(get_key(&pair.left), new_entry);
+        }
+
+        Ok(new_valueref(ret))
+    }
+}
+
+pub struct HashToAlist {}
+
+impl HashToAlist {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Callable for HashToAlist {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("hash->alist expects 1 argument");
+        }
+
+        let arg0 = &borrow_value(&args[0]);
+        let arg0 = downcast_value::<StructValue>(arg0);
+        if arg0.is_none() {
+            return error("hash->alist expects a hash table as the first argument");
+        }
+        let hash_table = arg0.unwrap();
+        if hash_table.struct_type.is_some() {
+            return error("hash->alist expects a hash table as the first argument");
+        }
+
+        let mut keys = hash_table.values.keys().collect::<Vec<&String>>();
+        keys.sort();
+
+        let mut ret = new_valueref(NilValue {});
+        for key in keys.iter().rev() {
+            let entry = hash_table.values.get(*key).unwrap();
+            let pair = new_valueref(PairValue::new(&entry.key, &entry.value));
+            ret = new_valueref(PairValue::new(&pair, &ret));
+        }
+
+        Ok(ret)
+    }
+}
+
+fn call_function(function: &ValueRef, args: &Vec<ValueRef>, caller_name: &str) -> EvalResult {
+    let function_type = borrow_value(function).get_type();
+    match function_type {
+        ValueType::BuiltInFunction | ValueType::Lambda => call_value(function, args),
+        _ => error(&format!("{} expects a function argument", caller_name)),
+    }
+}
+
 pub struct CreateSet {}
 
 impl CreateSet {
@@ -463,6 +796,7 @@ impl Callable for SetAddBang {
             return error("set-add! expects 2 arguments");
         }
 
+        check_mutable(&args[0], "set-add!")?;
         let mut arg0 = borrow_mut_value(&args[0]);
         let arg0 = arg0.as_any_mut().downcast_mut::<StructValue>();
         if arg0.is_none() {
@@ -499,6 +833,7 @@ impl Callable for SetRemoveBang {
             return error("set-remove! expects 2 arguments");
         }
 
+        check_mutable(&args[0], "set-remove!")?;
         let mut arg0 = borrow_mut_value(&args[0]);
         let arg0 = arg0.as_any_mut().downcast_mut::<StructValue>();
         if arg0.is_none() {
@@ -0,0 +1,201 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use super::*;
+
+// Deep equality of values: numbers, strings and symbols are compared by
+// value, lists, vectors and structs element by element. Functions and all
+// other values are only equal to themselves.
+pub fn values_equal(a: &ValueRef, b: &ValueRef) -> bool {
+    if Rc::ptr_eq(a, b) {
+        return true;
+    }
+    let a_value = borrow_value(a);
+    let b_value = borrow_value(b);
+    let is_symbol = |value_type| matches!(value_type, ValueType::Symbol | ValueType::Quote);
+    if is_symbol(a_value.get_type()) && is_symbol(b_value.get_type()) {
+        return a_value.to_string() == b_value.to_string();
+    }
+    if a_value.get_type() != b_value.get_type() {
+        return false;
+    }
+
+    match a_value.get_type() {
+        ValueType::Nil => true,
+        ValueType::Bool => {
+            downcast_value::<BoolValue>(&a_value).unwrap().value
+                == downcast_value::<BoolValue>(&b_value).unwrap().value
+        }
+        ValueType::Int => {
+            downcast_value::<IntValue>(&a_value).unwrap().value
+                == downcast_value::<IntValue>(&b_value).unwrap().value
+        }
+        ValueType::Str => {
+            downcast_value::<StrValue>(&a_value).unwrap().value
+                == downcast_value::<StrValue>(&b_value).unwrap().value
+        }
+        ValueType::Rational => {
+            let a_value = downcast_value::<RationalValue>(&a_value).unwrap();
+            let b_value = downcast_value::<RationalValue>(&b_value).unwrap();
+            // Rational literals are not necessarily in lowest terms
+            a_value.numerator as i128 * b_value.denominator as i128
+                == b_value.numerator as i128 * a_value.denominator as i128
+        }
+        ValueType::Real => {
+            let a_value = downcast_value::<RealValue>(&a_value).unwrap();
+            let b_value = downcast_value::<RealValue>(&b_value).unwrap();
+            a_value.value == b_value.value
+        }
+        ValueType::Pair => lists_equal(a, b),
+        ValueType::Vector => {
+            let a_value = downcast_value::<VectorValue>(&a_value).unwrap();
+            let b_value = downcast_value::<VectorValue>(&b_value).unwrap();
+            a_value.elements.len() == b_value.elements.len()
+                && a_value
+                    .elements
+                    .iter()
+                    .zip(b_value.elements.iter())
+                    .all(|(a, b)| values_equal(a, b))
+        }
+        ValueType::Struct => {
+            let a_value = downcast_value::<StructValue>(&a_value).unwrap();
+            let b_value = downcast_value::<StructValue>(&b_value).unwrap();
+            let same_type = match (&a_value.struct_type, &b_value.struct_type) {
+                (Some(a_type), Some(b_type)) => Rc::ptr_eq(a_type, b_type),
+                (None, None) => a_value.is_set() == b_value.is_set(),
+                _ => false,
+            };
+            same_type
+                && a_value.values.len() == b_value.values.len()
+                && a_value.values.iter().all(|(key, a_entry)| {
+                    b_value
+                        .values
+                        .get(key)
+                        .is_some_and(|b_entry| values_equal(&a_entry.value, &b_entry.value))
+                })
+        }
+        _ => false,
+    }
+}
+
+// Lists are compared iteratively to support long lists
+fn lists_equal(a: &ValueRef, b: &ValueRef) -> bool {
+    let mut a = a.clone();
+    let mut b = b.clone();
+    loop {
+        let (a_next, b_next) = {
+            let a_value = borrow_value(&a);
+            let b_value = borrow_value(&b);
+            let (Some(a_pair), Some(b_pair)) = (
+                downcast_value::<PairValue>(&a_value),
+                downcast_value::<PairValue>(&b_value),
+            ) else {
+                return values_equal(&a, &b);
+            };
+            if !values_equal(&a_pair.left, &b_pair.left) {
+                return false;
+            }
+            (a_pair.right.clone(), b_pair.right.clone())
+        };
+        a = a_next;
+        b = b_next;
+    }
+}
+
+// Canonical text of a value that is the same for all values that are equal
+// according to values_equal. Unlike the printed form it does not depend on
+// number formats or struct printers. Hash tables and sets store their
+// entries under this key.
+pub fn value_key(value: &ValueRef) -> String {
+    let mut key = String::new();
+    write_key(value, &mut key);
+    key
+}
+
+fn write_key(value: &ValueRef, key: &mut String) {
+    let value_ref = borrow_value(value);
+    match value_ref.get_type() {
+        ValueType::Nil
+        | ValueType::Bool
+        | ValueType::Int
+        | ValueType::Str
+        | ValueType::Symbol
+        | ValueType::Quote => key.push_str(&value_ref.to_string()),
+        ValueType::Rational => {
+            let rational = downcast_value::<RationalValue>(&value_ref).unwrap();
+            let rational = RationalValue::new(rational.numerator, rational.denominator);
+            key.push_str(&rational.to_string());
+        }
+        ValueType::Real => {
+            let real = downcast_value::<RealValue>(&value_ref).unwrap();
+            key.push_str(&format!("{:?}", real.value));
+        }
+        ValueType::Pair => {
+            key.push_str("(list");
+            let mut current = value.clone();
+            loop {
+                let next = match downcast_value::<PairValue>(&borrow_value(&current)) {
+                    Some(pair) => {
+                        key.push(' ');
+                        write_key(&pair.left, key);
+                        pair.right.clone()
+                    }
+                    None => break,
+                };
+                current = next;
+            }
+            if borrow_value(&current).get_type() != ValueType::Nil {
+                key.push_str(" . ");
+                write_key(&current, key);
+            }
+            key.push(')');
+        }
+        ValueType::Vector => {
+            key.push_str("(vector");
+            for element in &downcast_value::<VectorValue>(&value_ref).unwrap().elements {
+                key.push(' ');
+                write_key(element, key);
+            }
+            key.push(')');
+        }
+        ValueType::Struct => {
+            let struct_value = downcast_value::<StructValue>(&value_ref).unwrap();
+            let mut keys = struct_value.values.keys().collect::<Vec<_>>();
+            match &struct_value.struct_type {
+                Some(struct_type) => {
+                    let struct_type = borrow_value(struct_type);
+                    let struct_type = downcast_value::<StructTypeValue>(&struct_type).unwrap();
+                    key.push_str(&format!("(struct {}", struct_type.name));
+                    keys.sort_by_key(|field| struct_type.fields.iter().position(|f| f == *field));
+                }
+                None if struct_value.is_set() => {
+                    key.push_str("(set");
+                    keys.sort();
+                }
+                None => {
+                    key.push_str("(hash-table");
+                    keys.sort();
+                }
+            }
+            for field in keys {
+                key.push(' ');
+                key.push_str(field);
+                if !struct_value.is_set() {
+                    key.push(' ');
+                    write_key(&struct_value.values[field].value, key);
+                }
+            }
+            key.push(')');
+        }
+        value_type => {
+            key.push_str(&format!("<{} {:p}>", value_type.name(), Rc::as_ptr(value)));
+        }
+    }
+}
+
+pub fn value_hash(value: &ValueRef) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value_key(value).hash(&mut hasher);
+    hasher.finish()
+}
@@ -1,17 +1,32 @@
 use core::str;
 use std::cell::{Ref, RefCell, RefMut};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display};
+use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 
 use crate::frontend::lexer::tokens::Token;
-
-use super::environment::{Environment, EnvironmentBuilder};
+use crate::frontend::parser::{ast::Signature, ParseError, Span};
+
+use super::contracts::{check_arguments, check_result};
+use super::environment::{
+    upgrade_env, Environment, EnvironmentBuilder, EnvironmentRef, WeakEnvironmentRef,
+};
+use super::freeze::check_mutable;
+use super::gc::register_environment;
+use super::limits::count_allocation;
+use super::parameters::Parameter;
+use super::stats::{record_allocation, record_builtin_call};
 use super::{AstRef, Interpreter};
 
+mod equality;
+pub use equality::{value_hash, value_key, values_equal};
 mod numbers;
-pub use numbers::{IntValue, RationalValue, RealValue};
+pub use numbers::{real_format, with_real_format, IntValue, RationalValue, RealFormat, RealValue};
+mod shared_str;
+pub use shared_str::SharedStr;
 pub mod stream;
 
 #[derive(PartialEq, Debug, Clone, Copy, Eq, Hash)]
@@ -33,17 +48,93 @@ pub enum ValueType {
     BuiltInFunction,
     TailCall,
     Spread,
+    Socket,
+    Task,
+    Channel,
+    OutputPort,
+    Error,
+    Environment,
+    Eof,
+}
+
+impl ValueType {
+    pub const ALL: [ValueType; 24] = [
+        ValueType::Nil,
+        ValueType::Bool,
+        ValueType::Int,
+        ValueType::Rational,
+        ValueType::Real,
+        ValueType::Str,
+        ValueType::Symbol,
+        ValueType::Quote,
+        ValueType::Pair,
+        ValueType::Vector,
+        ValueType::Stream,
+        ValueType::StructType,
+        ValueType::Struct,
+        ValueType::Lambda,
+        ValueType::BuiltInFunction,
+        ValueType::TailCall,
+        ValueType::Spread,
+        ValueType::Socket,
+        ValueType::Task,
+        ValueType::Channel,
+        ValueType::OutputPort,
+        ValueType::Error,
+        ValueType::Environment,
+        ValueType::Eof,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::Nil => "nil",
+            ValueType::Bool => "bool",
+            ValueType::Int => "int",
+            ValueType::Rational => "rational",
+            ValueType::Real => "real",
+            ValueType::Str => "str",
+            ValueType::Symbol => "symbol",
+            ValueType::Quote => "quote",
+            ValueType::Pair => "pair",
+            ValueType::Vector => "vector",
+            ValueType::Stream => "stream",
+            ValueType::StructType => "struct-type",
+            ValueType::Struct => "struct",
+            ValueType::Lambda => "lambda",
+            ValueType::BuiltInFunction => "built-in-function",
+            ValueType::TailCall => "tail-call",
+            ValueType::Spread => "spread",
+            ValueType::Socket => "socket",
+            ValueType::Task => "task",
+            ValueType::Channel => "channel",
+            ValueType::OutputPort => "output-port",
+            ValueType::Error => "error",
+            ValueType::Environment => "environment",
+            ValueType::Eof => "eof-object",
+        }
+    }
 }
 
 pub trait Value: Display + Debug {
     fn get_type(&self) -> ValueType;
     fn as_any(&self) -> &dyn std::any::Any;
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+    fn display_string(&self) -> String {
+        self.to_string()
+    }
+
+    // Values that can be ordered (numbers, strings, symbols, vectors and
+    // lists) support <, > and compare
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        None
+    }
 }
 
 pub type ValueRef = Rc<RefCell<dyn Value>>;
 
 pub fn new_valueref<T: Value + 'static>(value: T) -> ValueRef {
+    count_allocation();
+    record_allocation(value.get_type());
     Rc::new(RefCell::new(value))
 }
 
@@ -59,6 +150,83 @@ pub fn downcast_value<'a, T: 'static>(value: &'a Ref<dyn Value>) -> Option<&'a T
     value.as_any().downcast_ref::<T>()
 }
 
+// Calls a function value from native code, resolving self tail calls
+pub fn call_value(function_ref: &ValueRef, args: &[ValueRef]) -> EvalResult {
+    let function = &borrow_value(function_ref);
+    let callable: &dyn Callable = match function.get_type() {
+        ValueType::BuiltInFunction => downcast_value::<BuiltInFunctionValue>(function).unwrap(),
+        ValueType::Lambda => downcast_value::<LambdaValue>(function).unwrap(),
+        _ => {
+            return Err(InterpreterError::type_error(
+                "Expected a function value",
+                function_ref,
+            ))
+        }
+    };
+
+    let mut args = args.to_vec();
+    loop {
+        let result = callable.call(&args)?;
+        let tail_call = downcast_value::<TailCallValue>(&borrow_value(&result))
+            .map(|tail_call| tail_call.arguments.clone());
+        match tail_call {
+            Some(arguments) => args = arguments,
+            None => return Ok(result),
+        }
+    }
+}
+
+// Copies mutable containers recursively, immutable values are shared
+pub fn deep_copy(value: &ValueRef) -> ValueRef {
+    let borrowed = borrow_value(value);
+    match borrowed.get_type() {
+        ValueType::Pair => {
+            let mut lefts = vec![];
+            let mut current = value.clone();
+            loop {
+                let next = match downcast_value::<PairValue>(&borrow_value(&current)) {
+                    Some(pair) => {
+                        lefts.push(deep_copy(&pair.left));
+                        pair.right.clone()
+                    }
+                    None => break,
+                };
+                current = next;
+            }
+            let tail = deep_copy(&current);
+            lefts.iter().rev().fold(tail, |right, left| {
+                new_valueref(PairValue::new(left, &right))
+            })
+        }
+        ValueType::Vector => {
+            let vector = downcast_value::<VectorValue>(&borrowed).unwrap();
+            new_valueref(VectorValue {
+                elements: vector.elements.iter().map(deep_copy).collect(),
+            })
+        }
+        ValueType::Struct => {
+            let struct_value = downcast_value::<StructValue>(&borrowed).unwrap();
+            let values = struct_value
+                .values
+                .iter()
+                .map(|(key, entry)| {
+                    let entry = StructEntry {
+                        key: deep_copy(&entry.key),
+                        value: deep_copy(&entry.value),
+                    };
+                    (key.clone(), entry)
+                })
+                .collect();
+            new_valueref(StructValue {
+                struct_type: struct_value.struct_type.clone(),
+                values,
+                is_set: struct_value.is_set,
+            })
+        }
+        _ => value.clone(),
+    }
+}
+
 #[derive(Debug)]
 pub struct NilValue {}
 
@@ -119,10 +287,14 @@ impl ComparableEq for BoolValue {
 
 #[derive(Debug)]
 pub struct StrValue {
-    pub value: String,
+    pub value: SharedStr,
 }
 
 impl Value for StrValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Str
     }
@@ -134,6 +306,10 @@ impl Value for StrValue {
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
         self
     }
+
+    fn display_string(&self) -> String {
+        self.value.to_string()
+    }
 }
 
 impl Display for StrValue {
@@ -148,6 +324,14 @@ impl Countable for StrValue {
     }
 }
 
+impl ComparableOrd for StrValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        let other = borrow_value(other);
+        let other = downcast_value::<StrValue>(&other)?;
+        Some(self.value.cmp(&other.value))
+    }
+}
+
 impl ComparableEq for StrValue {
     fn is_equal(&self, other: &ValueRef) -> bool {
         if let Some(other) = downcast_value::<StrValue>(&other.borrow()) {
@@ -172,6 +356,10 @@ impl SymbolValue {
 }
 
 impl Value for SymbolValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Symbol
     }
@@ -185,6 +373,21 @@ impl Value for SymbolValue {
     }
 }
 
+// Symbols and quoted identifiers are ordered by their names
+fn compare_symbols(symbol: &dyn Value, other: &ValueRef) -> Option<Ordering> {
+    let other = borrow_value(other);
+    match other.get_type() {
+        ValueType::Symbol | ValueType::Quote => Some(symbol.to_string().cmp(&other.to_string())),
+        _ => None,
+    }
+}
+
+impl ComparableOrd for SymbolValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        compare_symbols(self, other)
+    }
+}
+
 impl Display for SymbolValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "'{}", self.value)
@@ -204,6 +407,10 @@ impl QuoteValue {
 }
 
 impl Value for QuoteValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Quote
     }
@@ -217,6 +424,12 @@ impl Value for QuoteValue {
     }
 }
 
+impl ComparableOrd for QuoteValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        compare_symbols(self, other)
+    }
+}
+
 impl Display for QuoteValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let token_str = self.token.get_display_str().unwrap_or("".to_string());
@@ -293,7 +506,7 @@ impl PairValue {
         result
     }
 
-    fn get_elements(&self) -> Vec<ValueRef> {
+    pub fn get_elements(&self) -> Vec<ValueRef> {
         let mut elements = vec![];
         let mut current = new_valueref(self.clone());
 
@@ -323,6 +536,10 @@ impl PairValue {
 }
 
 impl Value for PairValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Pair
     }
@@ -336,6 +553,17 @@ impl Value for PairValue {
     }
 }
 
+impl ComparableOrd for PairValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        let other = borrow_value(other);
+        let other = downcast_value::<PairValue>(&other)?;
+        if !self.is_list() || !other.is_list() {
+            return None;
+        }
+        compare_elements(&self.get_elements(), &other.get_elements())
+    }
+}
+
 impl Display for PairValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if !self.is_list() {
@@ -355,6 +583,10 @@ pub struct VectorValue {
 }
 
 impl Value for VectorValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Vector
     }
@@ -368,6 +600,14 @@ impl Value for VectorValue {
     }
 }
 
+impl ComparableOrd for VectorValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        let other = borrow_value(other);
+        let other = downcast_value::<VectorValue>(&other)?;
+        compare_elements(&self.elements, &other.elements)
+    }
+}
+
 impl Display for VectorValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let elements_str: Vec<String> = self
@@ -389,6 +629,8 @@ impl Countable for VectorValue {
 pub struct StructTypeValue {
     pub name: String,
     pub fields: Vec<String>,
+    pub parent: Option<ValueRef>,
+    pub printer: Option<ValueRef>,
 }
 
 impl StructTypeValue {
@@ -396,6 +638,50 @@ impl StructTypeValue {
         Self {
             name: name.to_string(),
             fields: fields.clone(),
+            parent: None,
+            printer: None,
+        }
+    }
+
+    pub fn new_derived(name: &str, fields: &[String], parent: &ValueRef) -> Self {
+        let parent_type = borrow_value(parent);
+        let parent_type = downcast_value::<StructTypeValue>(&parent_type).unwrap();
+        let mut all_fields = parent_type.fields.clone();
+        all_fields.extend(fields.iter().cloned());
+
+        Self {
+            name: name.to_string(),
+            fields: all_fields,
+            parent: Some(parent.clone()),
+            printer: None,
+        }
+    }
+
+    pub fn is_subtype_of(&self, other: &StructTypeValue) -> bool {
+        if self.name == other.name {
+            return true;
+        }
+
+        match &self.parent {
+            Some(parent) => {
+                let parent = borrow_value(parent);
+                let parent = downcast_value::<StructTypeValue>(&parent).unwrap();
+                parent.is_subtype_of(other)
+            }
+            None => false,
+        }
+    }
+
+    // Derived struct types use the printer of their parent unless they
+    // define their own
+    pub fn find_printer(&self) -> Option<ValueRef> {
+        match (&self.printer, &self.parent) {
+            (Some(printer), _) => Some(printer.clone()),
+            (None, Some(parent)) => {
+                let parent = borrow_value(parent);
+                downcast_value::<StructTypeValue>(&parent).and_then(|parent| parent.find_printer())
+            }
+            (None, None) => None,
         }
     }
 }
@@ -443,7 +729,11 @@ impl Callable for CreateStructValue {
         let struct_type = struct_type.unwrap();
 
         if args.len() != struct_type.fields.len() {
-            return error("Number of arguments differs from number of fields");
+            return Err(InterpreterError::wrong_arity(
+                &format!("create-{}", struct_type.name),
+                &describe_arities(&[struct_type.fields.len()]),
+                args.len(),
+            ));
         }
 
         let mut values = HashMap::new();
@@ -459,6 +749,12 @@ impl Callable for CreateStructValue {
 
         Ok(new_valueref(StructValue::new(&self.struct_type, values)))
     }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        let struct_type = borrow_value(&self.struct_type);
+        let struct_type = downcast_value::<StructTypeValue>(&struct_type)?;
+        Some((vec![struct_type.fields.len()], false))
+    }
 }
 
 pub struct IsStructType {
@@ -498,7 +794,7 @@ impl Callable for IsStructType {
             let arg_type = downcast_value::<StructTypeValue>(&arg_type);
             match arg_type {
                 Some(arg_type) => Ok(new_valueref(BoolValue {
-                    value: arg_type.name == struct_type.name,
+                    value: arg_type.is_subtype_of(struct_type),
                 })),
                 None => Ok(new_valueref(BoolValue { value: false })),
             }
@@ -506,6 +802,10 @@ impl Callable for IsStructType {
             Ok(new_valueref(BoolValue { value: false }))
         }
     }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
 }
 
 pub struct GetStructField {
@@ -518,15 +818,9 @@ impl GetStructField {
             field: field.to_string(),
         }
     }
-}
 
-impl Callable for GetStructField {
-    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        if args.len() != 1 {
-            return error("getter function expects exactly one argument");
-        }
-
-        let struct_value = borrow_value(&args[0]);
+    fn get_field(&self, value: &ValueRef) -> EvalResult {
+        let struct_value = borrow_value(value);
         let struct_value = downcast_value::<StructValue>(&struct_value);
         if struct_value.is_none() {
             return error("getter function expects a struct");
@@ -541,6 +835,52 @@ impl Callable for GetStructField {
     }
 }
 
+impl Callable for GetStructField {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.len() != 1 {
+            return error("getter function expects exactly one argument");
+        }
+
+        let value_type = borrow_value(&args[0]).get_type();
+
+        match value_type {
+            ValueType::Vector => {
+                let vector = borrow_value(&args[0]);
+                let vector = downcast_value::<VectorValue>(&vector).unwrap();
+                let mut elements = vec![];
+                for element in &vector.elements {
+                    elements.push(self.get_field(element)?);
+                }
+                Ok(new_valueref(VectorValue { elements }))
+            }
+            ValueType::Pair => {
+                let pair = borrow_value(&args[0]);
+                let pair = downcast_value::<PairValue>(&pair).unwrap();
+                if !pair.is_list() {
+                    return error("getter function expects a struct or a list of structs");
+                }
+                let mut result = new_valueref(NilValue {});
+                for element in pair.get_elements().iter().rev() {
+                    result = new_valueref(PairValue::new(&self.get_field(element)?, &result));
+                }
+                Ok(result)
+            }
+            ValueType::Nil => Ok(args[0].clone()),
+            ValueType::Stream => {
+                let getter: Rc<dyn Callable> = Rc::new(GetStructField::new(&self.field));
+                let getter = new_valueref(BuiltInFunctionValue::new(&self.field, &getter));
+                let mapped = stream::StreamValue::new_mapped(getter, vec![args[0].clone()])?;
+                Ok(new_valueref(mapped))
+            }
+            _ => self.get_field(&args[0]),
+        }
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![1], false))
+    }
+}
+
 pub struct SetStructField {
     field: String,
 }
@@ -559,6 +899,7 @@ impl Callable for SetStructField {
             return error("set-struct function expects exactly two arguments");
         }
 
+        check_mutable(&args[0], "set-struct")?;
         let mut struct_value = borrow_mut_value(&args[0]);
         let struct_value = struct_value.as_any_mut().downcast_mut::<StructValue>();
         if struct_value.is_none() {
@@ -572,19 +913,89 @@ impl Callable for SetStructField {
             }),
             value: args[1].clone(),
         };
-        struct_value.values.insert(self.field.clone(), new_entry);
+        match struct_value.values.insert(self.field.clone(), new_entry) {
+            Some(old_entry) => Ok(old_entry.value),
+            None => Ok(new_valueref(NilValue {})),
+        }
+    }
 
-        Ok(new_valueref(NilValue {}))
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some((vec![2], false))
     }
 }
 
-#[derive(Debug)]
+pub struct GenericFunction {
+    name: String,
+    // The methods are looked up in the defining environment, which holds
+    // the generic function itself
+    env: WeakEnvironmentRef,
+}
+
+impl GenericFunction {
+    pub fn new(name: &str, env: &EnvironmentRef) -> Self {
+        Self {
+            name: name.to_string(),
+            env: Rc::downgrade(env),
+        }
+    }
+
+    fn find_method(&self, env: &EnvironmentRef, struct_type: &ValueRef) -> Option<ValueRef> {
+        let struct_type = borrow_value(struct_type);
+        let struct_type = downcast_value::<StructTypeValue>(&struct_type)?;
+
+        if let Some(method) = env.borrow().get_method(&self.name, &struct_type.name) {
+            return Some(method);
+        }
+
+        match &struct_type.parent {
+            Some(parent) => self.find_method(env, parent),
+            None => None,
+        }
+    }
+}
+
+impl Callable for GenericFunction {
+    fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
+        if args.is_empty() {
+            return error(&format!("{} expects at least one argument", self.name));
+        }
+
+        let struct_type = {
+            let arg0 = borrow_value(&args[0]);
+            match downcast_value::<StructValue>(&arg0) {
+                Some(StructValue {
+                    struct_type: Some(struct_type),
+                    ..
+                }) => struct_type.clone(),
+                _ => return error(&format!("{} expects a struct as first argument", self.name)),
+            }
+        };
+
+        let env = upgrade_env(&self.env)?;
+        let method = match self.find_method(&env, &struct_type) {
+            Some(method) => method,
+            None => {
+                return error(&format!(
+                    "no method {} defined for {}",
+                    self.name,
+                    borrow_value(&struct_type)
+                ))
+            }
+        };
+
+        let method = borrow_value(&method);
+        let method = downcast_value::<LambdaValue>(&method).unwrap();
+        method.call(args)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct StructEntry {
     pub key: ValueRef,
     pub value: ValueRef,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StructValue {
     pub struct_type: Option<ValueRef>,
     pub values: HashMap<String, StructEntry>,
@@ -615,6 +1026,10 @@ impl StructValue {
             is_set: true,
         }
     }
+
+    pub fn is_set(&self) -> bool {
+        self.is_set
+    }
 }
 
 impl Value for StructValue {
@@ -631,10 +1046,50 @@ impl Value for StructValue {
     }
 }
 
+thread_local! {
+    static PRINTING: RefCell<Vec<Vec<*const ()>>> = const { RefCell::new(Vec::new()) };
+}
+
+impl StructValue {
+    // Applies the printer registered with def-struct-printer. The printer
+    // gets a copy of the struct that shares the field values, a struct that is
+    // printed while its printer runs is shown in the default format.
+    fn print_custom(&self, struct_type: &ValueRef) -> Option<String> {
+        let printer =
+            downcast_value::<StructTypeValue>(&borrow_value(struct_type))?.find_printer()?;
+
+        let mut identity = self
+            .values
+            .values()
+            .map(|entry| Rc::as_ptr(&entry.value) as *const ())
+            .collect::<Vec<_>>();
+        identity.sort();
+        if PRINTING.with(|printing| printing.borrow().contains(&identity)) {
+            return None;
+        }
+
+        PRINTING.with(|printing| printing.borrow_mut().push(identity));
+        let text = call_value(&printer, &[new_valueref(self.clone())])
+            .ok()
+            .map(|result| {
+                let result = borrow_value(&result);
+                match downcast_value::<StrValue>(&result) {
+                    Some(text) => text.value.to_string(),
+                    None => result.to_string(),
+                }
+            });
+        PRINTING.with(|printing| printing.borrow_mut().pop());
+        text
+    }
+}
+
 impl Display for StructValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.struct_type {
             Some(struct_type) => {
+                if let Some(text) = self.print_custom(struct_type) {
+                    return write!(f, "{}", text);
+                }
                 let struct_type = borrow_value(struct_type);
                 let struct_type = downcast_value::<StructTypeValue>(&struct_type).unwrap();
                 let values_str = &struct_type
@@ -689,6 +1144,23 @@ pub fn error(message: &str) -> EvalResult {
 
 pub trait Callable {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult;
+
+    // Built-in functions that act on the environment of their caller (like
+    // current-environment) get it passed when they are called from code
+    fn call_in_environment(&self, args: &Vec<ValueRef>, _env: &EnvironmentRef) -> EvalResult {
+        self.call(args)
+    }
+
+    // Parameter objects are built-in functions that parameterize can rebind
+    fn as_parameter(&self) -> Option<&Parameter> {
+        None
+    }
+
+    // Numbers of arguments the function accepts in ascending order and
+    // whether it accepts more than the last of them, if known
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        None
+    }
 }
 
 pub trait Countable {
@@ -699,6 +1171,27 @@ pub trait ComparableEq {
     fn is_equal(&self, other: &ValueRef) -> bool;
 }
 
+pub trait ComparableOrd {
+    // None if the values cannot be compared
+    fn compare(&self, other: &ValueRef) -> Option<Ordering>;
+}
+
+pub fn compare_values(a: &ValueRef, b: &ValueRef) -> Option<Ordering> {
+    borrow_value(a).as_comparable_ord()?.compare(b)
+}
+
+// Sequences are ordered lexicographically
+fn compare_elements(a: &[ValueRef], b: &[ValueRef]) -> Option<Ordering> {
+    for (a, b) in a.iter().zip(b.iter()) {
+        match compare_values(a, b)? {
+            Ordering::Equal => continue,
+            ordering => return Some(ordering),
+        }
+    }
+    Some(a.len().cmp(&b.len()))
+}
+
+#[derive(Clone)]
 pub enum LambdaVariant {
     Arities(HashMap<usize, (Vec<String>, AstRef)>),
     Variadic(Vec<String>, String, AstRef),
@@ -723,6 +1216,11 @@ pub struct LambdaValue {
     pub name: Option<String>,
     pub variant: LambdaVariant,
     pub env: Rc<RefCell<Environment>>,
+    // Type annotations by number of (non variadic) parameters
+    pub signatures: HashMap<usize, Signature>,
+    // Parameters that need no binding by name (see ast::Lambda), by number
+    // of (non variadic) parameters
+    pub slot_only: HashMap<usize, Vec<bool>>,
 }
 
 impl LambdaValue {
@@ -733,21 +1231,73 @@ impl LambdaValue {
         body: &AstRef,
         env: &Rc<RefCell<Environment>>,
     ) -> Self {
+        register_environment(env);
         Self {
             name: name.clone(),
             variant: LambdaVariant::new(&parameters, &variadic, body),
             env: env.clone(),
+            signatures: HashMap::new(),
+            slot_only: HashMap::new(),
+        }
+    }
+
+    pub fn with_signature(mut self, signature: &Option<Signature>) -> Self {
+        if let Some(signature) = signature {
+            let num_parameters = self.num_parameters();
+            self.signatures.insert(num_parameters, signature.clone());
+        }
+        self
+    }
+
+    pub fn with_slot_only(mut self, slot_only: &[bool]) -> Self {
+        if !slot_only.is_empty() {
+            let num_parameters = self.num_parameters();
+            self.slot_only.insert(num_parameters, slot_only.to_vec());
+        }
+        self
+    }
+
+    // The same function bound to another environment
+    pub fn with_env(&self, env: &EnvironmentRef) -> Self {
+        register_environment(env);
+        Self {
+            name: self.name.clone(),
+            variant: self.variant.clone(),
+            env: env.clone(),
+            signatures: self.signatures.clone(),
+            slot_only: self.slot_only.clone(),
+        }
+    }
+
+    fn num_parameters(&self) -> usize {
+        match &self.variant {
+            LambdaVariant::Arities(arities) => *arities.keys().next().unwrap(),
+            LambdaVariant::Variadic(parameters, _, _) => parameters.len(),
         }
     }
 
-    pub fn merge_lambda(&mut self, other: &LambdaValue) -> Result<(), InterpreterError> {
+    // Returns the arities whose previous definition is replaced by other
+    pub fn merge_lambda(&mut self, other: &LambdaValue) -> Result<Vec<usize>, InterpreterError> {
         match &mut self.variant {
             LambdaVariant::Arities(arities) => match &other.variant {
                 LambdaVariant::Arities(other_arities) => {
+                    let mut replaced = Vec::new();
                     for (arity, (parameters, body)) in other_arities {
-                        arities.insert(*arity, (parameters.clone(), body.clone()));
+                        if arities
+                            .insert(*arity, (parameters.clone(), body.clone()))
+                            .is_some()
+                        {
+                            replaced.push(*arity);
+                        }
+                    }
+                    for (arity, signature) in &other.signatures {
+                        self.signatures.insert(*arity, signature.clone());
+                    }
+                    for (arity, slot_only) in &other.slot_only {
+                        self.slot_only.insert(*arity, slot_only.clone());
                     }
-                    Ok(())
+                    replaced.sort();
+                    Ok(replaced)
                 }
                 LambdaVariant::Variadic(_, _, _) => {
                     Err(InterpreterError::new("Arities already defined"))
@@ -759,6 +1309,42 @@ impl LambdaValue {
         }
     }
 
+    // Numbers of arguments the lambda accepts in ascending order. A variadic
+    // lambda accepts its number of parameters or more arguments.
+    pub fn arities(&self) -> (Vec<usize>, bool) {
+        match &self.variant {
+            LambdaVariant::Arities(arities) => {
+                let mut arities: Vec<usize> = arities.keys().copied().collect();
+                arities.sort();
+                (arities, false)
+            }
+            LambdaVariant::Variadic(parameters, _, _) => (vec![parameters.len()], true),
+        }
+    }
+
+    // Describes the definitions that a definition of other under the same
+    // name replaces, if any. Variadic lambdas cannot be merged and replace
+    // all existing definitions.
+    pub fn replaced_definitions(&self, other: &LambdaValue) -> Option<String> {
+        let (arities, variadic) = self.arities();
+        let (other_arities, other_variadic) = other.arities();
+        if variadic || other_variadic {
+            return Some(describe_lambda_arities(&arities, variadic));
+        }
+        let replaced = other_arities
+            .into_iter()
+            .filter(|arity| arities.contains(arity))
+            .collect::<Vec<_>>();
+        (!replaced.is_empty()).then(|| describe_arities(&replaced))
+    }
+
+    fn arity_error(&self, num_args: usize) -> InterpreterError {
+        let (arities, variadic) = self.arities();
+        let expected = describe_lambda_arities(&arities, variadic);
+        let name = self.name.as_deref().unwrap_or("lambda");
+        InterpreterError::wrong_arity(name, &expected, num_args)
+    }
+
     fn init_call_env(
         &self,
         args: &Vec<ValueRef>,
@@ -768,9 +1354,7 @@ impl LambdaValue {
                 let arity_variant = arities.get(&args.len());
                 match arity_variant {
                     Some((parameters, _)) => self.init_call_env_with_params(parameters, None, args),
-                    None => Err(InterpreterError::new(
-                        "Number of arguments differs from number of parameters",
-                    )),
+                    None => Err(self.arity_error(args.len())),
                 }
             }
             LambdaVariant::Variadic(parameters, variadic, _) => {
@@ -789,17 +1373,20 @@ impl LambdaValue {
         let num_params = parameters.len();
 
         if variadic.is_some() && num_args < num_params {
-            return Err(InterpreterError::new(
-                "Number of arguments differs from number of parameters",
-            ));
+            return Err(self.arity_error(num_args));
         }
 
         let call_env = EnvironmentBuilder::new().parent(&self.env.clone()).build();
+        let slot_only = self.slot_only.get(&num_params);
+        let is_slot_only =
+            |slot: usize| slot_only.is_some_and(|flags| flags.get(slot) == Some(&true));
 
         for (i, param) in parameters.iter().enumerate() {
-            call_env
-                .borrow_mut()
-                .set(param.to_string(), args[i].clone());
+            let mut call_env = call_env.borrow_mut();
+            if !is_slot_only(i) {
+                call_env.set(param.to_string(), args[i].clone());
+            }
+            call_env.push_slot(&args[i]);
         }
 
         if let Some(var_param) = &variadic {
@@ -813,23 +1400,63 @@ impl LambdaValue {
             } else {
                 VectorValue { elements: vec![] }
             };
-            call_env
-                .borrow_mut()
-                .set(var_param.to_string(), new_valueref(arg_list));
+            let arg_list = new_valueref(arg_list);
+            let mut call_env = call_env.borrow_mut();
+            if !is_slot_only(num_params) {
+                call_env.set(var_param.to_string(), arg_list.clone());
+            }
+            call_env.push_slot(&arg_list);
         }
 
         Ok(call_env)
     }
 
+    fn check_arguments(&self, args: &[ValueRef]) -> Result<(), InterpreterError> {
+        let (parameters, variadic) = match &self.variant {
+            LambdaVariant::Arities(arities) => match arities.get(&args.len()) {
+                Some((parameters, _)) => (parameters, None),
+                None => return Ok(()),
+            },
+            LambdaVariant::Variadic(parameters, variadic, _) => {
+                (parameters, Some(variadic.clone()))
+            }
+        };
+        match self.signatures.get(&parameters.len()) {
+            Some(signature) => check_arguments(
+                self.name.as_deref().unwrap_or("lambda"),
+                parameters,
+                &variadic,
+                signature,
+                args,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn check_result(&self, num_args: usize, result: &ValueRef) -> Result<(), InterpreterError> {
+        // Tail calls are checked when the called function returns
+        if result.borrow().get_type() == ValueType::TailCall {
+            return Ok(());
+        }
+        let num_parameters = match &self.variant {
+            LambdaVariant::Arities(_) => num_args,
+            LambdaVariant::Variadic(parameters, _, _) => parameters.len(),
+        };
+        match self.signatures.get(&num_parameters) {
+            Some(signature) => {
+                check_result(self.name.as_deref().unwrap_or("lambda"), signature, result)
+            }
+            None => Ok(()),
+        }
+    }
+
     fn get_body(&self, num_args: usize) -> Result<AstRef, InterpreterError> {
         match &self.variant {
             LambdaVariant::Arities(arities) => {
                 let arity_variant = arities.get(&num_args);
                 match arity_variant {
                     Some((_, body)) => Ok(body.clone()),
-                    None => Err(InterpreterError::new(
-                        "Number of arguments differs from number of parameters",
-                    )),
+                    None => Err(self.arity_error(num_args)),
                 }
             }
             LambdaVariant::Variadic(_, _, body) => Ok(body.clone()),
@@ -877,8 +1504,18 @@ impl Callable for LambdaValue {
         let mut interpreter = Interpreter::with_environment(&call_env);
 
         let body = self.get_body(args.len())?;
-        body.borrow().accept(&mut interpreter);
-        interpreter.stack.pop().unwrap()
+        if self.signatures.is_empty() || !call_env.borrow().get_settings().contracts {
+            return interpreter.eval_ast(&body);
+        }
+
+        self.check_arguments(args)?;
+        let result = interpreter.eval_ast(&body)?;
+        self.check_result(args.len(), &result)?;
+        Ok(result)
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        Some(self.arities())
     }
 }
 
@@ -924,7 +1561,17 @@ impl Value for BuiltInFunctionValue {
 
 impl Callable for BuiltInFunctionValue {
     fn call(&self, args: &Vec<ValueRef>) -> EvalResult {
-        self.function.call(args)
+        record_builtin_call(&self.name);
+        catch_panic(|| self.function.call(args))
+    }
+
+    fn call_in_environment(&self, args: &Vec<ValueRef>, env: &EnvironmentRef) -> EvalResult {
+        record_builtin_call(&self.name);
+        catch_panic(|| self.function.call_in_environment(args, env))
+    }
+
+    fn known_arities(&self) -> Option<(Vec<usize>, bool)> {
+        self.function.known_arities()
     }
 }
 
@@ -988,22 +1635,253 @@ impl Debug for SpreadValue {
     }
 }
 
-#[derive(Debug)]
-pub struct InterpreterError {
+// An error that was caught, so that BOLI code can inspect it
+pub struct ErrorValue {
+    pub error: InterpreterError,
+}
+
+impl ErrorValue {
+    pub fn new(error: InterpreterError) -> Self {
+        Self { error }
+    }
+}
+
+impl Value for ErrorValue {
+    fn get_type(&self) -> ValueType {
+        ValueType::Error
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Display for ErrorValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<error: {}>", self.error)
+    }
+}
+
+impl Debug for ErrorValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<error: {}>", self.error)
+    }
+}
+
+// Returned by read-line when the input is exhausted
+pub struct EofValue {}
+
+impl Value for EofValue {
+    fn get_type(&self) -> ValueType {
+        ValueType::Eof
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Display for EofValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<eof>")
+    }
+}
+
+impl Debug for EofValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<eof>")
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ErrorKind {
+    Eval,
+    Syntax,
+    Type,
+    Arity,
+    Name,
+    Io,
+    Arithmetic,
+    UserRaised,
+    Internal,
+    // Raised by exit, error handlers let it pass so that it ends the program
+    Exit(i32),
+}
+
+impl ErrorKind {
+    // Name of the symbol that error-kind returns
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Eval => "eval-error",
+            ErrorKind::Syntax => "syntax-error",
+            ErrorKind::Type => "type-error",
+            ErrorKind::Arity => "arity-error",
+            ErrorKind::Name => "name-error",
+            ErrorKind::Io => "io-error",
+            ErrorKind::Arithmetic => "arithmetic-error",
+            ErrorKind::UserRaised => "user-error",
+            ErrorKind::Internal => "internal-error",
+            ErrorKind::Exit(_) => "exit",
+        }
+    }
+}
+
+// The data is boxed so that EvalResult stays small, every nested call
+// keeps results on the stack
+#[derive(Debug, Clone)]
+pub struct InterpreterError(Box<ErrorData>);
+
+#[derive(Debug, Clone)]
+pub struct ErrorData {
     pub message: String,
+    pub kind: ErrorKind,
+    // The value that caused the error, e.g. an argument of the wrong type
+    pub value: Option<ValueRef>,
+    pub span: Option<Span>,
+}
+
+impl Deref for InterpreterError {
+    type Target = ErrorData;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for InterpreterError {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
 }
 
 impl InterpreterError {
     pub fn new(message: &str) -> Self {
-        Self {
+        Self::with_kind(ErrorKind::Eval, message)
+    }
+
+    pub fn with_kind(kind: ErrorKind, message: &str) -> Self {
+        Self(Box::new(ErrorData {
             message: message.to_string(),
+            kind,
+            value: None,
+            span: None,
+        }))
+    }
+
+    pub fn internal(message: &str) -> Self {
+        Self::with_kind(ErrorKind::Internal, message)
+    }
+
+    pub fn syntax_error(err: &ParseError) -> Self {
+        Self::with_kind(ErrorKind::Syntax, &err.message).span(err.span())
+    }
+
+    pub fn type_error(message: &str, value: &ValueRef) -> Self {
+        Self::with_kind(ErrorKind::Type, message).value(value)
+    }
+
+    pub fn arity_error(message: &str) -> Self {
+        Self::with_kind(ErrorKind::Arity, message)
+    }
+
+    pub fn name_error(name: &str) -> Self {
+        Self::with_kind(ErrorKind::Name, &format!("Undefined identifier: {}", name))
+    }
+
+    pub fn wrong_arity(function_name: &str, expected: &str, actual: usize) -> Self {
+        Self::arity_error(&format!(
+            "{} expects {}, got {}",
+            function_name, expected, actual
+        ))
+    }
+
+    pub fn io_error(message: &str) -> Self {
+        Self::with_kind(ErrorKind::Io, message)
+    }
+
+    pub fn division_by_zero() -> Self {
+        Self::with_kind(ErrorKind::Arithmetic, "division by zero")
+    }
+
+    pub fn user_raised(message: &str) -> Self {
+        Self::with_kind(ErrorKind::UserRaised, message)
+    }
+
+    pub fn exit(code: i32) -> Self {
+        Self::with_kind(ErrorKind::Exit(code), &format!("exit with code {}", code))
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        match self.kind {
+            ErrorKind::Exit(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    pub fn value(mut self, value: &ValueRef) -> Self {
+        self.value = Some(value.clone());
+        self
+    }
+
+    pub fn span(mut self, span: Option<Span>) -> Self {
+        self.span = span;
+        self
+    }
+
+    // The message prefixed by the kind of the error
+    pub fn headline(&self) -> String {
+        match self.kind {
+            ErrorKind::Eval | ErrorKind::UserRaised => self.message.clone(),
+            ErrorKind::Syntax => format!("SyntaxError: {}", self.message),
+            ErrorKind::Type => format!("TypeError: {}", self.message),
+            ErrorKind::Arity => format!("ArityError: {}", self.message),
+            ErrorKind::Name => format!("NameError: {}", self.message),
+            ErrorKind::Io => format!("IOError: {}", self.message),
+            ErrorKind::Arithmetic => format!("ArithmeticError: {}", self.message),
+            ErrorKind::Internal => format!("Internal error: {}", self.message),
+            ErrorKind::Exit(_) => self.message.clone(),
         }
     }
 }
 
+// Describes the supported numbers of arguments, e.g. "1 or 3 arguments"
+fn describe_lambda_arities(arities: &[usize], variadic: bool) -> String {
+    if variadic {
+        format!("at least {}", describe_arities(arities))
+    } else {
+        describe_arities(arities)
+    }
+}
+
+pub fn describe_arities(arities: &[usize]) -> String {
+    let numbers: Vec<String> = arities.iter().map(|arity| arity.to_string()).collect();
+    let numbers = match numbers.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, others)) => format!("{} or {}", others.join(", "), last),
+        None => "no".to_string(),
+    };
+    if arities == [1] {
+        format!("{} argument", numbers)
+    } else {
+        format!("{} arguments", numbers)
+    }
+}
+
 impl std::fmt::Display for InterpreterError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}", self.headline())?;
+        if let Some(span) = &self.span {
+            write!(f, " (line {}, column {})", span.line, span.column)?;
+        }
+        Ok(())
     }
 }
 
@@ -1012,3 +1890,24 @@ impl Error for InterpreterError {
         &self.message
     }
 }
+
+#[cfg(feature = "catch-panics")]
+pub fn catch_panic<F: FnOnce() -> EvalResult>(f: F) -> EvalResult {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = if let Some(msg) = payload.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            // Other payloads are used for control flow, e.g. to unwind
+            // the stack of a generator that is dropped while suspended
+            std::panic::resume_unwind(payload)
+        };
+        Err(InterpreterError::internal(&message))
+    })
+}
+
+#[cfg(not(feature = "catch-panics"))]
+pub fn catch_panic<F: FnOnce() -> EvalResult>(f: F) -> EvalResult {
+    f()
+}
@@ -1,11 +1,61 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+
 use super::*;
+use crate::frontend::lexer::number_format::number_format;
+
+// Exact numbers are compared exactly, as soon as a real number is involved
+// the comparison is done with floating point numbers
+fn compare_numbers(a: &dyn Value, b: &dyn Value) -> Option<Ordering> {
+    match (exact_parts(a), exact_parts(b)) {
+        (Some((n1, d1)), Some((n2, d2))) => {
+            let ordering = (n1 * d2).cmp(&(n2 * d1));
+            Some(if d1 * d2 < 0 {
+                ordering.reverse()
+            } else {
+                ordering
+            })
+        }
+        _ => to_float(a)?.partial_cmp(&to_float(b)?),
+    }
+}
+
+fn exact_parts(value: &dyn Value) -> Option<(i128, i128)> {
+    if let Some(int) = value.as_any().downcast_ref::<IntValue>() {
+        return Some((int.value as i128, 1));
+    }
+    value
+        .as_any()
+        .downcast_ref::<RationalValue>()
+        .map(|rational| (rational.numerator as i128, rational.denominator as i128))
+}
+
+fn to_float(value: &dyn Value) -> Option<f64> {
+    match exact_parts(value) {
+        Some((numerator, denominator)) => Some(numerator as f64 / denominator as f64),
+        None => value
+            .as_any()
+            .downcast_ref::<RealValue>()
+            .map(|real| real.value),
+    }
+}
 
 #[derive(Debug)]
 pub struct IntValue {
     pub value: i64,
 }
 
+impl ComparableOrd for IntValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        compare_numbers(self, &*borrow_value(other))
+    }
+}
+
 impl Value for IntValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Int
     }
@@ -74,7 +124,17 @@ impl RationalValue {
     }
 }
 
+impl ComparableOrd for RationalValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        compare_numbers(self, &*borrow_value(other))
+    }
+}
+
 impl Value for RationalValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Rational
     }
@@ -102,7 +162,17 @@ pub struct RealValue {
     pub value: f64,
 }
 
+impl ComparableOrd for RealValue {
+    fn compare(&self, other: &ValueRef) -> Option<Ordering> {
+        compare_numbers(self, &*borrow_value(other))
+    }
+}
+
 impl Value for RealValue {
+    fn as_comparable_ord(&self) -> Option<&dyn ComparableOrd> {
+        Some(self)
+    }
+
     fn get_type(&self) -> ValueType {
         ValueType::Real
     }
@@ -118,7 +188,81 @@ impl Value for RealValue {
 
 impl Display for RealValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let value_str = format!("{:?}", self.value).replace(".", ",");
-        write!(f, "{value_str}")
+        write!(f, "{}", real_format().format(self.value))
+    }
+}
+
+// How real numbers are converted to strings. By default all significant
+// digits are shown and the separator is the one of the number format.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RealFormat {
+    // Number of decimal places
+    pub precision: Option<usize>,
+    pub decimal_separator: Option<char>,
+    // Numbers with at least this absolute value use scientific notation
+    pub scientific_threshold: Option<f64>,
+}
+
+impl RealFormat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
     }
+
+    pub fn decimal_separator(mut self, decimal_separator: char) -> Self {
+        self.decimal_separator = Some(decimal_separator);
+        self
+    }
+
+    pub fn scientific_threshold(mut self, scientific_threshold: f64) -> Self {
+        self.scientific_threshold = Some(scientific_threshold);
+        self
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        let scientific = self
+            .scientific_threshold
+            .is_some_and(|threshold| value.is_finite() && value.abs() >= threshold);
+        let text = match (scientific, self.precision) {
+            (true, Some(precision)) => format!("{:.*e}", precision, value),
+            (true, None) => format!("{:e}", value),
+            (false, Some(precision)) => format!("{:.*}", precision, value),
+            (false, None) => format!("{:?}", value),
+        };
+        let decimal_separator = self
+            .decimal_separator
+            .unwrap_or(number_format().decimal_separator());
+        text.replace('.', &decimal_separator.to_string())
+    }
+}
+
+thread_local! {
+    static REAL_FORMAT: Cell<RealFormat> = Cell::new(RealFormat::new());
+}
+
+pub fn real_format() -> RealFormat {
+    REAL_FORMAT.with(|format| format.get())
+}
+
+struct ActiveRealFormat {
+    previous: RealFormat,
+}
+
+impl Drop for ActiveRealFormat {
+    fn drop(&mut self) {
+        REAL_FORMAT.with(|current| current.set(self.previous));
+    }
+}
+
+// Runs f with the real format of an interpreter. Real numbers have no access
+// to the environment when they are displayed, so the format is installed for
+// the duration of an evaluation and restored afterwards.
+pub fn with_real_format<T, F: FnOnce() -> T>(format: RealFormat, f: F) -> T {
+    let previous = REAL_FORMAT.with(|current| current.replace(format));
+    let _active_format = ActiveRealFormat { previous };
+    f()
 }
@@ -0,0 +1,113 @@
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, Range};
+use std::rc::Rc;
+
+// Immutable text that is shared by its clones. A substring refers to the
+// text it was taken from instead of copying it, which keeps the whole text
+// alive as long as the substring is.
+#[derive(Clone)]
+pub struct SharedStr {
+    text: Rc<str>,
+    start: usize,
+    end: usize,
+}
+
+impl SharedStr {
+    // The range is given in bytes relative to this string and has to lie on
+    // character boundaries
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(self.is_char_boundary(range.start) && self.is_char_boundary(range.end));
+        assert!(range.start <= range.end);
+        Self {
+            text: self.text.clone(),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        }
+    }
+}
+
+impl Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text[self.start..self.end]
+    }
+}
+
+impl From<Rc<str>> for SharedStr {
+    fn from(text: Rc<str>) -> Self {
+        let end = text.len();
+        Self {
+            text,
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl From<&str> for SharedStr {
+    fn from(text: &str) -> Self {
+        Rc::<str>::from(text).into()
+    }
+}
+
+impl From<String> for SharedStr {
+    fn from(text: String) -> Self {
+        Rc::<str>::from(text).into()
+    }
+}
+
+impl Display for SharedStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+impl Debug for SharedStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl PartialEq for SharedStr {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for SharedStr {}
+
+impl PartialOrd for SharedStr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SharedStr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl Hash for SharedStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slices_share_the_text() {
+        let text = SharedStr::from("äbcdef");
+        let sub = text.slice(2..5);
+        assert_eq!(&*sub, "bcd");
+        assert_eq!(&*sub.slice(1..3), "cd");
+        assert!(Rc::ptr_eq(&text.text, &sub.text));
+        assert_eq!(sub, SharedStr::from("bcd"));
+    }
+}
@@ -1,5 +1,6 @@
 use numbers::IntValue;
 
+use crate::interpreter::cancellation::is_cancelled;
 use crate::interpreter::misc_functions::is_truthy;
 
 use super::*;
@@ -33,6 +34,109 @@ pub enum StreamValue {
         sequence: ValueRef,
         initial: bool,
     },
+    Buffered {
+        source: Rc<RefCell<BufferedSource>>,
+        index: usize,
+    },
+    Cons {
+        head: Option<ValueRef>,
+        tail: Rc<RefCell<DelayedTail>>,
+    },
+    Taken {
+        remaining: usize,
+        sequence: ValueRef,
+    },
+    TakenWhile {
+        predicate_func: ValueRef,
+        sequence: ValueRef,
+        done: bool,
+    },
+    Interleaved {
+        sequences: Vec<ValueRef>,
+        index: usize,
+    },
+    Cycled {
+        original: ValueRef,
+        current: ValueRef,
+    },
+    Repeated {
+        value: ValueRef,
+        remaining: Option<usize>,
+    },
+    Flattened {
+        sequence: ValueRef,
+        nested: Vec<ValueRef>,
+        deep: bool,
+    },
+}
+
+#[derive(Debug)]
+pub struct DelayedTail {
+    thunk: Option<ValueRef>,
+    stream: Option<ValueRef>,
+    // An error of the thunk is returned on every access of the tail
+    error: Option<InterpreterError>,
+}
+
+impl DelayedTail {
+    // The thunk is taken out before it is called, so a tail that refers
+    // back to its own stream sees an empty remainder instead of a borrow panic
+    fn force(tail: &Rc<RefCell<DelayedTail>>) -> Result<Option<ValueRef>, InterpreterError> {
+        let thunk = tail.borrow_mut().thunk.take();
+        if let Some(thunk) = thunk {
+            match call_value(&thunk, &[]) {
+                Ok(stream) if stream.borrow().get_type() == ValueType::Stream => {
+                    tail.borrow_mut().stream = Some(stream)
+                }
+                Ok(_) => {}
+                Err(err) => tail.borrow_mut().error = Some(err),
+            }
+        }
+        let tail = tail.borrow();
+        match &tail.error {
+            Some(err) => Err(err.clone()),
+            None => Ok(tail.stream.clone()),
+        }
+    }
+}
+
+pub type SourceFn = Box<dyn FnMut() -> Result<Option<ValueRef>, InterpreterError>>;
+
+// The values of the source are computed once and shared by all copies of
+// the stream. An error of the source ends it and is returned on every
+// later access.
+pub struct BufferedSource {
+    values: Vec<ValueRef>,
+    next: Option<SourceFn>,
+    error: Option<InterpreterError>,
+}
+
+impl BufferedSource {
+    fn get(&mut self, index: usize) -> Result<Option<ValueRef>, InterpreterError> {
+        while self.values.len() <= index {
+            let Some(next) = self.next.as_mut() else {
+                return match &self.error {
+                    Some(err) => Err(err.clone()),
+                    None => Ok(None),
+                };
+            };
+            match next() {
+                Ok(Some(value)) => self.values.push(value),
+                Ok(None) => self.next = None,
+                Err(err) => {
+                    self.next = None;
+                    self.error = Some(err);
+                }
+            }
+        }
+        Ok(Some(self.values[index].clone()))
+    }
+}
+
+impl Debug for BufferedSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<buffered source ({} values)>", self.values.len())
+    }
 }
 
 impl StreamValue {
@@ -49,6 +153,37 @@ impl StreamValue {
         })
     }
 
+    pub fn new_buffered(next: SourceFn) -> Self {
+        Self::Buffered {
+            source: Rc::new(RefCell::new(BufferedSource {
+                values: Vec::new(),
+                next: Some(next),
+                error: None,
+            })),
+            index: 0,
+        }
+    }
+
+    pub fn new_cons(head: ValueRef, tail_thunk: ValueRef) -> Result<Self, InterpreterError> {
+        if !matches!(
+            tail_thunk.borrow().get_type(),
+            ValueType::BuiltInFunction | ValueType::Lambda
+        ) {
+            return Err(InterpreterError::new(
+                "Cons stream requires a function to compute the tail.",
+            ));
+        }
+
+        Ok(Self::Cons {
+            head: Some(head),
+            tail: Rc::new(RefCell::new(DelayedTail {
+                thunk: Some(tail_thunk),
+                stream: None,
+                error: None,
+            })),
+        })
+    }
+
     pub fn new_iterator(next_func: ValueRef, start: ValueRef) -> Result<Self, InterpreterError> {
         if !matches!(
             next_func.borrow().get_type(),
@@ -157,7 +292,136 @@ impl StreamValue {
         })
     }
 
-    pub fn next_value(&mut self) -> Option<ValueRef> {
+    pub fn new_taken(n: ValueRef, sequence: ValueRef) -> Result<Self, InterpreterError> {
+        let remaining = match downcast_value::<IntValue>(&borrow_value(&n)) {
+            Some(n) if n.value >= 0 => n.value as usize,
+            _ => {
+                return Err(InterpreterError::new(
+                    "Taken stream requires a non-negative integer.",
+                ))
+            }
+        };
+
+        if sequence.borrow().get_type() != ValueType::Stream {
+            return Err(InterpreterError::new("Taken stream requires a stream."));
+        }
+
+        Ok(Self::Taken {
+            remaining,
+            sequence,
+        })
+    }
+
+    pub fn new_taken_while(
+        predicate_func: ValueRef,
+        sequence: ValueRef,
+    ) -> Result<Self, InterpreterError> {
+        if !matches!(
+            predicate_func.borrow().get_type(),
+            ValueType::BuiltInFunction | ValueType::Lambda
+        ) {
+            return Err(InterpreterError::new(
+                "TakenWhile stream requires a function as predicate.",
+            ));
+        }
+
+        if sequence.borrow().get_type() != ValueType::Stream {
+            return Err(InterpreterError::new(
+                "TakenWhile stream requires a stream.",
+            ));
+        }
+
+        Ok(Self::TakenWhile {
+            predicate_func,
+            sequence,
+            done: false,
+        })
+    }
+
+    pub fn new_interleaved(sequences: Vec<ValueRef>) -> Result<Self, InterpreterError> {
+        if !sequences
+            .iter()
+            .all(|sequence| sequence.borrow().get_type() == ValueType::Stream)
+        {
+            return Err(InterpreterError::new(
+                "Interleaved stream requires streams.",
+            ));
+        }
+
+        Ok(Self::Interleaved {
+            sequences,
+            index: 0,
+        })
+    }
+
+    pub fn new_cycled(sequence: ValueRef) -> Result<Self, InterpreterError> {
+        if sequence.borrow().get_type() != ValueType::Stream {
+            return Err(InterpreterError::new("Cycled stream requires a stream."));
+        }
+
+        Ok(Self::Cycled {
+            current: Self::clone_sequence(&sequence),
+            original: sequence,
+        })
+    }
+
+    pub fn new_repeated(value: ValueRef, n: Option<ValueRef>) -> Result<Self, InterpreterError> {
+        let remaining = match n {
+            Some(n) => match downcast_value::<IntValue>(&borrow_value(&n)) {
+                Some(n) if n.value >= 0 => Some(n.value as usize),
+                _ => {
+                    return Err(InterpreterError::new(
+                        "Repeated stream requires a non-negative integer.",
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        Ok(Self::Repeated { value, remaining })
+    }
+
+    pub fn new_flattened(sequence: ValueRef, deep: bool) -> Result<Self, InterpreterError> {
+        if sequence.borrow().get_type() != ValueType::Stream {
+            return Err(InterpreterError::new("Flattened stream requires a stream."));
+        }
+
+        Ok(Self::Flattened {
+            sequence,
+            nested: Vec::new(),
+            deep,
+        })
+    }
+
+    // Returns a stream over the elements of a nested collection. Nil counts as
+    // the empty list, strings and improper pairs are not flattened.
+    fn nested_stream(value: &ValueRef) -> Option<ValueRef> {
+        let borrowed = borrow_value(value);
+        let elements = match borrowed.get_type() {
+            ValueType::Stream => return Some(Self::clone_sequence(value)),
+            ValueType::Vector => return Self::new_list(value.clone()).ok().map(new_valueref),
+            ValueType::Nil => Vec::new(),
+            ValueType::Pair => {
+                let pair = downcast_value::<PairValue>(&borrowed).unwrap();
+                if !pair.is_list() {
+                    return None;
+                }
+                pair.get_elements()
+            }
+            _ => return None,
+        };
+        let vector = new_valueref(VectorValue { elements });
+
+        Self::new_list(vector).ok().map(new_valueref)
+    }
+
+    // Errors of the functions that compute the values end the stream with
+    // the error
+    pub fn next_value(&mut self) -> Result<Option<ValueRef>, InterpreterError> {
+        if is_cancelled() {
+            return Ok(None);
+        }
+
         match self {
             Self::Vector {
                 vector: list,
@@ -168,9 +432,9 @@ impl StreamValue {
                 if *index < list.elements.len() {
                     let value = list.elements.get(*index).unwrap();
                     *index += 1;
-                    Some(value.clone())
+                    Ok(Some(value.clone()))
                 } else {
-                    None
+                    Ok(None)
                 }
             }
             Self::Iterator {
@@ -178,148 +442,201 @@ impl StreamValue {
                 current,
                 next_func,
             } => {
-                let result = match borrow_value(current).get_type() {
-                    ValueType::Nil => return None,
-                    _ => Some(current.clone()),
-                };
-
-                let next_func = &borrow_value(next_func);
-                let callable: &dyn Callable = match next_func.get_type() {
-                    ValueType::BuiltInFunction => {
-                        downcast_value::<BuiltInFunctionValue>(next_func).unwrap()
-                    }
-                    ValueType::Lambda => downcast_value::<LambdaValue>(next_func).unwrap(),
-                    _ => unreachable!(),
-                };
-
-                *current = callable
-                    .call(&vec![current.clone()])
-                    .unwrap_or(new_valueref(NilValue {}));
+                if borrow_value(current).get_type() == ValueType::Nil {
+                    return Ok(None);
+                }
+                let result = current.clone();
+                *current = call_value(next_func, std::slice::from_ref(current))?;
 
-                result
+                Ok(Some(result))
             }
             Self::Filtered {
                 predicate_func,
                 sequence,
-            } => {
-                let pred = &borrow_value(predicate_func);
-                let pred: &dyn Callable = match pred.get_type() {
-                    ValueType::BuiltInFunction => {
-                        downcast_value::<BuiltInFunctionValue>(pred).unwrap()
-                    }
-                    ValueType::Lambda => downcast_value::<LambdaValue>(pred).unwrap(),
-                    _ => unreachable!(),
+            } => loop {
+                let Some(value) = Self::next_of(sequence)? else {
+                    return Ok(None);
                 };
-
-                let mut seq = borrow_mut_value(sequence);
-                let seq = seq.as_any_mut().downcast_mut::<StreamValue>().unwrap();
-
-                loop {
-                    if let Some(value) = seq.next_value() {
-                        let result = pred.call(&vec![value.clone()]);
-                        if result.is_err() {
-                            return None;
-                        }
-                        let result = result.unwrap();
-                        if is_truthy(&result) {
-                            return Some(value);
-                        }
-                    } else {
-                        return None;
-                    }
+                if is_truthy(&call_value(predicate_func, std::slice::from_ref(&value))?) {
+                    return Ok(Some(value));
                 }
-            }
+            },
             Self::Mapped {
                 map_func,
                 sequences,
             } => {
-                let func = &borrow_value(map_func);
-                let func: &dyn Callable = match func.get_type() {
-                    ValueType::BuiltInFunction => {
-                        downcast_value::<BuiltInFunctionValue>(func).unwrap()
-                    }
-                    ValueType::Lambda => downcast_value::<LambdaValue>(func).unwrap(),
-                    _ => unreachable!(),
-                };
-
                 let mut args = Vec::new();
                 for sequence in sequences {
-                    if let Some(value) = borrow_mut_value(sequence)
-                        .as_any_mut()
-                        .downcast_mut::<StreamValue>()
-                        .unwrap()
-                        .next_value()
-                    {
-                        args.push(value);
-                    } else {
-                        return None;
+                    match Self::next_of(sequence)? {
+                        Some(value) => args.push(value),
+                        None => return Ok(None),
                     }
                 }
 
-                func.call(&args).ok()
+                call_value(map_func, &args).map(Some)
             }
             Self::Dropped {
                 n,
                 sequence,
                 initial,
             } => {
-                let mut sequence = borrow_mut_value(sequence);
-                let sequence = sequence.as_any_mut().downcast_mut::<StreamValue>().unwrap();
-
                 if *initial {
-                    let n = borrow_value(n);
-                    let n = downcast_value::<IntValue>(&n).unwrap().value;
-
+                    let n = downcast_value::<IntValue>(&borrow_value(n)).unwrap().value;
                     for _ in 0..n {
-                        if sequence.next_value().is_none() {
-                            return None;
+                        if Self::next_of(sequence)?.is_none() {
+                            return Ok(None);
                         }
                     }
                     *initial = false;
                 }
 
-                sequence.next_value()
+                Self::next_of(sequence)
             }
             Self::DroppedWhile {
                 predicate_func,
                 sequence,
                 initial,
             } => {
-                let pred = &borrow_value(predicate_func);
-                let pred: &dyn Callable = match pred.get_type() {
-                    ValueType::BuiltInFunction => {
-                        downcast_value::<BuiltInFunctionValue>(pred).unwrap()
+                if *initial {
+                    loop {
+                        let Some(value) = Self::next_of(sequence)? else {
+                            return Ok(None);
+                        };
+                        let result = call_value(predicate_func, std::slice::from_ref(&value))?;
+                        if !is_truthy(&result) {
+                            *initial = false;
+                            return Ok(Some(value));
+                        }
                     }
-                    ValueType::Lambda => downcast_value::<LambdaValue>(pred).unwrap(),
-                    _ => unreachable!(),
+                }
+
+                Self::next_of(sequence)
+            }
+            Self::Buffered { source, index } => {
+                let value = source.borrow_mut().get(*index)?;
+                if value.is_some() {
+                    *index += 1;
+                }
+                Ok(value)
+            }
+            Self::Cons { head, tail } => {
+                if let Some(head) = head.take() {
+                    return Ok(Some(head));
+                }
+
+                let Some(tail) = DelayedTail::force(tail)? else {
+                    return Ok(None);
                 };
+                let tail = downcast_value::<StreamValue>(&borrow_value(&tail))
+                    .unwrap()
+                    .clone();
+                *self = tail;
+                self.next_value()
+            }
+            Self::Taken {
+                remaining,
+                sequence,
+            } => {
+                if *remaining == 0 {
+                    return Ok(None);
+                }
+                *remaining -= 1;
 
-                let mut seq = borrow_mut_value(sequence);
-                let seq = seq.as_any_mut().downcast_mut::<StreamValue>().unwrap();
+                Self::next_of(sequence)
+            }
+            Self::TakenWhile {
+                predicate_func,
+                sequence,
+                done,
+            } => {
+                if *done {
+                    return Ok(None);
+                }
 
-                if *initial {
-                    loop {
-                        if let Some(value) = seq.next_value() {
-                            let result = pred.call(&vec![value.clone()]);
-                            if result.is_err() {
-                                return None;
-                            }
-                            let result = result.unwrap();
-                            if !is_truthy(&result) {
-                                *initial = false;
-                                return Some(value);
-                            }
-                        } else {
-                            return None;
-                        }
+                let value = Self::next_of(sequence)?;
+                let accepted = match &value {
+                    Some(value) => {
+                        is_truthy(&call_value(predicate_func, std::slice::from_ref(value))?)
                     }
+                    None => false,
+                };
+                if !accepted {
+                    *done = true;
+                    return Ok(None);
+                }
+
+                Ok(value)
+            }
+            Self::Interleaved { sequences, index } => {
+                if sequences.is_empty() {
+                    return Ok(None);
                 }
 
-                seq.next_value()
+                let Some(value) = Self::next_of(&sequences[*index])? else {
+                    return Ok(None);
+                };
+                *index = (*index + 1) % sequences.len();
+
+                Ok(Some(value))
             }
+            Self::Cycled { original, current } => {
+                if let Some(value) = Self::next_of(current)? {
+                    return Ok(Some(value));
+                }
+
+                // Restart from a fresh copy, an empty original ends the cycle
+                *current = Self::clone_sequence(original);
+                Self::next_of(current)
+            }
+            Self::Repeated { value, remaining } => {
+                if let Some(remaining) = remaining {
+                    if *remaining == 0 {
+                        return Ok(None);
+                    }
+                    *remaining -= 1;
+                }
+
+                Ok(Some(value.clone()))
+            }
+            Self::Flattened {
+                sequence,
+                nested,
+                deep,
+            } => loop {
+                let value = match nested.last() {
+                    Some(current) => match Self::next_of(current)? {
+                        Some(value) => value,
+                        None => {
+                            nested.pop();
+                            continue;
+                        }
+                    },
+                    None => match Self::next_of(sequence)? {
+                        Some(value) => value,
+                        None => return Ok(None),
+                    },
+                };
+
+                if nested.is_empty() || *deep {
+                    if let Some(stream) = Self::nested_stream(&value) {
+                        nested.push(stream);
+                        continue;
+                    }
+                }
+
+                return Ok(Some(value));
+            },
         }
     }
 
+    fn next_of(sequence: &ValueRef) -> Result<Option<ValueRef>, InterpreterError> {
+        borrow_mut_value(sequence)
+            .as_any_mut()
+            .downcast_mut::<StreamValue>()
+            .unwrap()
+            .next_value()
+    }
+
     fn clone_sequence(value: &ValueRef) -> ValueRef {
         let value = borrow_value(value);
         let value = value.as_any().downcast_ref::<StreamValue>().unwrap();
@@ -378,6 +695,51 @@ impl Clone for StreamValue {
                 sequence: Self::clone_sequence(sequence),
                 initial: true,
             },
+            Self::Buffered { source, index } => Self::Buffered {
+                source: source.clone(),
+                index: *index,
+            },
+            Self::Cons { head, tail } => Self::Cons {
+                head: head.clone(),
+                tail: tail.clone(),
+            },
+            Self::Taken {
+                remaining,
+                sequence,
+            } => Self::Taken {
+                remaining: *remaining,
+                sequence: Self::clone_sequence(sequence),
+            },
+            Self::TakenWhile {
+                predicate_func,
+                sequence,
+                done,
+            } => Self::TakenWhile {
+                predicate_func: predicate_func.clone(),
+                sequence: Self::clone_sequence(sequence),
+                done: *done,
+            },
+            Self::Interleaved { sequences, index } => Self::Interleaved {
+                sequences: sequences.iter().map(Self::clone_sequence).collect(),
+                index: *index,
+            },
+            Self::Cycled { original, current } => Self::Cycled {
+                original: original.clone(),
+                current: Self::clone_sequence(current),
+            },
+            Self::Repeated { value, remaining } => Self::Repeated {
+                value: value.clone(),
+                remaining: *remaining,
+            },
+            Self::Flattened {
+                sequence,
+                nested,
+                deep,
+            } => Self::Flattened {
+                sequence: Self::clone_sequence(sequence),
+                nested: nested.iter().map(Self::clone_sequence).collect(),
+                deep: *deep,
+            },
         }
     }
 }
@@ -410,7 +772,7 @@ mod tests {
         let mut seq = sequence.clone();
         let mut elements = Vec::new();
         for _ in 0..n {
-            if let Some(value) = seq.next_value() {
+            if let Some(value) = seq.next_value().unwrap() {
                 elements.push(value);
             } else {
                 break;
@@ -542,4 +904,38 @@ mod tests {
             "(vector 5 6 7 8 9 10 11 12 13 14)"
         );
     }
+
+    #[test]
+    fn test_taken() {
+        let mut interpreter = Interpreter::new();
+
+        let next_func = interpreter.eval("(λ (n) (+ n 1))").unwrap();
+        let start = new_valueref(IntValue { value: 0 });
+
+        let numbers = StreamValue::new_iterator(next_func, start).unwrap();
+
+        let taken = StreamValue::new_taken(
+            new_valueref(IntValue { value: 3 }),
+            new_valueref(numbers.clone()),
+        )
+        .unwrap();
+
+        assert_eq!(take(10, &taken).to_string(), "(vector 0 1 2)");
+        assert_eq!(take(2, &taken).to_string(), "(vector 0 1)");
+    }
+
+    #[test]
+    fn test_cycled() {
+        let list = new_valueref(VectorValue {
+            elements: vec![
+                new_valueref(IntValue { value: 1 }),
+                new_valueref(IntValue { value: 2 }),
+            ],
+        });
+
+        let list = new_valueref(StreamValue::new_list(list).unwrap());
+        let cycled = StreamValue::new_cycled(list).unwrap();
+
+        assert_eq!(take(5, &cycled).to_string(), "(vector 1 2 1 2 1)");
+    }
 }
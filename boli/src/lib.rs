@@ -1,3 +1,14 @@
+pub mod bench;
+pub mod convert;
+pub mod diagnostics;
 pub mod frontend;
 pub mod interpreter;
+pub mod lint;
+pub mod playground;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod repl;
+pub mod test_runner;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
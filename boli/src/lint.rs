@@ -0,0 +1,535 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::frontend::{
+    analysis::{analyze, DefinitionKind, ReferenceKind},
+    parser::{ast::*, json_visitor::JsonData, ParseError, Parser, Span},
+};
+use crate::interpreter::environment::{EnvironmentBuilder, EnvironmentRef};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarningKind {
+    UnusedDefinition,
+    ShadowedBinding,
+    UnreachableClause,
+    ArityMismatch,
+    UndefinedSetBang,
+    ConstantSetBang,
+    UseBeforeDefinition,
+}
+
+impl WarningKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedDefinition => "unused-definition",
+            WarningKind::ShadowedBinding => "shadowed-binding",
+            WarningKind::UnreachableClause => "unreachable-clause",
+            WarningKind::ArityMismatch => "arity-mismatch",
+            WarningKind::UndefinedSetBang => "undefined-set",
+            WarningKind::ConstantSetBang => "constant-set",
+            WarningKind::UseBeforeDefinition => "use-before-definition",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LintWarning {
+    pub kind: WarningKind,
+    pub name: String,
+    pub message: String,
+    // Index (starting at 1) of the top-level form the warning was found in
+    pub form: usize,
+    // Location of the name the warning is about, or of the top-level form
+    // if the name has no location
+    pub span: Option<Span>,
+}
+
+impl LintWarning {
+    pub fn to_json(&self) -> JsonData {
+        let mut data = HashMap::from([
+            (
+                "kind".to_string(),
+                JsonData::String(self.kind.name().to_string()),
+            ),
+            ("name".to_string(), JsonData::String(self.name.clone())),
+            (
+                "message".to_string(),
+                JsonData::String(self.message.clone()),
+            ),
+            ("form".to_string(), JsonData::Number(self.form as f64)),
+        ]);
+        let mut fields: Vec<String> = ["kind", "name", "message", "form"]
+            .iter()
+            .map(|field| field.to_string())
+            .collect();
+        if let Some(span) = self.span {
+            data.insert("line".to_string(), JsonData::Number(span.line as f64));
+            data.insert("column".to_string(), JsonData::Number(span.column as f64));
+            fields.extend(["line".to_string(), "column".to_string()]);
+        }
+        JsonData::Object(data, fields)
+    }
+}
+
+pub fn warnings_to_json(warnings: &[LintWarning]) -> JsonData {
+    JsonData::Array(warnings.iter().map(LintWarning::to_json).collect())
+}
+
+// Names that are defined by the prelude and thus known to every program
+pub fn prelude_names() -> HashSet<String> {
+    let env = EnvironmentBuilder::new().with_prelude(true).build();
+    let mut names = HashSet::new();
+    let mut current: Option<EnvironmentRef> = Some(env);
+    while let Some(env) = current {
+        names.extend(env.borrow().env.keys().cloned());
+        current = env.borrow().get_parent();
+    }
+    names
+}
+
+pub fn lint_code(code: &str, globals: &HashSet<String>) -> Result<Vec<LintWarning>, ParseError> {
+    let program = Parser::new().parse(code)?;
+    Ok(lint_program(&program, globals))
+}
+
+pub fn lint_program(program: &Program, globals: &HashSet<String>) -> Vec<LintWarning> {
+    let report = analyze(program);
+    let mut warnings = Vec::new();
+    let mut warn =
+        |kind: WarningKind, name: &str, message: String, form: usize, span: Option<Span>| {
+            warnings.push(LintWarning {
+                kind,
+                name: name.to_string(),
+                message,
+                form,
+                span,
+            });
+        };
+
+    for (definition, _) in report.shadowed_definitions() {
+        let name = &definition.name;
+        warn(
+            WarningKind::ShadowedBinding,
+            name,
+            format!("{} shadows a binding of an enclosing scope", name),
+            definition.form,
+            definition.span,
+        );
+    }
+
+    for definition in report.unused_definitions() {
+        let name = &definition.name;
+        warn(
+            WarningKind::UnusedDefinition,
+            name,
+            format!("{} is defined but never used", name),
+            definition.form,
+            definition.span,
+        );
+    }
+
+    for (reference, definition) in report.uses_before_definition() {
+        let name = &reference.name;
+        let message = if definition.arity.is_some() {
+            format!(
+                "{} is used before its definition (functions can be hoisted with --hoist-functions)",
+                name
+            )
+        } else {
+            format!("{} is used before its definition", name)
+        };
+        warn(
+            WarningKind::UseBeforeDefinition,
+            name,
+            message,
+            reference.form,
+            reference.span,
+        );
+    }
+
+    for reference in &report.references {
+        let name = &reference.name;
+        match reference.kind {
+            ReferenceKind::Call { arguments, spread } => {
+                let Some((parameters, variadic)) = reference
+                    .definition
+                    .and_then(|definition| report.definitions[definition].arity)
+                else {
+                    continue;
+                };
+                if spread || arguments == parameters || (variadic && arguments > parameters) {
+                    continue;
+                }
+                let expected = if variadic {
+                    format!("at least {}", parameters)
+                } else {
+                    parameters.to_string()
+                };
+                warn(
+                    WarningKind::ArityMismatch,
+                    name,
+                    format!(
+                        "{} expects {} argument(s) but is called with {}",
+                        name, expected, arguments
+                    ),
+                    reference.form,
+                    reference.span,
+                );
+            }
+            ReferenceKind::Assign
+                if reference.definition.is_none() && !globals.contains(name) && !report.dynamic =>
+            {
+                warn(
+                    WarningKind::UndefinedSetBang,
+                    name,
+                    format!("set! of undefined name {}", name),
+                    reference.form,
+                    reference.span,
+                );
+            }
+            ReferenceKind::Assign
+                if reference.definition.is_some_and(|definition| {
+                    report.definitions[definition].kind == DefinitionKind::Constant
+                }) =>
+            {
+                warn(
+                    WarningKind::ConstantSetBang,
+                    name,
+                    format!("set! of constant {}", name),
+                    reference.form,
+                    reference.span,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    let mut linter = Linter {
+        form: 0,
+        warnings: Vec::new(),
+    };
+    program.accept(&mut linter);
+    warnings.extend(linter.warnings);
+
+    for warning in warnings.iter_mut().filter(|warning| warning.span.is_none()) {
+        warning.span = program.spans.get(warning.form.wrapping_sub(1)).copied();
+    }
+    warnings.sort_by_key(|warning| {
+        let position = warning.span.map(|span| (span.line, span.column));
+        (warning.form, position)
+    });
+    warnings
+}
+
+// Finds the clauses of conditional expressions that are never evaluated
+struct Linter {
+    form: usize,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter {
+    fn warn(&mut self, message: &str) {
+        self.warnings.push(LintWarning {
+            kind: WarningKind::UnreachableClause,
+            name: String::new(),
+            message: message.to_string(),
+            form: self.form,
+            span: None,
+        });
+    }
+
+    // Returns the truth value of conditions that are literals
+    fn constant_condition(condition: &AstRef) -> Option<bool> {
+        let condition = &borrow_ast(condition);
+        if let Some(bool) = downcast_ast::<Bool>(condition) {
+            Some(bool.value)
+        } else if downcast_ast::<Nil>(condition).is_some() {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl AstVisitor for Linter {
+    fn visit_program(&mut self, program: &Program) {
+        for (i, child) in program.children.iter().enumerate() {
+            self.form = i + 1;
+            child.borrow().accept(self);
+        }
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        block.children.iter().for_each(|child| {
+            child.borrow().accept(self);
+        });
+    }
+
+    fn visit_integer(&mut self, _integer: &Integer) {}
+
+    fn visit_rational(&mut self, _rational: &Rational) {}
+
+    fn visit_real(&mut self, _real: &Real) {}
+
+    fn visit_bool(&mut self, _bool: &Bool) {}
+
+    fn visit_str(&mut self, _str: &Str) {}
+
+    fn visit_nil(&mut self) {}
+
+    fn visit_identifier(&mut self, _identifier: &Identifier) {}
+
+    fn visit_absolute_name(&mut self, _absolute_name: &AbsoluteName) {}
+
+    fn visit_symbol(&mut self, _symbol: &Symbol) {}
+
+    fn visit_quote(&mut self, _quote: &Quote) {}
+
+    fn visit_operator(&mut self, _operator: &Operator) {}
+
+    fn visit_logical_operator(&mut self, _operator: &LogicalOperator) {}
+
+    fn visit_pair(&mut self, pair: &Pair) {
+        pair.left.borrow().accept(self);
+        pair.right.borrow().accept(self);
+    }
+
+    fn visit_list(&mut self, list: &List) {
+        list.elements.iter().for_each(|element| {
+            element.borrow().accept(self);
+        });
+    }
+
+    fn visit_def(&mut self, def: &Definition) {
+        def.value.borrow().accept(self);
+    }
+
+    fn visit_destructuring_def(&mut self, def: &DestructuringDefinition) {
+        def.value.borrow().accept(self);
+    }
+
+    fn visit_struct_def(&mut self, _struct_def: &StructDefinition) {}
+
+    fn visit_method_def(&mut self, method_def: &MethodDefinition) {
+        method_def.value.borrow().accept(self);
+    }
+
+    fn visit_set_bang(&mut self, set_bang: &SetBang) {
+        set_bang.value.borrow().accept(self);
+    }
+
+    fn visit_if(&mut self, if_expr: &IfExpression) {
+        match Self::constant_condition(&if_expr.condition) {
+            Some(true) if downcast_ast::<Nil>(&borrow_ast(&if_expr.alternate)).is_none() => {
+                self.warn("clauses after an always true condition are unreachable");
+            }
+            Some(false) => {
+                self.warn("clause with an always false condition is unreachable");
+            }
+            _ => {}
+        }
+        if_expr.condition.borrow().accept(self);
+        if_expr.consequent.borrow().accept(self);
+        if_expr.alternate.borrow().accept(self);
+    }
+
+    fn visit_case(&mut self, case_expr: &CaseExpression) {
+        case_expr.key.borrow().accept(self);
+        for (_, consequent) in &case_expr.clauses {
+            consequent.borrow().accept(self);
+        }
+        case_expr.alternate.borrow().accept(self);
+    }
+
+    fn visit_while(&mut self, while_loop: &WhileLoop) {
+        while_loop.condition.borrow().accept(self);
+        while_loop.body.borrow().accept(self);
+    }
+
+    fn visit_for(&mut self, for_loop: &ForLoop) {
+        for_loop.start.borrow().accept(self);
+        for_loop.end.borrow().accept(self);
+        for_loop.step.borrow().accept(self);
+        for_loop.body.borrow().accept(self);
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        lambda.body.borrow().accept(self);
+    }
+
+    fn visit_call(&mut self, call: &Call) {
+        call.callee.borrow().accept(self);
+        call.arguments.iter().for_each(|arg| {
+            arg.borrow().accept(self);
+        });
+    }
+
+    fn visit_spread_expr(&mut self, spread_expr: &SpreadExpr) {
+        spread_expr.expr.borrow().accept(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(code: &str) -> Vec<(WarningKind, String, usize)> {
+        let globals = HashSet::from(["counter".to_string()]);
+        lint_code(code, &globals)
+            .unwrap()
+            .into_iter()
+            .map(|warning| (warning.kind, warning.name, warning.form))
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_definitions() {
+        let code = r#"
+        (def (f x)
+            (def unused 1)
+            (def _ignored 2)
+            (def used 3)
+            (+ x used))
+        (def top-level 42)
+        "#;
+        assert_eq!(
+            lint(code),
+            vec![(WarningKind::UnusedDefinition, "unused".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_shadowed_bindings() {
+        let code = r#"
+        (def (f x)
+            (let [(x 2)] x))
+        (def (g list) (list))
+        "#;
+        assert_eq!(
+            lint(code),
+            vec![(WarningKind::ShadowedBinding, "x".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_cond_clauses() {
+        let code = r#"
+        (def x 1)
+        (cond [(> x 0) 'pos] [#t 'other] [(< x 0) 'neg])
+        (cond [#false 'never] [#t 'always])
+        (if #t 1 nil)
+        "#;
+        assert_eq!(
+            lint(code),
+            vec![
+                (WarningKind::UnreachableClause, "".to_string(), 2),
+                (WarningKind::UnreachableClause, "".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arity_mismatches() {
+        let code = r#"
+        (def (add a b) (+ a b))
+        (def (sum first rest...) (+ first ...rest))
+        (add 1)
+        (add 1 2)
+        (sum)
+        (sum 1 2 3)
+        (add ...'(1 2 3))
+        (def (twice x) x)
+        (def (twice x y) y)
+        (twice 1)
+        "#;
+        assert_eq!(
+            lint(code),
+            vec![
+                (WarningKind::ArityMismatch, "add".to_string(), 3),
+                (WarningKind::ArityMismatch, "sum".to_string(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_bang_of_undefined_names() {
+        let code = r#"
+        (def x 1)
+        (set! x 2)
+        (set! counter 3)
+        (set! y 4)
+        "#;
+        assert_eq!(
+            lint(code),
+            vec![(WarningKind::UndefinedSetBang, "y".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn test_set_bang_of_constants() {
+        let code = r#"
+        (def-const limit 10)
+        (def count 0)
+        (set! limit 20)
+        (set! count 1)
+        "#;
+        assert_eq!(
+            lint(code),
+            vec![(WarningKind::ConstantSetBang, "limit".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_use_before_definition() {
+        let code = r#"
+        (def (f)
+            (def r (g))
+            (def (g) (h))
+            (def (h) r)
+            (+ r 1))
+        (def (even? n) (if (= n 0) #t (odd? (- n 1))))
+        (def (odd? n) (if (= n 0) #f (even? (- n 1))))
+        (def x y)
+        (def y 1)
+        (block (def z (+ z 1)) z)
+        "#;
+        assert_eq!(
+            lint(code),
+            vec![
+                (WarningKind::UseBeforeDefinition, "g".to_string(), 1),
+                (WarningKind::UseBeforeDefinition, "y".to_string(), 4),
+                (WarningKind::UseBeforeDefinition, "z".to_string(), 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_warning_locations() {
+        let code = "(def (f x)\n  (def (g) 1)\n  x)\n(cond [#t 1]\n      [#f 2])\n(set! y 2)";
+        let locations: Vec<(WarningKind, usize, usize)> = lint_code(code, &HashSet::new())
+            .unwrap()
+            .iter()
+            .map(|warning| {
+                let span = warning.span.unwrap();
+                (warning.kind, span.line, span.column)
+            })
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                (WarningKind::UnusedDefinition, 2, 9),
+                (WarningKind::UnreachableClause, 4, 1),
+                (WarningKind::UnreachableClause, 4, 1),
+                (WarningKind::UndefinedSetBang, 6, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        let warnings = lint_code("(set! y 1)", &HashSet::new()).unwrap();
+        let json = warnings_to_json(&warnings).to_string();
+        assert!(json.contains("\"kind\": \"undefined-set\""));
+        assert!(json.contains("\"name\": \"y\""));
+        assert!(json.contains("\"form\": 1"));
+    }
+}
@@ -1,22 +1,34 @@
 use boli::{
-    frontend::parser::{json_visitor::JsonData, Parser as BoliParser},
+    diagnostics::Diagnostics,
+    frontend::{
+        formatter::{format_code, DEFAULT_WIDTH},
+        lexer::number_format::{set_number_format, NumberFormat},
+        parser::{
+            binary::{serialize_program, COMPILED_EXTENSION},
+            json_visitor::JsonData,
+            sexpr_visitor::SexprVisitor,
+            Parser as BoliParser, Span,
+        },
+        typecheck::typecheck_code,
+    },
     interpreter::{
         environment::EnvironmentBuilder,
-        misc_functions::{Output, StdOutput},
-        module_mgmt::{file_system::new_directory, ModuleDirRef},
-        values::{
-            borrow_value, downcast_value, new_valueref, Callable, EvalResult, LambdaValue,
-            StrValue, ValueRef, ValueType,
-        },
+        misc_functions::{exit_code, Output, StdOutput},
+        module_mgmt::{file_system::new_directory, search_path::module_search_dirs, ModuleDirRef},
+        settings::Settings,
+        stats::{count_builtin_calls, reset_stats, runtime_stats},
+        values::{borrow_value, new_valueref, InterpreterError, StrValue, ValueRef, ValueType},
         Interpreter,
     },
-    repl,
+    lint::{lint_program, prelude_names, warnings_to_json},
+    repl, test_runner, watch,
 };
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::{
     cell::RefCell,
     fs::File,
-    io::{stdin, BufReader, Read, Result},
+    io::{stdin, BufReader, Read, Result, Write},
+    path::{Path, PathBuf},
     rc::Rc,
 };
 
@@ -49,55 +61,350 @@ struct Options {
     #[arg(short = 'r', long = "run", group = "action", help = "run interpreter")]
     run: bool,
 
+    #[arg(
+        short = 't',
+        long = "test",
+        group = "action",
+        help = "run all *-test.boli files in the input directory"
+    )]
+    test: bool,
+
+    #[arg(
+        short = 'f',
+        long = "format",
+        group = "action",
+        help = "format the input file in place (standard input is formatted to standard output)"
+    )]
+    format: bool,
+
+    #[arg(
+        long = "compile",
+        group = "action",
+        help = "compile the input file to a .bolc file next to it (standard input is compiled to standard output)"
+    )]
+    compile: bool,
+
+    #[arg(
+        short = 'c',
+        long = "check",
+        conflicts_with_all = ["interactive", "parse_only", "run", "test", "compile"],
+        help = "report lint warnings, or together with --format only check whether the input is formatted"
+    )]
+    check: bool,
+
+    #[arg(
+        long = "json",
+        requires = "check",
+        help = "output lint warnings as JSON"
+    )]
+    json: bool,
+
+    #[arg(
+        long = "ast-format",
+        value_enum,
+        default_value_t = AstFormat::Json,
+        help = "output format of the AST in --parse-only mode"
+    )]
+    ast_format: AstFormat,
+
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        help = "suppress the startup banner of the interactive REPL"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long = "stats",
+        help = "print statistics about the used resources to standard error after the run"
+    )]
+    stats: bool,
+
+    #[arg(
+        long = "contracts",
+        help = "check the type annotations of function arguments and results at call time"
+    )]
+    contracts: bool,
+
+    #[arg(
+        long = "strict-bool",
+        help = "require boolean values as conditions of if, cond, and, or and while"
+    )]
+    strict_bool: bool,
+
+    #[arg(
+        long = "strict",
+        help = "treat function definitions that replace an existing arity as errors instead of warnings"
+    )]
+    strict: bool,
+
+    #[arg(
+        long = "hoist-functions",
+        help = "evaluate the function definitions of a block before its other expressions"
+    )]
+    hoist_functions: bool,
+
+    #[arg(
+        long = "typecheck",
+        conflicts_with_all = ["interactive", "parse_only", "test", "format", "compile", "check"],
+        help = "report likely type errors as warnings to standard error before running the program"
+    )]
+    typecheck: bool,
+
+    #[arg(
+        long = "watch",
+        conflicts_with_all = ["parse_only", "test", "format", "compile", "check"],
+        help = "run the input file again whenever it or a module changes (reload it in the interactive REPL)"
+    )]
+    watch: bool,
+
+    #[arg(long = "no-color", help = "do not use colors in error messages")]
+    no_color: bool,
+
+    #[arg(
+        long = "number-format",
+        default_value = "de",
+        help = "decimal and grouping separators of real numbers: de (1.000,5) or en (1,000.5)"
+    )]
+    number_format: NumberFormat,
+
     #[arg(
         long = "module-dirs",
         required = false,
         default_value = "",
-        help = "colon separated list of directories to search for BOLI modules"
+        help = "colon separated list of directories to search for BOLI modules (in addition to boli.toml and BOLI_PATH)"
     )]
     module_dirs: String,
 
-    #[arg(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "ARGS",
+        help = "arguments passed to the main function of the script, put them after -- if they look like options of boli"
+    )]
     boli_args: Vec<String>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum AstFormat {
+    Json,
+    Sexpr,
+}
+
 fn main() -> Result<()> {
     let options = Options::parse();
-    let module_dirs = options
+    set_number_format(options.number_format);
+    let module_dirs: Vec<String> = options
         .module_dirs
         .split(':')
         .map(|s| s.to_string())
         .filter(|s| !s.is_empty())
         .collect();
+    let module_dirs = module_search_dirs(&module_dirs, &project_dir(&options.input_file))?;
+    let settings = Settings::new()
+        .strict(options.strict)
+        .strict_bool(options.strict_bool)
+        .hoist_functions(options.hoist_functions)
+        .contracts(options.contracts);
     let mut code: String = String::new();
 
-    if !options.interactive {
+    if options.watch && !options.interactive {
+        // The script runs in child processes with the same options
+        let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+        if let Some(index) = args.iter().position(|arg| arg == "--watch") {
+            args.remove(index);
+        }
+        return watch::run(&options.input_file, &module_dirs, &args);
+    }
+
+    if !options.interactive && !options.test {
         code = read_input(&options.input_file)?;
     }
 
+    let diagnostics = Diagnostics::for_stdout(options.no_color);
+
     if options.interactive {
-        repl::run(&options.input_file, &module_dirs)?;
+        repl::run(
+            &options.input_file,
+            &module_dirs,
+            options.quiet,
+            options.no_color,
+            options.watch,
+            settings,
+        )?;
+    } else if options.test {
+        let test_path = match options.input_file.as_str() {
+            "-" => ".",
+            path => path,
+        };
+        if !test_runner::run(test_path, &module_dirs, settings)? {
+            std::process::exit(1);
+        }
+    } else if options.format {
+        if !format(&code, &options.input_file, options.check, &diagnostics)? {
+            std::process::exit(1);
+        }
+    } else if options.compile {
+        if !compile(&code, &options.input_file, &diagnostics)? {
+            std::process::exit(1);
+        }
+    } else if options.check {
+        if !lint(&code, options.json, &diagnostics) {
+            std::process::exit(1);
+        }
     } else if options.parse_only {
-        parse(&code);
+        parse(&code, options.ast_format, &diagnostics);
     } else {
-        interpret(&code, &module_dirs, &options.boli_args);
+        if options.typecheck {
+            typecheck(&code);
+        }
+        interpret(
+            &code,
+            &module_dirs,
+            &options.boli_args,
+            options.stats,
+            settings,
+            // Errors of the program must not mix with its output
+            &Diagnostics::for_stderr(options.no_color),
+        );
     }
 
     Ok(())
 }
 
-fn parse(code: &str) {
+// Directory in which the search for the project manifest starts
+fn project_dir(input_file: &str) -> PathBuf {
+    let path = Path::new(input_file);
+    if input_file == "-" {
+        return PathBuf::from(".");
+    }
+    if path.is_dir() {
+        return path.to_path_buf();
+    }
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn parse(code: &str, ast_format: AstFormat, diagnostics: &Diagnostics) {
     let parser = BoliParser::new();
     let parse_result = parser.parse(&code);
 
     if let Ok(ast) = parse_result {
-        println!("{}", JsonData::from(ast));
+        match ast_format {
+            AstFormat::Json => println!("{}", JsonData::from(ast)),
+            AstFormat::Sexpr => println!("{}", SexprVisitor::new().to_sexpr(&ast)),
+        }
+    } else {
+        let err = parse_result.err().unwrap();
+        print!("{}", diagnostics.render_parse_error(code, &err));
+    }
+}
+
+fn format(code: &str, file_path: &str, check: bool, diagnostics: &Diagnostics) -> Result<bool> {
+    let formatted = match format_code(code, DEFAULT_WIDTH) {
+        Ok(formatted) => formatted,
+        Err(err) => {
+            print!("{}", diagnostics.render_parse_error(code, &err));
+            return Ok(false);
+        }
+    };
+
+    if check {
+        let is_formatted = formatted == code;
+        if !is_formatted {
+            println!("{} is not formatted", file_path);
+        }
+        Ok(is_formatted)
+    } else {
+        if file_path == "-" {
+            print!("{}", formatted);
+        } else if formatted != code {
+            std::fs::write(file_path, formatted)?;
+        }
+        Ok(true)
+    }
+}
+
+fn compile(code: &str, file_path: &str, diagnostics: &Diagnostics) -> Result<bool> {
+    let program = match BoliParser::new().parse(code) {
+        Ok(program) => program,
+        Err(err) => {
+            print!("{}", diagnostics.render_parse_error(code, &err));
+            return Ok(false);
+        }
+    };
+    let bytes = serialize_program(&program);
+
+    if file_path == "-" {
+        std::io::stdout().write_all(&bytes)?;
+    } else {
+        std::fs::write(
+            Path::new(file_path).with_extension(COMPILED_EXTENSION),
+            bytes,
+        )?;
+    }
+    Ok(true)
+}
+
+fn lint(code: &str, json: bool, diagnostics: &Diagnostics) -> bool {
+    let (program, errors) = BoliParser::new().parse_with_recovery(code);
+    if !errors.is_empty() {
+        for err in &errors {
+            print!("{}", diagnostics.render_parse_error(code, err));
+        }
+        return false;
+    }
+    let warnings = lint_program(&program, &prelude_names());
+
+    if json {
+        println!("{}", warnings_to_json(&warnings));
     } else {
-        println!("Error: {:?}", parse_result.err().unwrap());
+        for warning in &warnings {
+            println!(
+                "{}: {}: {}",
+                location(warning.span, warning.form),
+                warning.kind.name(),
+                warning.message
+            );
+        }
+    }
+
+    warnings.is_empty()
+}
+
+// Parse errors are left to the interpreter
+fn typecheck(code: &str) {
+    let Ok(warnings) = typecheck_code(code) else {
+        return;
+    };
+    for warning in &warnings {
+        eprintln!(
+            "warning: {}: {}",
+            location(warning.span, warning.form),
+            warning.message
+        );
     }
 }
 
-fn interpret(code: &str, module_dirs: &Vec<String>, args: &Vec<String>) {
+// Warnings of programs that were not parsed from source code only know the
+// top-level form they were found in
+fn location(span: Option<Span>, form: usize) -> String {
+    match span {
+        Some(span) => format!("{}:{}", span.line, span.column),
+        None => format!("form {}", form),
+    }
+}
+
+fn interpret(
+    code: &str,
+    module_dirs: &[String],
+    args: &[String],
+    stats: bool,
+    settings: Settings,
+    diagnostics: &Diagnostics,
+) {
     let search_dirs: Vec<ModuleDirRef> = if module_dirs.is_empty() {
         vec![new_directory(".", "")]
     } else {
@@ -113,42 +420,64 @@ fn interpret(code: &str, module_dirs: &Vec<String>, args: &Vec<String>) {
     let env = EnvironmentBuilder::new()
         .search_dirs(&search_dirs)
         .output(&output)
+        .settings(settings)
         .with_prelude(true)
         .build();
 
     let mut interpreter = Interpreter::with_environment(&env);
 
+    // Loading the prelude is not part of the report
+    count_builtin_calls(stats);
+    reset_stats();
     let result = eval_code(&mut interpreter, code, args);
+    if stats {
+        eprint!("{}", runtime_stats());
+    }
 
     match result {
-        Ok(value) => println!("{}", borrow_value(&value)),
-        Err(err) => println!("Error: {:?}", err),
+        // An integer result of main is the exit status of the program
+        Ok((value, true)) if borrow_value(&value).get_type() == ValueType::Int => {
+            exit(exit_code(&value).unwrap_or(1))
+        }
+        Ok((value, _)) => println!("{}", borrow_value(&value)),
+        Err(err) => match err.exit_code() {
+            Some(code) => exit(code),
+            None => {
+                eprint!("{}", diagnostics.render_error(code, &err));
+                std::process::exit(1);
+            }
+        },
     }
 }
 
-fn eval_code(interpreter: &mut Interpreter, code: &str, main_args: &Vec<String>) -> EvalResult {
-    let mut result = interpreter.eval(code);
-
-    if let Ok(_) = result {
-        let main_opt = interpreter.env.borrow().get("main");
-        if let Some(main) = main_opt {
-            if main.borrow().get_type() == ValueType::Lambda {
-                let main = &borrow_value(&main);
-                let main = downcast_value::<LambdaValue>(main).unwrap();
-                let args: Vec<ValueRef> = main_args
-                    .iter()
-                    .map(|s| {
-                        new_valueref(StrValue {
-                            value: s.to_string(),
-                        })
+fn exit(code: i32) -> ! {
+    let _ = std::io::stdout().flush();
+    std::process::exit(code);
+}
+
+// Returns the result of main if the program defines it, the result of the
+// program otherwise
+fn eval_code(
+    interpreter: &mut Interpreter,
+    code: &str,
+    main_args: &[String],
+) -> std::result::Result<(ValueRef, bool), InterpreterError> {
+    let result = interpreter.eval(code)?;
+
+    match interpreter.get_global("main") {
+        Some(main) if main.borrow().get_type() == ValueType::Lambda => {
+            let args: Vec<ValueRef> = main_args
+                .iter()
+                .map(|s| {
+                    new_valueref(StrValue {
+                        value: s.as_str().into(),
                     })
-                    .collect();
-                result = main.call(&args);
-            }
+                })
+                .collect();
+            Ok((interpreter.call("main", &args)?, true))
         }
+        _ => Ok((result, false)),
     }
-
-    result
 }
 
 fn read_input(file_path: &str) -> Result<String> {
@@ -0,0 +1,428 @@
+// Evaluation results for the web playground. Values are rendered as JSON or
+// HTML that keeps their structure, so vectors, structs and hash tables can
+// be shown as nested lists and tables. Parse errors carry the span of the
+// offending token to highlight it in the editor.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    frontend::parser::{json_visitor::JsonData, ParseError, Parser, Span},
+    interpreter::{
+        misc_functions::{with_redirected_output, BufferedOutput, OutputRef},
+        values::{
+            borrow_value, downcast_value, PairValue, StructTypeValue, StructValue, ValueRef,
+            VectorValue,
+        },
+        Interpreter,
+    },
+};
+
+#[derive(Debug)]
+pub struct PlaygroundError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl From<ParseError> for PlaygroundError {
+    fn from(err: ParseError) -> Self {
+        Self {
+            span: err.span(),
+            message: err.message,
+        }
+    }
+}
+
+pub struct Evaluation {
+    pub output: String,
+    pub result: Result<ValueRef, PlaygroundError>,
+}
+
+impl Evaluation {
+    pub fn to_json(&self) -> JsonData {
+        let mut object = JsonObject::new();
+        object.add("output", JsonData::String(self.output.clone()));
+        match &self.result {
+            Ok(value) => object.add("value", value_to_json(value)),
+            Err(err) => {
+                let mut error = JsonObject::new();
+                error.add("message", JsonData::String(err.message.clone()));
+                let span = match &err.span {
+                    Some(span) => {
+                        let mut span_object = JsonObject::new();
+                        span_object.add("line", JsonData::Number(span.line as f64));
+                        span_object.add("column", JsonData::Number(span.column as f64));
+                        span_object.add("length", JsonData::Number(span.length as f64));
+                        span_object.build()
+                    }
+                    None => JsonData::Null,
+                };
+                error.add("span", span);
+                object.add("error", error.build());
+            }
+        }
+        object.build()
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        if !self.output.is_empty() {
+            html.push_str(&format!(
+                "<pre class=\"boli-output\">{}</pre>",
+                escape_html(&self.output)
+            ));
+        }
+        match &self.result {
+            Ok(value) => html.push_str(&value_to_html(value)),
+            Err(err) => {
+                let span_attributes = match &err.span {
+                    Some(span) => format!(
+                        " data-line=\"{}\" data-column=\"{}\" data-length=\"{}\"",
+                        span.line, span.column, span.length
+                    ),
+                    None => String::new(),
+                };
+                html.push_str(&format!(
+                    "<div class=\"boli-error\"{}>{}</div>",
+                    span_attributes,
+                    escape_html(&err.message)
+                ));
+            }
+        }
+        html
+    }
+}
+
+// Evaluates the code and captures everything it prints
+pub fn evaluate(interpreter: &mut Interpreter, code: &str) -> Evaluation {
+    let buffered_output = BufferedOutput::new();
+    let output: OutputRef = Rc::new(RefCell::new(buffered_output.clone()));
+
+    let result = match Parser::new().parse(code) {
+        Ok(program) => with_redirected_output(&output, || interpreter.eval_program(program))
+            .map_err(|err| PlaygroundError {
+                message: err.to_string(),
+                span: err.span,
+            }),
+        Err(err) => Err(PlaygroundError::from(err)),
+    };
+
+    let output = buffered_output.take();
+    Evaluation { output, result }
+}
+
+struct JsonObject {
+    data: HashMap<String, JsonData>,
+    fields: Vec<String>,
+}
+
+impl JsonObject {
+    fn new() -> Self {
+        Self {
+            data: HashMap::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, name: &str, value: JsonData) {
+        self.data.insert(name.to_string(), value);
+        self.fields.push(name.to_string());
+    }
+
+    fn build(self) -> JsonData {
+        JsonData::Object(self.data, self.fields)
+    }
+}
+
+// Structured view of a value: collections are split into their elements,
+// all other values are represented by their text
+enum ValueView {
+    Atom(&'static str, String),
+    Vector(Vec<ValueRef>),
+    Pair(ValueRef, ValueRef),
+    Struct(String, Vec<(String, ValueRef)>),
+    HashTable(Vec<(ValueRef, ValueRef)>),
+    Set(Vec<ValueRef>),
+}
+
+fn view(value: &ValueRef) -> ValueView {
+    let value_ref = borrow_value(value);
+    let value_type = value_ref.get_type();
+
+    if let Some(vector) = downcast_value::<VectorValue>(&value_ref) {
+        return ValueView::Vector(vector.elements.clone());
+    }
+    if let Some(pair) = downcast_value::<PairValue>(&value_ref) {
+        return ValueView::Pair(pair.left.clone(), pair.right.clone());
+    }
+    if let Some(struct_value) = downcast_value::<StructValue>(&value_ref) {
+        return struct_view(struct_value);
+    }
+
+    ValueView::Atom(value_type.name(), value_ref.to_string())
+}
+
+fn struct_view(struct_value: &StructValue) -> ValueView {
+    if let Some(struct_type) = &struct_value.struct_type {
+        let struct_type = borrow_value(struct_type);
+        let struct_type = downcast_value::<StructTypeValue>(&struct_type).unwrap();
+        let fields = struct_type
+            .fields
+            .iter()
+            .filter_map(|field| {
+                struct_value
+                    .values
+                    .get(field)
+                    .map(|entry| (field.clone(), entry.value.clone()))
+            })
+            .collect();
+        return ValueView::Struct(struct_type.name.clone(), fields);
+    }
+
+    // Entries are sorted by key like in the textual representation
+    let mut keys: Vec<&String> = struct_value.values.keys().collect();
+    keys.sort();
+    let entries = keys.into_iter().map(|key| &struct_value.values[key]);
+
+    if struct_value.is_set() {
+        ValueView::Set(entries.map(|entry| entry.key.clone()).collect())
+    } else {
+        ValueView::HashTable(
+            entries
+                .map(|entry| (entry.key.clone(), entry.value.clone()))
+                .collect(),
+        )
+    }
+}
+
+// Every value is an object with a type field, e.g.
+//
+//   {"type": "vector", "elements": [{"type": "int", "text": "1"}]}
+pub fn value_to_json(value: &ValueRef) -> JsonData {
+    let mut object = JsonObject::new();
+    let typed = |object: &mut JsonObject, type_name: &str| {
+        object.add("type", JsonData::String(type_name.to_string()));
+    };
+    let values_to_json =
+        |values: &[ValueRef]| JsonData::Array(values.iter().map(value_to_json).collect());
+
+    match view(value) {
+        ValueView::Atom(type_name, text) => {
+            typed(&mut object, type_name);
+            object.add("text", JsonData::String(text));
+        }
+        ValueView::Vector(elements) => {
+            typed(&mut object, "vector");
+            object.add("elements", values_to_json(&elements));
+        }
+        ValueView::Pair(left, right) => {
+            typed(&mut object, "pair");
+            object.add("left", value_to_json(&left));
+            object.add("right", value_to_json(&right));
+        }
+        ValueView::Struct(name, fields) => {
+            typed(&mut object, "struct");
+            object.add("name", JsonData::String(name));
+            let fields = fields
+                .iter()
+                .map(|(name, value)| {
+                    let mut field = JsonObject::new();
+                    field.add("name", JsonData::String(name.clone()));
+                    field.add("value", value_to_json(value));
+                    field.build()
+                })
+                .collect();
+            object.add("fields", JsonData::Array(fields));
+        }
+        ValueView::HashTable(entries) => {
+            typed(&mut object, "hash-table");
+            let entries = entries
+                .iter()
+                .map(|(key, value)| {
+                    let mut entry = JsonObject::new();
+                    entry.add("key", value_to_json(key));
+                    entry.add("value", value_to_json(value));
+                    entry.build()
+                })
+                .collect();
+            object.add("entries", JsonData::Array(entries));
+        }
+        ValueView::Set(elements) => {
+            typed(&mut object, "set");
+            object.add("elements", values_to_json(&elements));
+        }
+    }
+
+    object.build()
+}
+
+// Collections become lists and tables, atoms spans with a CSS class per
+// type, e.g. <span class="boli-int">1</span>
+pub fn value_to_html(value: &ValueRef) -> String {
+    let items = |values: &[ValueRef]| -> String {
+        values
+            .iter()
+            .map(|value| format!("<li>{}</li>", value_to_html(value)))
+            .collect()
+    };
+
+    match view(value) {
+        ValueView::Atom(type_name, text) => {
+            format!(
+                "<span class=\"boli-{}\">{}</span>",
+                type_name,
+                escape_html(&text)
+            )
+        }
+        ValueView::Vector(elements) => {
+            format!("<ol class=\"boli-vector\">{}</ol>", items(&elements))
+        }
+        ValueView::Pair(left, right) => format!(
+            "<span class=\"boli-pair\">({} . {})</span>",
+            value_to_html(&left),
+            value_to_html(&right)
+        ),
+        ValueView::Struct(name, fields) => {
+            let rows: String = fields
+                .iter()
+                .map(|(name, value)| {
+                    format!(
+                        "<tr><th>{}</th><td>{}</td></tr>",
+                        escape_html(name),
+                        value_to_html(value)
+                    )
+                })
+                .collect();
+            format!(
+                "<table class=\"boli-struct\"><caption>{}</caption>{}</table>",
+                escape_html(&name),
+                rows
+            )
+        }
+        ValueView::HashTable(entries) => {
+            let rows: String = entries
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "<tr><td>{}</td><td>{}</td></tr>",
+                        value_to_html(key),
+                        value_to_html(value)
+                    )
+                })
+                .collect();
+            format!("<table class=\"boli-hash-table\">{}</table>", rows)
+        }
+        ValueView::Set(elements) => format!("<ul class=\"boli-set\">{}</ul>", items(&elements)),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(code: &str) -> Evaluation {
+        evaluate(&mut Interpreter::with_prelude(), code)
+    }
+
+    #[test]
+    fn test_value_to_html() {
+        let evaluation = eval(
+            r#"
+            (def-struct point (x y))
+            (def table (create-hash-table))
+            (hash-set! table 'k '(1))
+            (display "<p>")
+            (vector (create-point 1 "a&b") table)
+            "#,
+        );
+        assert_eq!(evaluation.output, "<p>");
+        assert_eq!(
+            evaluation.to_html(),
+            "<pre class=\"boli-output\">&lt;p&gt;</pre>\
+             <ol class=\"boli-vector\">\
+             <li><table class=\"boli-struct\"><caption>point</caption>\
+             <tr><th>x</th><td><span class=\"boli-int\">1</span></td></tr>\
+             <tr><th>y</th><td><span class=\"boli-str\">&quot;a&amp;b&quot;</span></td></tr>\
+             </table></li>\
+             <li><table class=\"boli-hash-table\">\
+             <tr><td><span class=\"boli-symbol\">&#39;k</span></td>\
+             <td><ol class=\"boli-vector\"><li><span class=\"boli-int\">1</span></li></ol></td></tr>\
+             </table></li>\
+             </ol>"
+        );
+    }
+
+    #[test]
+    fn test_value_to_json() {
+        let evaluation = eval("(def-struct point (x y)) (create-point 1 '(2))");
+        let json = evaluation.to_json();
+        let JsonData::Object(data, fields) = json else {
+            panic!("object expected");
+        };
+        assert_eq!(fields, vec!["output", "value"]);
+
+        let expected = {
+            let int = |text: &str| {
+                let mut object = JsonObject::new();
+                object.add("type", JsonData::String("int".to_string()));
+                object.add("text", JsonData::String(text.to_string()));
+                object.build()
+            };
+            let field = |name: &str, value: JsonData| {
+                let mut object = JsonObject::new();
+                object.add("name", JsonData::String(name.to_string()));
+                object.add("value", value);
+                object.build()
+            };
+            let mut vector = JsonObject::new();
+            vector.add("type", JsonData::String("vector".to_string()));
+            vector.add("elements", JsonData::Array(vec![int("2")]));
+
+            let mut object = JsonObject::new();
+            object.add("type", JsonData::String("struct".to_string()));
+            object.add("name", JsonData::String("point".to_string()));
+            object.add(
+                "fields",
+                JsonData::Array(vec![field("x", int("1")), field("y", vector.build())]),
+            );
+            object.build()
+        };
+        assert_eq!(data["value"], expected);
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let evaluation = eval("(displayln \"a\")\n(+ 1 2))");
+        let err = evaluation.result.as_ref().unwrap_err();
+        assert_eq!(
+            err.span,
+            Some(Span {
+                line: 2,
+                column: 8,
+                length: 1
+            })
+        );
+        assert!(evaluation.to_html().starts_with(
+            "<div class=\"boli-error\" data-line=\"2\" data-column=\"8\" data-length=\"1\">"
+        ));
+
+        let evaluation = eval("(displayln \"a\") (car nil)");
+        assert_eq!(evaluation.output, "a\n");
+        let json = evaluation.to_json().to_string();
+        assert!(json.contains("\"span\": null"));
+    }
+}
@@ -1,58 +1,108 @@
 use std::{
-    env,
+    collections::HashSet,
+    env, fs,
     io::{Error, ErrorKind, Result},
     rc::Rc,
+    time::Instant,
 };
 
-use crate::interpreter::{
-    self,
-    environment::{EnvironmentBuilder, EnvironmentRef},
-    module_mgmt::{file_system::new_directory, module_loader::ModuleLoader, ModuleDirRef},
+use rustyline::error::ReadlineError;
+
+use crate::{
+    diagnostics::Diagnostics,
+    interpreter::{
+        self,
+        cancellation::CancellationToken,
+        environment::{EnvironmentBuilder, EnvironmentRef},
+        module_mgmt::{file_system::new_directory, module_loader::ModuleLoader, ModuleDirRef},
+        prelude::PRELUDE_EXTENSIONS,
+        pretty_printer::{pretty_format_elided, DEFAULT_WIDTH},
+        settings::Settings,
+        values::{EvalResult, InterpreterError, ValueRef},
+    },
+    watch::Watcher,
 };
 
-pub fn run(module_file: &str, module_dirs: &Vec<String>) -> Result<()> {
-    let search_dirs = if !module_dirs.is_empty() {
-        get_search_dirs(&module_dirs)
+// Number of elements of a collection that are shown in results by default
+const DEFAULT_MAX_ELEMENTS: usize = 100;
+
+pub fn run(
+    module_file: &str,
+    module_dirs: &[String],
+    quiet: bool,
+    no_color: bool,
+    watch: bool,
+    settings: Settings,
+) -> Result<()> {
+    let module_dirs = if !module_dirs.is_empty() {
+        module_dirs.to_vec()
     } else {
-        get_search_dirs(&vec![".".to_string()])
+        vec![".".to_string()]
     };
+    let search_dirs = get_search_dirs(&module_dirs);
 
     let env = EnvironmentBuilder::new()
         .search_dirs(&search_dirs)
+        .settings(settings)
         .with_prelude(true)
         .build();
 
+    let mut session = Session::new(&env, no_color);
+
+    // Ctrl-C aborts the running evaluation and returns to the prompt. While
+    // the editor waits for input it reads Ctrl-C as a key instead.
+    let cancellation = session.cancellation.clone();
+    if let Err(e) = ctrlc::set_handler(move || cancellation.cancel()) {
+        eprintln!("warning: cannot handle Ctrl-C: {}", e);
+    }
+
     if module_file != "-" {
         load_module(module_file, &env)?;
     }
+    let mut watcher = watch.then(|| Watcher::new(module_file, &module_dirs));
 
-    let mut interpreter = interpreter::Interpreter::with_environment(&env);
     let mut input = String::new();
     let mut line: String;
     let mut continued = false;
-    let mut result_count = 0;
 
     let mut editor = rustyline::DefaultEditor::new().unwrap();
 
-    print_title();
+    if !quiet {
+        print_title(module_file, &module_dirs);
+    }
 
     loop {
         let prompt = if !continued { "boλi> " } else { "....> " };
 
-        line = editor.readline(prompt).unwrap_or("".to_string());
+        line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                // Discards a partial input
+                input.clear();
+                continued = false;
+                continue;
+            }
+            Err(_) => "".to_string(),
+        };
+
+        if watcher.as_mut().is_some_and(|watcher| watcher.changed()) {
+            reload_modules(module_file, &env);
+        }
         line = line.trim().to_string();
 
         if line.is_empty() {
             continue;
         }
 
-        if line.starts_with(':') {
-            if handle_command(&line) {
-                break;
-            } else {
-                line.clear();
-                continue;
+        if line.starts_with(':') && !continued {
+            match session.handle_command(&line) {
+                Reply::Quit => break,
+                Reply::Output(output) => print!("{}", output),
+                Reply::Error(error) => eprint!("{}", error),
+                Reply::Exit(code) => std::process::exit(code),
             }
+            line.clear();
+            continue;
         }
 
         if line.ends_with('\\') {
@@ -80,22 +130,217 @@ pub fn run(module_file: &str, module_dirs: &Vec<String>) -> Result<()> {
             Err(e) => eprintln!("Error adding history entry: {}", e),
         }
 
-        let result = interpreter.eval(&input);
+        match session.eval_input(&input) {
+            Reply::Output(output) => print!("{}", output),
+            Reply::Error(error) => eprint!("{}", error),
+            Reply::Quit => break,
+            Reply::Exit(code) => std::process::exit(code),
+        }
+
+        input.clear();
+    }
+
+    Ok(())
+}
+
+// Result of an input or command, output is printed to stdout and errors to
+// stderr
+enum Reply {
+    Quit,
+    Output(String),
+    Error(String),
+    // The evaluated code called exit
+    Exit(i32),
+}
+
+// State of an interactive session
+struct Session {
+    interpreter: interpreter::Interpreter,
+    env: EnvironmentRef,
+    // Names that are defined before the first input (built-ins and prelude)
+    predefined: HashSet<String>,
+    result_count: usize,
+    // Inputs that were evaluated successfully, in the order they were made
+    inputs: Vec<String>,
+    // Results are shown with at most max_elements elements per collection
+    max_elements: Option<usize>,
+    cancellation: CancellationToken,
+    diagnostics: Diagnostics,
+}
+
+impl Session {
+    fn new(env: &EnvironmentRef, no_color: bool) -> Self {
+        Self {
+            interpreter: interpreter::Interpreter::with_environment(env),
+            env: env.clone(),
+            predefined: env.borrow().env.keys().cloned().collect(),
+            result_count: 0,
+            inputs: Vec::new(),
+            max_elements: Some(DEFAULT_MAX_ELEMENTS),
+            cancellation: CancellationToken::new(),
+            diagnostics: Diagnostics::for_stderr(no_color),
+        }
+    }
+
+    fn eval(&mut self, code: &str) -> EvalResult {
+        self.cancellation.reset();
+        self.interpreter
+            .eval_with_cancellation(code, &self.cancellation)
+    }
 
-        match result {
+    fn eval_input(&mut self, input: &str) -> Reply {
+        match self.eval(input) {
             Ok(value) => {
-                let res = format!("${}", result_count);
-                result_count += 1;
-                interpreter.set_value(res.clone(), value.clone());
-                println!("{} = {}", res, value.borrow());
+                self.inputs.push(input.to_string());
+                Reply::Output(self.show_result(&value))
             }
-            Err(e) => eprintln!("{}", e),
+            Err(e) => self.error_reply(input, &e),
         }
+    }
 
-        input.clear();
+    // Results are bound to $1, $2, ... in the session environment, the
+    // most recent one to $it as well
+    fn show_result(&mut self, value: &ValueRef) -> String {
+        self.result_count += 1;
+        let res = format!("${}", self.result_count);
+        let mut env = self.env.borrow_mut();
+        env.bind(res.clone(), value.clone());
+        env.bind("$it".to_string(), value.clone());
+        let prefix = format!("{} = ", res);
+        let value = pretty_format_elided(
+            value,
+            prefix.chars().count(),
+            DEFAULT_WIDTH,
+            self.max_elements,
+        );
+        format!("{}{}\n", prefix, value)
     }
 
-    Ok(())
+    fn error_reply(&self, code: &str, err: &InterpreterError) -> Reply {
+        match err.exit_code() {
+            Some(exit_code) => Reply::Exit(exit_code),
+            None => Reply::Error(self.diagnostics.render_error(code, err)),
+        }
+    }
+
+    fn handle_command(&mut self, line: &str) -> Reply {
+        let (cmd, arg) = match line.split_once(char::is_whitespace) {
+            Some((cmd, arg)) => (cmd, arg.trim()),
+            None => (line, ""),
+        };
+        match (cmd, arg) {
+            (":q", "") => Reply::Quit,
+            (":h", "") => Reply::Output(help()),
+            (":bindings", "") => Reply::Output(self.bindings()),
+            (":full", "") => match self.env.borrow().get("$it") {
+                Some(value) => Reply::Output(format!(
+                    "{}\n",
+                    pretty_format_elided(&value, 0, DEFAULT_WIDTH, None)
+                )),
+                None => Reply::Error("There is no result yet\n".to_string()),
+            },
+            (":limit", limit) if !limit.is_empty() => match limit.parse::<usize>() {
+                Ok(0) => {
+                    self.max_elements = None;
+                    Reply::Output("Results are shown in full\n".to_string())
+                }
+                Ok(limit) => {
+                    self.max_elements = Some(limit);
+                    Reply::Output(format!(
+                        "Results show at most {} elements per collection\n",
+                        limit
+                    ))
+                }
+                Err(_) => Reply::Error(format!("Invalid limit: {}\n", limit)),
+            },
+            (":type", expr) if !expr.is_empty() => match self.eval(expr) {
+                Ok(value) => Reply::Output(format!("{}\n", value.borrow().get_type().name())),
+                Err(e) => self.error_reply(expr, &e),
+            },
+            (":time", expr) if !expr.is_empty() => {
+                let start = Instant::now();
+                let result = self.eval(expr);
+                let elapsed = start.elapsed();
+                match result {
+                    Ok(value) => {
+                        self.inputs.push(expr.to_string());
+                        Reply::Output(format!(
+                            "{}time: {:.3} ms\n",
+                            self.show_result(&value),
+                            elapsed.as_secs_f64() * 1000.0
+                        ))
+                    }
+                    Err(e) => self.error_reply(expr, &e),
+                }
+            }
+            (":load", file) if !file.is_empty() => {
+                let code = match fs::read_to_string(file) {
+                    Ok(code) => code,
+                    Err(e) => return Reply::Error(format!("Cannot read {}: {}\n", file, e)),
+                };
+                match self.eval(&code) {
+                    Ok(_) => {
+                        self.inputs.push(code);
+                        Reply::Output(format!("Loaded {}\n", file))
+                    }
+                    Err(e) => self.error_reply(&code, &e),
+                }
+            }
+            (":save", file) if !file.is_empty() => {
+                let mut code = String::from("; boli session\n");
+                for input in &self.inputs {
+                    code.push_str(input);
+                    code.push('\n');
+                }
+                match fs::write(file, code) {
+                    Ok(_) => Reply::Output(format!(
+                        "Saved {} input(s) to {}\n",
+                        self.inputs.len(),
+                        file
+                    )),
+                    Err(e) => Reply::Error(format!("Cannot write {}: {}\n", file, e)),
+                }
+            }
+            (":restore", file) if !file.is_empty() => {
+                let code = match fs::read_to_string(file) {
+                    Ok(code) => code,
+                    Err(e) => return Reply::Error(format!("Cannot read {}: {}\n", file, e)),
+                };
+                let inputs = split_inputs(&code);
+                for input in &inputs {
+                    match self.eval_input(input) {
+                        Reply::Error(error) => {
+                            return Reply::Error(format!("Restoring {} failed:\n{}", file, error))
+                        }
+                        Reply::Exit(code) => return Reply::Exit(code),
+                        _ => (),
+                    }
+                }
+                Reply::Output(format!(
+                    "Restored {} input(s) from {}\n",
+                    inputs.len(),
+                    file
+                ))
+            }
+            _ => Reply::Error(format!("Unknown command: {}\n", line)),
+        }
+    }
+
+    // Definitions made during the session (without the result values $1,
+    // $2, ... and $it) in alphabetical order
+    fn bindings(&self) -> String {
+        let env = self.env.borrow();
+        let mut names = env
+            .env
+            .keys()
+            .filter(|name| !self.predefined.contains(*name) && !name.starts_with('$'))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+            .iter()
+            .map(|name| format!("{} = {}\n", name, env.env[*name].get_value().borrow()))
+            .collect()
+    }
 }
 
 fn load_module(module_file: &str, env: &EnvironmentRef) -> Result<()> {
@@ -117,7 +362,20 @@ fn load_module(module_file: &str, env: &EnvironmentRef) -> Result<()> {
     Ok(())
 }
 
-fn get_search_dirs(module_dirs: &Vec<String>) -> Vec<ModuleDirRef> {
+// Changed modules are read again on their next require
+fn reload_modules(module_file: &str, env: &EnvironmentRef) {
+    env.borrow().get_module_cache().borrow_mut().clear();
+    if module_file == "-" {
+        println!("Modules changed");
+        return;
+    }
+    match load_module(module_file, env) {
+        Ok(_) => println!("Reloaded {}", module_file),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+fn get_search_dirs(module_dirs: &[String]) -> Vec<ModuleDirRef> {
     let mut search_dirs = vec![];
     for path in module_dirs {
         let dir: ModuleDirRef = new_directory(path, "");
@@ -126,32 +384,59 @@ fn get_search_dirs(module_dirs: &Vec<String>) -> Vec<ModuleDirRef> {
     search_dirs
 }
 
-fn print_title() {
+fn print_title(module_file: &str, module_dirs: &[String]) {
     println!(
         "(B)ollmeier's (O)wn (L)isp (I)mplementation - Version {}",
         env!("CARGO_PKG_VERSION")
     );
+    println!("Extensions: {}", PRELUDE_EXTENSIONS.join(", "));
+    println!("Module search dirs: {}", module_dirs.join(", "));
+    if module_file != "-" {
+        println!("Loaded module: {}", module_file);
+    }
     println!("Type :q to quit, :h for help.");
     println!("");
 }
 
-fn handle_command(cmd: &str) -> bool {
-    match cmd {
-        ":q" => true,
-        ":h" => {
-            print_help();
-            false
+fn help() -> String {
+    [
+        ":q              - Quit the interpreter",
+        ":h              - Show this help",
+        ":bindings       - List the definitions of the session",
+        ":full           - Show the last result in full",
+        ":limit <n>      - Show at most n elements per collection (0 = all)",
+        ":type <expr>    - Show the type of the value of expr",
+        ":time <expr>    - Evaluate expr and show the time it took",
+        ":load <file>    - Evaluate a script in the session",
+        ":save <file>    - Save the inputs of the session",
+        ":restore <file> - Replay the inputs of a saved session",
+    ]
+    .iter()
+    .map(|line| format!("{}\n", line))
+    .collect()
+}
+
+// Splits the code of a saved session into inputs the same way the prompt
+// combines lines into inputs
+fn split_inputs(code: &str) -> Vec<String> {
+    let mut inputs = Vec::new();
+    let mut input = String::new();
+    for line in code.lines() {
+        let line = line.trim();
+        if input.is_empty() && (line.is_empty() || line.starts_with(';')) {
+            continue;
         }
-        _ => {
-            eprintln!("Unknown command: {}", cmd);
-            false
+        input.push_str(line);
+        input.push('\n');
+        if !has_open_parens(&input) {
+            inputs.push(input.trim().to_string());
+            input.clear();
         }
     }
-}
-
-fn print_help() {
-    println!(":q - Quit the interpreter");
-    println!(":h - Show this help");
+    if !input.trim().is_empty() {
+        inputs.push(input.trim().to_string());
+    }
+    inputs
 }
 
 fn has_open_parens(s: &str) -> bool {
@@ -167,3 +452,154 @@ fn has_open_parens(s: &str) -> bool {
 
     count > 0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_session() -> Session {
+        let env = EnvironmentBuilder::new().with_prelude(true).build();
+        Session::new(&env, true)
+    }
+
+    fn output(reply: Reply) -> String {
+        match reply {
+            Reply::Output(output) => output,
+            Reply::Error(error) => panic!("unexpected error: {}", error),
+            Reply::Quit => panic!("unexpected quit"),
+            Reply::Exit(code) => panic!("unexpected exit with code {}", code),
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut session = new_session();
+        assert_eq!(output(session.eval_input("(def answer 42)")), "$1 = nil\n");
+        output(session.eval_input("(def (twice x) (* 2 x))"));
+        assert_eq!(
+            output(session.handle_command(":bindings")),
+            "answer = 42\ntwice = <lambda twice>\n"
+        );
+        assert_eq!(
+            output(session.handle_command(":type (twice 1,5)")),
+            "real\n"
+        );
+        assert_eq!(output(session.handle_command(":type   'a")), "symbol\n");
+
+        let timed = output(session.handle_command(":time (twice answer)"));
+        assert!(timed.starts_with("$3 = 84\ntime: "), "{}", timed);
+
+        let file = env::temp_dir().join("boli_repl_load_test.boli");
+        fs::write(&file, "(def loaded (twice 2))").unwrap();
+        let load = format!(":load {}", file.display());
+        assert!(output(session.handle_command(&load)).starts_with("Loaded"));
+        fs::remove_file(&file).unwrap();
+        assert_eq!(output(session.eval_input("loaded")), "$4 = 4\n");
+
+        assert!(matches!(session.handle_command(":type"), Reply::Error(_)));
+        assert!(matches!(session.handle_command(":load"), Reply::Error(_)));
+        assert!(matches!(session.handle_command(":q"), Reply::Quit));
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let file = env::temp_dir().join("boli_repl_session_test.boli");
+        let save = format!(":save {}", file.display());
+        let restore = format!(":restore {}", file.display());
+
+        let mut session = new_session();
+        output(session.eval_input("(def (square x) (* x x))"));
+        output(session.eval_input("(square 4)"));
+        assert!(matches!(session.eval_input("(undefined)"), Reply::Error(_)));
+        output(session.eval_input("(def total (+ $2 1))"));
+        assert_eq!(
+            output(session.handle_command(&save)),
+            format!("Saved 3 input(s) to {}\n", file.display())
+        );
+
+        let mut restored = new_session();
+        assert_eq!(
+            output(restored.handle_command(&restore)),
+            format!("Restored 3 input(s) from {}\n", file.display())
+        );
+        fs::remove_file(&file).unwrap();
+        assert_eq!(output(restored.eval_input("total")), "$4 = 17\n");
+    }
+
+    #[test]
+    fn test_result_history() {
+        let mut session = new_session();
+        output(session.eval_input("(+ 1 2)"));
+        output(session.eval_input("(λ (x) (* x 10))"));
+        assert_eq!(output(session.eval_input("($it $1)")), "$3 = 30\n");
+        output(session.eval_input("(λ (x y) x)"));
+        // $it is replaced, not merged with the previous function
+        assert_eq!(
+            output(session.eval_input("(list (arities $it) (arities $2))")),
+            "$5 = (list (list 2) (list 1))\n"
+        );
+        assert_eq!(output(session.handle_command(":bindings")), "");
+    }
+
+    #[test]
+    fn test_large_results() {
+        let numbers = |n: usize| {
+            let numbers = (0..n).map(|i| i.to_string()).collect::<Vec<_>>();
+            format!("(vector {})", numbers.join(" "))
+        };
+        let mut session = new_session();
+        session.handle_command(":limit 3");
+        assert_eq!(
+            output(session.eval_input(&numbers(10))),
+            "$1 = (vector 0 1 2 ...)\n"
+        );
+        assert_eq!(output(session.handle_command(":full")), numbers(10) + "\n");
+
+        session.handle_command(":limit 0");
+        let result = output(session.eval_input(&numbers(30)));
+        assert!(result.starts_with("$2 = (vector\n       0\n       1\n"));
+        assert!(result.ends_with("       29)\n"));
+        assert!(matches!(
+            session.handle_command(":limit x"),
+            Reply::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        // Stands in for the Ctrl-C handler
+        let mut session = new_session();
+        let cancellation = session.cancellation.clone();
+        let handler = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            cancellation.cancel();
+        });
+        let reply = session.eval_input("(frequencies (iterator 0 (λ (n) (+ n 1))))");
+        handler.join().unwrap();
+        match reply {
+            Reply::Error(error) => assert!(error.contains("evaluation cancelled"), "{}", error),
+            _ => panic!("evaluation was not cancelled"),
+        }
+
+        assert_eq!(output(session.eval_input("(+ 1 2)")), "$1 = 3\n");
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut session = new_session();
+        assert!(matches!(session.eval_input("(exit 5)"), Reply::Exit(5)));
+        assert!(matches!(
+            session.handle_command(":time (exit)"),
+            Reply::Exit(0)
+        ));
+    }
+
+    #[test]
+    fn test_split_inputs() {
+        let code = "; boli session\n(def (f x)\n  ; comment\n  (* x 2))\n\n(f 1) (f 2)\n";
+        assert_eq!(
+            split_inputs(code),
+            vec!["(def (f x)\n; comment\n(* x 2))", "(f 1) (f 2)"]
+        );
+    }
+}
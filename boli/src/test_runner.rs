@@ -0,0 +1,147 @@
+use std::{
+    cell::RefCell,
+    io::{Error, ErrorKind, Result},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::interpreter::{
+    self,
+    environment::EnvironmentBuilder,
+    module_mgmt::{
+        file_system::{join_path, new_directory, Directory},
+        ModuleDirRef, ModuleDirectory,
+    },
+    settings::Settings,
+    values::{borrow_value, downcast_value, IntValue, PairValue},
+};
+
+const TEST_FILE_SUFFIX: &str = "-test.boli";
+
+#[derive(Default)]
+struct Summary {
+    files: usize,
+    passed: i64,
+    failed: i64,
+    errors: usize,
+}
+
+// Runs all test files below the given directory (or the given test file)
+// and returns whether all of them passed
+pub fn run(path: &str, module_dirs: &[String], settings: Settings) -> Result<bool> {
+    let test_files = find_test_files(path)?;
+    let mut summary = Summary::default();
+
+    for (dir, file_name) in test_files {
+        run_test_file(&dir, &file_name, module_dirs, settings, &mut summary);
+    }
+
+    println!();
+    println!(
+        "test files: {}, passed: {}, failed: {}, errors: {}",
+        summary.files, summary.passed, summary.failed, summary.errors
+    );
+
+    Ok(summary.failed == 0 && summary.errors == 0)
+}
+
+fn find_test_files(path: &str) -> Result<Vec<(Rc<RefCell<Directory>>, String)>> {
+    let fs_path = Path::new(path);
+    if fs_path.is_file() {
+        let dir = fs_path
+            .parent()
+            .and_then(|dir| dir.to_str())
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or(".");
+        let file_name = fs_path.file_name().and_then(|name| name.to_str());
+        return match file_name {
+            Some(file_name) => Ok(vec![(new_directory(dir, ""), file_name.to_string())]),
+            None => Err(Error::new(ErrorKind::InvalidInput, "Invalid test file")),
+        };
+    }
+
+    if !fs_path.is_dir() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("Test directory not found: {}", path),
+        ));
+    }
+
+    let mut test_files = vec![];
+    collect_test_files(&new_directory(path, ""), &mut test_files);
+
+    Ok(test_files)
+}
+
+fn collect_test_files(
+    dir: &Rc<RefCell<Directory>>,
+    test_files: &mut Vec<(Rc<RefCell<Directory>>, String)>,
+) {
+    for file_name in dir.borrow().file_names() {
+        if file_name.ends_with(TEST_FILE_SUFFIX) {
+            test_files.push((dir.clone(), file_name));
+        }
+    }
+
+    let dir_path = dir.borrow().full_path();
+    for dir_name in dir.borrow().dir_names() {
+        collect_test_files(&new_directory(&dir_path, &dir_name), test_files);
+    }
+}
+
+fn run_test_file(
+    dir: &Rc<RefCell<Directory>>,
+    file_name: &str,
+    module_dirs: &[String],
+    settings: Settings,
+    summary: &mut Summary,
+) {
+    let file_path = join_path(&dir.borrow().full_path(), file_name);
+    println!("== {}", file_path);
+    summary.files += 1;
+
+    let code = match dir.borrow().get_file(file_name) {
+        Some(file) => file.borrow().read(),
+        None => String::new(),
+    };
+
+    let test_dir: ModuleDirRef = dir.clone();
+    let mut search_dirs = vec![test_dir];
+    for path in module_dirs {
+        let module_dir: ModuleDirRef = new_directory(path, "");
+        search_dirs.push(module_dir);
+    }
+
+    let env = EnvironmentBuilder::new()
+        .search_dirs(&search_dirs)
+        .settings(settings)
+        .with_prelude(true)
+        .build();
+    let mut interpreter = interpreter::Interpreter::with_environment(&env);
+
+    let result = interpreter
+        .eval(&code)
+        .and_then(|_| interpreter.eval("(run-test-suite)"));
+
+    let counts = result.map(|result| {
+        let result = borrow_value(&result);
+        let pair = downcast_value::<PairValue>(&result).unwrap();
+        let count = |value| {
+            downcast_value::<IntValue>(&borrow_value(value))
+                .unwrap()
+                .value
+        };
+        (count(&pair.left), count(&pair.right))
+    });
+
+    match counts {
+        Ok((passed, failed)) => {
+            summary.passed += passed;
+            summary.failed += failed;
+        }
+        Err(err) => {
+            println!("ERROR {}: {}", file_path, err);
+            summary.errors += 1;
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// JavaScript bindings for running BOLI in the browser, e.g.
+//
+//   import init, { eval as boliEval } from "./pkg/boli.js";
+//   await init();
+//   boliEval('(displayln "Hello") (+ 1 2)'); // "Hello\n3"
+//
+// Definitions are kept between calls until reset is called. There is no
+// file system, modules are added with addModule and kept in memory.
+
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    interpreter::{
+        environment::EnvironmentBuilder,
+        module_mgmt::memory::{new_memory_directory, MemoryDirectory},
+        values::borrow_value,
+        Interpreter,
+    },
+    playground::{self, Evaluation},
+};
+
+thread_local! {
+    static MODULES: Rc<RefCell<MemoryDirectory>> = new_memory_directory("");
+    static INTERPRETER: RefCell<Interpreter> = RefCell::new(new_interpreter());
+}
+
+fn new_interpreter() -> Interpreter {
+    let modules = MODULES.with(|modules| modules.clone());
+    let env = EnvironmentBuilder::new()
+        .with_prelude(true)
+        .search_dirs(&vec![modules])
+        .build();
+    Interpreter::with_environment(&env)
+}
+
+// Evaluates the code and returns the printed output followed by the value
+// of the last expression or the error message
+#[wasm_bindgen]
+pub fn eval(code: &str) -> String {
+    let evaluation = evaluate(code);
+
+    let mut text = evaluation.output;
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    match evaluation.result {
+        Ok(value) => text.push_str(&borrow_value(&value).to_string()),
+        Err(err) => text.push_str(&format!("Error: {}", err.message)),
+    }
+    text
+}
+
+// Like eval, but the result is rendered as JSON (see playground::value_to_json)
+#[wasm_bindgen(js_name = evalJson)]
+pub fn eval_json(code: &str) -> String {
+    evaluate(code).to_json().to_string()
+}
+
+// Like eval, but the result is rendered as HTML (see playground::value_to_html)
+#[wasm_bindgen(js_name = evalHtml)]
+pub fn eval_html(code: &str) -> String {
+    evaluate(code).to_html()
+}
+
+fn evaluate(code: &str) -> Evaluation {
+    INTERPRETER.with(|interpreter| playground::evaluate(&mut interpreter.borrow_mut(), code))
+}
+
+// Adds a module that can be required by the code, the path of the module
+// util::strings is "util/strings.boli"
+#[wasm_bindgen(js_name = addModule)]
+pub fn add_module(path: &str, code: &str) {
+    MODULES.with(|modules| modules.borrow_mut().add_file(path, code));
+}
+
+// Discards all definitions of previous calls, added modules are kept
+#[wasm_bindgen]
+pub fn reset() {
+    INTERPRETER.with(|interpreter| *interpreter.borrow_mut() = new_interpreter());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        reset();
+        assert_eq!(eval("(def (square x) (* x x))"), "nil");
+        assert_eq!(eval("(display \"x =\") (square 4)"), "x =\n16");
+        assert_eq!(eval("(displayln \"done\")"), "done\nnil");
+        assert!(eval("(square)").starts_with("Error: "));
+
+        reset();
+        assert!(eval("(square 2)").starts_with("Error: "));
+    }
+
+    #[test]
+    fn test_add_module() {
+        add_module("util/math.boli", "(def (cube x) (* x x x))");
+        reset();
+        assert_eq!(eval("(require 'util::math) (cube 2)"), "8");
+    }
+}
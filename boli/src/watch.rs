@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Detects changes of a script and the BOLI modules in the module directories
+// by comparing modification times. Files that are added or removed count as
+// changes as well.
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+impl Watcher {
+    pub fn new(input_file: &str, module_dirs: &[String]) -> Self {
+        let mut paths = module_dirs.iter().map(PathBuf::from).collect::<Vec<_>>();
+        if input_file != "-" {
+            paths.push(PathBuf::from(input_file));
+        }
+        let modified = scan(&paths);
+        Self { paths, modified }
+    }
+
+    pub fn changed(&mut self) -> bool {
+        let modified = scan(&self.paths);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+
+    pub fn wait_for_change(&mut self) {
+        while !self.changed() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn scan(paths: &[PathBuf]) -> HashMap<PathBuf, SystemTime> {
+    let mut modified = HashMap::new();
+    for path in paths {
+        scan_path(path, true, &mut modified);
+    }
+    modified
+}
+
+// Only .boli and .bolc files are watched in directories, the script itself
+// is watched whatever its name is
+fn scan_path(path: &Path, explicit: bool, modified: &mut HashMap<PathBuf, SystemTime>) {
+    if path.is_dir() {
+        let Ok(entries) = fs::read_dir(path) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            scan_path(&entry.path(), false, modified);
+        }
+        return;
+    }
+    let is_module = matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("boli" | "bolc")
+    );
+    if !explicit && !is_module {
+        return;
+    }
+    if let Ok(time) = fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        modified.insert(path.to_path_buf(), time);
+    }
+}
+
+// Runs the script in a child process with the given arguments and runs it
+// again whenever the script or one of the modules changes. Only ends when
+// the process is interrupted.
+pub fn run(input_file: &str, module_dirs: &[String], args: &[String]) -> Result<()> {
+    if input_file == "-" {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--watch requires an input file",
+        ));
+    }
+    let program = env::current_exe()?;
+    let mut watcher = Watcher::new(input_file, module_dirs);
+
+    loop {
+        match Command::new(&program).args(args).status() {
+            Ok(status) if !status.success() => {
+                eprintln!("[watch] {} failed ({})", input_file, status)
+            }
+            Ok(_) => (),
+            Err(e) => eprintln!("[watch] cannot run {}: {}", input_file, e),
+        }
+        eprintln!("[watch] waiting for changes of {}...", input_file);
+        watcher.wait_for_change();
+        eprintln!("[watch] change detected, running {} again", input_file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher() {
+        let dir = env::temp_dir().join("boli_watch_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        let script = dir.join("main.boli");
+        fs::write(&script, "(displayln 1)").unwrap();
+        fs::write(dir.join("lib").join("notes.txt"), "").unwrap();
+
+        let module_dirs = vec![dir.join("lib").display().to_string()];
+        let mut watcher = Watcher::new(&script.display().to_string(), &module_dirs);
+        assert!(!watcher.changed());
+
+        // Other files in the module directories are ignored
+        fs::write(dir.join("lib").join("notes.txt"), "todo").unwrap();
+        assert!(!watcher.changed());
+
+        fs::write(dir.join("lib").join("util.boli"), "(def x 1)").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(dir.join("lib").join("util.boli")).unwrap();
+        assert!(watcher.changed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -17,3 +17,345 @@ fn test_command_line_args() {
     let expected_output = std::fs::read_to_string("tests/output/clargs.out").unwrap();
     assert_eq!(output, expected_output);
 }
+
+#[test]
+fn test_test_mode() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd.arg("--test").arg("tests/input/testsuite/passing").ok();
+
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert!(output.contains("PASS square of integers"));
+    assert!(output.contains("PASS string-join"));
+    assert!(output.ends_with("test files: 2, passed: 3, failed: 0, errors: 0\n"));
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--test")
+        .arg("tests/input/testsuite/failing")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(output.contains("FAIL fails: Assertion failed: expected 3 but got 2"));
+    assert!(output.ends_with("test files: 1, passed: 1, failed: 1, errors: 0\n"));
+}
+
+#[test]
+fn test_lint_mode() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd.arg("--check").arg("tests/input/hello.boli").ok();
+    assert!(result.is_ok());
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--check")
+        .arg("--json")
+        .arg("-")
+        .write_stdin("(def (inc x) (+ x 1))\n(inc 1 2)")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(output.contains("\"kind\": \"arity-mismatch\""));
+    assert!(output.contains("\"form\": 2"));
+    assert!(output.contains("\"line\": 2"));
+    assert!(output.contains("\"column\": 2"));
+
+    // All syntax errors are reported
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--check")
+        .arg("-")
+        .write_stdin("(+ 1 2))\n(display \"x\"")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        output,
+        "error: Unexpected token\n \
+         --> line 1, column 8\n  \
+         |\n\
+         1 | (+ 1 2))\n  \
+         |        ^\n\
+         error: Unexpected end of input\n \
+         --> line 2, column 1\n  \
+         |\n\
+         2 | (display \"x\"\n  \
+         | ^\n"
+    );
+}
+
+#[test]
+fn test_parse_only_sexpr() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .arg("--parse-only")
+        .arg("--ast-format")
+        .arg("sexpr")
+        .arg("-")
+        .write_stdin("(let ([x 1]) (displayln x))")
+        .ok();
+
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert_eq!(output, "(block (def x 1) (displayln x))\n");
+}
+
+#[test]
+fn test_compile_mode() {
+    let dir = std::env::temp_dir().join(format!("boli-compile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("answer.boli"), "(def answer 42)").unwrap();
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd.arg("--compile").arg(dir.join("answer.boli")).ok();
+    assert!(result.is_ok());
+
+    let compiled = std::fs::read(dir.join("answer.bolc")).unwrap();
+    assert!(compiled.starts_with(b"BOLC"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_boli_path() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .env("BOLI_PATH", "tests/input")
+        .arg("-")
+        .write_stdin("(require 'greet::greet)\n(guten-tag \"Welt\")")
+        .ok();
+
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert!(output.starts_with("Guten Tag, Welt!\n"));
+}
+
+#[test]
+fn test_stats() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .arg("--stats")
+        .arg("-")
+        .write_stdin("(def (down n) (if (> n 0) (down (- n 1)) 'done))\n(down 3)")
+        .ok();
+
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "'done\n");
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert!(report.starts_with("Allocations:\n"));
+    assert!(report.contains("Peak environment depth: "));
+    assert!(report.contains("\n  >                             4\n"));
+}
+
+#[test]
+fn test_number_format() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .arg("--number-format")
+        .arg("en")
+        .arg("-")
+        .write_stdin("(+ 1,000 0.5 (string->real \"2,000.25\"))")
+        .ok();
+
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert_eq!(output, "3000.75\n");
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd.arg("-").write_stdin("(+ 1.000 0,5)").ok();
+
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert_eq!(output, "1000,5\n");
+}
+
+#[test]
+fn test_contracts() {
+    let code = "(def (id [x : int]) : int x)\n(id \"a\")";
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--contracts")
+        .arg("-")
+        .write_stdin(code)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let message = String::from_utf8(output.stderr).unwrap();
+    assert!(message.contains("id expects x of type int, got \"a\""));
+
+    // Without --contracts the annotations are not checked
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd.arg("-").write_stdin(code).ok();
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert_eq!(output, "\"a\"\n");
+}
+
+#[test]
+fn test_strict_bool() {
+    let code = "(if (vector) 1 2)";
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--strict-bool")
+        .arg("-")
+        .write_stdin(code)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let message = String::from_utf8(output.stderr).unwrap();
+    assert!(message.contains("if expects a boolean condition"));
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd.arg("-").write_stdin(code).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+}
+
+#[test]
+fn test_strict_redefinition() {
+    let code = "(def (f x) x)\n(def (f y) (* y 2))\n(f 21)";
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd.arg("-").write_stdin(code).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "42\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "warning: redefinition of f replaces its definition for 1 argument\n"
+    );
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--strict")
+        .arg("-")
+        .write_stdin(code)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let message = String::from_utf8(output.stderr).unwrap();
+    assert!(message.contains("redefinition of f replaces its definition for 1 argument"));
+}
+
+#[test]
+fn test_typecheck() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--typecheck")
+        .arg("-")
+        .write_stdin("(def (area [w : int] [h : int]) (* w h))\n(area 2 3,5)")
+        .output()
+        .unwrap();
+
+    // Warnings do not prevent the evaluation
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "7,0\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "warning: 2:2: area expects int as argument 2, got real\n"
+    );
+}
+
+#[test]
+fn test_piped_input() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .arg("tests/input/upper_lines.boli")
+        .write_stdin("one\ntwo\n")
+        .ok();
+
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert!(output.starts_with("ONE\nTWO\n"));
+}
+
+#[test]
+fn test_watch_requires_input_file() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd.arg("--watch").write_stdin("1").output().unwrap();
+
+    assert!(!output.status.success());
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.contains("--watch requires an input file"));
+}
+
+#[test]
+fn test_script_arguments() {
+    // Arguments after -- go to main even if they look like options
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .arg("tests/input/script.boli")
+        .arg("--")
+        .arg("-q")
+        .arg("--stats")
+        .arg("data.txt")
+        .ok();
+
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert!(output.starts_with("-q,--stats,data.txt\n"));
+}
+
+#[test]
+fn test_exit_status() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("-")
+        .write_stdin("(display \"partial\")\n(exit 2)\n(displayln \"unreachable\")")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "partial");
+
+    // The integer result of main is the exit status
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("-")
+        .write_stdin("(def (main args...) (if (> (count args) 1) 0 3))")
+        .arg("x")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_error_output() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("-")
+        .write_stdin("(println \"result\")\n(eprintln \"progress:\" 50)\n(car 1)")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "result\n");
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.starts_with("progress: 50\nerror: car requires a pair as argument"));
+}
+
+#[test]
+fn test_quiet_repl() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd.arg("-i").write_stdin(":q\n").output().unwrap();
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(output.contains("(B)ollmeier's (O)wn (L)isp (I)mplementation"));
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("-i")
+        .arg("--quiet")
+        .write_stdin(":q\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(!output.contains("(B)ollmeier's (O)wn (L)isp (I)mplementation"));
+    assert!(!output.contains("Type :q to quit"));
+}
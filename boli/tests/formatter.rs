@@ -0,0 +1,74 @@
+use assert_cmd::Command;
+use boli::frontend::{
+    formatter::{format_code, DEFAULT_WIDTH},
+    parser::{json_visitor::JsonData, Parser},
+};
+
+fn boli_files(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            boli_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "boli") {
+            files.push(path);
+        }
+    }
+}
+
+fn ast_json(code: &str) -> String {
+    JsonData::from(Parser::new().parse(code).unwrap()).to_string()
+}
+
+#[test]
+fn test_formatting_keeps_the_program() {
+    let mut files = Vec::new();
+    boli_files(std::path::Path::new("src"), &mut files);
+    boli_files(std::path::Path::new("tests/input"), &mut files);
+    assert!(!files.is_empty());
+
+    for file in files {
+        let code = std::fs::read_to_string(&file).unwrap();
+        let formatted = format_code(&code, DEFAULT_WIDTH).unwrap();
+
+        assert_eq!(ast_json(&formatted), ast_json(&code), "{:?}", file);
+        assert_eq!(
+            format_code(&formatted, DEFAULT_WIDTH).unwrap(),
+            formatted,
+            "{:?}",
+            file
+        );
+    }
+}
+
+#[test]
+fn test_format_check() {
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let output = cmd
+        .arg("--format")
+        .arg("--check")
+        .arg("tests/input/reverse.boli")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output, "tests/input/reverse.boli is not formatted\n");
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .arg("--format")
+        .arg("-")
+        .write_stdin("(def   x\n    42)")
+        .ok();
+    assert!(result.is_ok());
+    let output = String::from_utf8(result.unwrap().stdout).unwrap();
+    assert_eq!(output, "(def x\n  42)\n");
+
+    let mut cmd = Command::cargo_bin("boli").unwrap();
+    let result = cmd
+        .arg("--format")
+        .arg("--check")
+        .arg("-")
+        .write_stdin("(def x 42)\n")
+        .ok();
+    assert!(result.is_ok());
+}
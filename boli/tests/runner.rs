@@ -3,7 +3,7 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use boli::interpreter::{
     self,
     environment::EnvironmentBuilder,
-    misc_functions::OutputRef,
+    misc_functions::{BufferedOutput, OutputRef},
     module_mgmt::{
         extension::{new_extension, new_extension_dir},
         file_system::new_directory,
@@ -23,7 +23,7 @@ fn test_load_extension_module_ok() {
     ext_dir.borrow_mut().add_extension(&ext_module);
 
     let search_dirs: Vec<ModuleDirRef> = vec![new_directory("tests", "code"), ext_dir];
-    let output: OutputRef = Rc::new(RefCell::new(StringOutput::new()));
+    let output: OutputRef = Rc::new(RefCell::new(BufferedOutput::new()));
 
     let env = EnvironmentBuilder::new()
         .search_dirs(&search_dirs)
@@ -42,33 +42,6 @@ fn test_load_extension_module_ok() {
     assert_eq!(value.borrow().get_type(), ValueType::Int);
 }
 
-#[derive(Debug)]
-struct StringOutput {
-    output: String,
-}
-
-impl StringOutput {
-    fn new() -> Self {
-        Self {
-            output: String::new(),
-        }
-    }
-
-    fn get_output(&self) -> &str {
-        &self.output
-    }
-}
-
-impl interpreter::misc_functions::Output for StringOutput {
-    fn print(&mut self, text: &str) {
-        self.output.push_str(text);
-    }
-
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
-    }
-}
-
 fn read_expected_output_file(file_name: &str) -> String {
     let path = format!("tests/output/{}", file_name);
     std::fs::read_to_string(path).unwrap()
@@ -76,7 +49,8 @@ fn read_expected_output_file(file_name: &str) -> String {
 
 fn run_file(input_file_name: &str, expected_output_file: &str) {
     let code_dir: ModuleDirRef = new_directory("tests", "input");
-    let output: OutputRef = Rc::new(RefCell::new(StringOutput::new()));
+    let buffered_output = BufferedOutput::new();
+    let output: OutputRef = Rc::new(RefCell::new(buffered_output.clone()));
 
     let env = EnvironmentBuilder::new()
         .search_dirs(&vec![code_dir.clone()])
@@ -92,10 +66,7 @@ fn run_file(input_file_name: &str, expected_output_file: &str) {
     let result = interpreter.eval(&code);
     assert!(result.is_ok(), "Error: {:?}", result.err());
 
-    let str_out = output.borrow();
-    let str_out = str_out.as_any().downcast_ref::<StringOutput>().unwrap();
-
-    let actual_output = str_out.get_output();
+    let actual_output = buffered_output.text();
     let expected_output = read_expected_output_file(expected_output_file);
 
     assert_eq!(actual_output, expected_output);
@@ -125,3 +96,13 @@ fn test_person() {
 fn test_block_comment() {
     run_file("block_comment.boli", "block_comment.out");
 }
+
+#[test]
+fn test_display() {
+    run_file("display.boli", "display.out");
+}
+
+#[test]
+fn test_pretty_print() {
+    run_file("pretty_print.boli", "pretty_print.out");
+}